@@ -0,0 +1,455 @@
+use crate::instruction::Instruction;
+use crate::program::Program;
+use crate::run::local_slot;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+
+/// Facts about a program worked out without running it, for a quick overview of what
+/// unknown bytecode may do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgramStats {
+    /// The deepest the stack can get, or `None` if it cannot be bounded statically: a loop
+    /// or recursion may keep growing it, or `JMPDYN`/`HOSTCALL` make its effect unknown.
+    pub max_stack: Option<usize>,
+    /// An instruction that may run with fewer values on the stack than it takes, if any.
+    pub underflow: Option<usize>,
+    /// The memory cells named by instruction operands and the data section.
+    pub memory: Option<Range<usize>>,
+    /// Jumps to an earlier or the same instruction, as `(from, to)`; each may form a loop
+    /// that never ends.
+    pub back_edges: Vec<(usize, usize)>,
+    /// The number of `JMPDYN` instructions, whose targets are only known at runtime.
+    pub dynamic_jumps: usize,
+    /// The subroutines each reachable routine calls, keyed by entry index. The program
+    /// itself is the routine at 0.
+    pub calls: BTreeMap<usize, BTreeSet<usize>>,
+    /// True if a subroutine can call itself, directly or through others.
+    pub recursive: bool,
+}
+
+/// Where a subroutine leaves the stack when it returns, relative to its entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Returns {
+    Never,
+    At(isize),
+    Varies,
+}
+
+/// What a routine does to the stack, relative to the depth it was entered with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Summary {
+    /// `None` if the routine's stack use is unbounded or unknown.
+    max_depth: Option<isize>,
+    /// The lowest depth an instruction pops down to, and that instruction. A `CALL` stands
+    /// for everything its callee pops.
+    min_depth: (isize, usize),
+    returns: Returns,
+}
+
+/// Analyzes `program` statically.
+///
+/// Subroutines are summarized separately and each `CALL` applies its callee's summary, so
+/// a subroutine called from several places does not make their stacks look alike.
+///
+/// ```
+/// use vortex_vm::analysis::analyze;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::program::Program;
+///
+/// let program = Program::new(vec![
+///     Instruction::Push(3),
+///     Instruction::Call(5), // loop
+///     Instruction::SubS(1),
+///     Instruction::Jnz(1),
+///     Instruction::Ret,
+///     Instruction::Dup, // the subroutine
+///     Instruction::Add,
+///     Instruction::MemWrite(4, vec![1, 2]),
+///     Instruction::Ret,
+/// ]);
+/// let stats = analyze(&program);
+/// assert_eq!(stats.max_stack, Some(2));
+/// assert_eq!(stats.memory, Some(4..6));
+/// assert_eq!(stats.back_edges, vec![(3, 1)]);
+/// assert_eq!(stats.calls[&0].iter().copied().collect::<Vec<_>>(), vec![5]);
+/// ```
+pub fn analyze(program: &Program) -> ProgramStats {
+    let mut analyzer = Analyzer { instructions: &program.instructions, summaries: HashMap::new(), calls: BTreeMap::new(), open: Vec::new(), recursive: false };
+    let main = analyzer.summarize(0);
+
+    let instructions = &program.instructions;
+    let back_edges = instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instruction)| match instruction {
+            Instruction::Jiz(target) | Instruction::Jnz(target) | Instruction::Jmp(target) if *target as usize <= index => {
+                Some((index, *target as usize))
+            }
+            _ => None,
+        })
+        .collect();
+
+    ProgramStats {
+        max_stack: main.max_depth.map(|depth| depth.max(0) as usize),
+        underflow: (main.min_depth.0 < 0).then_some(main.min_depth.1),
+        memory: memory_range(program),
+        back_edges,
+        dynamic_jumps: instructions.iter().filter(|instruction| matches!(instruction, Instruction::JmpDyn)).count(),
+        calls: analyzer.calls,
+        recursive: analyzer.recursive,
+    }
+}
+
+struct Analyzer<'a> {
+    instructions: &'a [Instruction],
+    summaries: HashMap<usize, Summary>,
+    calls: BTreeMap<usize, BTreeSet<usize>>,
+    /// Routines currently being summarized, to detect recursion.
+    open: Vec<usize>,
+    recursive: bool,
+}
+
+impl Analyzer<'_> {
+    /// Follows every path from `entry` until it returns, halts or leaves the program.
+    fn summarize(&mut self, entry: usize) -> Summary {
+        if let Some(summary) = self.summaries.get(&entry) {
+            return *summary;
+        }
+        if self.open.contains(&entry) {
+            self.recursive = true;
+            return Summary { max_depth: None, min_depth: (0, entry), returns: Returns::Varies };
+        }
+        self.open.push(entry);
+        self.calls.entry(entry).or_default();
+
+        let len = self.instructions.len();
+        let mut bounded = true;
+        let mut max_depth = 0;
+        let mut min_depth = (0, entry);
+        let mut returns = Returns::Never;
+        let mut seen: HashMap<usize, (Range<isize>, usize)> = HashMap::new();
+        let mut work = vec![(entry, 0isize)];
+
+        while let Some((pc, depth)) = work.pop() {
+            let Some(instruction) = self.instructions.get(pc) else {
+                continue;
+            };
+
+            // Only revisit instructions with a depth outside the range seen there; widening
+            // it more often than there are instructions means a loop grows or shrinks the stack
+            let (depths, widened) = match seen.get(&pc) {
+                Some((depths, _)) if depths.contains(&depth) => continue,
+                Some((depths, widened)) => (depths.start.min(depth)..depths.end.max(depth + 1), widened + 1),
+                None => (depth..depth + 1, 0),
+            };
+            if widened > len {
+                bounded = false;
+                continue;
+            }
+            seen.insert(pc, (depths, widened));
+
+            let next = match instruction {
+                Instruction::Ret => {
+                    returns = match returns {
+                        Returns::Never => Returns::At(depth),
+                        Returns::At(at) if at == depth => Returns::At(at),
+                        _ => Returns::Varies,
+                    };
+                    continue;
+                }
+                Instruction::Halt(_) | Instruction::Abort(..) => continue,
+                // Where these continue, and with what stack, is only known at run time
+                Instruction::JmpDyn | Instruction::HostCall(_) | Instruction::Try(_) | Instruction::Throw => {
+                    bounded = false;
+                    continue;
+                }
+                // CLEAR also drops whatever a caller left on the stack, so only at the
+                // program's entry is the depth afterwards known
+                Instruction::Clear if entry != 0 => {
+                    bounded = false;
+                    continue;
+                }
+                Instruction::Clear => 0,
+                Instruction::Call(target) => {
+                    let target = *target as usize;
+                    self.calls.entry(entry).or_default().insert(target);
+                    let callee = self.summarize(target);
+                    if depth + callee.min_depth.0 < min_depth.0 {
+                        min_depth = (depth + callee.min_depth.0, pc);
+                    }
+                    match callee.max_depth {
+                        Some(callee_max) => max_depth = max_depth.max(depth + callee_max),
+                        None => bounded = false,
+                    }
+                    match callee.returns {
+                        Returns::Never => {}
+                        Returns::At(net) => work.push((pc + 1, depth + net)),
+                        Returns::Varies => bounded = false,
+                    }
+                    continue;
+                }
+                _ => {
+                    let (pops, pushes) = stack_effect(instruction);
+                    if depth - pops < min_depth.0 {
+                        min_depth = (depth - pops, pc);
+                    }
+                    let after = depth - pops + pushes;
+                    max_depth = max_depth.max(after);
+                    if may_push_nothing(instruction) {
+                        work.push((pc + 1, depth - pops));
+                    }
+                    after
+                }
+            };
+
+            match instruction {
+                Instruction::Jmp(target) => work.push((*target as usize, next)),
+                Instruction::Jiz(target) | Instruction::Jnz(target) => {
+                    work.push((*target as usize, next));
+                    work.push((pc + 1, next));
+                }
+                _ => work.push((pc + 1, next)),
+            }
+        }
+
+        self.open.pop();
+        let summary = Summary { max_depth: bounded.then_some(max_depth), min_depth, returns };
+        if !self.recursive || self.open.is_empty() {
+            self.summaries.insert(entry, summary);
+        }
+        summary
+    }
+}
+
+/// Returns how many values `instruction` pops and pushes. `JMPDYN` and `HOSTCALL`, whose
+/// effect depends on runtime values, and calls and returns are handled by the caller.
+///
+/// `DIV` and `MOD` count as pushing their result, but dividing by zero pushes nothing, see
+/// [`may_push_nothing`].
+pub(crate) fn stack_effect(instruction: &Instruction) -> (isize, isize) {
+    match instruction {
+        Instruction::Push(_) | Instruction::MemRead(_) | Instruction::ReadLine(_, _) | Instruction::ReadInt | Instruction::PerfRead(_) | Instruction::VmInfo(_) => (0, 1),
+        Instruction::Dup => (1, 2),
+        // Conditional jumps look at the top value without popping it
+        Instruction::Jiz(_) | Instruction::Jnz(_) => (1, 1),
+        Instruction::Swap => (2, 2),
+        Instruction::Rot => (3, 3),
+        Instruction::Over => (2, 3),
+        // A negative depth is skipped
+        Instruction::Pick(depth) => match isize::try_from(*depth) {
+            Ok(depth) if depth >= 0 => (depth + 1, depth + 2),
+            _ => (0, 0),
+        },
+        Instruction::Pop | Instruction::PrintInt | Instruction::JmpDyn | Instruction::Free => (1, 0),
+        Instruction::Load | Instruction::Alloc | Instruction::Assert(_) => (1, 1),
+        Instruction::MemCmp(..) | Instruction::Grow(_) | Instruction::MemSize | Instruction::Depth => (0, 1),
+        Instruction::AddS(_) | Instruction::SubS(_) | Instruction::MultS(_) | Instruction::DivS(_) | Instruction::ModS(_) | Instruction::CodeRead => {
+            (1, 1)
+        }
+        Instruction::Add
+        | Instruction::Sub
+        | Instruction::Mult
+        | Instruction::Div
+        | Instruction::Mod
+        | Instruction::Eq
+        | Instruction::Neq
+        | Instruction::Lt
+        | Instruction::Gt
+        | Instruction::Lte
+        | Instruction::Gte
+        | Instruction::AssertEq => (2, 1),
+        Instruction::CodeWrite | Instruction::Store => (2, 0),
+        Instruction::MemWriteS(_, len) => ((*len).max(0) as isize, 0),
+        // Slots outside a frame are skipped
+        Instruction::LoadLocal(index) if local_slot(*index).is_some() => (0, 1),
+        Instruction::StoreLocal(index) if local_slot(*index).is_some() => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+/// Returns whether `instruction` may leave out the values [`stack_effect`] says it pushes:
+/// `DIV` and `MOD` pop both operands and push nothing when the divisor is zero.
+pub(crate) fn may_push_nothing(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::Div | Instruction::Mod)
+}
+
+/// Returns the instructions control can move to after the one at `index`: its jump, call
+/// or handler target and the next instruction, unless it never falls through. An index
+/// equal to the program's length is where the program ends.
+///
+/// A call continues after itself once the callee returns, and a `TRY` at its handler when
+/// something inside it throws. Where `RET`, `THROW` and `JMPDYN` go depends on the stacks
+/// at run time, so they have no successors of their own.
+pub fn successors(instruction: &Instruction, index: usize) -> Vec<usize> {
+    match instruction {
+        Instruction::Jmp(target) => vec![*target as usize],
+        Instruction::Jiz(target) | Instruction::Jnz(target) | Instruction::Call(target) | Instruction::Try(target) => {
+            vec![index + 1, *target as usize]
+        }
+        Instruction::Ret | Instruction::Halt(_) | Instruction::Abort(..) | Instruction::Throw | Instruction::JmpDyn => Vec::new(),
+        _ => vec![index + 1],
+    }
+}
+
+/// Marks the instructions that can run, following every path from the entry point through
+/// the control flow graph [`successors`] describes.
+///
+/// `JMPDYN` may jump anywhere and `CODEREAD`/`CODEWRITE` treat the program's own code as
+/// data, so a program using any of them has every instruction marked.
+///
+/// ```
+/// use vortex_vm::analysis::reachable;
+/// use vortex_vm::instruction::Instruction;
+///
+/// let instructions = [
+///     Instruction::Jmp(2),
+///     Instruction::Push(1),
+///     Instruction::Push(2),
+///     Instruction::Halt(0),
+///     Instruction::Push(3),
+/// ];
+/// assert_eq!(reachable(&instructions), vec![true, false, true, true, false]);
+/// ```
+pub fn reachable(instructions: &[Instruction]) -> Vec<bool> {
+    let opaque = instructions.iter().any(|instruction| matches!(instruction, Instruction::JmpDyn | Instruction::CodeRead | Instruction::CodeWrite));
+    if opaque {
+        return vec![true; instructions.len()];
+    }
+
+    let mut reached = vec![false; instructions.len()];
+    let mut work = vec![0];
+    while let Some(index) = work.pop() {
+        if index >= instructions.len() || reached[index] {
+            continue;
+        }
+        reached[index] = true;
+        work.extend(successors(&instructions[index], index));
+    }
+    reached
+}
+
+/// Returns the runs of instructions in `program` that can never run, see [`reachable`].
+/// `.align` padding is not code and never counts.
+pub fn unreachable_code(program: &Program) -> Vec<Range<usize>> {
+    let reached = reachable(&program.instructions);
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for index in (0..reached.len()).filter(|&index| !reached[index] && !program.debug_info.is_padding(index)) {
+        match runs.last_mut() {
+            Some(run) if run.end == index => run.end += 1,
+            _ => runs.push(index..index + 1),
+        }
+    }
+    runs
+}
+
+/// Returns the smallest range covering every cell that operands and data refer to.
+fn memory_range(program: &Program) -> Option<Range<usize>> {
+    let cells = |addr: i32, len: usize| usize::try_from(addr).ok().filter(|_| len > 0).map(|start| start..start.saturating_add(len));
+    let operands = program.instructions.iter().filter_map(|instruction| match instruction {
+        Instruction::MemWrite(addr, values) => cells(*addr, values.len()),
+        Instruction::MemWriteS(addr, len)
+        | Instruction::Print(addr, len)
+        | Instruction::Abort(addr, len)
+        | Instruction::ReadLine(addr, len)
+        | Instruction::Protect(addr, len)
+        | Instruction::MemSet(addr, len, _) => cells(*addr, usize::try_from(*len).unwrap_or(0)),
+        Instruction::MemRead(addr) | Instruction::PrintStr(addr) => cells(*addr, 1),
+        Instruction::MemCopy(a, b, len) | Instruction::MemCmp(a, b, len) => {
+            let len = usize::try_from(*len).unwrap_or(0);
+            cells(*a, len).zip(cells(*b, len)).map(|(a, b)| a.start.min(b.start)..a.end.max(b.end))
+        }
+        _ => None,
+    });
+    let data = program.data.iter().filter(|block| !block.values.is_empty()).map(|block| block.start..block.end());
+
+    operands.chain(data).reduce(|covered, range| covered.start.min(range.start)..covered.end.max(range.end))
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+    use crate::spliter::parse_program;
+
+    #[test]
+    fn test_straight_line_stack_depth() {
+        let stats = analyze(&parse_program("PUSH 1\nPUSH 2\nPUSH 3\nADD\nADD\nPRINTINT").unwrap());
+        assert_eq!(stats.max_stack, Some(3));
+        assert_eq!(stats.memory, None);
+        assert_eq!(stats.underflow, None);
+        assert!(stats.back_edges.is_empty());
+        assert!(!stats.recursive);
+    }
+
+    #[test]
+    fn test_loop_that_grows_the_stack_is_unbounded() {
+        let stats = analyze(&parse_program("loop:\nPUSH 1\nJMP loop").unwrap());
+        assert_eq!(stats.max_stack, None);
+        assert_eq!(stats.back_edges, vec![(1, 0)]);
+    }
+
+    #[test]
+    fn test_balanced_loop_is_bounded() {
+        let stats = analyze(&parse_program("PUSH 10\nloop:\nPUSH 1\nPOP\nSUBS 1\nJNZ loop\nRET").unwrap());
+        assert_eq!(stats.max_stack, Some(2));
+        assert_eq!(stats.back_edges, vec![(4, 1)]);
+    }
+
+    #[test]
+    fn test_subroutines_are_summarized_per_call() {
+        let source = "CALL one\nCALL one\nADD\nHALT\none:\nPUSH 1\nCALL noop\nRET\nnoop:\nRET";
+        let stats = analyze(&parse_program(source).unwrap());
+        assert_eq!(stats.max_stack, Some(2));
+        assert_eq!(stats.calls.len(), 3);
+        assert_eq!(stats.calls[&0], BTreeSet::from([4]));
+        assert_eq!(stats.calls[&4], BTreeSet::from([7]));
+        assert!(stats.calls[&7].is_empty());
+    }
+
+    #[test]
+    fn test_underflow_is_found_through_calls() {
+        let stats = analyze(&parse_program("PUSH 1\nPUSH 2\nJIZ skip\nPOP\nskip:\nADD").unwrap());
+        assert_eq!(stats.underflow, Some(4));
+
+        let stats = analyze(&parse_program("PUSH 1\nCALL drop2\nRET\ndrop2:\nPOP\nPOP\nRET").unwrap());
+        assert_eq!(stats.underflow, Some(1));
+
+        let stats = analyze(&parse_program("PUSH 1\nPUSH 2\nCALL add\nRET\nadd:\nADD\nRET").unwrap());
+        assert_eq!(stats.underflow, None);
+    }
+
+    #[test]
+    fn test_recursion_and_dynamic_jumps() {
+        let stats = analyze(&parse_program("CALL f\nRET\nf:\nCALL f\nRET").unwrap());
+        assert!(stats.recursive);
+        assert_eq!(stats.max_stack, None);
+
+        let stats = analyze(&parse_program("PUSH 0\nJMPDYN").unwrap());
+        assert_eq!((stats.max_stack, stats.dynamic_jumps), (None, 1));
+    }
+
+    #[test]
+    fn test_clear_resets_the_depth_only_at_the_entry() {
+        let stats = analyze(&parse_program("loop:\nPUSH 1\nPUSH 2\nCLEAR\nDEPTH\nJIZ loop").unwrap());
+        assert_eq!((stats.max_stack, stats.underflow), (Some(3), None));
+
+        let stats = analyze(&parse_program("PUSH 1\nCALL reset\nPOP\nRET\nreset:\nCLEAR\nRET").unwrap());
+        assert_eq!(stats.max_stack, None);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let program = parse_program("CALL f\nHALT 0\nPUSH 1\nPOP\nf:\nTRY handler\nTHROW\nPUSH 2\nhandler:\nRET").unwrap();
+        assert_eq!(unreachable_code(&program), vec![2..4, 6..7]);
+
+        // Padding is not reported, and dynamic jumps may reach anything
+        let program = parse_program("HALT 0\n.align 4\nPUSH 1").unwrap();
+        assert_eq!(unreachable_code(&program), vec![4..5]);
+        assert!(unreachable_code(&parse_program("PUSH 3\nJMPDYN\nPUSH 1\nHALT 0").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_memory_range_includes_data() {
+        let stats = analyze(&parse_program(".data 20\n.word 1 2\n.text\nMEMREAD 3\nPRINT 8 4").unwrap());
+        assert_eq!(stats.memory, Some(3..22));
+    }
+}
@@ -1,29 +1,523 @@
-use crate::instruction::Instruction;
+use crate::diagnostic;
+use crate::diagnostics::Level;
+#[cfg(feature = "disassembler")]
+use crate::encoding::deserialize_instruction;
+#[cfg(feature = "assembler")]
+use crate::encoding::serialize_instruction;
+use crate::instruction::{Instruction, Word};
+#[cfg(feature = "assembler")]
+use crate::include::ExpandedSource;
+#[cfg(feature = "disassembler")]
+use crate::isa::check_capabilities;
+#[cfg(feature = "disassembler")]
+use crate::program::Region;
+#[cfg(feature = "disassembler")]
+use crate::run::MAX_MEMORY_SIZE;
+#[cfg(feature = "assembler")]
+use crate::isa::{required_capabilities, IsaVersion};
+use crate::program::{DataBlock, DebugInfo, Program, Requirements};
 use std::fs;
-use std::io::Write;
+#[cfg(feature = "assembler")]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Magic bytes every bytecode file starts with.
+pub const BYTECODE_MAGIC: [u8; 4] = *b"VVM\0";
+
+/// Returns whether `bytes` start like a bytecode file rather than assembly source.
+///
+/// ```
+/// use vortex_vm::assembler::is_bytecode;
+///
+/// assert!(is_bytecode(b"VVM\0\x02\0\0\0\x01\0\0\0"));
+/// assert!(!is_bytecode(b"PUSH 1"));
+/// ```
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(&BYTECODE_MAGIC)
+}
+
+/// The bytecode format version written by this build, whose operands are varints.
+pub const BYTECODE_VERSION: u16 = 2;
+
+/// The oldest bytecode format version this build reads, where every operand has a fixed
+/// width. Assembling for ISA 1.0 still writes it.
+pub const MIN_BYTECODE_VERSION: u16 = 1;
+
+/// Size in bytes of the [`BytecodeHeader`] at the start of every bytecode file.
+pub const HEADER_SIZE: usize = 12;
+
+/// Header flag set when the instructions are followed by an initial memory image.
+pub const FLAG_DATA: u16 = 0x0001;
+
+/// Header flag set when the header is followed by the program's [requirements](crate::program::Requirements).
+pub const FLAG_REQUIREMENTS: u16 = 0x0002;
+
+/// Header flag set when the program uses optional [`Capability`](crate::isa::Capability)s,
+/// whose mask then follows the requirements.
+pub const FLAG_CAPABILITIES: u16 = 0x0004;
+
+/// Header flag set along with [`FLAG_DATA`] when each data block records how its values
+/// are packed, see [`BytecodeHeader`].
+pub const FLAG_PACKED_DATA: u16 = 0x0008;
+
+/// Header flag set when the file ends with the source lines and label names of the
+/// instructions, written by `assemble --debug`.
+pub const FLAG_DEBUG: u16 = 0x0010;
+
+/// Header flag set when everything after the capability mask is zlib-compressed, written
+/// by `assemble --compress`.
+pub const FLAG_COMPRESSED: u16 = 0x0020;
+
+/// Packed data block encoding: every value as an i64.
+const DATA_RAW: u8 = 0;
+/// Packed data block encoding: every value as one byte, for values from 0 to 255.
+const DATA_BYTES: u8 = 1;
+/// Packed data block encoding: a run count (u32), then each run's length (u32) and value (i64).
+const DATA_RUNS: u8 = 2;
+
+/// Size in bytes of the requirements that follow the header with [`FLAG_REQUIREMENTS`].
+const REQUIREMENTS_SIZE: usize = 16;
+
+/// How hard `assemble --compress` tries, on miniz's scale from 0 to 10.
+#[cfg(all(feature = "assembler", feature = "compression"))]
+const COMPRESSION_LEVEL: u8 = 9;
+
+/// The most bytes compressed sections may inflate to, so a small file cannot claim
+/// gigabytes. A million instructions and a full memory of unpacked data fit well within it.
+#[cfg(all(feature = "disassembler", feature = "compression"))]
+const MAX_INFLATED_SIZE: usize = 64 << 20;
+
+/// The most values the data blocks of one file may hold together, so a run-length block
+/// cannot claim gigabytes either. Twice the memory `GROW` reaches by default.
+#[cfg(feature = "disassembler")]
+const MAX_DATA_VALUES: usize = 1 << 23;
+
+/// Where the sections that [`FLAG_COMPRESSED`] compresses start, after the header and the
+/// requirements and capabilities that `flags` say follow it.
+#[cfg(feature = "compression")]
+fn sections_offset(flags: u16) -> usize {
+    let mut offset = HEADER_SIZE;
+    if flags & FLAG_REQUIREMENTS != 0 {
+        offset += REQUIREMENTS_SIZE;
+    }
+    if flags & FLAG_CAPABILITIES != 0 {
+        offset += 4;
+    }
+    offset
+}
+
+/// The fixed-size header in front of the instruction stream of a bytecode file.
+///
+/// Layout (little-endian): magic `VVM\0`, format version (u16), flags (u16),
+/// instruction count (u32). The instructions follow as an opcode and its operands, which
+/// are fixed-width integers in format version 1 and LEB128 varints in version 2.
+///
+/// With [`FLAG_REQUIREMENTS`] set, the header is followed by the memory size (u64) and
+/// fuel (u64) the program requires, where 0 means no requirement. With [`FLAG_CAPABILITIES`]
+/// set, the capabilities the program uses come next as a bit mask (u32); loading fails
+/// before any instruction is read if this build lacks one of them.
+///
+/// With [`FLAG_DATA`] set, the instructions are followed by the program's data: a block
+/// count (u32), then for each block its start address (u32), its length (u32) and that
+/// many values (i64). With [`FLAG_PACKED_DATA`] also set, each block's length is followed
+/// by an encoding byte: 0 for values as i64, 1 for values as single bytes, or 2 for
+/// run-length encoded values. The assembler picks whichever is smallest.
+///
+/// With [`FLAG_DEBUG`] set, the file ends with the debug section: the source file name as
+/// its length (u32) and UTF-8 bytes, empty if unknown, a line count (u32) and the 1-based
+/// source line of each instruction (u32), then a label count (u32) and for each label the
+/// index of its instruction (u32) and its name as length (u32) and UTF-8 bytes, then a
+/// region count (u32) and for each `.region` its start (u32), length (u32) and name.
+///
+/// With [`FLAG_COMPRESSED`] set, the instructions and the sections after them are stored
+/// as one zlib stream, which loading inflates before reading them. The header,
+/// requirements and capabilities stay uncompressed, so they can be checked first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BytecodeHeader {
+    pub version: u16,
+    pub flags: u16,
+    pub instruction_count: u32,
+}
+
+impl BytecodeHeader {
+    /// Creates a header for the current format version.
+    #[cfg(feature = "assembler")]
+    pub fn new(instruction_count: u32) -> Self {
+        BytecodeHeader { version: BYTECODE_VERSION, flags: 0, instruction_count }
+    }
+
+    /// Encodes the header to its on-disk form.
+    #[cfg(feature = "assembler")]
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&BYTECODE_MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.instruction_count.to_le_bytes());
+        bytes
+    }
+
+    /// Reads and validates the header at the start of `bytecode`.
+    ///
+    /// ```
+    /// use vortex_vm::assembler::{BytecodeHeader, BYTECODE_VERSION};
+    ///
+    /// // The magic, format version 2, no flags and two instructions
+    /// let header = BytecodeHeader::parse(b"VVM\0\x02\0\0\0\x02\0\0\0").unwrap();
+    /// assert_eq!((header.version, header.instruction_count), (BYTECODE_VERSION, 2));
+    ///
+    /// assert!(BytecodeHeader::parse(b"PUSH 1").is_err());
+    /// ```
+    #[cfg(feature = "disassembler")]
+    pub fn parse(bytecode: &[u8]) -> Result<Self, String> {
+        if bytecode.len() < HEADER_SIZE || bytecode[0..4] != BYTECODE_MAGIC {
+            return Err("Not a Vortex VM bytecode file (missing VVM header)".to_string());
+        }
+
+        let header = BytecodeHeader {
+            version: u16::from_le_bytes([bytecode[4], bytecode[5]]),
+            flags: u16::from_le_bytes([bytecode[6], bytecode[7]]),
+            instruction_count: u32::from_le_bytes([bytecode[8], bytecode[9], bytecode[10], bytecode[11]]),
+        };
+
+        if !(MIN_BYTECODE_VERSION..=BYTECODE_VERSION).contains(&header.version) {
+            return Err(format!(
+                "Unsupported bytecode format version {} (this build reads versions {} to {}); re-assemble the program",
+                header.version, MIN_BYTECODE_VERSION, BYTECODE_VERSION
+            ));
+        }
+        if header.flags & !(FLAG_DATA | FLAG_REQUIREMENTS | FLAG_CAPABILITIES | FLAG_PACKED_DATA | FLAG_DEBUG | FLAG_COMPRESSED) != 0 {
+            return Err(format!("Unsupported bytecode flags 0x{:04X}", header.flags));
+        }
+
+        Ok(header)
+    }
+}
 
 /// Assembles assembly source code into bytecode format
+#[cfg(feature = "assembler")]
 pub fn assemble_source(source: &str) -> Result<Vec<u8>, String> {
-    // Parse the assembly source into instructions
-    let instructions = crate::spliter::split_instructions(source);
+    // Parse the assembly source into instructions and data
+    let program = crate::spliter::try_parse_program(source)?;
+
+    // Serialize the program to binary format
+    let bytecode = assemble_program(&program)?;
+    diagnostic!(Level::Debug, "Assembled {} instructions into {} bytes", program.instructions.len(), bytecode.len());
+    Ok(bytecode)
+}
 
-    // Serialize instructions to binary format
-    serialize_instructions(&instructions)
+/// Turns source code into a program ready to run, as loading the bytecode of
+/// [`assemble_source`] would, but without encoding it first.
+///
+/// ```
+/// use vortex_vm::assembler::compile_source;
+/// use vortex_vm::run::Vm;
+///
+/// let program = compile_source("PUSH 2\nMULTS 21").unwrap();
+/// let mut vm = Vm::new(program.instructions);
+/// vm.run(&mut Vec::new()).unwrap();
+/// assert_eq!(vm.stack, vec![42]);
+/// ```
+#[cfg(feature = "assembler")]
+pub fn compile_source(source: &str) -> Result<Program, String> {
+    let program = crate::spliter::try_parse_program(source)?;
+    diagnostic!(Level::Debug, "Compiled {} instructions", program.instructions.len());
+    Ok(program)
+}
+
+/// Assembles source code into bytecode that VMs implementing `isa` can load, failing if
+/// the program uses anything newer.
+///
+/// ```
+/// use vortex_vm::assembler::assemble_source_for;
+/// use vortex_vm::isa::IsaVersion;
+///
+/// assert!(assemble_source_for("PUSH 2\nMULTS 3", IsaVersion::V1_0).is_ok());
+/// assert!(assemble_source_for("PUSH 2\nPRINTINT", IsaVersion::V1_0).is_err());
+/// ```
+#[cfg(feature = "assembler")]
+pub fn assemble_source_for(source: &str, isa: IsaVersion) -> Result<Vec<u8>, String> {
+    let program = crate::spliter::try_parse_program(source)?;
+    isa.check_program(&program)?;
+    encode_program(&program, isa, false)
+}
+
+/// How [`assemble_file_with`] assembles a source file.
+#[cfg(feature = "assembler")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssembleOptions {
+    /// The instruction set the program must stay within, see [`assemble_source_for`].
+    pub isa: IsaVersion,
+    /// Remove code that can never run instead of warning about it, which needs the
+    /// `optimizer` feature.
+    pub optimize: bool,
+    /// Keep the source lines and label names in the bytecode, see [`FLAG_DEBUG`].
+    pub debug: bool,
+    /// Compress the bytecode, see [`compress_bytecode`].
+    pub compress: bool,
+}
+
+#[cfg(feature = "assembler")]
+impl Default for AssembleOptions {
+    fn default() -> Self {
+        AssembleOptions { isa: IsaVersion::LATEST, optimize: false, debug: false, compress: false }
+    }
+}
+
+/// Assembles source read by [`read_source`] or [`expand_source`], naming the file and line
+/// each error and unreachable piece of code comes from.
+///
+/// [`read_source`]: crate::include::read_source
+/// [`expand_source`]: crate::include::expand_source
+#[cfg(feature = "assembler")]
+pub fn assemble_expanded(source: &ExpandedSource, options: &AssembleOptions) -> Result<Vec<u8>, String> {
+    if options.debug && options.isa != IsaVersion::LATEST {
+        return Err(format!("ISA {} VMs cannot load debug information; assemble without --debug", options.isa.name()));
+    }
+    if options.compress && options.isa != IsaVersion::LATEST {
+        return Err(format!("ISA {} VMs cannot load compressed bytecode; assemble without --compress", options.isa.name()));
+    }
+    let mut program = compile_expanded(source, options)?;
+    if options.debug {
+        locate_source(&mut program, source);
+    }
+    let bytecode = encode_program(&program, options.isa, options.debug)?;
+    if options.compress {
+        return compress_bytecode(&bytecode);
+    }
+    Ok(bytecode)
+}
+
+/// Points the debug information of a program compiled from `source` at the file its code
+/// came from, with the line numbers of that file. When the code comes from several files,
+/// the lines stay those of the expanded source and no file is named.
+#[cfg(feature = "assembler")]
+fn locate_source(program: &mut Program, source: &ExpandedSource) {
+    let origins: Option<Vec<(&Path, usize)>> = program.debug_info.lines.iter().map(|&line| source.origin(line)).collect();
+    let Some(origins) = origins else {
+        return;
+    };
+    if let Some(&(file, _)) = origins.first()
+        && origins.iter().all(|&(path, _)| path == file)
+    {
+        program.debug_info.lines = origins.iter().map(|&(_, line)| line).collect();
+        program.debug_info.file = Some(file.to_path_buf());
+    }
+}
+
+/// Checks and prepares source like [`assemble_expanded`] without encoding it, for running
+/// it straight away. The program keeps its debug information.
+#[cfg(feature = "assembler")]
+pub fn compile_expanded(source: &ExpandedSource, options: &AssembleOptions) -> Result<Program, String> {
+    let mut program = crate::spliter::try_parse_program_with(&source.text, |line| source.describe_line(line))?;
+    if options.optimize {
+        remove_dead_code(&mut program)?;
+    } else {
+        for run in crate::analysis::unreachable_code(&program) {
+            let location = program.debug_info.source_line(run.start).map_or_else(|| format!("instruction {}", run.start), |line| source.describe_line(line));
+            diagnostic!(Level::Warn, "Unreachable code at {}: {} instruction(s) never run (--optimize removes them)", location, run.len());
+        }
+    }
+    options.isa.check_program(&program)?;
+    Ok(program)
+}
+
+#[cfg(all(feature = "assembler", feature = "optimizer"))]
+fn remove_dead_code(program: &mut Program) -> Result<(), String> {
+    let removed = program.eliminate_dead_code();
+    diagnostic!(Level::Info, "Removed {} unreachable instructions", removed);
+    Ok(())
+}
+
+#[cfg(all(feature = "assembler", not(feature = "optimizer")))]
+fn remove_dead_code(_program: &mut Program) -> Result<(), String> {
+    Err("Removing unreachable code needs a build with the 'optimizer' feature".to_string())
+}
+
+/// Serializes a program's instructions, data and requirements to bytecode. Debug
+/// information is not kept, see [`assemble_program_with_debug_info`].
+///
+/// ```
+/// use vortex_vm::assembler::assemble_program;
+/// use vortex_vm::spliter::parse_program;
+///
+/// let program = parse_program(".data 4\n.byte 1 2\n.text\nMEMREAD 5").unwrap();
+/// let bytecode = assemble_program(&program).unwrap();
+/// # #[cfg(feature = "disassembler")] {
+/// let decoded = vortex_vm::assembler::disassemble_program(&bytecode).unwrap();
+/// assert_eq!((decoded.instructions, decoded.data), (program.instructions, program.data));
+/// # }
+/// ```
+#[cfg(feature = "assembler")]
+pub fn assemble_program(program: &Program) -> Result<Vec<u8>, String> {
+    encode_program(program, IsaVersion::LATEST, false)
+}
+
+/// Serializes a program like [`assemble_program`] along with the source lines and label
+/// names of its instructions, so traces and the debugger can name them after loading.
+///
+/// ```
+/// use vortex_vm::assembler::assemble_program_with_debug_info;
+/// use vortex_vm::spliter::parse_program;
+///
+/// let program = parse_program("PUSH 3\nloop:\nSUBS 1\nJNZ loop").unwrap();
+/// let bytecode = assemble_program_with_debug_info(&program).unwrap();
+/// # #[cfg(feature = "disassembler")] {
+/// let decoded = vortex_vm::assembler::disassemble_program(&bytecode).unwrap();
+/// assert_eq!(decoded.symbolize(2).unwrap().to_string(), "loop+1 at line 4");
+/// # }
+/// ```
+#[cfg(feature = "assembler")]
+pub fn assemble_program_with_debug_info(program: &Program) -> Result<Vec<u8>, String> {
+    encode_program(program, IsaVersion::LATEST, true)
+}
+
+/// Serializes a program for VMs implementing `isa`, which decides whether the compact
+/// encodings newer VMs read may be used, with its debug information if `debug` is set.
+#[cfg(feature = "assembler")]
+fn encode_program(program: &Program, isa: IsaVersion, debug: bool) -> Result<Vec<u8>, String> {
+    let mut bytecode = serialize_instructions(&program.instructions, isa)?;
+    let mut flags = 0;
+
+    if program.requirements != Requirements::default() {
+        flags |= FLAG_REQUIREMENTS;
+        let memory = program.requirements.memory.unwrap_or(0) as u64;
+        let fuel = program.requirements.fuel.unwrap_or(0);
+        let requirements = [memory.to_le_bytes(), fuel.to_le_bytes()].concat();
+        bytecode.splice(HEADER_SIZE..HEADER_SIZE, requirements);
+    }
+    let capabilities = required_capabilities(&program.instructions);
+    if capabilities != 0 {
+        flags |= FLAG_CAPABILITIES;
+        let at = if flags & FLAG_REQUIREMENTS != 0 { HEADER_SIZE + REQUIREMENTS_SIZE } else { HEADER_SIZE };
+        bytecode.splice(at..at, capabilities.to_le_bytes());
+    }
+    if !program.data.is_empty() {
+        let packed = isa >= IsaVersion::V1_2;
+        flags |= if packed { FLAG_DATA | FLAG_PACKED_DATA } else { FLAG_DATA };
+        serialize_data(&program.data, packed, &mut bytecode)?;
+    }
+    if debug {
+        flags |= FLAG_DEBUG;
+        serialize_debug_info(&program.debug_info, &mut bytecode)?;
+    }
+
+    bytecode[6..8].copy_from_slice(&flags.to_le_bytes());
+    Ok(bytecode)
+}
+
+/// Compresses the instructions, data and debug information of `bytecode` into a zlib
+/// stream and sets [`FLAG_COMPRESSED`], which needs the `compression` feature. Loading
+/// the result gives the same program.
+///
+/// ```
+/// use vortex_vm::assembler::{assemble_source, compress_bytecode};
+///
+/// let bytecode = assemble_source(&"PUSH 1\nPRINTINT\n".repeat(100)).unwrap();
+/// let compressed = compress_bytecode(&bytecode).unwrap();
+/// assert!(compressed.len() < bytecode.len() / 4);
+/// # #[cfg(feature = "disassembler")] {
+/// use vortex_vm::assembler::disassemble_program;
+/// assert_eq!(disassemble_program(&compressed).unwrap(), disassemble_program(&bytecode).unwrap());
+/// # }
+/// ```
+#[cfg(all(feature = "assembler", feature = "compression"))]
+pub fn compress_bytecode(bytecode: &[u8]) -> Result<Vec<u8>, String> {
+    if bytecode.len() < HEADER_SIZE || bytecode[0..4] != BYTECODE_MAGIC {
+        return Err("Not a Vortex VM bytecode file (missing VVM header)".to_string());
+    }
+    let flags = u16::from_le_bytes([bytecode[6], bytecode[7]]);
+    if flags & FLAG_COMPRESSED != 0 {
+        return Ok(bytecode.to_vec());
+    }
+    let offset = sections_offset(flags);
+    if bytecode.len() < offset {
+        return Err("Incomplete bytecode header".to_string());
+    }
+    let mut compressed = bytecode[..offset].to_vec();
+    compressed.extend(miniz_oxide::deflate::compress_to_vec_zlib(&bytecode[offset..], COMPRESSION_LEVEL));
+    compressed[6..8].copy_from_slice(&(flags | FLAG_COMPRESSED).to_le_bytes());
+    diagnostic!(Level::Debug, "Compressed {} bytes of bytecode into {}", bytecode.len(), compressed.len());
+    Ok(compressed)
+}
+
+#[cfg(all(feature = "assembler", not(feature = "compression")))]
+pub fn compress_bytecode(_bytecode: &[u8]) -> Result<Vec<u8>, String> {
+    Err("Compressing bytecode needs a build with the 'compression' feature".to_string())
+}
+
+/// Inflates the sections of compressed bytecode, returning the bytecode as it was before
+/// [`compress_bytecode`].
+#[cfg(all(feature = "disassembler", feature = "compression"))]
+fn decompress_bytecode(bytecode: &[u8], flags: u16) -> Result<Vec<u8>, String> {
+    let offset = sections_offset(flags);
+    let sections = bytecode.get(offset..).ok_or("Incomplete compressed bytecode")?;
+    let inflated = inflate(sections, MAX_INFLATED_SIZE)?;
+    let mut bytecode = [&bytecode[..offset], &inflated].concat();
+    bytecode[6..8].copy_from_slice(&(flags & !FLAG_COMPRESSED).to_le_bytes());
+    Ok(bytecode)
+}
+
+/// Inflates a zlib stream, refusing one that inflates to more than `limit` bytes.
+#[cfg(all(feature = "disassembler", feature = "compression"))]
+fn inflate(stream: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(stream, limit).map_err(|e| match e.status {
+        miniz_oxide::inflate::TINFLStatus::HasMoreOutput => {
+            format!("Compressed bytecode inflates to more than {} bytes", limit)
+        }
+        _ => format!("Corrupt compressed bytecode: {}", e),
+    })
+}
+
+#[cfg(all(feature = "disassembler", not(feature = "compression")))]
+fn decompress_bytecode(_bytecode: &[u8], _flags: u16) -> Result<Vec<u8>, String> {
+    Err("Loading compressed bytecode needs a build with the 'compression' feature".to_string())
 }
 
 /// Deserializes bytecode back into instructions
+#[cfg(feature = "disassembler")]
 pub fn disassemble_bytecode(bytecode: &[u8]) -> Result<Vec<Instruction>, String> {
     deserialize_instructions(bytecode)
 }
 
+/// Deserializes bytecode back into a program with its data, and its debug information if
+/// it was assembled with some.
+#[cfg(feature = "disassembler")]
+pub fn disassemble_program(bytecode: &[u8]) -> Result<Program, String> {
+    deserialize_program(bytecode)
+}
+
 /// Assembles a .asv file to a .vvm file
+#[cfg(feature = "assembler")]
 pub fn assemble_file(input_path: &str, output_path: &str) -> Result<(), String> {
-    // Read the source file
-    let source = fs::read_to_string(input_path)
-        .map_err(|e| format!("Failed to read source file '{}': {}", input_path, e))?;
+    assemble_file_with_search_paths(input_path, output_path, &[])
+}
+
+/// Assembles a source file like [`assemble_file`], also looking for `%include`d files in
+/// `search_paths`.
+#[cfg(feature = "assembler")]
+pub fn assemble_file_with_search_paths(input_path: &str, output_path: &str, search_paths: &[PathBuf]) -> Result<(), String> {
+    assemble_file_for(input_path, output_path, search_paths, IsaVersion::LATEST)
+}
+
+/// Assembles a source file like [`assemble_file_with_search_paths`] for VMs implementing
+/// `isa`, see [`assemble_source_for`].
+#[cfg(feature = "assembler")]
+pub fn assemble_file_for(input_path: &str, output_path: &str, search_paths: &[PathBuf], isa: IsaVersion) -> Result<(), String> {
+    assemble_file_with(input_path, output_path, search_paths, &AssembleOptions { isa, ..AssembleOptions::default() })
+}
+
+/// Assembles a source file like [`assemble_file_with_search_paths`] with `options`.
+///
+/// Code that can never run is reported as a warning, or removed when optimizing, see
+/// [`Program::eliminate_dead_code`].
+#[cfg(feature = "assembler")]
+pub fn assemble_file_with(input_path: &str, output_path: &str, search_paths: &[PathBuf], options: &AssembleOptions) -> Result<(), String> {
+    // Read the source file and everything it includes
+    let source = crate::include::read_source(input_path, search_paths)?;
 
     // Assemble the source
-    let bytecode = assemble_source(&source)?;
+    let bytecode = assemble_expanded(&source, options)?;
 
     // Write the bytecode to output file
     fs::write(output_path, bytecode)
@@ -33,272 +527,569 @@ pub fn assemble_file(input_path: &str, output_path: &str) -> Result<(), String>
 }
 
 /// Loads instructions from a .vvm bytecode file
+#[cfg(feature = "disassembler")]
 pub fn load_bytecode_file(file_path: &str) -> Result<Vec<Instruction>, String> {
+    load_program_file(file_path).map(|program| program.instructions)
+}
+
+/// Loads a program and its data from a bytecode file
+#[cfg(feature = "disassembler")]
+pub fn load_program_file(file_path: &str) -> Result<Program, String> {
     // Read the bytecode file
     let bytecode = fs::read(file_path)
         .map_err(|e| format!("Failed to read bytecode file '{}': {}", file_path, e))?;
 
-    // Deserialize the instructions
-    disassemble_bytecode(&bytecode)
+    // Deserialize the instructions and data
+    let program = disassemble_program(&bytecode)?;
+    diagnostic!(Level::Info, "Loaded {} instructions from '{}'", program.instructions.len(), file_path);
+    Ok(program)
 }
 
-/// Serializes instructions to binary format
-fn serialize_instructions(instructions: &[Instruction]) -> Result<Vec<u8>, String> {
-    let mut bytecode = Vec::new();
-
+/// Serializes instructions to binary format, preceded by a [`BytecodeHeader`]
+#[cfg(feature = "assembler")]
+fn serialize_instructions(instructions: &[Instruction], isa: IsaVersion) -> Result<Vec<u8>, String> {
+    let count = u32::try_from(instructions.len()).map_err(|_| "Program has too many instructions".to_string())?;
+    let version = if isa >= IsaVersion::V1_2 { BYTECODE_VERSION } else { MIN_BYTECODE_VERSION };
+    let mut bytecode = BytecodeHeader { version, ..BytecodeHeader::new(count) }.to_bytes().to_vec();
     for instruction in instructions {
-        serialize_instruction(instruction, &mut bytecode)?;
+        encode_instruction(instruction, isa, &mut bytecode)?;
     }
-
     Ok(bytecode)
 }
 
-/// Deserializes instructions from binary format
+/// Serializes one instruction, in the compact encodings `isa` allows: varint operands
+/// (format version 2) and one byte per value for `MEMWRITE`s of bytes.
+#[cfg(feature = "assembler")]
+fn encode_instruction(instruction: &Instruction, isa: IsaVersion, output: &mut Vec<u8>) -> Result<(), String> {
+    if isa < IsaVersion::V1_2 {
+        return serialize_instruction(instruction, output);
+    }
+
+    let mut fixed = Vec::new();
+    match instruction {
+        // Character data takes one byte per value, where varints take two from 64 up
+        Instruction::MemWrite(addr, values) if values.iter().all(|&value| fits_byte(value)) => {
+            let len = u32::try_from(values.len()).map_err(|_| "MemWrite has too many values".to_string())?;
+            fixed.push(0x3A);
+            fixed.extend_from_slice(&addr.to_le_bytes());
+            fixed.extend_from_slice(&len.to_le_bytes());
+            fixed.extend(values.iter().map(|&value| value as u8));
+        }
+        _ => serialize_instruction(instruction, &mut fixed)?,
+    }
+    compact_operands(&fixed, output);
+    Ok(())
+}
+
+/// Returns how many bytes `instruction` takes in bytecode assembled for the latest ISA.
+///
+/// ```
+/// use vortex_vm::assembler::encoded_size;
+/// use vortex_vm::instruction::Instruction;
+///
+/// // Operands are varints, so small values take a byte
+/// assert_eq!(encoded_size(&Instruction::Push(7)), 2);
+/// assert_eq!(encoded_size(&Instruction::Push(1 << 40)), 7);
+/// // Values that fit in a byte are written one byte each
+/// assert_eq!(encoded_size(&Instruction::MemWrite(0, vec![104, 105])), 5);
+/// ```
+#[cfg(feature = "assembler")]
+pub fn encoded_size(instruction: &Instruction) -> usize {
+    let mut bytes = Vec::new();
+    // Only counts too large for a u32 fail to encode, and programs cannot hold those
+    let _ = encode_instruction(instruction, IsaVersion::LATEST, &mut bytes);
+    bytes.len()
+}
+
+/// Deserializes instructions from binary format, validating the [`BytecodeHeader`] first
+#[cfg(feature = "disassembler")]
 fn deserialize_instructions(bytecode: &[u8]) -> Result<Vec<Instruction>, String> {
+    deserialize_program(bytecode).map(|program| program.instructions)
+}
+
+/// Deserializes instructions and, if the header says there is one, the data section
+#[cfg(feature = "disassembler")]
+fn deserialize_program(bytecode: &[u8]) -> Result<Program, String> {
+    let header = BytecodeHeader::parse(bytecode)?;
+    if header.flags & FLAG_COMPRESSED != 0 {
+        return deserialize_program(&decompress_bytecode(bytecode, header.flags)?);
+    }
+    let has_data = header.flags & FLAG_DATA != 0;
+    let has_debug = header.flags & FLAG_DEBUG != 0;
     let mut instructions = Vec::new();
-    let mut offset = 0;
+    let mut offset = HEADER_SIZE;
+
+    let mut requirements = Requirements::default();
+    if header.flags & FLAG_REQUIREMENTS != 0 {
+        let Some(fields) = bytecode.get(HEADER_SIZE..HEADER_SIZE + REQUIREMENTS_SIZE) else {
+            return Err("Incomplete program requirements".to_string());
+        };
+        let memory = u64::from_le_bytes(fields[0..8].try_into().unwrap());
+        let fuel = u64::from_le_bytes(fields[8..16].try_into().unwrap());
+        let memory = usize::try_from(memory)
+            .ok()
+            .filter(|&memory| memory <= MAX_MEMORY_SIZE)
+            .ok_or_else(|| format!("Program requires {} memory cells, more than the {} a VM may have", memory, MAX_MEMORY_SIZE))?;
+        requirements = Requirements { memory: (memory > 0).then_some(memory), fuel: (fuel > 0).then_some(fuel) };
+        offset += REQUIREMENTS_SIZE;
+    }
+    if header.flags & FLAG_CAPABILITIES != 0 {
+        let Some(mask) = bytecode.get(offset..offset + 4) else {
+            return Err("Incomplete capability mask".to_string());
+        };
+        check_capabilities(u32::from_le_bytes(mask.try_into().unwrap()))?;
+        offset += 4;
+    }
 
-    while offset < bytecode.len() {
-        let (instruction, consumed) = deserialize_instruction(&bytecode[offset..])?;
-        instructions.push(instruction);
+    // With a data or debug section the instruction count says where the instructions end
+    while offset < bytecode.len() && !((has_data || has_debug) && instructions.len() == header.instruction_count as usize) {
+        let consumed = if header.version >= 2 {
+            let (fixed, consumed) = expand_operands(&bytecode[offset..])?;
+            instructions.push(deserialize_instruction(&fixed)?.0);
+            consumed
+        } else {
+            let (instruction, consumed) = deserialize_instruction(&bytecode[offset..])?;
+            instructions.push(instruction);
+            consumed
+        };
         offset += consumed;
     }
 
-    Ok(instructions)
+    if instructions.len() != header.instruction_count as usize {
+        return Err(format!(
+            "Corrupt bytecode: header declares {} instructions but {} were found",
+            header.instruction_count,
+            instructions.len()
+        ));
+    }
+
+    let mut program = Program::new(instructions);
+    program.requirements = requirements;
+    if has_data {
+        let (data, len) = deserialize_data(&bytecode[offset..], header.flags & FLAG_PACKED_DATA != 0)?;
+        program.data = data;
+        offset += len;
+    }
+    if has_debug {
+        let (debug_info, len) = deserialize_debug_info(&bytecode[offset..], program.instructions.len())?;
+        program.debug_info = debug_info;
+        offset += len;
+    }
+    if offset != bytecode.len() {
+        let section = if has_debug { "debug" } else { "data" };
+        return Err(format!("Corrupt bytecode: {} unexpected bytes after the {} section", bytecode.len() - offset, section));
+    }
+    Ok(program)
 }
 
-/// Serializes a single instruction to binary format
-fn serialize_instruction(instruction: &Instruction, output: &mut Vec<u8>) -> Result<(), String> {
-    match instruction {
-        Instruction::Null => {
-            output.write_all(&[0x00]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Push(value) => {
-            output.write_all(&[0x01]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Dup => {
-            output.write_all(&[0x02]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Swap => {
-            output.write_all(&[0x03]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Pop => {
-            output.write_all(&[0x04]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Ret => {
-            output.write_all(&[0x05]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Jiz(target) => {
-            output.write_all(&[0x06]).map_err(|e| format!("Write error: {}", e))?;
-            serialize_string(target, output)?;
-        }
-        Instruction::Jnz(target) => {
-            output.write_all(&[0x07]).map_err(|e| format!("Write error: {}", e))?;
-            serialize_string(target, output)?;
-        }
-        Instruction::AddS(value) => {
-            output.write_all(&[0x08]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Add => {
-            output.write_all(&[0x09]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::SubS(value) => {
-            output.write_all(&[0x0A]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Sub => {
-            output.write_all(&[0x0B]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::MultS(value) => {
-            output.write_all(&[0x0C]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Mult => {
-            output.write_all(&[0x0D]).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::DivS(value) => {
-            output.write_all(&[0x0E]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Div => {
-            output.write_all(&[0x0F]).map_err(|e| format!("Write error: {}", e))?;
+/// Returns whether `value` can be stored in a single byte.
+#[cfg(feature = "assembler")]
+fn fits_byte(value: Word) -> bool {
+    (0..=255).contains(&value)
+}
+
+/// Splits `values` into runs of equal values, as (length, value) pairs.
+#[cfg(feature = "assembler")]
+fn runs(values: &[Word]) -> Vec<(u32, Word)> {
+    let mut runs: Vec<(u32, Word)> = Vec::new();
+    for &value in values {
+        match runs.last_mut() {
+            Some((len, last)) if *last == value && *len < u32::MAX => *len += 1,
+            _ => runs.push((1, value)),
         }
-        Instruction::MemWrite(addr, values) => {
-            output.write_all(&[0x10]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-            let len = values.len() as u32;
-            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-            for value in values {
-                output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+    }
+    runs
+}
+
+/// Serializes data blocks in the packed layout described on [`BytecodeHeader`], or in the
+/// unpacked layout of ISA 1.1 if `packed` is false
+#[cfg(feature = "assembler")]
+fn serialize_data(data: &[DataBlock], packed: bool, output: &mut Vec<u8>) -> Result<(), String> {
+    let count = u32::try_from(data.len()).map_err(|_| "Program has too many data blocks".to_string())?;
+    output.extend_from_slice(&count.to_le_bytes());
+
+    for block in data {
+        let start = u32::try_from(block.start).map_err(|_| format!("Data address {} is too large", block.start))?;
+        let len = u32::try_from(block.values.len()).map_err(|_| format!("Data block at {} is too large", block.start))?;
+        output.extend_from_slice(&start.to_le_bytes());
+        output.extend_from_slice(&len.to_le_bytes());
+        if !packed {
+            for value in &block.values {
+                output.extend_from_slice(&value.to_le_bytes());
             }
+            continue;
         }
-        Instruction::MemWriteS(addr, len) => {
-            output.write_all(&[0x11]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::MemRead(addr) => {
-            output.write_all(&[0x12]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-        }
-        Instruction::Print(addr, len) => {
-            output.write_all(&[0x13]).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
-            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+
+        let runs = runs(&block.values);
+        let raw_size = block.values.len() * 8;
+        let bytes_size = if block.values.iter().all(|&value| fits_byte(value)) { block.values.len() } else { usize::MAX };
+        let runs_size = 4 + runs.len() * 12;
+
+        if runs_size < bytes_size.min(raw_size) {
+            output.push(DATA_RUNS);
+            output.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+            for (len, value) in runs {
+                output.extend_from_slice(&len.to_le_bytes());
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+        } else if bytes_size < raw_size {
+            output.push(DATA_BYTES);
+            output.extend(block.values.iter().map(|&value| value as u8));
+        } else {
+            output.push(DATA_RAW);
+            for value in &block.values {
+                output.extend_from_slice(&value.to_le_bytes());
+            }
         }
     }
-
     Ok(())
 }
 
-/// Deserializes a single instruction from binary format
-fn deserialize_instruction(bytes: &[u8]) -> Result<(Instruction, usize), String> {
-    if bytes.is_empty() {
-        return Err("Empty bytecode".to_string());
-    }
+/// Deserializes data blocks written by `serialize_data`, or in the unpacked layout of
+/// older files if `packed` is false, returning them with the number of bytes they took
+#[cfg(feature = "disassembler")]
+fn deserialize_data(bytes: &[u8], packed: bool) -> Result<(Vec<DataBlock>, usize), String> {
+    let field = |offset: usize, len: usize| bytes.get(offset..offset + len).ok_or_else(|| "Incomplete data section".to_string());
+    let read_u32 = |offset: usize| -> Result<u32, String> { field(offset, 4).map(|field| u32::from_le_bytes(field.try_into().unwrap())) };
+    let read_words = |offset: usize, len: usize| -> Result<Vec<Word>, String> {
+        let values = field(offset, len.checked_mul(8).ok_or_else(|| "Incomplete data section".to_string())?)?;
+        Ok(values.chunks_exact(8).map(|value| Word::from_le_bytes(value.try_into().unwrap())).collect())
+    };
 
-    let opcode = bytes[0];
-    let mut offset = 1;
+    let count = read_u32(0)?;
+    let mut offset = 4;
+    let mut data = Vec::new();
+    let mut total = 0;
+    for _ in 0..count {
+        let start = read_u32(offset)? as usize;
+        let len = read_u32(offset + 4)? as usize;
+        offset += 8;
+        total += len;
+        if total > MAX_DATA_VALUES {
+            return Err(format!("Corrupt bytecode: data blocks hold more than {} values", MAX_DATA_VALUES));
+        }
 
-    match opcode {
-        0x00 => Ok((Instruction::Null, offset)),
-        0x01 => {
-            if bytes.len() < offset + 4 {
-                return Err("Incomplete Push instruction".to_string());
+        let encoding = if packed {
+            offset += 1;
+            field(offset - 1, 1)?[0]
+        } else {
+            DATA_RAW
+        };
+        let values = match encoding {
+            DATA_RAW => {
+                let values = read_words(offset, len)?;
+                offset += len * 8;
+                values
             }
-            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::Push(value), offset))
-        }
-        0x02 => Ok((Instruction::Dup, offset)),
-        0x03 => Ok((Instruction::Swap, offset)),
-        0x04 => Ok((Instruction::Pop, offset)),
-        0x05 => Ok((Instruction::Ret, offset)),
-        0x06 => {
-            let (target, consumed) = deserialize_string(&bytes[offset..])?;
-            offset += consumed;
-            Ok((Instruction::Jiz(target), offset))
-        }
-        0x07 => {
-            let (target, consumed) = deserialize_string(&bytes[offset..])?;
-            offset += consumed;
-            Ok((Instruction::Jnz(target), offset))
-        }
-        0x08 => {
-            if bytes.len() < offset + 4 {
-                return Err("Incomplete AddS instruction".to_string());
+            DATA_BYTES => {
+                let values = field(offset, len)?.iter().map(|&byte| Word::from(byte)).collect();
+                offset += len;
+                values
             }
-            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::AddS(value), offset))
-        }
-        0x09 => Ok((Instruction::Add, offset)),
-        0x0A => {
-            if bytes.len() < offset + 4 {
-                return Err("Incomplete SubS instruction".to_string());
+            DATA_RUNS => {
+                let run_count = read_u32(offset)? as usize;
+                offset += 4;
+                let mut values = Vec::new();
+                for _ in 0..run_count {
+                    let run_len = read_u32(offset)? as usize;
+                    let value = read_words(offset + 4, 1)?[0];
+                    offset += 12;
+                    if values.len() + run_len > len {
+                        return Err(format!("Corrupt bytecode: data block at {} has more values than its length", start));
+                    }
+                    values.resize(values.len() + run_len, value);
+                }
+                if values.len() != len {
+                    return Err(format!("Corrupt bytecode: data block at {} has fewer values than its length", start));
+                }
+                values
             }
-            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::SubS(value), offset))
+            _ => return Err(format!("Corrupt bytecode: unknown data encoding {} at address {}", encoding, start)),
+        };
+        data.push(DataBlock { start, values });
+    }
+    Ok((data, offset))
+}
+
+/// Serializes the source lines and labels of `debug_info` in the layout described on
+/// [`BytecodeHeader`]
+#[cfg(feature = "assembler")]
+fn serialize_debug_info(debug_info: &DebugInfo, output: &mut Vec<u8>) -> Result<(), String> {
+    let too_large = || "Debug information is too large".to_string();
+    let write_len = |len: usize, output: &mut Vec<u8>| -> Result<(), String> {
+        output.extend_from_slice(&u32::try_from(len).map_err(|_| too_large())?.to_le_bytes());
+        Ok(())
+    };
+    let write_text = |text: &str, output: &mut Vec<u8>| -> Result<(), String> {
+        write_len(text.len(), output)?;
+        output.extend_from_slice(text.as_bytes());
+        Ok(())
+    };
+
+    let file = debug_info.file.as_ref().map(|file| file.to_string_lossy().into_owned()).unwrap_or_default();
+    write_text(&file, output)?;
+    write_len(debug_info.lines.len(), output)?;
+    for &line in &debug_info.lines {
+        write_len(line, output)?;
+    }
+    write_len(debug_info.labels.len(), output)?;
+    for (name, index) in &debug_info.labels {
+        write_len(*index, output)?;
+        write_text(name, output)?;
+    }
+    write_len(debug_info.regions.len(), output)?;
+    for region in &debug_info.regions {
+        write_len(region.start, output)?;
+        write_len(region.len, output)?;
+        write_text(&region.name, output)?;
+    }
+    Ok(())
+}
+
+/// Deserializes a debug section written by `serialize_debug_info` for a program of
+/// `instruction_count` instructions, returning it with the number of bytes it took
+#[cfg(feature = "disassembler")]
+fn deserialize_debug_info(bytes: &[u8], instruction_count: usize) -> Result<(DebugInfo, usize), String> {
+    let field = |offset: usize, len: usize| bytes.get(offset..offset.saturating_add(len)).ok_or_else(|| "Incomplete debug section".to_string());
+    let read_u32 = |offset: usize| -> Result<usize, String> { field(offset, 4).map(|field| u32::from_le_bytes(field.try_into().unwrap()) as usize) };
+    let read_text = |offset: usize| -> Result<(String, usize), String> {
+        let len = read_u32(offset)?;
+        let text = std::str::from_utf8(field(offset + 4, len)?).map_err(|_| "Corrupt bytecode: debug section holds a name that is not UTF-8".to_string())?;
+        Ok((text.to_string(), 4 + len))
+    };
+
+    let mut debug_info = DebugInfo::default();
+    let (file, mut offset) = read_text(0)?;
+    debug_info.file = (!file.is_empty()).then(|| PathBuf::from(file));
+
+    let line_count = read_u32(offset)?;
+    offset += 4;
+    if line_count > instruction_count {
+        return Err(format!("Corrupt bytecode: debug section has {} lines for {} instructions", line_count, instruction_count));
+    }
+    for _ in 0..line_count {
+        debug_info.lines.push(read_u32(offset)?);
+        offset += 4;
+    }
+
+    let label_count = read_u32(offset)?;
+    offset += 4;
+    for _ in 0..label_count {
+        let index = read_u32(offset)?;
+        let (name, len) = read_text(offset + 4)?;
+        if index > instruction_count {
+            return Err(format!("Corrupt bytecode: label '{}' marks instruction {} past the end", name, index));
         }
-        0x0B => Ok((Instruction::Sub, offset)),
-        0x0C => {
-            if bytes.len() < offset + 4 {
-                return Err("Incomplete MultS instruction".to_string());
-            }
-            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::MultS(value), offset))
+        debug_info.labels.push((name, index));
+        offset += 4 + len;
+    }
+
+    let region_count = read_u32(offset)?;
+    offset += 4;
+    for _ in 0..region_count {
+        let (start, len) = (read_u32(offset)?, read_u32(offset + 4)?);
+        let (name, name_len) = read_text(offset + 8)?;
+        debug_info.regions.push(Region { name, start, len });
+        offset += 8 + name_len;
+    }
+    Ok((debug_info, offset))
+}
+
+/// A fixed-width operand field of the version 1 encoding, which version 2 stores as a varint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// An i32, stored as a signed LEB128 varint.
+    I32,
+    /// A u32 such as a jump target or a count, stored as an unsigned LEB128 varint.
+    U32,
+    /// An i64, stored as a signed LEB128 varint.
+    I64,
+    /// A raw byte, stored as is.
+    Byte,
+}
+
+#[cfg(feature = "assembler")]
+impl Field {
+    fn width(self) -> usize {
+        match self {
+            Field::I32 | Field::U32 => 4,
+            Field::I64 => 8,
+            Field::Byte => 1,
         }
-        0x0D => Ok((Instruction::Mult, offset)),
-        0x0E => {
-            if bytes.len() < offset + 4 {
-                return Err("Incomplete DivS instruction".to_string());
+    }
+}
+
+/// Returns the operand fields that follow `opcode`, and the field repeated as many times as
+/// the last of them says for instructions with a variable number of values, or `None` for
+/// an unknown opcode.
+fn operand_layout(opcode: u8) -> Option<(&'static [Field], Option<Field>)> {
+    use Field::*;
+    let layout: (&'static [Field], Option<Field>) = match opcode {
+        0x00 | 0x02..=0x05 | 0x09 | 0x0B | 0x0D | 0x0F | 0x15..=0x1A | 0x1C | 0x1E | 0x22 | 0x23 | 0x25 | 0x26 | 0x29 | 0x2A | 0x2F
+        | 0x30 | 0x31 | 0x33 | 0x34 | 0x36 | 0x37 | 0x3C | 0x3D | 0x41 => (&[], None),
+        0x01 | 0x08 | 0x0A | 0x0C | 0x0E | 0x12 | 0x1B | 0x21 | 0x27 | 0x28 | 0x2E | 0x35 | 0x38 | 0x39 | 0x43 => (&[I32], None),
+        0x06 | 0x07 | 0x14 | 0x1D | 0x3B | 0x3E | 0x3F => (&[U32], None),
+        0x11 | 0x13 | 0x24 | 0x32 | 0x42 => (&[I32, I32], None),
+        0x2B..=0x2D => (&[I32, I32, I32], None),
+        0x1F | 0x40 | 0x44..=0x48 => (&[I64], None),
+        0x4A => (&[I32, I32, I64], None),
+        0x10 => (&[I32, U32], Some(I32)),
+        0x49 => (&[I32, U32], Some(I64)),
+        0x3A => (&[I32, U32], Some(Byte)),
+        0x20 => (&[U32], Some(Byte)),
+        _ => return None,
+    };
+    Some(layout)
+}
+
+/// Rewrites one instruction in the version 1 encoding of `serialize_instruction` to the
+/// compact encoding of version 2, where every operand but raw bytes is an LEB128 varint.
+#[cfg(feature = "assembler")]
+fn compact_operands(fixed: &[u8], output: &mut Vec<u8>) {
+    let Some((fields, repeated)) = operand_layout(fixed[0]) else {
+        output.extend_from_slice(fixed);
+        return;
+    };
+    output.push(fixed[0]);
+
+    let mut offset = 1;
+    let mut last = 0;
+    let copy = |field: Field, offset: &mut usize, output: &mut Vec<u8>| -> u64 {
+        let bytes = &fixed[*offset..*offset + field.width()];
+        *offset += field.width();
+        match field {
+            Field::I32 => write_sleb(i32::from_le_bytes(bytes.try_into().unwrap()).into(), output),
+            Field::U32 => {
+                let value = u32::from_le_bytes(bytes.try_into().unwrap());
+                write_uleb(value.into(), output);
+                return value.into();
             }
-            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::DivS(value), offset))
+            Field::I64 => write_sleb(i64::from_le_bytes(bytes.try_into().unwrap()), output),
+            Field::Byte => output.push(bytes[0]),
         }
-        0x0F => Ok((Instruction::Div, offset)),
-        0x10 => {
-            if bytes.len() < offset + 12 {
-                return Err("Incomplete MemWrite instruction".to_string());
+        0
+    };
+    for &field in fields {
+        last = copy(field, &mut offset, output);
+    }
+    if let Some(field) = repeated {
+        for _ in 0..last {
+            copy(field, &mut offset, output);
+        }
+    }
+}
+
+/// Reads one instruction in the version 2 encoding and rewrites it to the version 1
+/// encoding `deserialize_instruction` reads, returning that and the bytes consumed.
+#[cfg(feature = "disassembler")]
+fn expand_operands(bytes: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let Some(&opcode) = bytes.first() else {
+        return Err("Empty bytecode".to_string());
+    };
+    // Unknown opcodes are reported by `deserialize_instruction`
+    let Some((fields, repeated)) = operand_layout(opcode) else {
+        return Ok((vec![opcode], 1));
+    };
+
+    let mut fixed = vec![opcode];
+    let mut offset = 1;
+    let mut read = |field: Field, offset: &mut usize| -> Result<u64, String> {
+        let incomplete = || format!("Incomplete instruction with opcode 0x{:02X}", opcode);
+        let out_of_range = || format!("Corrupt bytecode: operand of opcode 0x{:02X} is out of range", opcode);
+        match field {
+            Field::I32 => {
+                let (value, len) = read_sleb(&bytes[*offset..]).ok_or_else(incomplete)?;
+                *offset += len;
+                fixed.extend_from_slice(&i32::try_from(value).map_err(|_| out_of_range())?.to_le_bytes());
+                Ok(0)
             }
-            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            let len = u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-
-            let mut values = Vec::new();
-            for _ in 0..len {
-                if bytes.len() < offset + 4 {
-                    return Err("Incomplete MemWrite values".to_string());
-                }
-                let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-                values.push(value);
-                offset += 4;
+            Field::U32 => {
+                let (value, len) = read_uleb(&bytes[*offset..]).ok_or_else(incomplete)?;
+                *offset += len;
+                fixed.extend_from_slice(&u32::try_from(value).map_err(|_| out_of_range())?.to_le_bytes());
+                Ok(value)
             }
-            Ok((Instruction::MemWrite(addr, values), offset))
-        }
-        0x11 => {
-            if bytes.len() < offset + 8 {
-                return Err("Incomplete MemWriteS instruction".to_string());
+            Field::I64 => {
+                let (value, len) = read_sleb(&bytes[*offset..]).ok_or_else(incomplete)?;
+                *offset += len;
+                fixed.extend_from_slice(&value.to_le_bytes());
+                Ok(0)
             }
-            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            let len = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::MemWriteS(addr, len), offset))
-        }
-        0x12 => {
-            if bytes.len() < offset + 4 {
-                return Err("Incomplete MemRead instruction".to_string());
+            Field::Byte => {
+                let byte = *bytes.get(*offset).ok_or_else(incomplete)?;
+                *offset += 1;
+                fixed.push(byte);
+                Ok(0)
             }
-            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::MemRead(addr), offset))
         }
-        0x13 => {
-            if bytes.len() < offset + 8 {
-                return Err("Incomplete Print instruction".to_string());
-            }
-            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            let len = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
-            offset += 4;
-            Ok((Instruction::Print(addr, len), offset))
+    };
+    let mut last = 0;
+    for &field in fields {
+        last = read(field, &mut offset)?;
+    }
+    if let Some(field) = repeated {
+        for _ in 0..last {
+            read(field, &mut offset)?;
         }
-        _ => Err(format!("Unknown opcode: 0x{:02X}", opcode))
     }
+    Ok((fixed, offset))
 }
 
-/// Serializes a string to binary format (null-terminated)
-fn serialize_string(s: &str, output: &mut Vec<u8>) -> Result<(), String> {
-    output.write_all(s.as_bytes()).map_err(|e| format!("Write error: {}", e))?;
-    output.write_all(&[0]).map_err(|e| format!("Write error: {}", e))?; // Null terminator
-    Ok(())
+#[cfg(feature = "assembler")]
+fn write_uleb(mut value: u64, output: &mut Vec<u8>) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
 }
 
-/// Deserializes a string from binary format (null-terminated)
-fn deserialize_string(bytes: &[u8]) -> Result<(String, usize), String> {
-    let mut end = 0;
-    while end < bytes.len() && bytes[end] != 0 {
-        end += 1;
+#[cfg(feature = "assembler")]
+fn write_sleb(mut value: i64, output: &mut Vec<u8>) {
+    loop {
+        let byte = value as u8 & 0x7F;
+        value >>= 7;
+        // Done once the rest is all sign bits and the sign bit of this byte agrees
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
     }
+}
 
-    if end >= bytes.len() {
-        return Err("Unterminated string in bytecode".to_string());
+/// Reads an unsigned LEB128 varint, returning it and its length, or `None` if it is
+/// truncated or does not fit in 64 bits.
+#[cfg(feature = "disassembler")]
+fn read_uleb(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (index, &byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7F).checked_shl(7 * index as u32)?;
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
     }
+    None
+}
 
-    let s = String::from_utf8(bytes[..end].to_vec())
-        .map_err(|e| format!("Invalid UTF-8 string in bytecode: {}", e))?;
-
-    Ok((s, end + 1))
+/// Reads a signed LEB128 varint like `read_uleb`.
+#[cfg(feature = "disassembler")]
+fn read_sleb(bytes: &[u8]) -> Option<(i64, usize)> {
+    let mut value = 0i64;
+    for (index, &byte) in bytes.iter().enumerate().take(10) {
+        let shift = 7 * index as u32;
+        value |= i64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            if shift + 7 < 64 && byte & 0x40 != 0 {
+                value |= -1 << (shift + 7);
+            }
+            return Some((value, index + 1));
+        }
+    }
+    None
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "assembler", feature = "disassembler"))]
 mod tests {
     use super::*;
     use crate::instruction::Instruction;
@@ -318,13 +1109,14 @@ mod tests {
 
     #[test]
     fn test_assemble_jump_instructions() {
-        let source = "JIZ main\nJNZ end";
+        let source = "main:\nJIZ main\nJNZ end\nend:\nRET";
         let bytecode = assemble_source(source).unwrap();
         let instructions = disassemble_bytecode(&bytecode).unwrap();
 
         assert_eq!(instructions, vec![
-            Instruction::Jiz("main".to_string()),
-            Instruction::Jnz("end".to_string()),
+            Instruction::Jiz(0),
+            Instruction::Jnz(2),
+            Instruction::Ret,
         ]);
     }
 
@@ -356,26 +1148,367 @@ mod tests {
         assert_eq!(instructions, vec![
             Instruction::Push(10),
             Instruction::SubS(1),
-            Instruction::Jnz("0".to_string()),
+            Instruction::Jnz(0),
             Instruction::Ret,
         ]);
     }
 
+    #[test]
+    fn test_assemble_comparison_instructions() {
+        let source = "EQ\nNEQ\nLT\nGT\nLTE\nGTE";
+        let bytecode = assemble_source(source).unwrap();
+        assert_eq!(&bytecode[HEADER_SIZE..], &[0x15, 0x16, 0x17, 0x18, 0x19, 0x1A]);
+
+        let instructions = disassemble_bytecode(&bytecode).unwrap();
+        assert_eq!(instructions, vec![
+            Instruction::Eq,
+            Instruction::Neq,
+            Instruction::Lt,
+            Instruction::Gt,
+            Instruction::Lte,
+            Instruction::Gte,
+        ]);
+    }
+
     #[test]
     fn test_round_trip() {
         let original_instructions = vec![
             Instruction::Push(123),
             Instruction::Dup,
+            Instruction::Rot,
+            Instruction::Over,
+            Instruction::Pick(2),
+            Instruction::Depth,
+            Instruction::Clear,
             Instruction::Add,
+            Instruction::ModS(7),
+            Instruction::Mod,
             Instruction::MemWrite(0, vec![1, 2, 3]),
             Instruction::Print(0, 3),
-            Instruction::Jiz("5".to_string()),
+            Instruction::Jiz(5),
+            Instruction::Call(0),
+            Instruction::Jmp(2),
+            Instruction::JmpDyn,
+            Instruction::HostCall("rand".to_string()),
+            Instruction::Halt(-2),
+            Instruction::Abort(8, 5),
+            Instruction::CodeRead,
+            Instruction::CodeWrite,
+            Instruction::ReadLine(16, 80),
+            Instruction::ReadInt,
+            Instruction::PrintInt,
+            Instruction::PrintStr(30),
+            Instruction::PerfRead(1),
+            Instruction::VmInfo(3),
+            Instruction::ProfBegin(0),
+            Instruction::ProfEnd(7),
+            Instruction::Assert(-1 << 40),
+            Instruction::AssertEq,
+            Instruction::Load,
+            Instruction::Store,
+            Instruction::LoadLocal(3),
+            Instruction::Try(6),
+            Instruction::EndTry,
+            Instruction::Throw,
+            Instruction::StoreLocal(0),
+            Instruction::MemCopy(10, 0, 4),
+            Instruction::MemSet(0, 8, -1),
+            Instruction::Protect(16, 4),
+            Instruction::MemCmp(0, 10, 4),
+            Instruction::Grow(3),
+            Instruction::MemSize,
+            Instruction::Alloc,
+            Instruction::Free,
+            // Values that need 64 bits take the wide opcodes
+            Instruction::AddS(1 << 40),
+            Instruction::SubS(-1 << 35),
+            Instruction::MultS(3_000_000_000),
+            Instruction::DivS(Word::MIN),
+            Instruction::ModS(1 << 33),
+            Instruction::MemWrite(4, vec![1, 1 << 40]),
+            Instruction::MemSet(0, 2, -1 << 40),
             Instruction::Ret,
         ];
 
-        let bytecode = serialize_instructions(&original_instructions).unwrap();
-        let decoded_instructions = deserialize_instructions(&bytecode).unwrap();
+        // Files in the fixed-width format of version 1 still load
+        for isa in [IsaVersion::LATEST, IsaVersion::V1_0] {
+            let bytecode = serialize_instructions(&original_instructions, isa).unwrap();
+            let decoded_instructions = deserialize_instructions(&bytecode).unwrap();
+            assert_eq!(original_instructions, decoded_instructions);
+        }
+    }
+
+    #[test]
+    fn test_operands_are_varints() {
+        let bytecode = assemble_source("PUSH -5\nPUSH 300\nJMP 0\nPUSH 1700000000000").unwrap();
+        assert_eq!(BytecodeHeader::parse(&bytecode).unwrap().version, 2);
+        assert_eq!(
+            &bytecode[HEADER_SIZE..],
+            &[0x01, 0x7B, 0x01, 0xAC, 0x02, 0x1D, 0x00, 0x1F, 0x80, 0xD0, 0x95, 0xFF, 0xBC, 0x31]
+        );
+
+        let old = assemble_source_for("PUSH -5", IsaVersion::V1_0).unwrap();
+        assert_eq!(BytecodeHeader::parse(&old).unwrap().version, 1);
+        assert_eq!(&old[HEADER_SIZE..], &[0x01, 0xFB, 0xFF, 0xFF, 0xFF]);
+
+        // A varint running past the end or beyond the operand's range is corrupt
+        let mut truncated = bytecode[..HEADER_SIZE].to_vec();
+        truncated.extend_from_slice(&[0x01, 0x80]);
+        assert!(disassemble_bytecode(&truncated).unwrap_err().contains("Incomplete instruction with opcode 0x01"));
+        let mut too_large = bytecode[..HEADER_SIZE].to_vec();
+        too_large.extend_from_slice(&[0x01, 0x80, 0x80, 0x80, 0x80, 0x10]);
+        assert!(disassemble_bytecode(&too_large).unwrap_err().contains("out of range"));
+    }
+
+    #[test]
+    fn test_header_is_written() {
+        let bytecode = assemble_source("PUSH 1\nDUP\nRET").unwrap();
+
+        assert_eq!(&bytecode[0..4], b"VVM\0");
+        assert_eq!(BytecodeHeader::parse(&bytecode).unwrap(), BytecodeHeader::new(3));
+    }
+
+    #[test]
+    fn test_rejects_missing_header() {
+        // A raw opcode stream from before the header was introduced
+        let err = disassemble_bytecode(&[0x01, 0x05, 0x00, 0x00, 0x00, 0x05]).unwrap_err();
+        assert!(err.contains("missing VVM header"));
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let mut bytecode = assemble_source("RET").unwrap();
+        bytecode[4] = 9;
+
+        let err = disassemble_bytecode(&bytecode).unwrap_err();
+        assert!(err.contains("Unsupported bytecode format version 9"));
+    }
+
+    #[test]
+    fn test_rejects_truncated_programs() {
+        let mut bytecode = assemble_source("PUSH 1\nRET").unwrap();
+        bytecode.pop();
+
+        let err = disassemble_bytecode(&bytecode).unwrap_err();
+        assert!(err.contains("header declares 2 instructions but 1 were found"));
+    }
+
+    #[test]
+    fn test_data_round_trip() {
+        let bytecode = assemble_source(".data 8\n.word -1 70000\n.text\nPRINTSTR 8\n.data 0\n.string \"A\"").unwrap();
+        assert_eq!(BytecodeHeader::parse(&bytecode).unwrap().flags, FLAG_DATA | FLAG_PACKED_DATA);
+
+        let program = disassemble_program(&bytecode).unwrap();
+        assert_eq!(program.instructions, vec![Instruction::PrintStr(8)]);
+        assert_eq!(
+            program.data,
+            vec![DataBlock { start: 0, values: vec![65, 0] }, DataBlock { start: 8, values: vec![-1, 70000] }]
+        );
+
+        // Programs without data keep the original layout
+        assert_eq!(BytecodeHeader::parse(&assemble_source("RET").unwrap()).unwrap().flags, 0);
+    }
+
+    #[test]
+    fn test_data_blocks_are_packed() {
+        let data = vec![
+            DataBlock { start: 0, values: b"Hello, world".iter().map(|&byte| Word::from(byte)).collect() },
+            DataBlock { start: 100, values: vec![-1; 1000] },
+            DataBlock { start: 2000, values: vec![-1, 70000, i64::MAX] },
+        ];
+        let mut bytes = Vec::new();
+        serialize_data(&data, true, &mut bytes).unwrap();
+
+        // One byte per character, one run for the fill and plain words for the rest
+        let encodings = [bytes[12], bytes[12 + 12 + 9], bytes[12 + 12 + 9 + 16 + 9]];
+        assert_eq!(encodings, [DATA_BYTES, DATA_RUNS, DATA_RAW]);
+        assert_eq!(bytes.len(), 4 + 3 * 9 + 12 + 16 + 24);
+        assert_eq!(deserialize_data(&bytes, true).unwrap(), (data, bytes.len()));
+
+        // Files written before packing still load
+        let mut unpacked = 1u32.to_le_bytes().to_vec();
+        for field in [8u32, 2] {
+            unpacked.extend_from_slice(&field.to_le_bytes());
+        }
+        unpacked.extend_from_slice(&65i64.to_le_bytes());
+        unpacked.extend_from_slice(&(-2i64).to_le_bytes());
+        let old = vec![DataBlock { start: 8, values: vec![65, -2] }];
+        assert_eq!(deserialize_data(&unpacked, false).unwrap().0, old);
+        // and ISA 1.1 still gets them
+        let mut written = Vec::new();
+        serialize_data(&old, false, &mut written).unwrap();
+        assert_eq!(written, unpacked);
+
+        bytes[12] = 9;
+        assert!(deserialize_data(&bytes, true).unwrap_err().contains("unknown data encoding 9"));
+    }
+
+    #[test]
+    fn test_memwrite_bytes_are_packed() {
+        let source = "MemWrite 0 72 101 108 108 111\nMemWrite 8 300 -1";
+        let packed = assemble_source(source).unwrap();
+        let unpacked = assemble_source_for(source, IsaVersion::V1_0).unwrap();
+
+        assert_eq!(packed[HEADER_SIZE], 0x3A);
+        assert_eq!(unpacked[HEADER_SIZE], 0x10);
+        // Varints shrink the addresses and counts as well
+        assert_eq!((packed.len(), unpacked.len()), (HEADER_SIZE + 8 + 6, HEADER_SIZE + 29 + 17));
+        assert_eq!(disassemble_bytecode(&packed).unwrap(), disassemble_bytecode(&unpacked).unwrap());
+    }
+
+    #[test]
+    fn test_requirements_round_trip() {
+        let bytecode = assemble_source(".requires mem 8192\n.requires fuel 500\nRET\n.data\n.byte 1").unwrap();
+        assert_eq!(BytecodeHeader::parse(&bytecode).unwrap().flags, FLAG_DATA | FLAG_PACKED_DATA | FLAG_REQUIREMENTS);
+
+        let program = disassemble_program(&bytecode).unwrap();
+        assert_eq!(program.requirements, Requirements { memory: Some(8192), fuel: Some(500) });
+        assert_eq!(program.instructions, vec![Instruction::Ret]);
+        assert_eq!(program.data.len(), 1);
+
+        let program = disassemble_program(&assemble_source(".requires fuel 9\nRET").unwrap()).unwrap();
+        assert_eq!(program.requirements, Requirements { memory: None, fuel: Some(9) });
+
+        // The smallest amounts come back as written, and amounts no run could meet are refused
+        let program = disassemble_program(&assemble_source(".requires fuel 1\n.requires mem 1\nRET").unwrap()).unwrap();
+        assert_eq!(program.requirements, Requirements { memory: Some(1), fuel: Some(1) });
+        assert!(assemble_source(".requires fuel 0\nRET").unwrap_err().contains("'.requires fuel 0' can never be met"));
+        assert!(assemble_source(".requires mem 1000000000000\nRET").unwrap_err().contains("more than the 4194304 a VM may have"));
+
+        let mut bytecode = assemble_source(".requires mem 8192\nRET").unwrap();
+        bytecode[HEADER_SIZE..HEADER_SIZE + 8].copy_from_slice(&1_000_000_000_000u64.to_le_bytes());
+        assert_eq!(
+            disassemble_program(&bytecode).unwrap_err(),
+            format!("Program requires 1000000000000 memory cells, more than the {} a VM may have", MAX_MEMORY_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_compiled_source_matches_loaded_bytecode() {
+        let source = ".requires fuel 50\n.data 8\n.word -1 70000\n.data 0\n.string \"A\"\n.text\nstart:\nPUSH 70000\nJNZ start\nPRINTSTR 8";
+        let compiled = compile_source(source).unwrap();
+        let loaded = disassemble_program(&assemble_source(source).unwrap()).unwrap();
+        assert_eq!((&compiled.instructions, &compiled.data, &compiled.requirements), (&loaded.instructions, &loaded.data, &loaded.requirements));
+        assert_eq!(compiled.debug_info.labels, vec![("start".to_string(), 0)]);
+        assert!(compile_source("PUSH").is_err());
+    }
+
+    #[test]
+    fn test_capabilities_are_recorded_and_checked() {
+        let mut bytecode = assemble_source(".requires fuel 3\nREADINT\nHOSTCALL rand").unwrap();
+        let header = BytecodeHeader::parse(&bytecode).unwrap();
+        assert_eq!(header.flags, FLAG_REQUIREMENTS | FLAG_CAPABILITIES);
+        let mask_at = HEADER_SIZE + REQUIREMENTS_SIZE;
+        assert_eq!(bytecode[mask_at..mask_at + 4], [0b101, 0, 0, 0]);
+        assert_eq!(disassemble_bytecode(&bytecode).unwrap().len(), 2);
+
+        // A feature this build does not know about stops loading before the instructions
+        bytecode[mask_at + 1] = 0x01;
+        let err = disassemble_bytecode(&bytecode).unwrap_err();
+        assert!(err.contains("lacks feature bit 8"), "{}", err);
+
+        assert_eq!(BytecodeHeader::parse(&assemble_source("PUSH 1").unwrap()).unwrap().flags, 0);
+    }
+
+    #[test]
+    fn test_rejects_truncated_data() {
+        let mut bytecode = assemble_source(".data\n.byte 1 2\n.text\nRET").unwrap();
+        bytecode.pop();
+        assert_eq!(disassemble_program(&bytecode).unwrap_err(), "Incomplete data section");
+
+        bytecode.extend_from_slice(&[0, 0]);
+        assert!(disassemble_program(&bytecode).unwrap_err().contains("1 unexpected bytes"));
+    }
+
+    #[test]
+    fn test_debug_info_round_trip() {
+        let program = crate::spliter::parse_program(".region msg 0 2\n.data 0\n.string \"hi\"\n.text\nmain:\nPUSH 2\nloop:\nSUBS 1\nJNZ loop").unwrap();
+        let mut bytecode = assemble_program_with_debug_info(&program).unwrap();
+        assert_eq!(BytecodeHeader::parse(&bytecode).unwrap().flags, FLAG_DATA | FLAG_PACKED_DATA | FLAG_DEBUG);
+
+        let decoded = disassemble_program(&bytecode).unwrap();
+        assert_eq!((&decoded.instructions, &decoded.data), (&program.instructions, &program.data));
+        assert_eq!(decoded.debug_info.lines, vec![6, 8, 9]);
+        assert_eq!(decoded.debug_info.labels, vec![("main".to_string(), 0), ("loop".to_string(), 1)]);
+        assert_eq!(decoded.debug_info.regions, program.debug_info.regions);
+        assert_eq!(decoded.debug_info.file, None);
+
+        bytecode.pop();
+        assert_eq!(disassemble_program(&bytecode).unwrap_err(), "Incomplete debug section");
+    }
+
+    #[test]
+    fn test_debug_info_names_the_source_file() {
+        let source = ExpandedSource {
+            text: "start:\nPUSH 1\nRET".to_string(),
+            origins: (1..=3).map(|line| (PathBuf::from("prog.vvm"), line + 10)).collect(),
+        };
+        let options = AssembleOptions { debug: true, ..AssembleOptions::default() };
+        let program = disassemble_program(&assemble_expanded(&source, &options).unwrap()).unwrap();
+        assert_eq!(program.symbolize(1).unwrap().to_string(), "start+1 at prog.vvm:13");
+
+        let options = AssembleOptions { isa: IsaVersion::V1_0, ..options };
+        assert!(assemble_expanded(&source, &options).unwrap_err().contains("without --debug"));
+    }
 
-        assert_eq!(original_instructions, decoded_instructions);
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_compressed_bytecode_round_trip() {
+        let source = ExpandedSource {
+            text: ".requires fuel 900
+.data 0
+.string \"hello\"
+.text
+main:
+READINT
+PRINT 0 5
+JMP main".to_string(),
+            origins: Vec::new(),
+        };
+        let options = AssembleOptions { compress: true, debug: true, ..AssembleOptions::default() };
+        let mut bytecode = assemble_expanded(&source, &options).unwrap();
+        let flags = BytecodeHeader::parse(&bytecode).unwrap().flags;
+        assert_eq!(flags, FLAG_REQUIREMENTS | FLAG_CAPABILITIES | FLAG_DATA | FLAG_PACKED_DATA | FLAG_DEBUG | FLAG_COMPRESSED);
+        // The requirements and capabilities are left as they are
+        assert_eq!(bytecode[HEADER_SIZE + 8..HEADER_SIZE + 16], 900u64.to_le_bytes());
+
+        let plain = assemble_expanded(&source, &AssembleOptions { compress: false, ..options }).unwrap();
+        assert_eq!(disassemble_program(&bytecode).unwrap(), disassemble_program(&plain).unwrap());
+        assert_eq!(compress_bytecode(&bytecode).unwrap(), bytecode);
+
+        let last = bytecode.len() - 1;
+        bytecode[last] ^= 0xFF;
+        assert!(disassemble_program(&bytecode).unwrap_err().starts_with("Corrupt compressed bytecode"));
+
+        let options = AssembleOptions { isa: IsaVersion::V1_0, debug: false, ..options };
+        assert!(assemble_expanded(&source, &options).unwrap_err().contains("without --compress"));
+
+        assert!(compress_bytecode(b"VVM").unwrap_err().contains("missing VVM header"));
+        let header_only = &plain[..HEADER_SIZE];
+        assert_eq!(compress_bytecode(header_only).unwrap_err(), "Incomplete bytecode header");
+
+        // Streams that inflate past the limit are refused rather than allocated
+        let zeros = miniz_oxide::deflate::compress_to_vec_zlib(&[0; 4097], 1);
+        assert_eq!(inflate(&zeros, 4097).unwrap().len(), 4097);
+        assert_eq!(inflate(&zeros, 4096).unwrap_err(), "Compressed bytecode inflates to more than 4096 bytes");
+    }
+
+    #[test]
+    fn test_data_runs_are_limited() {
+        // One block claiming u32::MAX values in a single run is refused rather than allocated
+        let header = BytecodeHeader { flags: FLAG_DATA | FLAG_PACKED_DATA, ..BytecodeHeader::new(0) };
+        let mut bytecode = header.to_bytes().to_vec();
+        bytecode.extend_from_slice(&1u32.to_le_bytes());
+        bytecode.extend_from_slice(&0u32.to_le_bytes());
+        bytecode.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytecode.push(DATA_RUNS);
+        bytecode.extend_from_slice(&1u32.to_le_bytes());
+        bytecode.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytecode.extend_from_slice(&0i64.to_le_bytes());
+        assert_eq!(bytecode.len(), 41);
+        assert_eq!(
+            disassemble_program(&bytecode).unwrap_err(),
+            format!("Corrupt bytecode: data blocks hold more than {} values", MAX_DATA_VALUES)
+        );
     }
 }
@@ -0,0 +1,395 @@
+use crate::instruction::{Instruction, Word};
+use crate::manifest::program_hash;
+use crate::program::{DataBlock, Program, Requirements};
+use crate::spliter::try_parse_program;
+use std::fs;
+
+const PATCH_HEADER: &str = "; Vortex VM bytecode patch, apply it with `vortex-vm patch <program> <patch> <output>`";
+
+/// Most cells of the table used to find the instructions two programs share. Programs
+/// that differ in more instructions than this allows are treated as entirely replaced
+/// between their common start and end.
+const DIFF_CELL_LIMIT: usize = 4_000_000;
+
+/// One difference between two programs' instructions, see [`Hunk::changes`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The instruction at `old` became `to` at `new`.
+    Changed { old: usize, new: usize, from: Instruction, to: Instruction },
+    /// The instruction at `old` is gone.
+    Removed { old: usize, instruction: Instruction },
+    /// The instruction at `new` is new.
+    Added { new: usize, instruction: Instruction },
+}
+
+impl Change {
+    /// Describes the change, with old indices located in `old` and new ones in `new`.
+    pub fn describe(&self, old: &Program, new: &Program) -> String {
+        match self {
+            Change::Changed { old: old_index, new: new_index, from, to } if old_index == new_index => {
+                format!("changed {}: {} -> {}", locate(old, *old_index), from, to)
+            }
+            Change::Changed { old: old_index, new: new_index, from, to } => {
+                format!("changed {} (now {}): {} -> {}", locate(old, *old_index), locate(new, *new_index), from, to)
+            }
+            Change::Removed { old: index, instruction } => format!("removed {}: {}", locate(old, *index), instruction),
+            Change::Added { new: index, instruction } => format!("added {}: {}", locate(new, *index), instruction),
+        }
+    }
+}
+
+/// Names an instruction by index and, where the program has labels, by symbol.
+fn locate(program: &Program, index: usize) -> String {
+    match program.symbolize(index) {
+        Some(symbol) => format!("{} ({})", index, symbol),
+        None => index.to_string(),
+    }
+}
+
+/// A run of instructions replaced by others: `removed` starting at `old_start` in the old
+/// program became `added` starting at `new_start` in the new one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub new_start: usize,
+    pub removed: Vec<Instruction>,
+    pub added: Vec<Instruction>,
+}
+
+impl Hunk {
+    /// Lists the hunk instruction by instruction, pairing removed and added instructions
+    /// up as changes from the start.
+    pub fn changes(&self) -> Vec<Change> {
+        let paired = self.removed.len().min(self.added.len());
+        let changed = self.removed.iter().zip(&self.added).enumerate().map(|(offset, (from, to))| Change::Changed {
+            old: self.old_start + offset,
+            new: self.new_start + offset,
+            from: from.clone(),
+            to: to.clone(),
+        });
+        let removed = self.removed[paired..].iter().enumerate().map(|(offset, instruction)| Change::Removed {
+            old: self.old_start + paired + offset,
+            instruction: instruction.clone(),
+        });
+        let added = self.added[paired..].iter().enumerate().map(|(offset, instruction)| Change::Added {
+            new: self.new_start + paired + offset,
+            instruction: instruction.clone(),
+        });
+        changed.chain(removed).chain(added).collect()
+    }
+}
+
+/// Finds the instructions that differ between `old` and `new`, keeping as many
+/// instructions in common as possible.
+///
+/// Inserting or removing code moves the jump targets behind it, so jumps over the edit
+/// show up as changed too.
+///
+/// ```
+/// use vortex_vm::bindiff::{diff_instructions, Hunk};
+/// use vortex_vm::spliter::split_instructions;
+///
+/// let old = split_instructions("PUSH 1\nDUP\nADD").unwrap();
+/// let new = split_instructions("PUSH 2\nDUP\nADD\nPRINTINT").unwrap();
+/// let hunks = diff_instructions(&old, &new);
+/// assert_eq!(hunks.len(), 2);
+/// assert_eq!((hunks[0].old_start, hunks[0].removed.len(), hunks[0].added.len()), (0, 1, 1));
+/// assert_eq!((hunks[1].old_start, hunks[1].new_start, hunks[1].added.len()), (3, 3, 1));
+/// ```
+pub fn diff_instructions(old: &[Instruction], new: &[Instruction]) -> Vec<Hunk> {
+    // Only the middle between the common start and end needs the table
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return Vec::new();
+    }
+    let (rows, columns) = (old_middle.len() + 1, new_middle.len() + 1);
+    if rows.saturating_mul(columns) > DIFF_CELL_LIMIT {
+        return vec![Hunk { old_start: prefix, new_start: prefix, removed: old_middle.to_vec(), added: new_middle.to_vec() }];
+    }
+
+    // common[i * columns + j] is how many instructions old_middle[i..] and new_middle[j..] share
+    let mut common = vec![0u32; rows * columns];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            common[i * columns + j] = if old_middle[i] == new_middle[j] {
+                common[(i + 1) * columns + j + 1] + 1
+            } else {
+                common[(i + 1) * columns + j].max(common[i * columns + j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            hunks.extend(current.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = current.get_or_insert_with(|| Hunk { old_start: prefix + i, new_start: prefix + j, removed: Vec::new(), added: Vec::new() });
+        if i < old_middle.len() && (j == new_middle.len() || common[(i + 1) * columns + j] >= common[i * columns + j + 1]) {
+            hunk.removed.push(old_middle[i].clone());
+            i += 1;
+        } else {
+            hunk.added.push(new_middle[j].clone());
+            j += 1;
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+/// The changes that turn one bytecode program into another, written by `vortex-vm bindiff
+/// --output` and applied by `vortex-vm patch`.
+///
+/// A patch records the [`program_hash`] of the program it applies to and of the result, so
+/// it is never applied to the wrong program and a damaged patch is noticed. Its text form
+/// lists each hunk as `@ OLD NEW` followed by the removed (`-`) and added (`+`)
+/// instructions, which makes it easy to review before deploying it.
+///
+/// ```
+/// use vortex_vm::bindiff::Patch;
+/// use vortex_vm::spliter::parse_program;
+///
+/// let old = parse_program("PUSH 1\nPRINTINT").unwrap();
+/// let new = parse_program("PUSH 2\nPRINTINT\nHALT 0").unwrap();
+/// let patch = Patch::between(&old, &new).unwrap();
+/// let patch = Patch::parse(&patch.to_text()).unwrap();
+/// assert_eq!(patch.apply(&old).unwrap().instructions, new.instructions);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    pub base_hash: u64,
+    pub result_hash: u64,
+    pub hunks: Vec<Hunk>,
+    /// The new data, if it changed.
+    pub data: Option<Vec<DataBlock>>,
+    /// The new requirements, if they changed.
+    pub requirements: Option<Requirements>,
+}
+
+impl Patch {
+    /// Makes the patch that turns `old` into `new`. Debug information is not part of
+    /// bytecode and is not compared.
+    pub fn between(old: &Program, new: &Program) -> Result<Self, String> {
+        Ok(Patch {
+            base_hash: program_hash(old)?,
+            result_hash: program_hash(new)?,
+            hunks: diff_instructions(&old.instructions, &new.instructions),
+            data: (old.data != new.data).then(|| new.data.clone()),
+            requirements: (old.requirements != new.requirements).then_some(new.requirements),
+        })
+    }
+
+    /// Returns whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty() && self.data.is_none() && self.requirements.is_none()
+    }
+
+    /// Applies the patch to `program`, which must be the program it was made from.
+    pub fn apply(&self, program: &Program) -> Result<Program, String> {
+        if program_hash(program)? != self.base_hash {
+            return Err("The patch was made for a different program".to_string());
+        }
+
+        let mut instructions = Vec::with_capacity(program.instructions.len());
+        let mut next = 0;
+        for hunk in &self.hunks {
+            let end = hunk.old_start + hunk.removed.len();
+            if hunk.old_start < next || program.instructions.get(hunk.old_start..end) != Some(hunk.removed.as_slice()) {
+                return Err(format!("The instructions at {} are not the ones the patch removes", hunk.old_start));
+            }
+            instructions.extend_from_slice(&program.instructions[next..hunk.old_start]);
+            instructions.extend_from_slice(&hunk.added);
+            next = end;
+        }
+        instructions.extend_from_slice(&program.instructions[next..]);
+
+        let patched = Program {
+            instructions,
+            data: self.data.clone().unwrap_or_else(|| program.data.clone()),
+            requirements: self.requirements.unwrap_or(program.requirements),
+            ..Program::default()
+        };
+        if program_hash(&patched)? != self.result_hash {
+            return Err("The patched program is not the one the patch was made for; the patch is damaged".to_string());
+        }
+        Ok(patched)
+    }
+
+    /// Renders the patch in its text form.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nbase {:016x}\nresult {:016x}\n", PATCH_HEADER, self.base_hash, self.result_hash);
+        for hunk in &self.hunks {
+            text.push_str(&format!("@ {} {}\n", hunk.old_start, hunk.new_start));
+            for instruction in &hunk.removed {
+                text.push_str(&format!("- {}\n", instruction));
+            }
+            for instruction in &hunk.added {
+                text.push_str(&format!("+ {}\n", instruction));
+            }
+        }
+        if let Some(requirements) = &self.requirements {
+            let memory = requirements.memory.unwrap_or(0);
+            text.push_str(&format!("requires {} {}\n", memory, requirements.fuel.unwrap_or(0)));
+        }
+        if let Some(data) = &self.data {
+            text.push_str(&format!("data {}\n", data.len()));
+            for block in data {
+                let values: Vec<String> = block.values.iter().map(Word::to_string).collect();
+                text.push_str(&format!("{} {}\n", block.start, values.join(" ")));
+            }
+        }
+        text
+    }
+
+    /// Parses a patch from its text form. Lines starting with `;` are comments.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())).filter(|(_, line)| !line.is_empty() && !line.starts_with(';'));
+        let mut patch = Patch { base_hash: 0, result_hash: 0, hunks: Vec::new(), data: None, requirements: None };
+        let (mut has_base, mut has_result) = (false, false);
+
+        while let Some((number, line)) = lines.next() {
+            let invalid = |e: String| format!("Invalid patch at line {}: {}", number, e);
+            let (keyword, rest) = line.split_once(' ').map_or((line, ""), |(keyword, rest)| (keyword, rest.trim()));
+            match keyword {
+                "base" => (patch.base_hash, has_base) = (parse_hash(rest).map_err(invalid)?, true),
+                "result" => (patch.result_hash, has_result) = (parse_hash(rest).map_err(invalid)?, true),
+                "@" => {
+                    let [old_start, new_start] = parse_numbers::<usize, 2>(rest).map_err(invalid)?;
+                    patch.hunks.push(Hunk { old_start, new_start, removed: Vec::new(), added: Vec::new() });
+                }
+                "-" | "+" => {
+                    let hunk = patch.hunks.last_mut().ok_or_else(|| invalid("instruction outside a hunk".to_string()))?;
+                    let instruction = parse_instruction(rest).map_err(invalid)?;
+                    if keyword == "-" {
+                        hunk.removed.push(instruction);
+                    } else {
+                        hunk.added.push(instruction);
+                    }
+                }
+                "requires" => {
+                    let [memory, fuel] = parse_numbers::<u64, 2>(rest).map_err(invalid)?;
+                    let memory = usize::try_from(memory).map_err(|_| invalid(format!("memory {} is too large", memory)))?;
+                    patch.requirements = Some(Requirements { memory: (memory > 0).then_some(memory), fuel: (fuel > 0).then_some(fuel) });
+                }
+                "data" => {
+                    let [count] = parse_numbers::<usize, 1>(rest).map_err(invalid)?;
+                    let mut data = Vec::new();
+                    for _ in 0..count {
+                        let (number, line) = lines.next().ok_or_else(|| format!("Invalid patch: {} data block(s) missing at the end", count - data.len()))?;
+                        let mut fields = line.split_whitespace();
+                        let start = fields.next().and_then(|start| start.parse().ok());
+                        let values: Result<Vec<Word>, _> = fields.map(str::parse).collect();
+                        match (start, values) {
+                            (Some(start), Ok(values)) => data.push(DataBlock { start, values }),
+                            _ => return Err(format!("Invalid patch at line {}: expected a data block's start and values", number)),
+                        }
+                    }
+                    patch.data = Some(data);
+                }
+                _ => return Err(invalid(format!("unknown entry '{}'", keyword))),
+            }
+        }
+
+        if !has_base || !has_result {
+            return Err("Invalid patch: missing the base or result hash".to_string());
+        }
+        Ok(patch)
+    }
+
+    /// Writes the patch to `path`.
+    pub fn write_file(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("Failed to write patch '{}': {}", path, e))
+    }
+
+    /// Reads a patch from `path`.
+    pub fn read_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read patch '{}': {}", path, e))?;
+        Self::parse(&text)
+    }
+}
+
+fn parse_hash(text: &str) -> Result<u64, String> {
+    u64::from_str_radix(text, 16).map_err(|_| format!("invalid hash '{}'", text))
+}
+
+/// Parses exactly `N` numbers separated by whitespace.
+fn parse_numbers<T: std::str::FromStr, const N: usize>(text: &str) -> Result<[T; N], String> {
+    let numbers: Vec<T> = text.split_whitespace().map(str::parse).collect::<Result<_, _>>().map_err(|_| format!("invalid number in '{}'", text))?;
+    numbers.try_into().map_err(|_| format!("expected {} number(s), got '{}'", N, text))
+}
+
+/// Parses a single instruction as written by its `Display` implementation.
+fn parse_instruction(text: &str) -> Result<Instruction, String> {
+    let program = try_parse_program(text)?;
+    match <[Instruction; 1]>::try_from(program.instructions) {
+        Ok([instruction]) => Ok(instruction),
+        Err(_) => Err(format!("expected one instruction, got '{}'", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spliter::parse_program;
+
+    #[test]
+    fn test_diff_keeps_common_instructions() {
+        let old = parse_program("PUSH 1\nDUP\nADD\nPRINTINT\nRET").unwrap();
+        let new = parse_program("PUSH 1\nADD\nPUSH 3\nMULT\nPRINTINT\nRET").unwrap();
+        let hunks = diff_instructions(&old.instructions, &new.instructions);
+        assert_eq!(hunks, [
+            Hunk { old_start: 1, new_start: 1, removed: vec![Instruction::Dup], added: Vec::new() },
+            Hunk { old_start: 3, new_start: 2, removed: Vec::new(), added: vec![Instruction::Push(3), Instruction::Mult] },
+        ]);
+        assert_eq!(hunks[1].changes(), [
+            Change::Added { new: 2, instruction: Instruction::Push(3) },
+            Change::Added { new: 3, instruction: Instruction::Mult },
+        ]);
+        assert!(diff_instructions(&old.instructions, &old.instructions).is_empty());
+    }
+
+    #[test]
+    fn test_changes_are_located_by_label() {
+        let old = parse_program("main:\nPUSH 1\nloop:\nSUBS 1\nJNZ loop").unwrap();
+        let new = parse_program("main:\nPUSH 5\nloop:\nSUBS 1\nJNZ loop").unwrap();
+        let hunks = diff_instructions(&old.instructions, &new.instructions);
+        let changes: Vec<String> = hunks.iter().flat_map(Hunk::changes).map(|change| change.describe(&old, &new)).collect();
+        assert_eq!(changes, ["changed 0 (main at line 2): PUSH 1 -> PUSH 5"]);
+    }
+
+    #[test]
+    fn test_patch_round_trip_with_data_and_requirements() {
+        let old = parse_program(".data 0\n.string \"hi\"\n.text\nPRINT 0 2\nHOSTCALL log\nJMP 0").unwrap();
+        let new = parse_program(".requires fuel 100\n.data 0\n.string \"ho\"\n.text\nPRINT 0 2\nMEMWRITE 5 1 2\nHOSTCALL log\nJMP 0").unwrap();
+        let patch = Patch::between(&old, &new).unwrap();
+        assert_eq!(patch.requirements, Some(Requirements { memory: None, fuel: Some(100) }));
+        assert_eq!(Patch::parse(&patch.to_text()).unwrap(), patch);
+
+        let patched = patch.apply(&old).unwrap();
+        assert_eq!((patched.instructions, patched.data), (new.instructions.clone(), new.data.clone()));
+        assert!(Patch::between(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_patch_checks_what_it_applies_to() {
+        let old = parse_program("PUSH 1").unwrap();
+        let new = parse_program("PUSH 2").unwrap();
+        let patch = Patch::between(&old, &new).unwrap();
+        assert_eq!(patch.apply(&new).unwrap_err(), "The patch was made for a different program");
+
+        let damaged = Patch::parse(&patch.to_text().replace("+ PUSH 2", "+ PUSH 3")).unwrap();
+        assert!(damaged.apply(&old).unwrap_err().contains("damaged"));
+
+        assert!(Patch::parse("base 00\n@ 0 0\n+ PUSH 1").unwrap_err().contains("missing"));
+        assert!(Patch::parse("base 00\nresult 00\n+ PUSH 1").unwrap_err().contains("line 3"));
+        assert!(Patch::parse("base 00\nresult 00\n@ 0 0\n+ FLY").is_err());
+    }
+}
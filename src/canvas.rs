@@ -0,0 +1,127 @@
+use crate::instruction::Word;
+use crate::run::{TraceEvent, TraceSink};
+use std::io::Write;
+
+/// Lines of a step's diagram are indented to start below its instruction.
+const STEP_INDENT: &str = "       ";
+
+/// Draws `stack` as a column of cells, the top one first, like the stack diagrams of
+/// textbooks. `note` may return a remark for the value at an index (counted from the
+/// bottom), which is written next to its cell.
+///
+/// ```
+/// use vortex_vm::canvas::draw_stack;
+///
+/// let diagram = draw_stack(&[7, 42], |index| (index == 1).then(|| "top".to_string()));
+/// assert_eq!(diagram, "| 42 | <- top\n|  7 |\n+----+\n");
+/// ```
+pub fn draw_stack(stack: &[Word], note: impl Fn(usize) -> Option<String>) -> String {
+    if stack.is_empty() {
+        return "(empty)\n".to_string();
+    }
+
+    let width = stack.iter().map(|value| value.to_string().len()).max().unwrap_or(1);
+    let mut diagram = String::new();
+    for (index, value) in stack.iter().enumerate().rev() {
+        diagram.push_str(&format!("| {:>width$} |", value, width = width));
+        if let Some(note) = note(index) {
+            diagram.push_str(&format!(" <- {}", note));
+        }
+        diagram.push('\n');
+    }
+    diagram.push_str(&format!("+{}+\n", "-".repeat(width + 2)));
+    diagram
+}
+
+/// Draws the stack after the step in `event`, under the instruction that ran and the
+/// values it took off the stack. The cells it pushed are marked.
+///
+/// ```
+/// use vortex_vm::canvas::draw_step;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::TraceEvent;
+///
+/// let event = TraceEvent { pc: 2, instruction: &Instruction::Add, stack_before: &[1, 4, 3], stack_after: &[1, 7] };
+/// assert_eq!(draw_step(&event), "    2: ADD  (pops 4 3)\n       | 7 | <- top, pushed\n       | 1 |\n       +---+\n");
+/// ```
+pub fn draw_step(event: &TraceEvent) -> String {
+    // Values below the first difference were left alone, the rest were replaced
+    let kept = event.stack_before.iter().zip(event.stack_after).take_while(|(before, after)| before == after).count();
+    let popped = &event.stack_before[kept..];
+
+    let mut diagram = format!("{:>5}: {}", event.pc, event.instruction);
+    if !popped.is_empty() {
+        let values: Vec<String> = popped.iter().map(|value| value.to_string()).collect();
+        diagram.push_str(&format!("  (pops {})", values.join(" ")));
+    }
+    diagram.push('\n');
+
+    let top = event.stack_after.len().saturating_sub(1);
+    let stack = draw_stack(event.stack_after, |index| match (index == top, index >= kept) {
+        (true, true) => Some("top, pushed".to_string()),
+        (true, false) => Some("top".to_string()),
+        (false, true) => Some("pushed".to_string()),
+        (false, false) => None,
+    });
+    for line in stack.lines() {
+        diagram.push_str(STEP_INDENT);
+        diagram.push_str(line);
+        diagram.push('\n');
+    }
+    diagram
+}
+
+/// A [`TraceSink`] that writes a stack diagram of every step to `W`, see [`draw_step`],
+/// for showing how a stack machine works one instruction at a time.
+///
+/// ```
+/// use vortex_vm::canvas::StackCanvas;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::Vm;
+///
+/// let mut canvas = StackCanvas::new(Vec::new());
+/// let mut vm = Vm::new(vec![Instruction::Push(2), Instruction::Dup]);
+/// vm.run_traced(&mut Vec::new(), &mut canvas).unwrap();
+///
+/// let diagrams = String::from_utf8(canvas.into_inner()).unwrap();
+/// assert!(diagrams.ends_with("    1: DUP\n       | 2 | <- top, pushed\n       | 2 |\n       +---+\n\n"));
+/// ```
+#[derive(Debug)]
+pub struct StackCanvas<W> {
+    out: W,
+}
+
+impl<W: Write> StackCanvas<W> {
+    /// Draws to `out`.
+    pub fn new(out: W) -> Self {
+        StackCanvas { out }
+    }
+
+    /// Returns the writer the diagrams went to.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> TraceSink for StackCanvas<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        // A diagram that cannot be written is not worth stopping the program for
+        let _ = writeln!(self.out, "{}", draw_step(event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Instruction;
+
+    #[test]
+    fn test_draw_step_marks_what_changed() {
+        let swap = TraceEvent { pc: 14, instruction: &Instruction::Swap, stack_before: &[5, -30, 2], stack_after: &[5, 2, -30] };
+        let expected = "   14: SWAP  (pops -30 2)\n       | -30 | <- top, pushed\n       |   2 | <- pushed\n       |   5 |\n       +-----+\n";
+        assert_eq!(draw_step(&swap), expected);
+
+        let pop = TraceEvent { pc: 0, instruction: &Instruction::Pop, stack_before: &[9], stack_after: &[] };
+        assert_eq!(draw_step(&pop), "    0: POP  (pops 9)\n       (empty)\n");
+    }
+}
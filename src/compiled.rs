@@ -0,0 +1,273 @@
+use crate::instruction::{Instruction, Word};
+use crate::program::{DataBlock, Program};
+use crate::run::{
+    execute_binary, execute_compare, execute_dup, execute_immediate, execute_load, execute_memread, execute_pick,
+    execute_rot, execute_store, execute_swap, ArithOp, Vm, VmConfig, VmError,
+};
+use std::fmt;
+use std::sync::Arc;
+
+/// An instruction lowered to a closure with its operands baked in. It returns the index of
+/// the next instruction.
+type Op = Box<dyn Fn(&mut Vm) -> Result<usize, VmError> + Send + Sync>;
+
+/// A program whose instructions were lowered once to closures, so running it many times,
+/// as a server re-running templates does, skips decoding and dispatching each instruction
+/// on every step.
+///
+/// Runs behave exactly like [`Vm::run`]: the step limit, strict mode, uninitialized-read
+/// tracking and provenance all still apply. Instructions that print, read input, call the
+/// host or touch the program are not lowered and go through [`Vm::step`].
+///
+/// A compiled program is `Send + Sync`, so one `Arc<CompiledProgram>` can serve VMs on
+/// many threads at once; they all share its instructions instead of copying them.
+///
+/// ```
+/// use vortex_vm::compiled::CompiledProgram;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::program::Program;
+/// use vortex_vm::run::VmConfig;
+///
+/// let program = Program::new(vec![
+///     Instruction::Push(6),
+///     Instruction::SubS(1),
+///     Instruction::Jnz(1),
+///     Instruction::Push(42),
+/// ]);
+/// let compiled = CompiledProgram::new(&program);
+/// for _ in 0..3 {
+///     let mut vm = compiled.vm(VmConfig::default());
+///     compiled.run(&mut vm, &mut Vec::new()).unwrap();
+///     assert_eq!(vm.stack, vec![0, 42]);
+/// }
+/// ```
+pub struct CompiledProgram {
+    instructions: Arc<Vec<Instruction>>,
+    data: Vec<DataBlock>,
+    ops: Vec<Option<Op>>,
+}
+
+impl CompiledProgram {
+    /// Lowers every instruction of `program`.
+    pub fn new(program: &Program) -> Self {
+        let len = program.instructions.len();
+        let ops = program.instructions.iter().enumerate().map(|(pc, instruction)| lower(instruction, pc, len)).collect();
+        CompiledProgram { instructions: Arc::new(program.instructions.clone()), data: program.data.clone(), ops }
+    }
+
+    /// The instructions this was compiled from.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Creates a VM with `config`, ready to [`run`](CompiledProgram::run) this program with
+    /// its data already loaded. The VM shares the compiled program's instructions.
+    pub fn vm(&self, config: VmConfig) -> Vm {
+        let mut vm = Vm::with_config(Arc::clone(&self.instructions), config);
+        vm.load_data(&self.data);
+        vm
+    }
+
+    /// Runs `vm` until it returns, falls off the end, or traps, like [`Vm::run`].
+    ///
+    /// Only VMs from [`CompiledProgram::vm`] use the lowered instructions. Any other VM, or
+    /// one whose program a `CODEWRITE` has changed, is interpreted as usual.
+    pub fn run(&self, vm: &mut Vm, output_buffer: &mut Vec<u8>) -> Result<(), VmError> {
+        while vm.is_running() {
+            // Changing a shared program copies it, so this also catches code writes
+            if !Arc::ptr_eq(&vm.program, &self.instructions) {
+                return vm.run(output_buffer);
+            }
+
+            let pc = vm.pc;
+            match &self.ops[pc] {
+                Some(op) => run_lowered(vm, op, pc).or_else(|error| vm.catch(error))?,
+                None => vm.step(output_buffer)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the lowered instruction at `pc` with the same bookkeeping as [`Vm::step`].
+fn run_lowered(vm: &mut Vm, op: &Op, pc: usize) -> Result<(), VmError> {
+    vm.begin_step(pc)?;
+    let depth_before = vm.stack.len();
+    vm.pc = op(vm)?;
+    vm.finish_step(pc, depth_before)
+}
+
+impl fmt::Debug for CompiledProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompiledProgram")
+            .field("instructions", &self.instructions)
+            .field("data", &self.data)
+            .field("lowered", &self.ops.iter().filter(|op| op.is_some()).count())
+            .finish()
+    }
+}
+
+/// Lowers the instruction at `pc` of a program with `len` instructions, or returns `None`
+/// if it is left to [`Vm::step`].
+fn lower(instruction: &Instruction, pc: usize, len: usize) -> Option<Op> {
+    let next = pc + 1;
+    // Jumps past the end of the program fall through, see `Vm::step`
+    let jump = |target: u32| if target as usize <= len { target as usize } else { next };
+
+    let op: Op = match *instruction {
+        Instruction::Null => Box::new(move |_| Ok(next)),
+        Instruction::Push(value) => Box::new(move |vm| {
+            vm.stack.push(value);
+            Ok(next)
+        }),
+        Instruction::Pop => Box::new(move |vm| {
+            vm.stack.pop();
+            Ok(next)
+        }),
+        Instruction::Dup => Box::new(move |vm| Ok(execute_dup(&mut vm.stack, pc))),
+        Instruction::Swap => Box::new(move |vm| Ok(execute_swap(&mut vm.stack, pc))),
+        Instruction::Rot => Box::new(move |vm| Ok(execute_rot(&mut vm.stack, pc))),
+        Instruction::Over => Box::new(move |vm| Ok(execute_pick(&mut vm.stack, pc, 1))),
+        Instruction::Pick(depth) => Box::new(move |vm| Ok(execute_pick(&mut vm.stack, pc, depth))),
+        Instruction::Depth => Box::new(move |vm| {
+            vm.stack.push(vm.stack.len() as Word);
+            Ok(next)
+        }),
+        Instruction::Clear => Box::new(move |vm| {
+            vm.stack.clear();
+            Ok(next)
+        }),
+        Instruction::Jiz(target) => {
+            let target = jump(target);
+            Box::new(move |vm| Ok(if vm.stack.last() == Some(&0) { target } else { next }))
+        }
+        Instruction::Jnz(target) => {
+            let target = jump(target);
+            Box::new(move |vm| Ok(if vm.stack.last().is_some_and(|&value| value != 0) { target } else { next }))
+        }
+        Instruction::Jmp(target) => {
+            let target = jump(target);
+            Box::new(move |_| Ok(target))
+        }
+        Instruction::AddS(n) => immediate(pc, ArithOp::Add, n),
+        Instruction::SubS(n) => immediate(pc, ArithOp::Sub, n),
+        Instruction::MultS(n) => immediate(pc, ArithOp::Mul, n),
+        Instruction::DivS(n) => immediate(pc, ArithOp::Div, n),
+        Instruction::ModS(n) => immediate(pc, ArithOp::Rem, n),
+        Instruction::Add => binary(pc, ArithOp::Add),
+        Instruction::Sub => binary(pc, ArithOp::Sub),
+        Instruction::Mult => binary(pc, ArithOp::Mul),
+        Instruction::Div => binary(pc, ArithOp::Div),
+        Instruction::Mod => binary(pc, ArithOp::Rem),
+        Instruction::Eq => compare(pc, |b, a| b == a),
+        Instruction::Neq => compare(pc, |b, a| b != a),
+        Instruction::Lt => compare(pc, |b, a| b < a),
+        Instruction::Gt => compare(pc, |b, a| b > a),
+        Instruction::Lte => compare(pc, |b, a| b <= a),
+        Instruction::Gte => compare(pc, |b, a| b >= a),
+        Instruction::MemRead(addr) => Box::new(move |vm| Ok(execute_memread(&mut vm.stack, &vm.mem, pc, addr))),
+        Instruction::Load => Box::new(move |vm| execute_load(&mut vm.stack, &vm.mem, pc)),
+        Instruction::Store => Box::new(move |vm| execute_store(&mut vm.stack, &mut vm.mem, pc)),
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn immediate(pc: usize, op: ArithOp, n: Word) -> Op {
+    Box::new(move |vm| execute_immediate(&mut vm.stack, pc, vm.config.arithmetic, op, n))
+}
+
+fn binary(pc: usize, op: ArithOp) -> Op {
+    Box::new(move |vm| execute_binary(&mut vm.stack, pc, vm.config.arithmetic, op))
+}
+
+fn compare(pc: usize, compare: fn(Word, Word) -> bool) -> Op {
+    Box::new(move |vm| Ok(execute_compare(&mut vm.stack, pc, compare)))
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+    use crate::run::UninitReadMode;
+    use crate::spliter::parse_program;
+
+    const SOURCE: &str = ".data 8\n.string \"ok\"\n.text\nPUSH 5\nloop:\nDUP\nPUSH 0\nOVER\nROT\nPICK 1\nPOP\nPOP\nMEMWRITES 0 1\nPUSH 0\nLOAD\nADD\nPOP\nSUBS 1\nJNZ loop\nCALL show\nPUSH 9\nPUSH 3\nSTORE\nPUSH 3\nLOAD\nHALT 3\nshow:\nPRINTSTR 8\nRET";
+
+    fn interpret(config: VmConfig) -> (Vm, Result<(), VmError>, Vec<u8>) {
+        let program = parse_program(SOURCE).unwrap();
+        let mut vm = Vm::with_config(program.instructions, config);
+        vm.load_data(&program.data);
+        let mut output = Vec::new();
+        let result = vm.run(&mut output);
+        (vm, result, output)
+    }
+
+    #[test]
+    fn test_compiled_runs_match_the_interpreter() {
+        let compiled = CompiledProgram::new(&parse_program(SOURCE).unwrap());
+        let configs = [
+            VmConfig::default(),
+            VmConfig { strict: true, track_provenance: true, ..VmConfig::default() },
+            VmConfig { uninit_reads: UninitReadMode::Trap, ..VmConfig::default() },
+            VmConfig { max_steps: Some(20), ..VmConfig::default() },
+            VmConfig { max_stack_depth: Some(2), ..VmConfig::default() },
+        ];
+
+        for config in configs {
+            let (expected, expected_result, expected_output) = interpret(config.clone());
+
+            let mut vm = compiled.vm(config);
+            let mut output = Vec::new();
+            let result = compiled.run(&mut vm, &mut output);
+
+            assert_eq!(result, expected_result);
+            assert_eq!(output, expected_output);
+            assert_eq!(vm.stack, expected.stack);
+            assert_eq!(vm.mem, expected.mem);
+            assert_eq!((vm.pc, vm.steps, vm.exit_code), (expected.pc, expected.steps, expected.exit_code));
+            assert_eq!(vm.high_water, expected.high_water);
+            assert_eq!(vm.recent_trace(), expected.recent_trace());
+        }
+    }
+
+    #[test]
+    fn test_code_writes_fall_back_to_the_interpreter() {
+        // Rewrites the PUSH 1 at index 4 into PUSH 7 before reaching it
+        let word = crate::encoding::encode_word(&Instruction::Push(7)).unwrap();
+        let source = format!("PUSH 4\nPUSH {}\nCODEWRITE\nNULL\nPUSH 1", word);
+        let compiled = CompiledProgram::new(&parse_program(&source).unwrap());
+
+        let mut vm = compiled.vm(VmConfig { self_modifying: true, ..VmConfig::default() });
+        compiled.run(&mut vm, &mut Vec::new()).unwrap();
+        assert_eq!(vm.stack, vec![7]);
+    }
+
+    #[test]
+    fn test_other_programs_are_interpreted() {
+        let compiled = CompiledProgram::new(&parse_program("PUSH 1").unwrap());
+        let mut vm = Vm::new(vec![Instruction::Push(2), Instruction::Push(3)]);
+        compiled.run(&mut vm, &mut Vec::new()).unwrap();
+        assert_eq!(vm.stack, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_threads_share_one_program() {
+        let compiled = Arc::new(CompiledProgram::new(&parse_program(SOURCE).unwrap()));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let compiled = Arc::clone(&compiled);
+                std::thread::spawn(move || {
+                    let mut vm = compiled.vm(VmConfig::default());
+                    assert!(Arc::ptr_eq(&vm.program, &compiled.instructions));
+                    let mut output = Vec::new();
+                    compiled.run(&mut vm, &mut output).unwrap();
+                    (vm.stack, vm.exit_code, output)
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), (vec![0, 9], Some(3), b"ok".to_vec()));
+        }
+    }
+}
@@ -0,0 +1,829 @@
+use crate::canvas::draw_stack;
+use crate::instruction::{Instruction, Word};
+use crate::program::{DebugInfo, Program};
+use crate::run::{Vm, VmConfig, VmError};
+use crate::spliter::{parse_line, split_instructions, MNEMONICS};
+use regex::bytes::Regex;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The commands understood by [`Debugger::handle_command`].
+pub const COMMANDS: &[&str] = &[
+    "step", "continue", "break", "delete", "break-output", "eval", "eval!", "patch", "nop", "list", "stack", "canvas", "origins",
+    "mem", "dump", "regions", "where", "help", "quit",
+];
+
+/// Maximum number of instructions an `eval` snippet may execute.
+pub const EVAL_STEP_LIMIT: usize = 10_000;
+
+/// The state produced by running an `eval` snippet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalResult {
+    pub stack: Vec<Word>,
+    pub output: Vec<u8>,
+}
+
+/// Why execution stopped and control returned to the debugger.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// A single step completed.
+    Step,
+    /// Execution reached a breakpoint at the given instruction index.
+    Breakpoint(usize),
+    /// Program output started matching a watched pattern.
+    OutputMatch(String),
+    /// The program returned or ran past its last instruction.
+    Halted,
+    /// The program trapped.
+    Trapped(VmError),
+}
+
+/// Output a [`Debugger`] pauses on.
+#[derive(Debug, Clone)]
+enum OutputPattern {
+    Text(String),
+    Regex(Regex),
+}
+
+impl fmt::Display for OutputPattern {
+    /// Formats the pattern as `break-output` takes it: text as is, a regex as `/regex/`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputPattern::Text(text) => write!(f, "{}", text),
+            OutputPattern::Regex(regex) => write!(f, "/{}/", regex.as_str()),
+        }
+    }
+}
+
+/// What [`Debugger::reload`] did with a new version of the program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReloadOutcome {
+    /// The new program is identical to the running one.
+    Unchanged,
+    /// The new program replaced the running one; stack, memory and position were kept.
+    HotSwapped { first_change: usize },
+    /// Code that already ran or will be returned to changed, so the program starts over.
+    Restarted { first_change: usize },
+}
+
+/// An interactive debugger driving a [`Vm`] one instruction at a time.
+///
+/// Commands are plain text lines (see [`Debugger::handle_command`]) so the same
+/// debugger can be driven from the CLI or from tests.
+///
+/// ```
+/// use vortex_vm::debugger::{Debugger, StopReason};
+/// use vortex_vm::spliter::split_instructions;
+///
+/// let program = split_instructions("MemWrite 0 111 107\nPrint 0 2\nPush 1\nRet").unwrap();
+/// let mut debugger = Debugger::new(program);
+/// debugger.break_on_output("ok");
+///
+/// // Pauses right after the Print that produced "ok"
+/// assert_eq!(debugger.continue_execution(), StopReason::OutputMatch("ok".to_string()));
+/// assert_eq!(debugger.vm.pc, 2);
+/// ```
+pub struct Debugger {
+    pub vm: Vm,
+    pub output: Vec<u8>,
+    pub debug_info: DebugInfo,
+    breakpoints: BTreeSet<usize>,
+    output_patterns: Vec<OutputPattern>,
+    trap: Option<VmError>,
+    shown_output: usize,
+    last_stop: Option<StopReason>,
+}
+
+impl Debugger {
+    /// Creates a debugger paused before the first instruction of `program`.
+    pub fn new(program: Vec<Instruction>) -> Self {
+        Self::from_program(Program::new(program))
+    }
+
+    /// Creates a debugger for a program with debug information, so memory regions
+    /// can be referred to by name.
+    pub fn from_program(program: Program) -> Self {
+        Self::with_config(program, VmConfig::default())
+    }
+
+    /// Creates a debugger whose VM runs with the given configuration.
+    ///
+    /// Stack provenance is always tracked so traps can say where their operands came from.
+    pub fn with_config(program: Program, config: VmConfig) -> Self {
+        let config = VmConfig { track_provenance: true, ..config };
+
+        let mut vm = Vm::with_config(program.instructions, config);
+        vm.load_data(&program.data);
+
+        Debugger {
+            vm,
+            output: Vec::new(),
+            debug_info: program.debug_info,
+            breakpoints: BTreeSet::new(),
+            output_patterns: Vec::new(),
+            trap: None,
+            shown_output: 0,
+            last_stop: None,
+        }
+    }
+
+    /// Pauses execution before the instruction at `index` runs.
+    pub fn add_breakpoint(&mut self, index: usize) {
+        self.breakpoints.insert(index);
+    }
+
+    /// Removes a breakpoint, returning whether it existed.
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        self.breakpoints.remove(&index)
+    }
+
+    /// Replaces the instruction at `index`, returning the instruction it replaced.
+    ///
+    /// Patching the instruction a trap occurred at clears the trap so execution can continue.
+    pub fn patch(&mut self, index: usize, instruction: Instruction) -> Result<Instruction, String> {
+        let Some(slot) = self.vm.program_mut().get_mut(index) else {
+            return Err(format!("Instruction index {} is out of range (program has {} instructions)", index, self.vm.program.len()));
+        };
+
+        let previous = std::mem::replace(slot, instruction);
+        if self.trap.as_ref().is_some_and(|trap| trap.pc() == index) {
+            self.trap = None;
+        }
+        Ok(previous)
+    }
+
+    /// Swaps in a new version of the program, e.g. after its source file was edited.
+    ///
+    /// Jump targets are resolved numbers, so any change to the label layout used by
+    /// earlier code shows up as a changed instruction. If nothing up to the next
+    /// instruction or any pending return address changed, the VM keeps running the new
+    /// code with its current stack and memory; otherwise it starts over from the beginning.
+    /// Breakpoints and output patterns are kept either way.
+    ///
+    /// ```
+    /// use vortex_vm::debugger::{Debugger, ReloadOutcome};
+    /// use vortex_vm::spliter::parse_program;
+    ///
+    /// let mut debugger = Debugger::from_program(parse_program("PUSH 6\nMULTS 7\nPRINTINT").unwrap());
+    /// debugger.handle_command("step 2");
+    ///
+    /// // Only the instruction that has not run yet changed, so the result is kept
+    /// let outcome = debugger.reload(parse_program("PUSH 6\nMULTS 7\nDUP\nPRINTINT").unwrap());
+    /// assert_eq!(outcome, ReloadOutcome::HotSwapped { first_change: 2 });
+    /// assert_eq!(debugger.vm.stack, vec![42]);
+    /// ```
+    pub fn reload(&mut self, program: Program) -> ReloadOutcome {
+        let old = &self.vm.program;
+        let first_change = old.iter().zip(&program.instructions).take_while(|(a, b)| a == b).count();
+        if first_change == old.len() && first_change == program.instructions.len() {
+            self.debug_info = program.debug_info;
+            return ReloadOutcome::Unchanged;
+        }
+
+        let resumes_in_prefix = self.vm.pc <= first_change && self.vm.call_stack.iter().all(|&addr| addr <= first_change);
+        if !resumes_in_prefix {
+            let config = self.vm.config.clone();
+            self.vm = Vm::with_config(program.instructions, config);
+            self.vm.load_data(&program.data);
+            self.debug_info = program.debug_info;
+            self.output.clear();
+            self.shown_output = 0;
+            self.trap = None;
+            return ReloadOutcome::Restarted { first_change };
+        }
+
+        self.vm.program = program.instructions.into();
+        self.vm.code_version += 1;
+        self.debug_info = program.debug_info;
+        if self.trap.as_ref().is_some_and(|trap| trap.pc() >= first_change) {
+            self.trap = None;
+        }
+        ReloadOutcome::HotSwapped { first_change }
+    }
+
+    /// Runs an assembly snippet against a copy of the current VM state.
+    ///
+    /// Instructions in `snippet` are separated by newlines or `|`, and jump targets are relative
+    /// to the snippet. When `apply` is true the resulting stack and memory replace the real VM's
+    /// state; otherwise the program being debugged is left untouched.
+    pub fn eval(&mut self, snippet: &str, apply: bool) -> Result<EvalResult, String> {
+        let program = split_instructions(&snippet.replace('|', "\n"))?;
+        if program.is_empty() {
+            return Err("Nothing to evaluate".to_string());
+        }
+
+        let mut scratch = self.vm.clone();
+        scratch.program = program.into();
+        scratch.pc = 0;
+        scratch.halted = false;
+        scratch.call_stack.clear();
+
+        let mut output = Vec::new();
+        let mut steps = 0;
+        while scratch.is_running() {
+            if steps == EVAL_STEP_LIMIT {
+                return Err(format!("Snippet did not finish within {} steps", EVAL_STEP_LIMIT));
+            }
+            scratch.step(&mut output).map_err(|e| format!("Snippet trapped: {}", e))?;
+            steps += 1;
+        }
+
+        if apply {
+            self.vm.replace_stack(scratch.stack.clone());
+            self.vm.mem = scratch.mem;
+        }
+
+        Ok(EvalResult { stack: scratch.stack, output })
+    }
+
+    /// Pauses execution as soon as the program output contains `pattern`.
+    ///
+    /// The check runs after every instruction, so execution stops right after the
+    /// instruction that completed the match.
+    pub fn break_on_output(&mut self, pattern: &str) {
+        self.output_patterns.push(OutputPattern::Text(pattern.to_string()));
+    }
+
+    /// Pauses execution as soon as the program output matches the regular expression
+    /// `pattern`, like [`Debugger::break_on_output`] does for plain text. The stop reason
+    /// names the pattern as `/pattern/`.
+    ///
+    /// ```
+    /// use vortex_vm::debugger::{Debugger, StopReason};
+    /// use vortex_vm::instruction::Instruction;
+    ///
+    /// let program = vec![Instruction::MemWrite(0, vec![0x65, 0x37]), Instruction::Print(0, 2), Instruction::Ret];
+    /// let mut debugger = Debugger::new(program);
+    /// debugger.break_on_output_regex(r"e\d").unwrap();
+    ///
+    /// assert_eq!(debugger.continue_execution(), StopReason::OutputMatch(r"/e\d/".to_string()));
+    /// assert!(debugger.break_on_output_regex("(").is_err());
+    /// ```
+    pub fn break_on_output_regex(&mut self, pattern: &str) -> Result<(), String> {
+        let regex = Regex::new(pattern).map_err(|e| format!("Invalid regular expression: {}", e))?;
+        self.output_patterns.push(OutputPattern::Regex(regex));
+        Ok(())
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> StopReason {
+        if let Some(trap) = &self.trap {
+            return StopReason::Trapped(trap.clone());
+        }
+        if !self.vm.is_running() {
+            return StopReason::Halted;
+        }
+
+        let output_before = self.output.len();
+        if let Err(e) = self.vm.step(&mut self.output) {
+            self.trap = Some(e.clone());
+            return StopReason::Trapped(e);
+        }
+
+        if let Some(pattern) = self.matched_output_pattern(output_before) {
+            return StopReason::OutputMatch(pattern);
+        }
+        if !self.vm.is_running() {
+            return StopReason::Halted;
+        }
+        StopReason::Step
+    }
+
+    /// Runs until a breakpoint, an output match, a trap, or the end of the program.
+    pub fn continue_execution(&mut self) -> StopReason {
+        loop {
+            match self.step() {
+                StopReason::Step => {
+                    if self.breakpoints.contains(&self.vm.pc) {
+                        return StopReason::Breakpoint(self.vm.pc);
+                    }
+                }
+                reason => return reason,
+            }
+        }
+    }
+
+    /// Returns the first watched pattern that appears in output written since `output_before`.
+    fn matched_output_pattern(&self, output_before: usize) -> Option<String> {
+        if self.output.len() == output_before {
+            return None;
+        }
+
+        self.output_patterns
+            .iter()
+            .find(|pattern| match pattern {
+                OutputPattern::Text(text) => {
+                    // Include enough earlier output to catch matches spanning several Prints
+                    let start = output_before.saturating_sub(text.len().saturating_sub(1));
+                    let window = String::from_utf8_lossy(&self.output[start..]);
+                    window.contains(text.as_str())
+                }
+                // A match may start anywhere, but must end in the new output
+                OutputPattern::Regex(regex) => regex.find_iter(&self.output).any(|found| found.end() > output_before),
+            })
+            .map(OutputPattern::to_string)
+    }
+
+    /// Returns the words worth offering for tab completion: commands, region and label
+    /// names, and mnemonics for `patch` and `eval`.
+    pub fn completion_words(&self) -> Vec<String> {
+        COMMANDS
+            .iter()
+            .map(|command| command.to_string())
+            .chain(self.debug_info.regions.iter().map(|region| region.name.clone()))
+            .chain(self.debug_info.labels.iter().map(|(name, _)| name.clone()))
+            .chain(MNEMONICS.iter().map(|mnemonic| mnemonic.to_string()))
+            .collect()
+    }
+
+    /// Handles one debugger command and returns the text to show the user,
+    /// or `None` when the user asked to quit.
+    ///
+    /// Supported commands: `step [n]`, `continue`, `break <index|label>`, `delete <index|label>`,
+    /// `break-output <text>`, `eval <snippet>`, `eval! <snippet>`, `patch <index> <instruction>`,
+    /// `nop <index>`, `list [index]`, `dump <region>`, `regions`,
+    /// `stack`, `canvas`, `origins`, `mem <addr> [len]`, `where`, `help`, `quit`.
+    pub fn handle_command(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        let response = match command {
+            "" => String::new(),
+            "s" | "step" => {
+                let count = if argument.is_empty() { Ok(1) } else { argument.parse::<usize>() };
+                match count {
+                    Ok(count) => {
+                        let mut reason = StopReason::Step;
+                        for _ in 0..count {
+                            reason = self.step();
+                            if reason != StopReason::Step {
+                                break;
+                            }
+                        }
+                        self.describe_stop(&reason)
+                    }
+                    Err(_) => format!("Invalid step count: {}", argument),
+                }
+            }
+            "c" | "continue" => {
+                let reason = self.continue_execution();
+                self.describe_stop(&reason)
+            }
+            "b" | "break" => match self.resolve_location(argument) {
+                Ok(index) => {
+                    self.add_breakpoint(index);
+                    format!("Breakpoint set at instruction {}", index)
+                }
+                Err(e) => e,
+            },
+            "d" | "delete" => match self.resolve_location(argument) {
+                Ok(index) if self.remove_breakpoint(index) => format!("Breakpoint at instruction {} removed", index),
+                Ok(index) => format!("No breakpoint at instruction {}", index),
+                Err(e) => e,
+            },
+            "break-output" => {
+                if argument.is_empty() {
+                    "Usage: break-output <text> or break-output /<regex>/".to_string()
+                } else if let Some(pattern) = argument.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+                    match self.break_on_output_regex(pattern) {
+                        Ok(()) => format!("Will pause when output matches /{}/", pattern),
+                        Err(e) => e,
+                    }
+                } else {
+                    self.break_on_output(argument);
+                    format!("Will pause when output contains \"{}\"", argument)
+                }
+            }
+            "eval" => self.eval_command(argument, false),
+            "eval!" => self.eval_command(argument, true),
+            "patch" => self.patch_command(argument),
+            "nop" => self.patch_command(&format!("{} NULL", argument)),
+            "list" => self.format_listing(argument),
+            "stack" => format!("Stack: {:?}", self.vm.stack),
+            "canvas" => self.draw_canvas(),
+            "origins" => self.format_origins(),
+            "mem" => self.format_memory(argument),
+            "dump" => self.format_region(argument),
+            "regions" => self.format_regions(),
+            "w" | "where" => self.describe_location(),
+            "h" | "help" => HELP.to_string(),
+            "q" | "quit" => return None,
+            _ => format!("Unknown command '{}'. Type 'help' for a list of commands.", command),
+        };
+
+        Some(response)
+    }
+
+    /// Returns why the last `step` or `continue` command stopped, once per command.
+    pub fn take_last_stop(&mut self) -> Option<StopReason> {
+        self.last_stop.take()
+    }
+
+    fn describe_stop(&mut self, reason: &StopReason) -> String {
+        self.last_stop = Some(reason.clone());
+        let mut text = String::new();
+
+        // Show output produced since the last command
+        if self.output.len() > self.shown_output {
+            text.push_str(&format!("Output: {:?}\n", String::from_utf8_lossy(&self.output[self.shown_output..])));
+            self.shown_output = self.output.len();
+        }
+
+        match reason {
+            StopReason::Step => text.push_str(&self.describe_location()),
+            StopReason::Breakpoint(index) => {
+                text.push_str(&format!("Breakpoint hit at instruction {}\n", index));
+                text.push_str(&self.describe_location());
+            }
+            StopReason::OutputMatch(pattern) => {
+                text.push_str(&format!("Output matched \"{}\"\n", pattern));
+                text.push_str(&self.describe_location());
+            }
+            StopReason::Halted => text.push_str(&format!("Program finished. Stack: {:?}", self.vm.stack)),
+            StopReason::Trapped(e) => {
+                text.push_str(&format!("Program trapped: {}", e));
+                if let Some((operand, origin)) = self.vm.trap_operand(e) {
+                    text.push_str(&format!("\n{} pushed at {}", operand, self.debug_info.describe_location(origin)));
+                }
+            }
+        }
+
+        text
+    }
+
+    fn describe_location(&self) -> String {
+        match self.vm.program.get(self.vm.pc) {
+            Some(instruction) if self.vm.is_running() => match self.debug_info.symbol(self.vm.pc) {
+                Some(symbol) => format!("Next: {:>4}: {}  ({})    Stack: {:?}", self.vm.pc, instruction, symbol, self.vm.stack),
+                None => format!("Next: {:>4}: {}    Stack: {:?}", self.vm.pc, instruction, self.vm.stack),
+            },
+            _ => format!("Program finished. Stack: {:?}", self.vm.stack),
+        }
+    }
+
+    fn eval_command(&mut self, snippet: &str, apply: bool) -> String {
+        match self.eval(snippet, apply) {
+            Ok(result) => {
+                let mut text = format!("Stack: {:?}", result.stack);
+                if !result.output.is_empty() {
+                    text.push_str(&format!("\nOutput: {:?}", String::from_utf8_lossy(&result.output)));
+                }
+                if apply {
+                    text.push_str("\n(applied to the program state)");
+                }
+                text
+            }
+            Err(e) => e,
+        }
+    }
+
+    fn patch_command(&mut self, argument: &str) -> String {
+        let (index, source) = argument.split_once(char::is_whitespace).unwrap_or((argument, ""));
+        let Ok(index) = index.parse::<usize>() else {
+            return "Usage: patch <index> <instruction>".to_string();
+        };
+        let Some(instruction) = parse_line(source) else {
+            return format!("Invalid instruction: {}", source.trim());
+        };
+
+        match self.patch(index, instruction) {
+            Ok(previous) => format!("Patched {}: {} -> {}", index, previous, self.vm.program[index]),
+            Err(e) => e,
+        }
+    }
+
+    /// Reads an instruction index, or the name of a label from the debug information.
+    fn resolve_location(&self, argument: &str) -> Result<usize, String> {
+        if let Ok(index) = argument.parse::<usize>() {
+            return Ok(index);
+        }
+        match self.debug_info.labels.iter().find(|(name, _)| name == argument) {
+            Some((_, index)) => Ok(*index),
+            None if self.debug_info.labels.is_empty() => Err(format!("Invalid instruction index: {}", argument)),
+            None => Err(format!("Unknown label or invalid instruction index: {}", argument)),
+        }
+    }
+
+    fn format_listing(&self, argument: &str) -> String {
+        let center = argument.parse::<usize>().unwrap_or(self.vm.pc);
+        let start = center.saturating_sub(3);
+        let end = (center + 4).min(self.vm.program.len());
+
+        (start..end)
+            .map(|index| {
+                let marker = if index == self.vm.pc { "=>" } else { "  " };
+                format!("{} {:>4}: {}", marker, index, self.vm.program[index])
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn format_region(&self, name: &str) -> String {
+        let Some(region) = self.debug_info.region(name) else {
+            return format!("Unknown region '{}'. Type 'regions' to list declared regions.", name);
+        };
+
+        let start = region.start.min(self.vm.mem.len());
+        let end = region.end().min(self.vm.mem.len());
+        format!("Region {} ({}..{}): {:?}", region.name, region.start, region.end(), &self.vm.mem[start..end])
+    }
+
+    fn format_regions(&self) -> String {
+        if self.debug_info.regions.is_empty() {
+            return "No regions declared".to_string();
+        }
+
+        self.debug_info
+            .regions
+            .iter()
+            .map(|region| format!("{:<16} {}..{}", region.name, region.start, region.end()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Draws the stack as a diagram, noting where each value was pushed.
+    fn draw_canvas(&self) -> String {
+        let len = self.vm.stack.len();
+        let diagram = draw_stack(&self.vm.stack, |index| {
+            let origin = self.vm.stack_origin(len - 1 - index).map(|origin| format!("pushed at {}", self.debug_info.describe_location(origin)));
+            match (index + 1 == len, origin) {
+                (true, Some(origin)) => Some(format!("top, {}", origin)),
+                (true, None) => Some("top".to_string()),
+                (false, origin) => origin,
+            }
+        });
+        diagram.trim_end().to_string()
+    }
+
+    fn format_origins(&self) -> String {
+        if self.vm.stack.is_empty() {
+            return "Stack is empty".to_string();
+        }
+
+        (0..self.vm.stack.len())
+            .map(|depth| {
+                let value = self.vm.stack[self.vm.stack.len() - 1 - depth];
+                let origin = match self.vm.stack_origin(depth) {
+                    Some(origin) => format!("pushed at {}", self.debug_info.describe_location(origin)),
+                    None => "origin unknown".to_string(),
+                };
+                format!("[{}] {:<12} {}", depth, value, origin)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn format_memory(&self, argument: &str) -> String {
+        let parts: Vec<&str> = argument.split_whitespace().collect();
+        let (Some(start), len) = (parts.first().and_then(|p| p.parse::<usize>().ok()), parts.get(1)) else {
+            return "Usage: mem <addr> [len]".to_string();
+        };
+        let len = len.and_then(|l| l.parse::<usize>().ok()).unwrap_or(8);
+
+        if start >= self.vm.mem.len() {
+            return format!("Address {} is out of bounds", start);
+        }
+        let end = (start + len).min(self.vm.mem.len());
+        format!("Memory {}..{}: {:?}", start, end, &self.vm.mem[start..end])
+    }
+}
+
+const HELP: &str = "\
+Commands:
+  step [n]            Execute n instructions (default 1)
+  continue            Run until a breakpoint, output match, trap, or the end
+  break <index>       Pause before the instruction at <index> or a label
+  delete <index>      Remove the breakpoint at <index> or a label
+  break-output <text> Pause once the program output contains <text>, or matches
+                      the regular expression <text> written as /<text>/
+  eval <snippet>      Run instructions (separated by '|') on a copy of the state
+  eval! <snippet>     Like eval, but keep the resulting stack and memory
+  patch <index> <ins> Replace the instruction at <index>, e.g. 'patch 3 PUSH 10'
+  nop <index>         Replace the instruction at <index> with NULL
+  list [index]        Show the instructions around <index> (default: next)
+  stack               Show the stack
+  canvas              Draw the stack as a diagram with where each value was pushed
+  origins             Show where each stack value was pushed (top first)
+  mem <addr> [len]    Show memory cells
+  dump <region>       Show the memory of a region declared with .region
+  regions             List declared regions
+  where               Show the next instruction
+  quit                Exit the debugger";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spliter::{parse_program, split_instructions};
+
+    #[test]
+    fn test_breakpoint_pauses_before_instruction() {
+        let program = split_instructions("Push 1\nPush 2\nAdd\nRet").unwrap();
+        let mut debugger = Debugger::new(program);
+        debugger.add_breakpoint(2);
+
+        assert_eq!(debugger.continue_execution(), StopReason::Breakpoint(2));
+        assert_eq!(debugger.vm.stack, vec![1, 2]);
+        assert_eq!(debugger.continue_execution(), StopReason::Halted);
+        assert_eq!(debugger.vm.stack, vec![3]);
+    }
+
+    #[test]
+    fn test_break_on_output_spanning_prints() {
+        // "ab" then "cd": the pattern "bc" only appears once both have been printed
+        let program = split_instructions("
+            MemWrite 0 97 98 99 100
+            Print 0 2
+            Print 2 2
+            Push 7
+            Ret
+        ").unwrap();
+        let mut debugger = Debugger::new(program);
+        debugger.break_on_output("bc");
+
+        assert_eq!(debugger.continue_execution(), StopReason::OutputMatch("bc".to_string()));
+        assert_eq!(debugger.vm.pc, 3);
+        assert_eq!(debugger.output, b"abcd");
+    }
+
+    #[test]
+    fn test_break_on_output_regex() {
+        // "a1" then "b2": the regex matches across both prints, but not in the first alone
+        let program = split_instructions("
+            MemWrite 0 97 49 98 50
+            Print 0 2
+            Print 2 2
+            Ret
+        ").unwrap();
+        let mut debugger = Debugger::new(program);
+        assert_eq!(debugger.handle_command("break-output /[0-9]b[0-9]/").unwrap(), "Will pause when output matches /[0-9]b[0-9]/");
+        assert!(debugger.handle_command("break-output /a(/").unwrap().starts_with("Invalid regular expression"));
+
+        assert_eq!(debugger.continue_execution(), StopReason::OutputMatch("/[0-9]b[0-9]/".to_string()));
+        assert_eq!(debugger.vm.pc, 3);
+
+        // Matches already reported do not stop the program again
+        assert_eq!(debugger.continue_execution(), StopReason::Halted);
+    }
+
+    #[test]
+    fn test_trap_is_sticky() {
+        let program = vec![Instruction::Push(1), Instruction::Call(10)];
+        let mut debugger = Debugger::new(program);
+
+        let reason = debugger.continue_execution();
+        assert!(matches!(reason, StopReason::Trapped(_)));
+        assert_eq!(debugger.step(), reason);
+    }
+
+    #[test]
+    fn test_patch_and_continue() {
+        let program = split_instructions("Push 2\nMultS 3\nRet").unwrap();
+        let mut debugger = Debugger::new(program);
+        debugger.step();
+
+        assert_eq!(
+            debugger.handle_command("patch 1 MULTS 10").unwrap(),
+            "Patched 1: MULTS 3 -> MULTS 10"
+        );
+        assert_eq!(debugger.continue_execution(), StopReason::Halted);
+        assert_eq!(debugger.vm.stack, vec![20]);
+    }
+
+    #[test]
+    fn test_patching_trap_site_allows_continuing() {
+        let program = vec![Instruction::Push(1), Instruction::Call(10), Instruction::Push(2), Instruction::Ret];
+        let mut debugger = Debugger::new(program);
+        assert!(matches!(debugger.continue_execution(), StopReason::Trapped(_)));
+
+        debugger.handle_command("nop 1").unwrap();
+        assert_eq!(debugger.continue_execution(), StopReason::Halted);
+        assert_eq!(debugger.vm.stack, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_patch_rejects_bad_input() {
+        let program = split_instructions("Ret").unwrap();
+        let mut debugger = Debugger::new(program);
+
+        assert!(debugger.patch(5, Instruction::Null).is_err());
+        assert_eq!(debugger.handle_command("patch 0 FROB").unwrap(), "Invalid instruction: FROB");
+        assert_eq!(*debugger.vm.program, vec![Instruction::Ret]);
+    }
+
+    #[test]
+    fn test_eval_runs_on_a_copy() {
+        let program = split_instructions("Push 6\nPush 7\nRet").unwrap();
+        let mut debugger = Debugger::new(program);
+        debugger.handle_command("step 2").unwrap();
+
+        let result = debugger.eval("MULT | MemWriteS 0 1 | MemRead 0", false).unwrap();
+        assert_eq!(result.stack, vec![42]);
+        assert_eq!(debugger.vm.stack, vec![6, 7]);
+        assert_eq!(debugger.vm.mem[0], 0);
+        assert_eq!(debugger.vm.pc, 2);
+    }
+
+    #[test]
+    fn test_eval_apply_updates_state() {
+        let program = split_instructions("Push 6\nRet").unwrap();
+        let mut debugger = Debugger::new(program);
+        debugger.step();
+
+        assert_eq!(debugger.handle_command("eval! AddS 1").unwrap(), "Stack: [7]\n(applied to the program state)");
+        assert_eq!(debugger.vm.stack, vec![7]);
+        assert_eq!(debugger.vm.pc, 1);
+    }
+
+    #[test]
+    fn test_eval_stops_runaway_snippets() {
+        let mut debugger = Debugger::new(split_instructions("Ret").unwrap());
+        let err = debugger.eval("Push 1 | Jnz 0", false).unwrap_err();
+        assert!(err.contains("did not finish"));
+    }
+
+    #[test]
+    fn test_dump_named_region() {
+        let program = parse_program(".region pair 4 2\nMemWrite 4 9 8\nRet").unwrap();
+        let mut debugger = Debugger::from_program(program);
+        debugger.continue_execution();
+
+        assert_eq!(debugger.handle_command("dump pair").unwrap(), "Region pair (4..6): [9, 8]");
+        assert!(debugger.handle_command("dump nope").unwrap().starts_with("Unknown region"));
+        assert!(debugger.handle_command("regions").unwrap().starts_with("pair"));
+    }
+
+    #[test]
+    fn test_trap_reports_operand_origin() {
+        let program = parse_program("Push 7\nPush 99\n\nSwap\nJmpDyn").unwrap();
+        let mut debugger = Debugger::from_program(program);
+
+        let text = debugger.handle_command("continue").unwrap();
+        assert_eq!(text, "Program trapped: invalid jump target 7 at instruction 3\njump target pushed at line 1");
+        assert_eq!(debugger.handle_command("origins").unwrap(), "[0] 7            pushed at line 1\n[1] 99           pushed at line 2");
+        assert_eq!(debugger.handle_command("canvas").unwrap(), "|  7 | <- top, pushed at line 1\n| 99 | <- pushed at line 2\n+----+");
+    }
+
+    #[test]
+    fn test_handle_commands() {
+        let program = split_instructions("Push 5\nDup\nRet").unwrap();
+        let mut debugger = Debugger::new(program);
+
+        assert_eq!(debugger.handle_command("step 2").unwrap(), "Next:    2: RET    Stack: [5, 5]");
+        assert_eq!(debugger.handle_command("stack").unwrap(), "Stack: [5, 5]");
+        assert_eq!(debugger.handle_command("mem 0 2").unwrap(), "Memory 0..2: [0, 0]");
+        assert!(debugger.handle_command("bogus").unwrap().starts_with("Unknown command"));
+        assert_eq!(debugger.handle_command("quit"), None);
+    }
+
+    #[test]
+    fn test_labels_name_locations() {
+        let mut debugger = Debugger::from_program(parse_program("PUSH 2\nloop:\nSUBS 1\nJNZ loop").unwrap());
+        assert_eq!(debugger.handle_command("step").unwrap(), "Next:    1: SUBS 1  (loop at line 3)    Stack: [2]");
+
+        assert_eq!(debugger.handle_command("break loop").unwrap(), "Breakpoint set at instruction 1");
+        assert_eq!(debugger.handle_command("step").unwrap(), "Next:    2: JNZ 1  (loop+1 at line 4)    Stack: [1]");
+        assert_eq!(debugger.handle_command("continue").unwrap(), "Breakpoint hit at instruction 1\nNext:    1: SUBS 1  (loop at line 3)    Stack: [1]");
+        assert_eq!(debugger.handle_command("delete loop").unwrap(), "Breakpoint at instruction 1 removed");
+        assert_eq!(debugger.handle_command("break main").unwrap(), "Unknown label or invalid instruction index: main");
+    }
+
+    #[test]
+    fn test_last_stop_is_reported_once() {
+        let mut debugger = Debugger::new(split_instructions("Push 1\nPush 2\nRet").unwrap());
+        debugger.handle_command("break 1");
+        debugger.handle_command("continue");
+
+        assert_eq!(debugger.take_last_stop(), Some(StopReason::Breakpoint(1)));
+        assert_eq!(debugger.take_last_stop(), None);
+        debugger.handle_command("stack");
+        assert_eq!(debugger.take_last_stop(), None);
+    }
+
+    #[test]
+    fn test_reload_restarts_when_executed_code_changed() {
+        let mut debugger = Debugger::from_program(parse_program("PUSH 1\nloop:\nSUBS 1\nJNZ loop\nRET").unwrap());
+        debugger.handle_command("step 2");
+
+        // The loop body already ran, so a new decrement cannot be swapped in
+        let outcome = debugger.reload(parse_program("PUSH 1\nloop:\nSUBS 2\nJNZ loop\nRET").unwrap());
+        assert_eq!(outcome, ReloadOutcome::Restarted { first_change: 1 });
+        assert_eq!((debugger.vm.pc, debugger.vm.stack.len()), (0, 0));
+    }
+
+    #[test]
+    fn test_reload_keeps_state_for_pending_code() {
+        let mut debugger = Debugger::new(split_instructions("PUSH 3\nCALL 4\nPRINTINT\nRET\nDUP\nADD\nRET").unwrap());
+        debugger.add_breakpoint(5);
+        debugger.continue_execution();
+
+        // The return address (2) lies before the change, so the call can finish
+        let outcome = debugger.reload(parse_program("PUSH 3\nCALL 4\nPRINTINT\nRET\nDUP\nMULT\nRET").unwrap());
+        assert_eq!(outcome, ReloadOutcome::HotSwapped { first_change: 5 });
+        assert_eq!(debugger.continue_execution(), StopReason::Halted);
+        assert_eq!(debugger.output, b"9");
+
+        let same = debugger.vm.program.to_vec();
+        assert_eq!(debugger.reload(Program::new(same)), ReloadOutcome::Unchanged);
+    }
+}
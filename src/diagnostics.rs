@@ -0,0 +1,143 @@
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Environment variable that sets the diagnostics level when no `-v` flag is given.
+pub const LOG_ENV_VAR: &str = "VORTEX_LOG";
+
+/// How much the loader, assembler and interpreter report on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Problems that stop the current operation.
+    Error = 0,
+    /// Suspicious input that is ignored or worked around (the default).
+    Warn = 1,
+    /// Progress of the loader and assembler.
+    Info = 2,
+    /// Details such as sizes and label tables.
+    Debug = 3,
+    /// Every executed instruction.
+    Trace = 4,
+}
+
+impl Level {
+    /// Parses a level name as used in `VORTEX_LOG` (case-insensitive).
+    ///
+    /// ```
+    /// use vortex_vm::diagnostics::Level;
+    ///
+    /// assert_eq!(Level::parse("Debug"), Some(Level::Debug));
+    /// assert_eq!(Level::parse("loud"), None);
+    /// ```
+    pub fn parse(name: &str) -> Option<Level> {
+        match name.trim().to_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns the level selected by `-v` repeated `count` times.
+    pub fn from_verbosity(count: usize) -> Level {
+        match count {
+            0 => Level::Warn,
+            1 => Level::Info,
+            2 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    fn from_u8(value: u8) -> Level {
+        match value {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            3 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "Error",
+            Level::Warn => "Warning",
+            Level::Info => "Info",
+            Level::Debug => "Debug",
+            Level::Trace => "Trace",
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Sets the most detailed level that is reported.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Returns the most detailed level that is reported.
+pub fn level() -> Level {
+    Level::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Returns true if messages at `level` are reported.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Sets the level from `VORTEX_LOG`, warning about values it does not understand.
+pub fn init_from_env() {
+    if let Ok(value) = env::var(LOG_ENV_VAR) {
+        match Level::parse(&value) {
+            Some(level) => set_level(level),
+            None => eprintln!("Warning: Ignoring unknown {} level '{}'", LOG_ENV_VAR, value),
+        }
+    }
+}
+
+/// Writes a message to stderr, prefixed with its level. Use [`diagnostic!`](crate::diagnostic)
+/// so the message is only formatted when its level is enabled.
+pub fn emit(level: Level, message: fmt::Arguments) {
+    eprintln!("{}: {}", level.prefix(), message);
+}
+
+/// Reports a diagnostic at the given [`Level`] if that level is enabled.
+///
+/// ```
+/// use vortex_vm::diagnostic;
+/// use vortex_vm::diagnostics::Level;
+///
+/// diagnostic!(Level::Info, "Loaded {} instructions", 12);
+/// ```
+#[macro_export]
+macro_rules! diagnostic {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::diagnostics::enabled($level) {
+            $crate::diagnostics::emit($level, format_args!($($arg)*));
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_are_ordered_by_detail() {
+        assert!(Level::Error < Level::Warn && Level::Debug < Level::Trace);
+        assert_eq!(Level::from_verbosity(0), Level::Warn);
+        assert_eq!(Level::from_verbosity(2), Level::Debug);
+        assert_eq!(Level::from_verbosity(7), Level::Trace);
+    }
+
+    #[test]
+    fn test_level_names_round_trip() {
+        for level in [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+            assert_eq!(Level::parse(&format!("{:?}", level)), Some(level));
+            assert_eq!(Level::from_u8(level as u8), level);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::run::{Vm, VmError};
+use crate::instruction::{Instruction, Word};
+use std::fmt;
+use std::fs;
+
+/// Number of memory cells captured around the faulting address.
+pub const MEMORY_WINDOW: usize = 16;
+
+const DUMP_HEADER: &str = "VORTEX-VM CRASH DUMP";
+
+/// A snapshot of the VM state at the moment a program trapped.
+///
+/// Dumps are stored as a line-based `key: value` text file so they can be attached to
+/// bug reports and read back with `vortex-vm inspect-dump`.
+///
+/// ```
+/// use vortex_vm::dump::CrashDump;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::Vm;
+///
+/// let mut vm = Vm::new(vec![Instruction::Push(1), Instruction::Call(9)]);
+/// let err = vm.run(&mut Vec::new()).unwrap_err();
+///
+/// let dump = CrashDump::capture(&vm, &err);
+/// assert_eq!(dump.pc, 1);
+/// assert_eq!(dump.instruction.as_deref(), Some("CALL 9"));
+/// assert_eq!(CrashDump::parse(&dump.to_text()).unwrap(), dump);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrashDump {
+    pub error: String,
+    pub pc: usize,
+    pub instruction: Option<String>,
+    pub source_line: Option<String>,
+    pub stack: Vec<Word>,
+    pub call_stack: Vec<usize>,
+    pub memory_start: usize,
+    pub memory: Vec<Word>,
+    pub trace: Vec<(usize, String)>,
+}
+
+impl CrashDump {
+    /// Captures the state of `vm` after it stopped with `error`.
+    pub fn capture(vm: &Vm, error: &VmError) -> Self {
+        let pc = error.pc();
+        let instruction = vm.program.get(pc);
+
+        let memory_start = instruction
+            .and_then(memory_operand)
+            .unwrap_or(0)
+            .min(vm.mem.len().saturating_sub(MEMORY_WINDOW));
+        let memory_end = (memory_start + MEMORY_WINDOW).min(vm.mem.len());
+
+        let trace = vm
+            .recent_trace()
+            .into_iter()
+            .map(|i| (i, vm.program.get(i).map(|ins| ins.to_string()).unwrap_or_default()))
+            .collect();
+
+        CrashDump {
+            error: error.to_string(),
+            pc,
+            instruction: instruction.map(|ins| ins.to_string()),
+            source_line: None,
+            stack: vm.stack.clone(),
+            call_stack: vm.call_stack.clone(),
+            memory_start,
+            memory: vm.mem[memory_start..memory_end].to_vec(),
+            trace,
+        }
+    }
+
+    /// Attaches the source location of the faulting instruction, e.g. `prog.vvm:12: Call foo`.
+    pub fn with_source_line(mut self, source_line: String) -> Self {
+        self.source_line = Some(source_line);
+        self
+    }
+
+    /// Serializes the dump to its text file format.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        text.push_str(DUMP_HEADER);
+        text.push('\n');
+        text.push_str(&format!("error: {}\n", self.error));
+        text.push_str(&format!("pc: {}\n", self.pc));
+        if let Some(instruction) = &self.instruction {
+            text.push_str(&format!("instruction: {}\n", instruction));
+        }
+        if let Some(source_line) = &self.source_line {
+            text.push_str(&format!("source: {}\n", source_line));
+        }
+        text.push_str(&format!("stack: {}\n", join(&self.stack)));
+        text.push_str(&format!("call_stack: {}\n", join(&self.call_stack)));
+        text.push_str(&format!("memory_start: {}\n", self.memory_start));
+        text.push_str(&format!("memory: {}\n", join(&self.memory)));
+        for (index, instruction) in &self.trace {
+            text.push_str(&format!("trace: {} {}\n", index, instruction));
+        }
+        text
+    }
+
+    /// Parses a dump previously produced by [`CrashDump::to_text`].
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(DUMP_HEADER) {
+            return Err("Not a Vortex VM crash dump".to_string());
+        }
+
+        let mut dump = CrashDump {
+            error: String::new(),
+            pc: 0,
+            instruction: None,
+            source_line: None,
+            stack: Vec::new(),
+            call_stack: Vec::new(),
+            memory_start: 0,
+            memory: Vec::new(),
+            trace: Vec::new(),
+        };
+
+        for line in lines {
+            let Some((key, value)) = line.split_once(": ").or_else(|| line.strip_suffix(':').map(|k| (k, ""))) else {
+                return Err(format!("Malformed crash dump line: {}", line));
+            };
+
+            match key {
+                "error" => dump.error = value.to_string(),
+                "pc" => dump.pc = parse_number(key, value)?,
+                "instruction" => dump.instruction = Some(value.to_string()),
+                "source" => dump.source_line = Some(value.to_string()),
+                "stack" => dump.stack = parse_list(key, value)?,
+                "call_stack" => dump.call_stack = parse_list(key, value)?,
+                "memory_start" => dump.memory_start = parse_number(key, value)?,
+                "memory" => dump.memory = parse_list(key, value)?,
+                "trace" => {
+                    let (index, instruction) = value.split_once(' ').unwrap_or((value, ""));
+                    dump.trace.push((parse_number(key, index)?, instruction.to_string()));
+                }
+                _ => return Err(format!("Unknown crash dump field: {}", key)),
+            }
+        }
+
+        Ok(dump)
+    }
+
+    /// Writes the dump to `path`.
+    pub fn write_file(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("Failed to write crash dump '{}': {}", path, e))
+    }
+
+    /// Reads a dump from `path`.
+    pub fn read_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read crash dump '{}': {}", path, e))?;
+        Self::parse(&text)
+    }
+}
+
+impl fmt::Display for CrashDump {
+    /// Pretty-prints the dump for humans.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Trap: {}", self.error)?;
+        match &self.instruction {
+            Some(instruction) => writeln!(f, "At instruction {}: {}", self.pc, instruction)?,
+            None => writeln!(f, "At instruction {}", self.pc)?,
+        }
+        if let Some(source_line) = &self.source_line {
+            writeln!(f, "Source: {}", source_line)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Stack (top first):")?;
+        if self.stack.is_empty() {
+            writeln!(f, "    <empty>")?;
+        }
+        for (depth, value) in self.stack.iter().rev().enumerate() {
+            writeln!(f, "    [{}] {}", depth, value)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Call stack (innermost first):")?;
+        if self.call_stack.is_empty() {
+            writeln!(f, "    <empty>")?;
+        }
+        for return_addr in self.call_stack.iter().rev() {
+            writeln!(f, "    return to {}", return_addr)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Memory {}..{}:", self.memory_start, self.memory_start + self.memory.len())?;
+        for (row, chunk) in self.memory.chunks(8).enumerate() {
+            writeln!(f, "    {:>5}: {}", self.memory_start + row * 8, join(chunk))?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Recent instructions (oldest first):")?;
+        for (index, instruction) in &self.trace {
+            let marker = if *index == self.pc { "=>" } else { "  " };
+            writeln!(f, "  {} {:>5}: {}", marker, index, instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the memory address an instruction operates on, if any.
+fn memory_operand(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::MemWrite(addr, _)
+        | Instruction::MemWriteS(addr, _)
+        | Instruction::MemRead(addr)
+        | Instruction::MemCopy(addr, _, _)
+        | Instruction::MemSet(addr, _, _)
+        | Instruction::MemCmp(addr, _, _)
+        | Instruction::Protect(addr, _)
+        | Instruction::Print(addr, _)
+        | Instruction::Abort(addr, _) => usize::try_from(*addr).ok(),
+        _ => None,
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_number(key: &str, value: &str) -> Result<usize, String> {
+    value.trim().parse().map_err(|_| format!("Invalid {} in crash dump: {}", key, value))
+}
+
+fn parse_list<T: std::str::FromStr>(key: &str, value: &str) -> Result<Vec<T>, String> {
+    value
+        .split_whitespace()
+        .map(|v| v.parse().map_err(|_| format!("Invalid {} in crash dump: {}", key, value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trapped_vm(program: Vec<Instruction>) -> (Vm, VmError) {
+        let mut vm = Vm::new(program);
+        let mut output = Vec::new();
+        let err = vm.run(&mut output).unwrap_err();
+        (vm, err)
+    }
+
+    #[test]
+    fn test_capture_records_state() {
+        let (vm, err) = trapped_vm(vec![
+            Instruction::Push(4),
+            Instruction::Push(2),
+            Instruction::Call(7),
+        ]);
+        let dump = CrashDump::capture(&vm, &err);
+
+        assert_eq!(dump.pc, 2);
+        assert_eq!(dump.instruction.as_deref(), Some("CALL 7"));
+        assert_eq!(dump.stack, vec![4, 2]);
+        assert_eq!(dump.memory_start, 0);
+        assert_eq!(dump.memory.len(), MEMORY_WINDOW);
+        assert_eq!(dump.trace, vec![
+            (0, "PUSH 4".to_string()),
+            (1, "PUSH 2".to_string()),
+            (2, "CALL 7".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let (vm, err) = trapped_vm(vec![Instruction::Call(3)]);
+        let dump = CrashDump::capture(&vm, &err).with_source_line("prog.vvm:4: Call 3".to_string());
+
+        let parsed = CrashDump::parse(&dump.to_text()).unwrap();
+        assert_eq!(parsed, dump);
+    }
+
+    #[test]
+    fn test_parse_rejects_other_files() {
+        assert!(CrashDump::parse("PUSH 1\nRET").is_err());
+    }
+
+    #[test]
+    fn test_pretty_print_marks_faulting_instruction() {
+        let (vm, err) = trapped_vm(vec![Instruction::Push(1), Instruction::Call(5)]);
+        let text = CrashDump::capture(&vm, &err).to_string();
+
+        assert!(text.contains("Trap: invalid call target '5' at instruction 1"));
+        assert!(text.contains("=>     1: CALL 5"));
+    }
+}
@@ -0,0 +1,752 @@
+use crate::instruction::{Instruction, Word};
+use std::io::Write;
+
+/// Encodes an instruction as a single word for `CODEREAD`: its opcode in bits 32..40 and
+/// its operand, if any, in the low 32 bits.
+///
+/// Only instructions without operands or with a single 32-bit operand fit; others return `None`.
+///
+/// ```
+/// use vortex_vm::encoding::{decode_word, encode_word};
+/// use vortex_vm::instruction::Instruction;
+///
+/// let word = encode_word(&Instruction::Push(-3)).unwrap();
+/// assert_eq!(decode_word(word), Ok(Instruction::Push(-3)));
+/// assert_eq!(encode_word(&Instruction::Print(0, 2)), None);
+/// ```
+pub fn encode_word(instruction: &Instruction) -> Option<Word> {
+    if matches!(instruction, Instruction::HostCall(_)) {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    serialize_instruction(instruction, &mut bytes).ok()?;
+    match bytes.len() {
+        1 => Some(Word::from(bytes[0]) << 32),
+        5 => {
+            let operand = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+            Some((Word::from(bytes[0]) << 32) | Word::from(operand))
+        }
+        _ => None,
+    }
+}
+
+/// Decodes a word written by `CODEWRITE` back into an instruction, see [`encode_word`].
+pub fn decode_word(word: Word) -> Result<Instruction, String> {
+    let opcode = u8::try_from(word >> 32).map_err(|_| format!("Invalid instruction word 0x{:X}", word))?;
+    let operand = (word as u32).to_le_bytes();
+
+    let mut bytes = vec![opcode];
+    bytes.extend_from_slice(&operand);
+    let (instruction, consumed) = deserialize_instruction(&bytes)?;
+
+    let fits = match consumed {
+        1 => operand == [0; 4],
+        5 => !matches!(instruction, Instruction::HostCall(_)),
+        _ => false,
+    };
+    if !fits {
+        return Err(format!("Instruction word 0x{:X} does not encode a single instruction", word));
+    }
+    Ok(instruction)
+}
+
+
+/// Serializes a single instruction to binary format
+pub(crate) fn serialize_instruction(instruction: &Instruction, output: &mut Vec<u8>) -> Result<(), String> {
+    match instruction {
+        Instruction::Null => {
+            output.write_all(&[0x00]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Push(value) => {
+            // Values that fit in 32 bits keep the original compact encoding
+            if let Ok(narrow) = i32::try_from(*value) {
+                output.write_all(&[0x01]).map_err(|e| format!("Write error: {}", e))?;
+                output.write_all(&narrow.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            } else {
+                output.write_all(&[0x1F]).map_err(|e| format!("Write error: {}", e))?;
+                output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            }
+        }
+        Instruction::Dup => {
+            output.write_all(&[0x02]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Swap => {
+            output.write_all(&[0x03]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Rot => {
+            output.write_all(&[0x33]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Over => {
+            output.write_all(&[0x34]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Pick(depth) => {
+            output.write_all(&[0x35]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&depth.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Depth => {
+            output.write_all(&[0x36]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Clear => {
+            output.write_all(&[0x37]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Pop => {
+            output.write_all(&[0x04]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Ret => {
+            output.write_all(&[0x05]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Halt(code) => {
+            output.write_all(&[0x21]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&code.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Abort(addr, len) => {
+            output.write_all(&[0x42]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Jiz(target) => {
+            output.write_all(&[0x06]).map_err(|e| format!("Write error: {}", e))?;
+            serialize_target(*target, output)?;
+        }
+        Instruction::Jnz(target) => {
+            output.write_all(&[0x07]).map_err(|e| format!("Write error: {}", e))?;
+            serialize_target(*target, output)?;
+        }
+        Instruction::Jmp(target) => {
+            output.write_all(&[0x1D]).map_err(|e| format!("Write error: {}", e))?;
+            serialize_target(*target, output)?;
+        }
+        Instruction::Try(target) => {
+            output.write_all(&[0x3B]).map_err(|e| format!("Write error: {}", e))?;
+            serialize_target(*target, output)?;
+        }
+        Instruction::EndTry => {
+            output.write_all(&[0x3C]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Throw => {
+            output.write_all(&[0x3D]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::JmpDyn => {
+            output.write_all(&[0x1E]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Call(target) => {
+            output.write_all(&[0x14]).map_err(|e| format!("Write error: {}", e))?;
+            serialize_target(*target, output)?;
+        }
+        Instruction::HostCall(name) => {
+            output.write_all(&[0x20]).map_err(|e| format!("Write error: {}", e))?;
+            serialize_name(name, output)?;
+        }
+        Instruction::AddS(value) => serialize_immediate(0x08, 0x44, *value, output)?,
+        Instruction::Add => {
+            output.write_all(&[0x09]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::SubS(value) => serialize_immediate(0x0A, 0x45, *value, output)?,
+        Instruction::Sub => {
+            output.write_all(&[0x0B]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::MultS(value) => serialize_immediate(0x0C, 0x46, *value, output)?,
+        Instruction::Mult => {
+            output.write_all(&[0x0D]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::DivS(value) => serialize_immediate(0x0E, 0x47, *value, output)?,
+        Instruction::Div => {
+            output.write_all(&[0x0F]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::ModS(value) => serialize_immediate(0x1B, 0x48, *value, output)?,
+        Instruction::Mod => {
+            output.write_all(&[0x1C]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Eq => {
+            output.write_all(&[0x15]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Neq => {
+            output.write_all(&[0x16]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Lt => {
+            output.write_all(&[0x17]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Gt => {
+            output.write_all(&[0x18]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Lte => {
+            output.write_all(&[0x19]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Gte => {
+            output.write_all(&[0x1A]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::MemWrite(addr, values) => {
+            // Values that all fit in 32 bits keep the original four bytes each
+            let narrow: Option<Vec<i32>> = values.iter().map(|&value| i32::try_from(value).ok()).collect();
+            output.write_all(&[if narrow.is_some() { 0x10 } else { 0x49 }]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            let len = values.len() as u32;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            match narrow {
+                Some(narrow) => {
+                    for value in narrow {
+                        output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+                    }
+                }
+                None => {
+                    for value in values {
+                        output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+                    }
+                }
+            }
+        }
+        Instruction::MemWriteS(addr, len) => {
+            output.write_all(&[0x11]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::MemRead(addr) => {
+            output.write_all(&[0x12]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Load => {
+            output.write_all(&[0x29]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Store => {
+            output.write_all(&[0x2A]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::LoadLocal(index) => {
+            output.write_all(&[0x38]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&index.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::StoreLocal(index) => {
+            output.write_all(&[0x39]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&index.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::MemCopy(dst, src, len) => {
+            output.write_all(&[0x2B]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&dst.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&src.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::MemSet(addr, len, value) => {
+            let narrow = i32::try_from(*value);
+            output.write_all(&[if narrow.is_ok() { 0x2C } else { 0x4A }]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            match narrow {
+                Ok(narrow) => output.write_all(&narrow.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?,
+                Err(_) => output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?,
+            }
+        }
+        Instruction::MemCmp(lhs, rhs, len) => {
+            output.write_all(&[0x2D]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&lhs.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&rhs.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Grow(pages) => {
+            output.write_all(&[0x2E]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&pages.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::MemSize => {
+            output.write_all(&[0x2F]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Alloc => {
+            output.write_all(&[0x30]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Free => {
+            output.write_all(&[0x31]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Protect(addr, len) => {
+            output.write_all(&[0x32]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Print(addr, len) => {
+            output.write_all(&[0x13]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::PrintInt => {
+            output.write_all(&[0x26]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::PerfRead(counter) => {
+            output.write_all(&[0x28]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&counter.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::VmInfo(field) => {
+            output.write_all(&[0x43]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&field.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::ProfBegin(phase) => {
+            output.write_all(&[0x3E]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&phase.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::ProfEnd(phase) => {
+            output.write_all(&[0x3F]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&phase.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::Assert(value) => {
+            output.write_all(&[0x40]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::AssertEq => {
+            output.write_all(&[0x41]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::PrintStr(addr) => {
+            output.write_all(&[0x27]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::ReadLine(addr, max_len) => {
+            output.write_all(&[0x24]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&addr.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&max_len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::ReadInt => {
+            output.write_all(&[0x25]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::CodeRead => {
+            output.write_all(&[0x22]).map_err(|e| format!("Write error: {}", e))?;
+        }
+        Instruction::CodeWrite => {
+            output.write_all(&[0x23]).map_err(|e| format!("Write error: {}", e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deserializes a single instruction from binary format
+pub(crate) fn deserialize_instruction(bytes: &[u8]) -> Result<(Instruction, usize), String> {
+    if bytes.is_empty() {
+        return Err("Empty bytecode".to_string());
+    }
+
+    let opcode = bytes[0];
+    let mut offset = 1;
+
+    match opcode {
+        0x00 => Ok((Instruction::Null, offset)),
+        0x01 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete Push instruction".to_string());
+            }
+            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::Push(Word::from(value)), offset))
+        }
+        0x02 => Ok((Instruction::Dup, offset)),
+        0x03 => Ok((Instruction::Swap, offset)),
+        0x04 => Ok((Instruction::Pop, offset)),
+        0x05 => Ok((Instruction::Ret, offset)),
+        0x06 => {
+            let target = deserialize_target(&bytes[offset..], "Jiz")?;
+            offset += 4;
+            Ok((Instruction::Jiz(target), offset))
+        }
+        0x07 => {
+            let target = deserialize_target(&bytes[offset..], "Jnz")?;
+            offset += 4;
+            Ok((Instruction::Jnz(target), offset))
+        }
+        0x08 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete AddS instruction".to_string());
+            }
+            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::AddS(Word::from(value)), offset))
+        }
+        0x09 => Ok((Instruction::Add, offset)),
+        0x0A => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete SubS instruction".to_string());
+            }
+            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::SubS(Word::from(value)), offset))
+        }
+        0x0B => Ok((Instruction::Sub, offset)),
+        0x0C => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete MultS instruction".to_string());
+            }
+            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::MultS(Word::from(value)), offset))
+        }
+        0x0D => Ok((Instruction::Mult, offset)),
+        0x0E => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete DivS instruction".to_string());
+            }
+            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::DivS(Word::from(value)), offset))
+        }
+        0x0F => Ok((Instruction::Div, offset)),
+        0x10 => {
+            if bytes.len() < offset + 12 {
+                return Err("Incomplete MemWrite instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            let len = u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+
+            let mut values = Vec::new();
+            for _ in 0..len {
+                if bytes.len() < offset + 4 {
+                    return Err("Incomplete MemWrite values".to_string());
+                }
+                let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+                values.push(Word::from(value));
+                offset += 4;
+            }
+            Ok((Instruction::MemWrite(addr, values), offset))
+        }
+        // MemWrite with a value that needs 64 bits, written by `serialize_instruction`
+        0x49 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete MemWrite instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            let len = u32::from_le_bytes([bytes[offset + 4], bytes[offset + 5], bytes[offset + 6], bytes[offset + 7]]) as usize;
+            offset += 8;
+
+            let size = len.checked_mul(8).ok_or_else(|| "Incomplete MemWrite values".to_string())?;
+            let values = bytes.get(offset..offset + size).ok_or_else(|| "Incomplete MemWrite values".to_string())?;
+            let values = values.chunks_exact(8).map(|value| Word::from_le_bytes(value.try_into().unwrap())).collect();
+            offset += size;
+            Ok((Instruction::MemWrite(addr, values), offset))
+        }
+        // MemWrite with one byte per value, written by `serialize_instructions`
+        0x3A => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete MemWrite instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            let len = u32::from_le_bytes([bytes[offset + 4], bytes[offset + 5], bytes[offset + 6], bytes[offset + 7]]) as usize;
+            offset += 8;
+
+            let values = bytes.get(offset..offset + len).ok_or_else(|| "Incomplete MemWrite values".to_string())?;
+            let values = values.iter().map(|&byte| Word::from(byte)).collect();
+            offset += len;
+            Ok((Instruction::MemWrite(addr, values), offset))
+        }
+        0x11 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete MemWriteS instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            let len = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::MemWriteS(addr, len), offset))
+        }
+        0x12 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete MemRead instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::MemRead(addr), offset))
+        }
+        0x13 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete Print instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            let len = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::Print(addr, len), offset))
+        }
+        0x14 => {
+            let target = deserialize_target(&bytes[offset..], "Call")?;
+            offset += 4;
+            Ok((Instruction::Call(target), offset))
+        }
+        0x15 => Ok((Instruction::Eq, offset)),
+        0x16 => Ok((Instruction::Neq, offset)),
+        0x17 => Ok((Instruction::Lt, offset)),
+        0x18 => Ok((Instruction::Gt, offset)),
+        0x19 => Ok((Instruction::Lte, offset)),
+        0x1A => Ok((Instruction::Gte, offset)),
+        0x1B => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete ModS instruction".to_string());
+            }
+            let value = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::ModS(Word::from(value)), offset))
+        }
+        0x1C => Ok((Instruction::Mod, offset)),
+        0x1D => {
+            let target = deserialize_target(&bytes[offset..], "Jmp")?;
+            offset += 4;
+            Ok((Instruction::Jmp(target), offset))
+        }
+        0x3B => {
+            let target = deserialize_target(&bytes[offset..], "Try")?;
+            offset += 4;
+            Ok((Instruction::Try(target), offset))
+        }
+        0x3C => Ok((Instruction::EndTry, offset)),
+        0x3D => Ok((Instruction::Throw, offset)),
+        0x1E => Ok((Instruction::JmpDyn, offset)),
+        0x1F => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete wide Push instruction".to_string());
+            }
+            let mut value_bytes = [0u8; 8];
+            value_bytes.copy_from_slice(&bytes[offset..offset + 8]);
+            offset += 8;
+            Ok((Instruction::Push(Word::from_le_bytes(value_bytes)), offset))
+        }
+        0x20 => {
+            let (name, consumed) = deserialize_name(&bytes[offset..], "HostCall")?;
+            offset += consumed;
+            Ok((Instruction::HostCall(name), offset))
+        }
+        0x21 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete Halt instruction".to_string());
+            }
+            let code = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::Halt(code), offset))
+        }
+        0x42 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete Abort instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            let len = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::Abort(addr, len), offset))
+        }
+        0x22 => Ok((Instruction::CodeRead, offset)),
+        0x23 => Ok((Instruction::CodeWrite, offset)),
+        0x24 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete ReadLine instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            let max_len = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::ReadLine(addr, max_len), offset))
+        }
+        0x25 => Ok((Instruction::ReadInt, offset)),
+        0x26 => Ok((Instruction::PrintInt, offset)),
+        0x28 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete PerfRead instruction".to_string());
+            }
+            let counter = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::PerfRead(counter), offset))
+        }
+        0x43 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete VmInfo instruction".to_string());
+            }
+            let field = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::VmInfo(field), offset))
+        }
+        0x3E | 0x3F => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete profiling instruction".to_string());
+            }
+            let phase = u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            let instruction = if opcode == 0x3E { Instruction::ProfBegin(phase) } else { Instruction::ProfEnd(phase) };
+            Ok((instruction, offset))
+        }
+        0x40 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete Assert instruction".to_string());
+            }
+            let mut value_bytes = [0u8; 8];
+            value_bytes.copy_from_slice(&bytes[offset..offset + 8]);
+            offset += 8;
+            Ok((Instruction::Assert(Word::from_le_bytes(value_bytes)), offset))
+        }
+        0x41 => Ok((Instruction::AssertEq, offset)),
+        // Arithmetic immediates that need 64 bits, written by `serialize_immediate`
+        0x44..=0x48 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete wide arithmetic instruction".to_string());
+            }
+            let value = Word::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+            offset += 8;
+            let instruction = match opcode {
+                0x44 => Instruction::AddS(value),
+                0x45 => Instruction::SubS(value),
+                0x46 => Instruction::MultS(value),
+                0x47 => Instruction::DivS(value),
+                _ => Instruction::ModS(value),
+            };
+            Ok((instruction, offset))
+        }
+        0x4A => {
+            if bytes.len() < offset + 16 {
+                return Err("Incomplete MemSet instruction".to_string());
+            }
+            let addr = i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+            let len = i32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap());
+            let value = Word::from_le_bytes(bytes[offset + 8..offset + 16].try_into().unwrap());
+            offset += 16;
+            Ok((Instruction::MemSet(addr, len, value), offset))
+        }
+        0x27 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete PrintStr instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::PrintStr(addr), offset))
+        }
+        0x29 => Ok((Instruction::Load, offset)),
+        0x2A => Ok((Instruction::Store, offset)),
+        0x2E => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete Grow instruction".to_string());
+            }
+            let pages = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::Grow(pages), offset))
+        }
+        0x2F => Ok((Instruction::MemSize, offset)),
+        0x30 => Ok((Instruction::Alloc, offset)),
+        0x31 => Ok((Instruction::Free, offset)),
+        0x33 => Ok((Instruction::Rot, offset)),
+        0x34 => Ok((Instruction::Over, offset)),
+        0x35 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete Pick instruction".to_string());
+            }
+            let depth = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            Ok((Instruction::Pick(depth), offset))
+        }
+        0x36 => Ok((Instruction::Depth, offset)),
+        0x37 => Ok((Instruction::Clear, offset)),
+        0x38 | 0x39 => {
+            if bytes.len() < offset + 4 {
+                return Err("Incomplete local variable instruction".to_string());
+            }
+            let index = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            offset += 4;
+            let instruction = if opcode == 0x38 { Instruction::LoadLocal(index) } else { Instruction::StoreLocal(index) };
+            Ok((instruction, offset))
+        }
+        0x32 => {
+            if bytes.len() < offset + 8 {
+                return Err("Incomplete Protect instruction".to_string());
+            }
+            let addr = i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+            let len = i32::from_le_bytes([bytes[offset + 4], bytes[offset + 5], bytes[offset + 6], bytes[offset + 7]]);
+            offset += 8;
+            Ok((Instruction::Protect(addr, len), offset))
+        }
+        0x2B..=0x2D => {
+            if bytes.len() < offset + 12 {
+                return Err("Incomplete bulk memory instruction".to_string());
+            }
+            let operand = |at: usize| i32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+            let (a, b, c) = (operand(offset), operand(offset + 4), operand(offset + 8));
+            offset += 12;
+            let instruction = match opcode {
+                0x2B => Instruction::MemCopy(a, b, c),
+                0x2C => Instruction::MemSet(a, b, Word::from(c)),
+                _ => Instruction::MemCmp(a, b, c),
+            };
+            Ok((instruction, offset))
+        }
+        _ => Err(format!("Unknown opcode: 0x{:02X}", opcode))
+    }
+}
+
+/// Serializes an arithmetic immediate under `opcode` as an i32, or under `wide_opcode` as
+/// an i64 if it does not fit in 32 bits.
+fn serialize_immediate(opcode: u8, wide_opcode: u8, value: Word, output: &mut Vec<u8>) -> Result<(), String> {
+    match i32::try_from(value) {
+        Ok(narrow) => {
+            output.write_all(&[opcode]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&narrow.to_le_bytes()).map_err(|e| format!("Write error: {}", e))
+        }
+        Err(_) => {
+            output.write_all(&[wide_opcode]).map_err(|e| format!("Write error: {}", e))?;
+            output.write_all(&value.to_le_bytes()).map_err(|e| format!("Write error: {}", e))
+        }
+    }
+}
+
+/// Serializes a resolved jump or call target as a little-endian instruction index
+fn serialize_target(target: u32, output: &mut Vec<u8>) -> Result<(), String> {
+    output.write_all(&target.to_le_bytes()).map_err(|e| format!("Write error: {}", e))
+}
+
+/// Deserializes a jump or call target written by `serialize_target`
+fn deserialize_target(bytes: &[u8], name: &str) -> Result<u32, String> {
+    if bytes.len() < 4 {
+        return Err(format!("Incomplete {} instruction", name));
+    }
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Serializes a name as a little-endian u32 byte length followed by its UTF-8 bytes
+fn serialize_name(name: &str, output: &mut Vec<u8>) -> Result<(), String> {
+    let len = u32::try_from(name.len()).map_err(|_| format!("Name too long: {}", name))?;
+    output.write_all(&len.to_le_bytes()).map_err(|e| format!("Write error: {}", e))?;
+    output.write_all(name.as_bytes()).map_err(|e| format!("Write error: {}", e))
+}
+
+/// Deserializes a name written by `serialize_name`, returning it and the bytes consumed
+fn deserialize_name(bytes: &[u8], instruction: &str) -> Result<(String, usize), String> {
+    if bytes.len() < 4 {
+        return Err(format!("Incomplete {} instruction", instruction));
+    }
+    let len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let Some(name_bytes) = bytes.get(4..4 + len) else {
+        return Err(format!("Incomplete {} instruction", instruction));
+    };
+
+    let name = String::from_utf8(name_bytes.to_vec()).map_err(|e| format!("Invalid UTF-8 name in bytecode: {}", e))?;
+    Ok((name, 4 + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_encoding_width() {
+        // 32-bit values keep the 5-byte encoding used by older .vvm files
+        let mut narrow = Vec::new();
+        serialize_instruction(&Instruction::Push(-5), &mut narrow).unwrap();
+        assert_eq!(narrow, vec![0x01, 0xFB, 0xFF, 0xFF, 0xFF]);
+
+        let wide_value = 1_700_000_000_000;
+        let mut wide = Vec::new();
+        serialize_instruction(&Instruction::Push(wide_value), &mut wide).unwrap();
+        assert_eq!(wide.len(), 9);
+        assert_eq!(wide[0], 0x1F);
+        assert_eq!(deserialize_instruction(&wide).unwrap(), (Instruction::Push(wide_value), 9));
+    }
+
+    #[test]
+    fn test_instruction_words_round_trip() {
+        for instruction in [Instruction::Ret, Instruction::Jnz(7), Instruction::MemRead(-1), Instruction::Halt(3)] {
+            let word = encode_word(&instruction).unwrap();
+            assert_eq!(decode_word(word), Ok(instruction));
+        }
+
+        assert_eq!(encode_word(&Instruction::Push(1 << 40)), None);
+        assert_eq!(encode_word(&Instruction::AddS(1 << 40)), None);
+        assert!(decode_word(Word::from(0x05u8) << 32 | 1).is_err());
+        assert!(decode_word(Word::from(0x13u8) << 32).is_err());
+        assert!(decode_word(-1).is_err());
+    }
+}
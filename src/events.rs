@@ -0,0 +1,140 @@
+use crate::diagnostic;
+use crate::diagnostics::Level;
+use crate::instruction::Word;
+use std::fs::File;
+use std::io::{self, Write};
+
+/// Something that happened during a run, reported to tools such as editor plugins.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The program was loaded (and assembled, for source files).
+    Assembled { file: String, instructions: usize },
+    /// Execution is about to begin.
+    Started,
+    /// The debugger paused at a breakpoint or an output match, before instruction `pc`.
+    BreakpointHit { pc: usize },
+    /// The program printed something.
+    OutputChunk { text: String },
+    /// The program trapped at instruction `pc`.
+    Trapped { pc: usize, message: String },
+    /// Execution ended, normally or after a trap. Always the last event of a run.
+    Finished { stack: Vec<Word>, steps: u64, exit_code: i32 },
+}
+
+impl Event {
+    /// Returns the event as a single-line JSON object whose `event` field names its kind.
+    ///
+    /// ```
+    /// use vortex_vm::events::Event;
+    ///
+    /// let event = Event::Finished { stack: vec![1, 2], steps: 4, exit_code: 0 };
+    /// assert_eq!(event.to_json(), r#"{"event":"finished","stack":[1,2],"steps":4,"exit_code":0}"#);
+    /// ```
+    pub fn to_json(&self) -> String {
+        match self {
+            Event::Assembled { file, instructions } => {
+                format!(r#"{{"event":"assembled","file":{},"instructions":{}}}"#, json_string(file), instructions)
+            }
+            Event::Started => r#"{"event":"started"}"#.to_string(),
+            Event::BreakpointHit { pc } => format!(r#"{{"event":"breakpoint-hit","pc":{}}}"#, pc),
+            Event::OutputChunk { text } => format!(r#"{{"event":"output-chunk","text":{}}}"#, json_string(text)),
+            Event::Trapped { pc, message } => {
+                format!(r#"{{"event":"trapped","pc":{},"message":{}}}"#, pc, json_string(message))
+            }
+            Event::Finished { stack, steps, exit_code } => {
+                let stack: Vec<String> = stack.iter().map(|value| value.to_string()).collect();
+                format!(r#"{{"event":"finished","stack":[{}],"steps":{},"exit_code":{}}}"#, stack.join(","), steps, exit_code)
+            }
+        }
+    }
+}
+
+/// Writes events as newline-delimited JSON, one object per line, flushing after each.
+pub struct EventStream {
+    out: Box<dyn Write>,
+}
+
+impl EventStream {
+    /// Creates a stream writing to `out`.
+    pub fn new(out: Box<dyn Write>) -> Self {
+        EventStream { out }
+    }
+
+    /// Opens the file at `path` for events, or stderr if `path` is `-`.
+    ///
+    /// Paths like `/dev/fd/3` let a tool read events from a dedicated descriptor.
+    pub fn open(path: &str) -> Result<Self, String> {
+        if path == "-" {
+            return Ok(Self::new(Box::new(io::stderr())));
+        }
+        let file = File::create(path).map_err(|e| format!("Failed to open event stream '{}': {}", path, e))?;
+        Ok(Self::new(Box::new(file)))
+    }
+
+    /// Writes one event. A consumer that went away must not stop the run, so write
+    /// errors are only reported as warnings.
+    pub fn emit(&mut self, event: &Event) {
+        if let Err(e) = writeln!(self.out, "{}", event.to_json()).and_then(|_| self.out.flush()) {
+            diagnostic!(Level::Warn, "Failed to write event: {}", e);
+        }
+    }
+}
+
+/// Quotes and escapes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer that can be inspected after being handed to an [`EventStream`].
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_strings_are_escaped() {
+        let event = Event::OutputChunk { text: "say \"hi\"\n\\\u{1}".to_string() };
+        assert_eq!(event.to_json(), r#"{"event":"output-chunk","text":"say \"hi\"\n\\\u0001"}"#);
+    }
+
+    #[test]
+    fn test_stream_writes_one_event_per_line() {
+        let buffer = SharedBuffer::default();
+        let mut stream = EventStream::new(Box::new(buffer.clone()));
+        stream.emit(&Event::Started);
+        stream.emit(&Event::Trapped { pc: 3, message: "stack underflow".to_string() });
+
+        let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            written,
+            "{\"event\":\"started\"}\n{\"event\":\"trapped\",\"pc\":3,\"message\":\"stack underflow\"}\n"
+        );
+    }
+}
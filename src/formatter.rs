@@ -0,0 +1,201 @@
+use crate::spliter::{tokenize_line, LineTokens};
+
+/// Indentation of the code under a label.
+const INDENT: &str = "    ";
+
+/// What a line of assembly is, which decides how it is indented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Blank,
+    /// A line holding only a comment.
+    Comment,
+    /// A label, kept as written along with anything after it on the line, which the
+    /// assembler reports rather than the formatter guessing at.
+    Label,
+    /// `.data`, `.text`, `.requires` and the other directives that apply to the whole
+    /// program, constants and `%include`s, which always start at the left margin.
+    TopLevel,
+    /// Instructions and the directives laying out code or data, indented under a label.
+    Code,
+}
+
+fn classify(tokens: &LineTokens) -> LineKind {
+    let Some(first) = tokens.parts.first() else {
+        return if tokens.comment.is_some() { LineKind::Comment } else { LineKind::Blank };
+    };
+    let directive = first.to_lowercase();
+    if first.ends_with(':') {
+        LineKind::Label
+    } else if first.starts_with('%')
+        || matches!(directive.as_str(), ".data" | ".text" | ".requires" | ".region" | ".budget")
+        || tokens.parts.get(1).is_some_and(|part| part.eq_ignore_ascii_case("equ"))
+    {
+        LineKind::TopLevel
+    } else {
+        LineKind::Code
+    }
+}
+
+fn is_section(tokens: &LineTokens, section: &str) -> bool {
+    tokens.parts.first().is_some_and(|first| first.eq_ignore_ascii_case(section))
+}
+
+/// Writes the code of a line with single spaces between its parts, uppercase mnemonics and
+/// `EQU`s, and lowercase directives.
+fn normalize_code(kind: LineKind, parts: &[&str]) -> String {
+    let mut parts: Vec<String> = parts.iter().map(|part| part.to_string()).collect();
+    match kind {
+        LineKind::TopLevel if parts.get(1).is_some_and(|part| part.eq_ignore_ascii_case("equ")) => parts[1] = "EQU".to_string(),
+        LineKind::TopLevel | LineKind::Code if parts[0].starts_with('.') => parts[0] = parts[0].to_lowercase(),
+        LineKind::Code => parts[0] = parts[0].to_uppercase(),
+        _ => {}
+    }
+    parts.join(" ")
+}
+
+/// Formats assembly source in the house style: labels, section directives, constants and
+/// includes at the left margin, code under a label and data under `.data` indented by four
+/// spaces, mnemonics in uppercase, single spaces between operands, and the trailing comments
+/// of consecutive lines aligned. A comment on its own line is indented like the code that follows it.
+///
+/// Formatting only changes whitespace and the case of mnemonics and directives, so the
+/// result assembles to the same program. Runs of blank lines are kept to one.
+///
+/// ```
+/// use vortex_vm::formatter::format_source;
+///
+/// let source = "main:\npush   7 ; seven\n  mults 6   ; times six\nRet";
+/// assert_eq!(format_source(source), "main:\n    PUSH 7  ; seven\n    MULTS 6 ; times six\n    RET\n");
+/// ```
+pub fn format_source(source: &str) -> String {
+    let lines: Vec<LineTokens> = source.lines().map(tokenize_line).collect();
+    let kinds: Vec<LineKind> = lines.iter().map(classify).collect();
+
+    // Indent each line, and comments like the next line with code
+    let mut indents = vec![""; lines.len()];
+    let mut indented = false;
+    for (index, kind) in kinds.iter().enumerate() {
+        match kind {
+            LineKind::Label => indented = true,
+            // Data is indented under its `.data`, code starts at the margin until its first label
+            LineKind::TopLevel => indented = is_section(&lines[index], ".data") || (indented && !is_section(&lines[index], ".text")),
+            LineKind::Code if indented => indents[index] = INDENT,
+            _ => {}
+        }
+    }
+    for index in (0..lines.len()).rev() {
+        if kinds[index] == LineKind::Comment {
+            let next = (index + 1..lines.len()).find(|&next| !matches!(kinds[next], LineKind::Blank | LineKind::Comment));
+            indents[index] = next.map_or("", |next| indents[next]);
+        }
+    }
+
+    let codes: Vec<String> = lines
+        .iter()
+        .zip(&kinds)
+        .zip(&indents)
+        .map(|((tokens, kind), indent)| match kind {
+            LineKind::Blank | LineKind::Comment => String::new(),
+            _ => format!("{}{}", indent, normalize_code(*kind, &tokens.parts)),
+        })
+        .collect();
+
+    // Trailing comments line up within each run of consecutive lines with code
+    let mut comment_columns = vec![0; lines.len()];
+    let mut start = 0;
+    while start < lines.len() {
+        if matches!(kinds[start], LineKind::Blank | LineKind::Comment) {
+            start += 1;
+            continue;
+        }
+        let end = (start..lines.len()).find(|&end| matches!(kinds[end], LineKind::Blank | LineKind::Comment)).unwrap_or(lines.len());
+        let commented = (start..end).filter(|&index| lines[index].comment.is_some());
+        let column = commented.clone().map(|index| codes[index].len()).max().unwrap_or(0) + 1;
+        for index in commented {
+            comment_columns[index] = column;
+        }
+        start = end;
+    }
+
+    let mut formatted = String::new();
+    for (index, tokens) in lines.iter().enumerate() {
+        let line = match (kinds[index], tokens.comment) {
+            (LineKind::Blank, _) => {
+                if formatted.is_empty() || formatted.ends_with("\n\n") {
+                    continue;
+                }
+                String::new()
+            }
+            (LineKind::Comment, Some(comment)) => format!("{}{}", indents[index], comment),
+            (_, Some(comment)) => format!("{:width$}{}", codes[index], comment, width = comment_columns[index]),
+            (_, None) => codes[index].clone(),
+        };
+        formatted.push_str(&line);
+        formatted.push('\n');
+    }
+    while formatted.ends_with("\n\n") {
+        formatted.pop();
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_labels_and_comments() {
+        let source = "\
+; header
+
+
+N equ 3
+.DATA 0
+msg:
+.string   \"a  ;  b\"  ; kept as is
+.Text
+  ; entry point
+main:
+push N
+   ; loop body
+loop:   
+subs 1 ; count down
+jnz loop    ; again
+
+%include \"lib.vvm\"
+";
+        let expected = "\
+; header
+
+N EQU 3
+.data 0
+msg:
+    .string \"a  ;  b\" ; kept as is
+.text
+; entry point
+main:
+    PUSH N
+; loop body
+loop:
+    SUBS 1   ; count down
+    JNZ loop ; again
+
+%include \"lib.vvm\"
+";
+        assert_eq!(format_source(source), expected);
+        assert_eq!(format_source(expected), expected);
+    }
+
+    #[test]
+    fn test_code_before_any_label_stays_at_the_margin() {
+        assert_eq!(format_source("push 60\n  subs 7\n\n\n"), "PUSH 60\nSUBS 7\n");
+        assert_eq!(format_source(""), "");
+    }
+
+    #[test]
+    fn test_code_after_a_label_is_left_as_written() {
+        // Not valid assembly, so the label keeps its case for the assembler to report
+        let source = "main:\nloop: subs   1\njnz loop\n";
+        assert_eq!(format_source(source), "main:\nloop: subs 1\n    JNZ loop\n");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::instruction::Word;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// The free-list allocator behind `ALLOC` and `FREE`.
+///
+/// The heap lives in VM memory above the cells the program started with, so it never
+/// overlaps data at fixed addresses. Allocations reuse the first free block that is big
+/// enough and otherwise grow memory; freed blocks merge with free neighbours so memory
+/// does not fragment into pieces too small to reuse.
+///
+/// ```
+/// use vortex_vm::heap::Heap;
+///
+/// let mut heap = Heap::default();
+/// let mut mem = vec![0; 16];
+/// let a = heap.alloc(4, &mut mem, 0, 64).unwrap();
+/// let b = heap.alloc(4, &mut mem, 0, 64).unwrap();
+/// assert_eq!((a, b), (16..20, 20..24));
+///
+/// heap.free(16);
+/// assert_eq!(heap.alloc(2, &mut mem, 0, 64), Some(16..18));
+/// assert_eq!(heap.allocated(), 6);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Heap {
+    /// Live blocks by start address, with their length.
+    used: BTreeMap<usize, usize>,
+    /// Free blocks by start address, with their length. Neighbours are always merged.
+    free: BTreeMap<usize, usize>,
+    /// The total length of the live blocks.
+    allocated: usize,
+}
+
+impl Heap {
+    /// Allocates `size` cells, growing `mem` with `fill` cells up to `limit` cells if no free
+    /// block is big enough. Returns `None` if `size` is zero or memory would exceed `limit`.
+    pub fn alloc(&mut self, size: usize, mem: &mut Vec<Word>, fill: Word, limit: usize) -> Option<Range<usize>> {
+        if size == 0 {
+            return None;
+        }
+
+        let fit = self.free.iter().find(|&(_, &len)| len >= size).map(|(&start, &len)| (start, len));
+        let start = match fit {
+            Some((start, len)) => {
+                self.free.remove(&start);
+                if len > size {
+                    self.free.insert(start + size, len - size);
+                }
+                start
+            }
+            None => {
+                // Extend a free block at the very end of memory rather than leave it behind
+                let tail = self.free.last_key_value().filter(|&(&start, &len)| start + len == mem.len()).map(|(&start, _)| start);
+                let start = tail.unwrap_or(mem.len());
+                let end = start.checked_add(size).filter(|&end| end <= limit)?;
+                if let Some(tail) = tail {
+                    self.free.remove(&tail);
+                }
+                mem.resize(end, fill);
+                start
+            }
+        };
+
+        self.used.insert(start, size);
+        self.allocated += size;
+        Some(start..start + size)
+    }
+
+    /// Frees the block starting at `addr` and returns its cells, or `None` if no live block
+    /// starts there.
+    pub fn free(&mut self, addr: usize) -> Option<Range<usize>> {
+        let len = self.used.remove(&addr)?;
+        self.allocated -= len;
+        let freed = addr..addr + len;
+
+        let mut start = addr;
+        let mut end = addr + len;
+        if let Some((&before, &before_len)) = self.free.range(..addr).next_back()
+            && before + before_len == addr
+        {
+            self.free.remove(&before);
+            start = before;
+        }
+        if let Some(after_len) = self.free.remove(&end) {
+            end += after_len;
+        }
+        self.free.insert(start, end - start);
+        Some(freed)
+    }
+
+    /// The number of cells in live blocks.
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// The live blocks as address ranges, lowest first.
+    pub fn blocks(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.used.iter().map(|(&start, &len)| start..start + len)
+    }
+
+    /// Forgets every block, live or free.
+    pub fn clear(&mut self) {
+        self.used.clear();
+        self.free.clear();
+        self.allocated = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heap_with(sizes: &[usize]) -> (Heap, Vec<Word>, Vec<Range<usize>>) {
+        let mut heap = Heap::default();
+        let mut mem = Vec::new();
+        let blocks = sizes.iter().map(|&size| heap.alloc(size, &mut mem, 0, 1024).unwrap()).collect();
+        (heap, mem, blocks)
+    }
+
+    #[test]
+    fn test_freed_neighbours_merge() {
+        let (mut heap, mut mem, blocks) = heap_with(&[4, 4, 4, 4]);
+
+        // Free every other block: two holes of 4 that cannot hold 8
+        heap.free(blocks[0].start);
+        heap.free(blocks[2].start);
+        assert_eq!(heap.alloc(8, &mut mem, 0, 16), None);
+
+        // Freeing the block between them leaves one hole of 12
+        heap.free(blocks[1].start);
+        assert_eq!(heap.free.len(), 1);
+        assert_eq!(heap.alloc(12, &mut mem, 0, 16), Some(0..12));
+        assert_eq!(mem.len(), 16);
+    }
+
+    #[test]
+    fn test_first_fit_splits_blocks() {
+        let (mut heap, mut mem, blocks) = heap_with(&[10, 2]);
+        heap.free(blocks[0].start);
+
+        assert_eq!(heap.alloc(3, &mut mem, 0, 1024), Some(0..3));
+        assert_eq!(heap.alloc(3, &mut mem, 0, 1024), Some(3..6));
+        assert_eq!(heap.alloc(5, &mut mem, 0, 1024), Some(12..17));
+        assert_eq!(heap.alloc(4, &mut mem, 0, 1024), Some(6..10));
+        assert_eq!(heap.allocated(), 17);
+    }
+
+    #[test]
+    fn test_free_tail_is_extended() {
+        let (mut heap, mut mem, blocks) = heap_with(&[4, 4]);
+        heap.free(blocks[1].start);
+
+        // The free block at the end grows instead of being skipped
+        assert_eq!(heap.alloc(6, &mut mem, 7, 1024), Some(4..10));
+        assert_eq!(mem, vec![0, 0, 0, 0, 0, 0, 0, 0, 7, 7]);
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let (mut heap, mut mem, blocks) = heap_with(&[4]);
+        assert_eq!(heap.alloc(0, &mut mem, 0, 1024), None);
+        assert_eq!(heap.alloc(1021, &mut mem, 0, 1024), None);
+        assert_eq!(heap.free(blocks[0].start + 1), None);
+        assert_eq!(heap.free(blocks[0].start), Some(0..4));
+        assert_eq!(heap.free(blocks[0].start), None);
+        assert_eq!(mem.len(), 4);
+    }
+}
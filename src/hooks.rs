@@ -0,0 +1,238 @@
+use crate::instruction::Word;
+use crate::program::DebugInfo;
+use crate::run::{OutputSink, Vm};
+use crate::toml::{self, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// What a `HOSTCALL` configured in a hook file does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostHook {
+    /// Pushes a constant.
+    Return(Word),
+    /// Pops the top value and writes it to a file as a line of text.
+    Echo(PathBuf),
+    /// Fails with a message, trapping the program.
+    Fail(String),
+}
+
+/// Sends the output of `Print`s to a memory range to a file, see [`Vm::intercept_print`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintHook {
+    /// The name of the hook, which is also the region it covers unless `range` is given.
+    pub name: String,
+    pub range: Option<Range<usize>>,
+    pub file: PathBuf,
+}
+
+/// Host functions and print destinations set up from a TOML file, so that programs
+/// written for an embedding application can be run from the command line.
+///
+/// Each `[hostcall.NAME]` table defines `HOSTCALL NAME` with an `action`: `return` pushes
+/// `value`, `echo` pops a value and appends it as a line to `file`, and `fail` traps with
+/// `message`. Each `[print.NAME]` table sends every `Print` starting in the region `NAME`,
+/// or in `start`..`start + len`, to `file`. A `file` of `-` is standard error.
+///
+/// ```
+/// use vortex_vm::hooks::Hooks;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::program::DebugInfo;
+/// use vortex_vm::run::Vm;
+///
+/// let hooks = Hooks::parse("[hostcall.answer]\naction = \"return\"\nvalue = 42").unwrap();
+/// let mut vm = Vm::new(vec![Instruction::HostCall("answer".to_string())]);
+/// hooks.install(&mut vm, &DebugInfo::default()).unwrap();
+///
+/// vm.run(&mut Vec::new()).unwrap();
+/// assert_eq!(vm.stack, vec![42]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hooks {
+    pub host_fns: BTreeMap<String, HostHook>,
+    pub prints: Vec<PrintHook>,
+}
+
+impl Hooks {
+    /// Parses a hook file.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        // Collect the fields of each table first, since they may come in any order
+        let mut tables: Vec<(String, HashMap<String, Value>)> = Vec::new();
+        for entry in toml::parse(text).map_err(|e| format!("Invalid hook file: {}", e))? {
+            if entry.table.is_empty() {
+                return Err(format!("Hook field '{}' at line {} is outside a [hostcall.NAME] or [print.NAME] table", entry.key, entry.line));
+            }
+            if tables.last().is_none_or(|(table, _)| *table != entry.table) {
+                tables.push((entry.table.clone(), HashMap::new()));
+            }
+            if let Some((_, fields)) = tables.last_mut() {
+                fields.insert(entry.key, entry.value);
+            }
+        }
+
+        let mut hooks = Hooks::default();
+        for (table, mut fields) in tables {
+            let describe = |e: String| format!("Invalid hook [{}]: {}", table, e);
+            match table.split_once('.') {
+                Some(("hostcall", name)) => {
+                    let hook = host_hook(&mut fields).map_err(describe)?;
+                    hooks.host_fns.insert(name.to_string(), hook);
+                }
+                Some(("print", name)) => {
+                    let hook = print_hook(name, &mut fields).map_err(describe)?;
+                    hooks.prints.push(hook);
+                }
+                _ => return Err(format!("Unknown hook table [{}], use [hostcall.NAME] or [print.NAME]", table)),
+            }
+            if let Some(key) = fields.keys().next() {
+                return Err(format!("Unknown field '{}' in hook [{}]", key, table));
+            }
+        }
+        Ok(hooks)
+    }
+
+    /// Reads a hook file from `path`.
+    pub fn read_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read hook file '{}': {}", path, e))?;
+        Self::parse(&text)
+    }
+
+    /// Registers every hook with `vm`, creating the files they write to. Print hooks
+    /// without a range look up their region in `debug_info`.
+    pub fn install(&self, vm: &mut Vm, debug_info: &DebugInfo) -> Result<(), String> {
+        let mut sinks: HashMap<PathBuf, OutputSink> = HashMap::new();
+        let mut open = |path: &PathBuf| -> Result<OutputSink, String> {
+            if let Some(sink) = sinks.get(path) {
+                return Ok(Arc::clone(sink));
+            }
+            let sink: OutputSink = if path.as_os_str() == "-" {
+                Arc::new(Mutex::new(io::stderr()))
+            } else {
+                let file = File::create(path).map_err(|e| format!("Failed to create hook output '{}': {}", path.display(), e))?;
+                Arc::new(Mutex::new(file))
+            };
+            sinks.insert(path.clone(), Arc::clone(&sink));
+            Ok(sink)
+        };
+
+        for (name, hook) in &self.host_fns {
+            match hook {
+                HostHook::Return(value) => {
+                    let value = *value;
+                    vm.register_host_fn(name, move |stack| {
+                        stack.push(value);
+                        Ok(())
+                    });
+                }
+                HostHook::Echo(path) => {
+                    let sink = open(path)?;
+                    vm.register_host_fn(name, move |stack| {
+                        let value = stack.pop().ok_or("nothing on the stack to echo")?;
+                        let mut sink = sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                        writeln!(sink, "{}", value).map_err(|e| e.to_string())
+                    });
+                }
+                HostHook::Fail(message) => {
+                    let message = message.clone();
+                    vm.register_host_fn(name, move |_| Err(message.clone()));
+                }
+            }
+        }
+
+        for hook in &self.prints {
+            let range = match (&hook.range, debug_info.region(&hook.name)) {
+                (Some(range), _) => range.clone(),
+                (None, Some(region)) => region.start..region.end(),
+                (None, None) => return Err(format!("Print hook '{}' names no region of the program; give its start and len", hook.name)),
+            };
+            vm.intercept_print(range, open(&hook.file)?);
+        }
+        Ok(())
+    }
+}
+
+/// Reads a `[hostcall.NAME]` table, removing the fields it knows from `fields`.
+fn host_hook(fields: &mut HashMap<String, Value>) -> Result<HostHook, String> {
+    let action = fields.remove("action").ok_or("missing 'action'")?.string()?;
+    match action.as_str() {
+        "return" => Ok(HostHook::Return(fields.remove("value").ok_or("missing 'value'")?.integer()?)),
+        "echo" => Ok(HostHook::Echo(PathBuf::from(fields.remove("file").ok_or("missing 'file'")?.string()?))),
+        "fail" => {
+            let message = fields.remove("message").map(Value::string).transpose()?;
+            Ok(HostHook::Fail(message.unwrap_or_else(|| "failed".to_string())))
+        }
+        _ => Err(format!("unknown action '{}', use return, echo or fail", action)),
+    }
+}
+
+/// Reads a `[print.NAME]` table, removing the fields it knows from `fields`.
+fn print_hook(name: &str, fields: &mut HashMap<String, Value>) -> Result<PrintHook, String> {
+    let file = PathBuf::from(fields.remove("file").ok_or("missing 'file'")?.string()?);
+    let start = fields.remove("start").map(Value::integer::<usize>).transpose()?;
+    let len = fields.remove("len").map(Value::integer::<usize>).transpose()?;
+    let range = match (start, len) {
+        (Some(start), Some(len)) => Some(start..start.saturating_add(len)),
+        (None, None) => None,
+        _ => return Err("give both 'start' and 'len', or neither to use the region".to_string()),
+    };
+    Ok(PrintHook { name: name.to_string(), range, file })
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+    use crate::run::VmError;
+    use crate::spliter::parse_program;
+
+    #[test]
+    fn test_parse_hooks() {
+        let text = "[hostcall.log]\naction = \"echo\"\nfile = \"-\"\n\n[hostcall.rng]\naction = \"fail\"\n\n[print.screen]\nfile = \"screen.txt\"\n\n[print.raw]\nfile = \"raw.txt\"\nstart = 100\nlen = 8";
+        let hooks = Hooks::parse(text).unwrap();
+        assert_eq!(hooks.host_fns["log"], HostHook::Echo(PathBuf::from("-")));
+        assert_eq!(hooks.host_fns["rng"], HostHook::Fail("failed".to_string()));
+        assert_eq!(hooks.prints, [
+            PrintHook { name: "screen".to_string(), range: None, file: PathBuf::from("screen.txt") },
+            PrintHook { name: "raw".to_string(), range: Some(100..108), file: PathBuf::from("raw.txt") },
+        ]);
+
+        assert!(Hooks::parse("action = \"fail\"").is_err());
+        assert!(Hooks::parse("[syscall.1]\naction = \"fail\"").is_err());
+        assert!(Hooks::parse("[hostcall.x]\naction = \"explode\"").is_err());
+        assert!(Hooks::parse("[hostcall.x]\naction = \"return\"").is_err());
+        assert!(Hooks::parse("[hostcall.x]\naction = \"fail\"\ncolour = 1").is_err());
+        assert!(Hooks::parse("[print.x]\nfile = \"a\"\nstart = 1").is_err());
+    }
+
+    #[test]
+    fn test_installed_hooks_run() {
+        let dir = std::env::temp_dir().join(format!("vortex_hooks_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("log.txt");
+        let screen = dir.join("screen.txt");
+        let text = format!(
+            "[hostcall.log]\naction = \"echo\"\nfile = {}\n[hostcall.no]\naction = \"fail\"\nmessage = \"denied\"\n[print.screen]\nfile = {}",
+            toml::quote(&log.to_string_lossy()),
+            toml::quote(&screen.to_string_lossy())
+        );
+        let hooks = Hooks::parse(&text).unwrap();
+
+        let program = parse_program(".region screen 10 2\nMEMWRITE 10 104 105\nPRINT 10 2\nPUSH 7\nHOSTCALL log\nHOSTCALL no").unwrap();
+        let mut vm = Vm::new(program.instructions.clone());
+        hooks.install(&mut vm, &program.debug_info).unwrap();
+        let mut output = Vec::new();
+        let err = vm.run(&mut output).unwrap_err();
+
+        assert_eq!(err, VmError::HostFunctionFailed { pc: 4, name: "no".to_string(), message: "denied".to_string() });
+        assert!(output.is_empty());
+        assert_eq!(fs::read_to_string(&log).unwrap(), "7\n");
+        assert_eq!(fs::read_to_string(&screen).unwrap(), "hi");
+
+        // Print hooks need a region when they have no range
+        let mut vm = Vm::new(Vec::new());
+        assert!(hooks.install(&mut vm, &DebugInfo::default()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use crate::diagnostic;
+use crate::diagnostics::Level;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Assembly source with every `%include "file"` line replaced by the lines of that file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExpandedSource {
+    pub text: String,
+    /// The file each line of `text` came from and its 1-based line number there.
+    pub origins: Vec<(PathBuf, usize)>,
+}
+
+impl ExpandedSource {
+    /// Returns the file and line that line `line` (1-based) of the expanded text came from.
+    pub fn origin(&self, line: usize) -> Option<(&Path, usize)> {
+        let (path, line) = self.origins.get(line.checked_sub(1)?)?;
+        Some((path.as_path(), *line))
+    }
+
+    /// Describes where line `line` (1-based) of the expanded text came from, as `file:line`.
+    pub fn describe_line(&self, line: usize) -> String {
+        match self.origin(line) {
+            Some((path, line)) => format!("{}:{}", path.display(), line),
+            None => format!("line {}", line),
+        }
+    }
+}
+
+/// Reads the assembly source at `path` and expands its `%include` directives.
+///
+/// An included path is looked up relative to the directory of the file that includes it
+/// first, then in each of `search_paths` in order. Including a file that is already being
+/// expanded is reported as an include cycle.
+///
+/// ```no_run
+/// use std::path::PathBuf;
+/// use vortex_vm::include::read_source;
+///
+/// let source = read_source("program.vvm", &[PathBuf::from("lib")]).unwrap();
+/// println!("{}", source.text);
+/// ```
+pub fn read_source(path: &str, search_paths: &[PathBuf]) -> Result<ExpandedSource, String> {
+    let mut expanded = ExpandedSource::default();
+    expand_file(Path::new(path), search_paths, &mut Vec::new(), &mut expanded)?;
+    diagnostic!(Level::Debug, "Expanded '{}' to {} lines", path, expanded.origins.len());
+    Ok(expanded)
+}
+
+/// Expands the `%include` directives of source that was already read, like [`read_source`].
+/// `path` names the source in locations, and its directory is where includes are looked
+/// up first; it does not have to exist, e.g. for source read from standard input.
+///
+/// ```
+/// use std::path::Path;
+/// use vortex_vm::include::expand_source;
+///
+/// let source = expand_source("PUSH 1\nPUSH 2", Path::new("<stdin>"), &[]).unwrap();
+/// assert_eq!(source.describe_line(2), "<stdin>:2");
+/// ```
+pub fn expand_source(text: &str, path: &Path, search_paths: &[PathBuf]) -> Result<ExpandedSource, String> {
+    let mut expanded = ExpandedSource::default();
+    expand_text(text, path, search_paths, &mut Vec::new(), &mut expanded)?;
+    diagnostic!(Level::Debug, "Expanded '{}' to {} lines", path.display(), expanded.origins.len());
+    Ok(expanded)
+}
+
+/// Appends the lines of `path` to `expanded`, expanding includes recursively. `open` holds
+/// the files currently being expanded, outermost first.
+fn expand_file(path: &Path, search_paths: &[PathBuf], open: &mut Vec<PathBuf>, expanded: &mut ExpandedSource) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Failed to read source file '{}': {}", path.display(), e))?;
+    expand_text(&source, path, search_paths, open, expanded)
+}
+
+/// Appends the lines of `source`, read from `path`, to `expanded`, see [`expand_file`].
+fn expand_text(source: &str, path: &Path, search_paths: &[PathBuf], open: &mut Vec<PathBuf>, expanded: &mut ExpandedSource) -> Result<(), String> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if open.contains(&canonical) {
+        let chain: Vec<String> = open.iter().chain([&canonical]).map(|file| file.display().to_string()).collect();
+        return Err(format!("Include cycle: {}", chain.join(" -> ")));
+    }
+    open.push(canonical);
+
+    for (index, line) in source.lines().enumerate() {
+        let location = format!("{}:{}", path.display(), index + 1);
+        match parse_include(line) {
+            Some(Ok(included)) => {
+                let resolved = resolve_include(path, included, search_paths)
+                    .ok_or_else(|| format!("{}: Included file '{}' not found", location, included))?;
+                diagnostic!(Level::Info, "Including '{}'", resolved.display());
+                expand_file(&resolved, search_paths, open, expanded)?;
+            }
+            Some(Err(e)) => return Err(format!("{}: {}", location, e)),
+            None => {
+                expanded.text.push_str(line);
+                expanded.text.push('\n');
+                expanded.origins.push((path.to_path_buf(), index + 1));
+            }
+        }
+    }
+
+    open.pop();
+    Ok(())
+}
+
+/// Recognizes `%include "file"` lines, returning the quoted path or an error if the
+/// directive is malformed. Other lines return `None`.
+fn parse_include(line: &str) -> Option<Result<&str, String>> {
+    let trimmed = line.trim_start();
+    let directive = trimmed.get(..8).filter(|directive| directive.eq_ignore_ascii_case("%include"))?;
+    let rest = &trimmed[directive.len()..];
+    if rest.chars().next().is_some_and(|c| !c.is_whitespace() && c != '"') {
+        return None;
+    }
+
+    let quoted = rest.trim_start();
+    let Some((included, after)) = quoted.strip_prefix('"').and_then(|quoted| quoted.split_once('"')) else {
+        return Some(Err("Expected %include \"file\"".to_string()));
+    };
+    let after = after.trim();
+    if included.is_empty() || !(after.is_empty() || after.starts_with(';')) {
+        return Some(Err("Expected %include \"file\"".to_string()));
+    }
+    Some(Ok(included))
+}
+
+/// Finds an included file next to the including file or in one of the search paths.
+fn resolve_include(including: &Path, included: &str, search_paths: &[PathBuf]) -> Option<PathBuf> {
+    let included = Path::new(included);
+    if included.is_absolute() {
+        return included.is_file().then(|| included.to_path_buf());
+    }
+
+    let base = including.parent().unwrap_or(Path::new(""));
+    std::iter::once(base)
+        .chain(search_paths.iter().map(PathBuf::as_path))
+        .map(|directory| directory.join(included))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// Creates a fresh directory under the system temp directory holding `files`.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("vortex_vm_include_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_parse_include() {
+        assert_eq!(parse_include("  %include \"lib/print.vvm\" ; helpers"), Some(Ok("lib/print.vvm")));
+        assert_eq!(parse_include("%INCLUDE\"a.vvm\""), Some(Ok("a.vvm")));
+        assert!(matches!(parse_include("%include lib.vvm"), Some(Err(_))));
+        assert!(matches!(parse_include("%include \"a.vvm\" PUSH 1"), Some(Err(_))));
+        assert_eq!(parse_include("%includes \"a.vvm\""), None);
+        assert_eq!(parse_include("PUSH 1"), None);
+    }
+
+    #[test]
+    fn test_includes_are_expanded_with_origins() {
+        let root = write_files("expand", &[
+            ("main.vvm", "CALL print\nRET\n%include \"lib/print.vvm\"\n%include \"shared.vvm\""),
+            ("lib/print.vvm", "print:\n%include \"inner.vvm\"\nRET"),
+            ("lib/inner.vvm", "PRINTINT"),
+            ("std/shared.vvm", "NULL"),
+        ]);
+
+        let main = root.join("main.vvm");
+        let expanded = read_source(main.to_str().unwrap(), &[root.join("std")]).unwrap();
+        assert_eq!(expanded.text, "CALL print\nRET\nprint:\nPRINTINT\nRET\nNULL\n");
+        assert_eq!(expanded.origin(4), Some((root.join("lib").join("inner.vvm").as_path(), 1)));
+        assert_eq!(expanded.describe_line(5), format!("{}:3", root.join("lib").join("print.vvm").display()));
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_missing_includes_and_cycles_are_errors() {
+        let root = write_files("errors", &[
+            ("missing.vvm", "PUSH 1\n%include \"nowhere.vvm\""),
+            ("a.vvm", "%include \"b.vvm\""),
+            ("b.vvm", "%include \"a.vvm\""),
+        ]);
+
+        let err = read_source(root.join("missing.vvm").to_str().unwrap(), &[]).unwrap_err();
+        assert!(err.ends_with("missing.vvm:2: Included file 'nowhere.vvm' not found"), "{}", err);
+
+        let err = read_source(root.join("a.vvm").to_str().unwrap(), &[]).unwrap_err();
+        assert!(err.starts_with("Include cycle: "), "{}", err);
+        assert_eq!(err.matches("a.vvm").count(), 2, "{}", err);
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_expand_source_that_is_not_a_file() {
+        let root = write_files("text", &[("lib.vvm", "NULL")]);
+        let expanded = expand_source("%include \"lib.vvm\"\nHALT", &root.join("<stdin>"), &[]).unwrap();
+        assert_eq!(expanded.text, "NULL\nHALT\n");
+        assert_eq!(expanded.describe_line(2), format!("{}:2", root.join("<stdin>").display()));
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Stdin};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Where `READLINE` and `READINT` get their input from, see [`Vm::set_input_source`].
+///
+/// [`Vm::set_input_source`]: crate::run::Vm::set_input_source
+pub trait InputSource: Send {
+    /// Returns the next line without its line ending, or `None` at the end of input.
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// Input read line by line from any [`BufRead`], such as a `Cursor` over test data.
+#[derive(Debug)]
+pub struct ReaderInput<R>(pub R);
+
+impl<R: BufRead + Send> InputSource for ReaderInput<R> {
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        if self.0.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+}
+
+impl<S: InputSource + ?Sized> InputSource for Box<S> {
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        (**self).read_line()
+    }
+}
+
+/// Passes on the lines of another source and keeps a copy of each, ending in `\n`, so a
+/// run can be repeated with exactly the input it read.
+///
+/// ```
+/// use vortex_vm::input::{InputSource, RecordingInput, StringInput};
+///
+/// let mut input = RecordingInput::new(StringInput::new("1\r\n2\n3"));
+/// let recorded = input.recorded();
+/// input.read_line().unwrap();
+/// input.read_line().unwrap();
+/// assert_eq!(*recorded.lock().unwrap(), b"1\n2\n");
+/// ```
+#[derive(Debug)]
+pub struct RecordingInput<S> {
+    source: S,
+    recorded: Arc<Mutex<Vec<u8>>>,
+}
+
+impl<S: InputSource> RecordingInput<S> {
+    /// Records the lines read from `source`.
+    pub fn new(source: S) -> Self {
+        RecordingInput { source, recorded: Arc::default() }
+    }
+
+    /// Returns the lines read so far, shared so they can be looked at after the source
+    /// was handed to a VM.
+    pub fn recorded(&self) -> Arc<Mutex<Vec<u8>>> {
+        Arc::clone(&self.recorded)
+    }
+}
+
+impl<S: InputSource> InputSource for RecordingInput<S> {
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let line = self.source.read_line()?;
+        if let Some(line) = &line {
+            let mut recorded = self.recorded.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            recorded.extend_from_slice(line);
+            recorded.push(b'\n');
+        }
+        Ok(line)
+    }
+}
+
+/// Input typed by the user on the terminal, or piped into the process.
+pub type StdinInput = ReaderInput<BufReader<Stdin>>;
+
+/// Input read from a file.
+pub type FileInput = ReaderInput<BufReader<File>>;
+
+/// Input held in memory.
+pub type StringInput = ReaderInput<Cursor<Vec<u8>>>;
+
+impl StdinInput {
+    /// Reads the process's standard input.
+    pub fn stdin() -> Self {
+        ReaderInput(BufReader::new(io::stdin()))
+    }
+}
+
+impl FileInput {
+    /// Opens the file at `path` for reading.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        File::open(path).map(|file| ReaderInput(BufReader::new(file)))
+    }
+}
+
+impl StringInput {
+    /// Reads the lines of `text`.
+    pub fn new(text: impl Into<Vec<u8>>) -> Self {
+        ReaderInput(Cursor::new(text.into()))
+    }
+}
+
+/// Lines that become available one after another, as if a user typed them, for testing how
+/// programs behave while they wait for input, e.g. with a
+/// [`CancellationToken`](crate::run::CancellationToken).
+///
+/// Each line has a delay measured from when the previous line became available, or from
+/// when the script was created for the first line. A read before its line is available
+/// waits for it.
+///
+/// ```
+/// use std::time::Duration;
+/// use vortex_vm::input::ScriptedInput;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::Vm;
+///
+/// let script = ScriptedInput::new().line("20").line_after(Duration::from_millis(5), "22");
+/// let mut vm = Vm::new(vec![Instruction::ReadInt, Instruction::ReadInt, Instruction::Add]);
+/// vm.set_input_source(script);
+///
+/// vm.run(&mut Vec::new()).unwrap();
+/// assert_eq!(vm.stack, vec![42]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScriptedInput {
+    lines: VecDeque<(Duration, Vec<u8>)>,
+    /// When the last line read became available.
+    available_since: Instant,
+}
+
+impl ScriptedInput {
+    /// Creates an empty script.
+    pub fn new() -> Self {
+        ScriptedInput { lines: VecDeque::new(), available_since: Instant::now() }
+    }
+
+    /// Adds a line that is available right after the one before it.
+    pub fn line(self, line: impl Into<Vec<u8>>) -> Self {
+        self.line_after(Duration::ZERO, line)
+    }
+
+    /// Adds a line that becomes available `delay` after the one before it.
+    pub fn line_after(mut self, delay: Duration, line: impl Into<Vec<u8>>) -> Self {
+        self.lines.push_back((delay, line.into()));
+        self
+    }
+}
+
+impl Default for ScriptedInput {
+    fn default() -> Self {
+        ScriptedInput::new()
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some((delay, line)) = self.lines.pop_front() else {
+            return Ok(None);
+        };
+        let available_at = self.available_since + delay;
+        thread::sleep(available_at.saturating_duration_since(Instant::now()));
+        self.available_since = available_at;
+        Ok(Some(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader_input_strips_line_endings() {
+        let mut input = StringInput::new("one\r\ntwo\n\nlast");
+        let lines: Vec<_> = std::iter::from_fn(|| input.read_line().unwrap()).collect();
+        assert_eq!(lines, [b"one".to_vec(), b"two".to_vec(), Vec::new(), b"last".to_vec()]);
+    }
+
+    #[test]
+    fn test_scripted_lines_wait_for_their_delay() {
+        let started = Instant::now();
+        let mut script = ScriptedInput::new().line("now").line_after(Duration::from_millis(20), "later");
+
+        assert_eq!(script.read_line().unwrap(), Some(b"now".to_vec()));
+        assert!(started.elapsed() < Duration::from_millis(20));
+        assert_eq!(script.read_line().unwrap(), Some(b"later".to_vec()));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(script.read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn test_file_input() {
+        let path = std::env::temp_dir().join(format!("vortex_input_{}.txt", std::process::id()));
+        std::fs::write(&path, "7\n").unwrap();
+        let mut input = FileInput::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(input.read_line().unwrap(), Some(b"7".to_vec()));
+        assert_eq!(input.read_line().unwrap(), None);
+        assert!(FileInput::open(&path).is_err());
+    }
+}
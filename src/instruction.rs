@@ -1,27 +1,206 @@
-#[derive(Debug, PartialEq)]
+use std::fmt;
+
+/// The type of the values held on the stack and in memory.
+pub type Word = i64;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     Null,
 
-    Push(i32),
+    Push(Word),
     Dup,
     Swap,
+    Rot,
+    Over,
+    Pick(i32),
+    Depth,
+    Clear,
     Pop,
     Ret,
+    Halt(i32),
+    Abort(i32, i32),
 
-    Jiz(String),
-    Jnz(String),
+    Jiz(u32),
+    Jnz(u32),
+    Jmp(u32),
+    JmpDyn,
+    Call(u32),
+    HostCall(String),
+    Try(u32),
+    EndTry,
+    Throw,
 
-    AddS(i32),
+    AddS(Word),
     Add,
-    SubS(i32),
+    SubS(Word),
     Sub,
-    MultS(i32),
+    MultS(Word),
     Mult,
-    DivS(i32),
+    DivS(Word),
     Div,
+    ModS(Word),
+    Mod,
+
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Lte,
+    Gte,
 
-    MemWrite(i32, Vec<i32>),
+    MemWrite(i32, Vec<Word>),
     MemWriteS(i32, i32),
     MemRead(i32),
+    Load,
+    Store,
+    LoadLocal(i32),
+    StoreLocal(i32),
+    MemCopy(i32, i32, i32),
+    MemSet(i32, i32, Word),
+    MemCmp(i32, i32, i32),
+    Grow(i32),
+    MemSize,
+    Alloc,
+    Free,
+    Protect(i32, i32),
     Print(i32, i32),
+    PrintInt,
+    PrintStr(i32),
+    ReadLine(i32, i32),
+    ReadInt,
+
+    CodeRead,
+    CodeWrite,
+
+    PerfRead(i32),
+    VmInfo(i32),
+    ProfBegin(u32),
+    ProfEnd(u32),
+
+    Assert(Word),
+    AssertEq,
+}
+
+impl Instruction {
+    /// Returns the absolute instruction index a jump or call refers to.
+    pub fn jump_target(&self) -> Option<u32> {
+        match self {
+            Instruction::Jiz(target)
+            | Instruction::Jnz(target)
+            | Instruction::Jmp(target)
+            | Instruction::Call(target)
+            | Instruction::Try(target) => Some(*target),
+            _ => None,
+        }
+    }
+
+    /// Returns the absolute instruction index a jump or call refers to, for relocation.
+    pub fn jump_target_mut(&mut self) -> Option<&mut u32> {
+        match self {
+            Instruction::Jiz(target)
+            | Instruction::Jnz(target)
+            | Instruction::Jmp(target)
+            | Instruction::Call(target)
+            | Instruction::Try(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Returns the address each memory operand starts at: one for most memory
+    /// instructions, the destination and source of `MEMCOPY` and both ranges of `MEMCMP`.
+    pub fn memory_addresses(&self) -> Vec<i32> {
+        match self {
+            Instruction::MemWrite(addr, _)
+            | Instruction::MemWriteS(addr, _)
+            | Instruction::Print(addr, _)
+            | Instruction::Abort(addr, _)
+            | Instruction::ReadLine(addr, _)
+            | Instruction::Protect(addr, _)
+            | Instruction::MemSet(addr, _, _)
+            | Instruction::MemRead(addr)
+            | Instruction::PrintStr(addr) => vec![*addr],
+            Instruction::MemCopy(a, b, _) | Instruction::MemCmp(a, b, _) => vec![*a, *b],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction as assembly source that `split_instructions` parses back.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Null => write!(f, "NULL"),
+            Instruction::Push(value) => write!(f, "PUSH {}", value),
+            Instruction::Dup => write!(f, "DUP"),
+            Instruction::Swap => write!(f, "SWAP"),
+            Instruction::Rot => write!(f, "ROT"),
+            Instruction::Over => write!(f, "OVER"),
+            Instruction::Pick(depth) => write!(f, "PICK {}", depth),
+            Instruction::Depth => write!(f, "DEPTH"),
+            Instruction::Clear => write!(f, "CLEAR"),
+            Instruction::Pop => write!(f, "POP"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Halt(code) => write!(f, "HALT {}", code),
+            Instruction::Abort(addr, len) => write!(f, "ABORT {} {}", addr, len),
+            Instruction::Jiz(target) => write!(f, "JIZ {}", target),
+            Instruction::Jnz(target) => write!(f, "JNZ {}", target),
+            Instruction::Jmp(target) => write!(f, "JMP {}", target),
+            Instruction::JmpDyn => write!(f, "JMPDYN"),
+            Instruction::Call(target) => write!(f, "CALL {}", target),
+            Instruction::HostCall(name) => write!(f, "HOSTCALL {}", name),
+            Instruction::Try(target) => write!(f, "TRY {}", target),
+            Instruction::EndTry => write!(f, "ENDTRY"),
+            Instruction::Throw => write!(f, "THROW"),
+            Instruction::PrintInt => write!(f, "PRINTINT"),
+            Instruction::PrintStr(addr) => write!(f, "PRINTSTR {}", addr),
+            Instruction::ReadLine(addr, max_len) => write!(f, "READLINE {} {}", addr, max_len),
+            Instruction::ReadInt => write!(f, "READINT"),
+            Instruction::CodeRead => write!(f, "CODEREAD"),
+            Instruction::CodeWrite => write!(f, "CODEWRITE"),
+            Instruction::PerfRead(counter) => write!(f, "PERFREAD {}", counter),
+            Instruction::VmInfo(field) => write!(f, "VMINFO {}", field),
+            Instruction::ProfBegin(phase) => write!(f, "PROFBEGIN {}", phase),
+            Instruction::ProfEnd(phase) => write!(f, "PROFEND {}", phase),
+            Instruction::Assert(value) => write!(f, "ASSERT {}", value),
+            Instruction::AssertEq => write!(f, "ASSERTEQ"),
+            Instruction::AddS(value) => write!(f, "ADDS {}", value),
+            Instruction::Add => write!(f, "ADD"),
+            Instruction::SubS(value) => write!(f, "SUBS {}", value),
+            Instruction::Sub => write!(f, "SUB"),
+            Instruction::MultS(value) => write!(f, "MULTS {}", value),
+            Instruction::Mult => write!(f, "MULT"),
+            Instruction::DivS(value) => write!(f, "DIVS {}", value),
+            Instruction::Div => write!(f, "DIV"),
+            Instruction::ModS(value) => write!(f, "MODS {}", value),
+            Instruction::Mod => write!(f, "MOD"),
+            Instruction::Eq => write!(f, "EQ"),
+            Instruction::Neq => write!(f, "NEQ"),
+            Instruction::Lt => write!(f, "LT"),
+            Instruction::Gt => write!(f, "GT"),
+            Instruction::Lte => write!(f, "LTE"),
+            Instruction::Gte => write!(f, "GTE"),
+            Instruction::MemWrite(addr, values) => {
+                write!(f, "MEMWRITE {}", addr)?;
+                for value in values {
+                    write!(f, " {}", value)?;
+                }
+                Ok(())
+            }
+            Instruction::MemWriteS(addr, len) => write!(f, "MEMWRITES {} {}", addr, len),
+            Instruction::MemRead(addr) => write!(f, "MEMREAD {}", addr),
+            Instruction::Load => write!(f, "LOAD"),
+            Instruction::Store => write!(f, "STORE"),
+            Instruction::LoadLocal(index) => write!(f, "LOADLOCAL {}", index),
+            Instruction::StoreLocal(index) => write!(f, "STORELOCAL {}", index),
+            Instruction::MemCopy(dst, src, len) => write!(f, "MEMCOPY {} {} {}", dst, src, len),
+            Instruction::MemSet(addr, len, value) => write!(f, "MEMSET {} {} {}", addr, len, value),
+            Instruction::MemCmp(lhs, rhs, len) => write!(f, "MEMCMP {} {} {}", lhs, rhs, len),
+            Instruction::Grow(pages) => write!(f, "GROW {}", pages),
+            Instruction::MemSize => write!(f, "MEMSIZE"),
+            Instruction::Alloc => write!(f, "ALLOC"),
+            Instruction::Free => write!(f, "FREE"),
+            Instruction::Protect(addr, len) => write!(f, "PROTECT {} {}", addr, len),
+            Instruction::Print(addr, len) => write!(f, "PRINT {} {}", addr, len),
+        }
+    }
 }
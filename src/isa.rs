@@ -0,0 +1,349 @@
+use crate::instruction::Instruction;
+use crate::program::{Program, Requirements};
+
+/// An optional group of instructions that a VM build may not support.
+///
+/// Bytecode records the capabilities a program uses so a VM without one of them refuses
+/// to load it, instead of failing with an unknown opcode when the instruction is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// `HOSTCALL`, which calls into the embedding application.
+    HostCalls,
+    /// `CODEREAD` and `CODEWRITE`.
+    SelfModifying,
+    /// `READLINE` and `READINT`.
+    Input,
+}
+
+impl Capability {
+    /// Every capability, in bit order.
+    pub const ALL: [Capability; 3] = [Capability::HostCalls, Capability::SelfModifying, Capability::Input];
+
+    /// The bit that stands for this capability in a capability mask.
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// A human-readable name for error messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::HostCalls => "host calls",
+            Capability::SelfModifying => "self-modifying code",
+            Capability::Input => "input",
+        }
+    }
+
+    /// Returns the capability an instruction needs, if any.
+    pub fn of(instruction: &Instruction) -> Option<Capability> {
+        match instruction {
+            Instruction::HostCall(_) => Some(Capability::HostCalls),
+            Instruction::CodeRead | Instruction::CodeWrite => Some(Capability::SelfModifying),
+            Instruction::ReadLine(_, _) | Instruction::ReadInt => Some(Capability::Input),
+            _ => None,
+        }
+    }
+}
+
+/// The capabilities this build of the VM can execute.
+pub const SUPPORTED_CAPABILITIES: u32 = 0b111;
+
+/// Returns the capability mask of everything `instructions` use.
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::isa::{required_capabilities, Capability};
+///
+/// let mask = required_capabilities(&[Instruction::ReadInt, Instruction::PrintInt]);
+/// assert_eq!(mask, Capability::Input.bit());
+/// ```
+pub fn required_capabilities(instructions: &[Instruction]) -> u32 {
+    instructions.iter().filter_map(Capability::of).fold(0, |mask, capability| mask | capability.bit())
+}
+
+/// Checks that this build supports every capability in `mask`.
+///
+/// ```
+/// use vortex_vm::isa::{check_capabilities, Capability};
+///
+/// assert!(check_capabilities(Capability::HostCalls.bit()).is_ok());
+/// assert!(check_capabilities(1 << 20).unwrap_err().contains("lacks feature bit 20"));
+/// ```
+pub fn check_capabilities(mask: u32) -> Result<(), String> {
+    let missing = mask & !SUPPORTED_CAPABILITIES;
+    if missing == 0 {
+        return Ok(());
+    }
+
+    let features: Vec<String> = (0..u32::BITS)
+        .filter(|bit| missing & (1 << bit) != 0)
+        .map(|bit| match Capability::ALL.iter().find(|capability| capability.bit() == 1 << bit) {
+            Some(capability) => format!("'{}'", capability.name()),
+            None => format!("bit {}", bit),
+        })
+        .collect();
+    Err(format!(
+        "This VM build lacks feature {} used by the program; upgrade the VM or re-assemble without it",
+        features.join(", ")
+    ))
+}
+
+/// A released version of the instruction set, for assembling programs that older VMs can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IsaVersion {
+    /// The original stack, arithmetic, memory and conditional jump instructions, without
+    /// data sections, requirements or capability masks.
+    V1_0,
+    /// Adds `JMP`, calls, host calls, input, self-modifying code, comparisons, `PRINTINT`,
+    /// `PRINTSTR`, `HALT`, 64-bit pushes, data sections, requirements and capability masks.
+    /// Operands are fixed-width and data values are stored as i64.
+    V1_1,
+    /// Adds the instructions from `ROT` to `VMINFO` listed in [`IsaVersion::supports`],
+    /// 64-bit arithmetic immediates and `MEMWRITE` values, varint operands, packed data, and
+    /// the debug and compressed bytecode sections.
+    V1_2,
+}
+
+impl IsaVersion {
+    /// The version this build assembles for by default.
+    pub const LATEST: IsaVersion = IsaVersion::V1_2;
+
+    /// Parses a version name such as `1.0`.
+    pub fn parse(name: &str) -> Result<IsaVersion, String> {
+        match name {
+            "1.0" => Ok(IsaVersion::V1_0),
+            "1.1" => Ok(IsaVersion::V1_1),
+            "1.2" => Ok(IsaVersion::V1_2),
+            _ => Err(format!("Unknown ISA version '{}' (expected 1.0, 1.1 or 1.2)", name)),
+        }
+    }
+
+    /// The version's name as accepted by [`IsaVersion::parse`].
+    pub fn name(self) -> &'static str {
+        match self {
+            IsaVersion::V1_0 => "1.0",
+            IsaVersion::V1_1 => "1.1",
+            IsaVersion::V1_2 => "1.2",
+        }
+    }
+
+    /// The version as `major * 100 + minor`, which is how `VMINFO` reports it.
+    pub fn number(self) -> i64 {
+        match self {
+            IsaVersion::V1_0 => 100,
+            IsaVersion::V1_1 => 101,
+            IsaVersion::V1_2 => 102,
+        }
+    }
+
+    /// Returns whether VMs implementing this version can decode `instruction`.
+    pub fn supports(self, instruction: &Instruction) -> bool {
+        match self {
+            IsaVersion::V1_0 => Self::in_1_0(instruction),
+            IsaVersion::V1_1 => !has_wide_value(instruction) && !matches!(
+                instruction,
+                Instruction::Rot
+                    | Instruction::Over
+                    | Instruction::Pick(_)
+                    | Instruction::Depth
+                    | Instruction::Clear
+                    | Instruction::Abort(..)
+                    | Instruction::Try(_)
+                    | Instruction::EndTry
+                    | Instruction::Throw
+                    | Instruction::Load
+                    | Instruction::Store
+                    | Instruction::LoadLocal(_)
+                    | Instruction::StoreLocal(_)
+                    | Instruction::MemCopy(..)
+                    | Instruction::MemSet(..)
+                    | Instruction::MemCmp(..)
+                    | Instruction::Grow(_)
+                    | Instruction::MemSize
+                    | Instruction::Alloc
+                    | Instruction::Free
+                    | Instruction::Protect(..)
+                    | Instruction::ProfBegin(_)
+                    | Instruction::ProfEnd(_)
+                    | Instruction::Assert(_)
+                    | Instruction::AssertEq
+                    | Instruction::VmInfo(_)
+            ),
+            IsaVersion::V1_2 => true,
+        }
+    }
+
+    /// Returns whether `instruction` was part of ISA 1.0.
+    fn in_1_0(instruction: &Instruction) -> bool {
+        if has_wide_value(instruction) {
+            return false;
+        }
+        match instruction {
+            // 64-bit pushes use an encoding added in 1.1
+            Instruction::Push(value) => i32::try_from(*value).is_ok(),
+            Instruction::Null
+            | Instruction::Dup
+            | Instruction::Swap
+            | Instruction::Pop
+            | Instruction::Ret
+            | Instruction::Jiz(_)
+            | Instruction::Jnz(_)
+            | Instruction::AddS(_)
+            | Instruction::Add
+            | Instruction::SubS(_)
+            | Instruction::Sub
+            | Instruction::MultS(_)
+            | Instruction::Mult
+            | Instruction::DivS(_)
+            | Instruction::Div
+            | Instruction::MemWrite(_, _)
+            | Instruction::MemWriteS(_, _)
+            | Instruction::MemRead(_)
+            | Instruction::Print(_, _) => true,
+            _ => false,
+        }
+    }
+
+    /// Checks that `program` only uses instructions and bytecode sections this version has,
+    /// listing every problem, one per line.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::isa::IsaVersion;
+    /// use vortex_vm::program::Program;
+    ///
+    /// let program = Program::new(vec![Instruction::Push(1), Instruction::Jmp(0)]);
+    /// assert!(IsaVersion::V1_1.check_program(&program).is_ok());
+    /// assert_eq!(
+    ///     IsaVersion::V1_0.check_program(&program).unwrap_err(),
+    ///     "instruction 1: 'JMP' is not available in ISA 1.0"
+    /// );
+    /// ```
+    pub fn check_program(self, program: &Program) -> Result<(), String> {
+        let mut problems = Vec::new();
+        for (index, instruction) in program.instructions.iter().enumerate() {
+            if !self.supports(instruction) {
+                let text = instruction.to_string();
+                let mnemonic = match instruction {
+                    // Only the operand keeps these out, so show it
+                    Instruction::Push(_) => text.as_str(),
+                    _ if has_wide_value(instruction) => text.as_str(),
+                    _ => text.split_whitespace().next().unwrap_or_default(),
+                };
+                problems.push(format!(
+                    "{}: '{}' is not available in ISA {}",
+                    program.debug_info.describe_location(index),
+                    mnemonic,
+                    self.name()
+                ));
+            }
+        }
+        if self < IsaVersion::V1_1 {
+            if !program.data.is_empty() {
+                problems.push(format!("Data sections are not available in ISA {}", self.name()));
+            }
+            if program.requirements != Requirements::default() {
+                problems.push(format!(".requires is not available in ISA {}", self.name()));
+            }
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(problems.join("\n")) }
+    }
+}
+
+/// Returns whether an arithmetic immediate or a `MEMWRITE` value of `instruction` needs
+/// more than 32 bits, which only ISA 1.2 can encode.
+fn has_wide_value(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::AddS(value)
+        | Instruction::SubS(value)
+        | Instruction::MultS(value)
+        | Instruction::DivS(value)
+        | Instruction::ModS(value) => i32::try_from(*value).is_err(),
+        Instruction::MemWrite(_, values) => values.iter().any(|&value| i32::try_from(value).is_err()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_bits_are_distinct_and_supported() {
+        let mask = Capability::ALL.iter().fold(0, |mask, capability| {
+            assert_eq!(mask & capability.bit(), 0);
+            mask | capability.bit()
+        });
+        assert_eq!(mask, SUPPORTED_CAPABILITIES);
+    }
+
+    #[test]
+    fn test_required_capabilities() {
+        let program = [
+            Instruction::HostCall("rand".to_string()),
+            Instruction::CodeWrite,
+            Instruction::Push(1),
+            Instruction::HostCall("time".to_string()),
+        ];
+        assert_eq!(required_capabilities(&program), Capability::HostCalls.bit() | Capability::SelfModifying.bit());
+        assert_eq!(required_capabilities(&[Instruction::Ret]), 0);
+    }
+
+    #[test]
+    fn test_missing_features_are_listed() {
+        let err = check_capabilities(SUPPORTED_CAPABILITIES | 1 << 3 | 1 << 31).unwrap_err();
+        assert!(err.starts_with("This VM build lacks feature bit 3, bit 31 used by the program"), "{}", err);
+    }
+
+    #[test]
+    fn test_isa_version_names() {
+        for version in [IsaVersion::V1_0, IsaVersion::V1_1, IsaVersion::V1_2] {
+            assert_eq!(IsaVersion::parse(version.name()), Ok(version));
+        }
+        assert!(IsaVersion::parse("2.0").is_err());
+    }
+
+    #[test]
+    fn test_isa_1_0_rejects_newer_features() {
+        let mut program = Program::new(vec![
+            Instruction::Push(1),
+            Instruction::Push(1 << 40),
+            Instruction::PrintInt,
+            Instruction::MemRead(0),
+        ]);
+        program.data = vec![crate::program::DataBlock { start: 0, values: vec![1] }];
+
+        let err = IsaVersion::V1_0.check_program(&program).unwrap_err();
+        let problems: Vec<&str> = err.lines().collect();
+        assert_eq!(problems, vec![
+            "instruction 1: 'PUSH 1099511627776' is not available in ISA 1.0",
+            "instruction 2: 'PRINTINT' is not available in ISA 1.0",
+            "Data sections are not available in ISA 1.0",
+        ]);
+        assert!(IsaVersion::LATEST.check_program(&program).is_ok());
+    }
+
+    #[test]
+    fn test_isa_1_1_rejects_newer_instructions() {
+        let program = Program::new(vec![
+            Instruction::Jmp(1),
+            Instruction::Push(1 << 40),
+            Instruction::Rot,
+            Instruction::VmInfo(1),
+            Instruction::PrintInt,
+            Instruction::AddS(1 << 40),
+            Instruction::MemWrite(0, vec![1, -1 << 40]),
+            Instruction::MultS(-7),
+        ]);
+
+        let err = IsaVersion::V1_1.check_program(&program).unwrap_err();
+        let problems: Vec<&str> = err.lines().collect();
+        assert_eq!(problems, vec![
+            "instruction 2: 'ROT' is not available in ISA 1.1",
+            "instruction 3: 'VMINFO' is not available in ISA 1.1",
+            "instruction 5: 'ADDS 1099511627776' is not available in ISA 1.1",
+            "instruction 6: 'MEMWRITE 0 1 -1099511627776' is not available in ISA 1.1",
+        ]);
+        assert!(IsaVersion::V1_2.check_program(&program).is_ok());
+    }
+}
@@ -1,4 +1,52 @@
 pub mod run;
 pub mod instruction;
+#[cfg(feature = "assembler")]
 pub mod spliter;
+#[cfg(any(feature = "assembler", feature = "disassembler"))]
 pub mod assembler;
+pub mod encoding;
+pub mod program;
+#[cfg(feature = "cli")]
+pub mod dump;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+#[cfg(feature = "debugger")]
+pub mod repl;
+#[cfg(feature = "debugger")]
+pub mod line_editor;
+#[cfg(feature = "assembler")]
+pub mod render;
+#[cfg(feature = "assembler")]
+pub mod formatter;
+#[cfg(feature = "assembler")]
+pub mod lint;
+#[cfg(feature = "assembler")]
+pub mod size;
+pub mod diagnostics;
+#[cfg(feature = "cli")]
+pub mod events;
+#[cfg(feature = "assembler")]
+pub mod include;
+pub mod isa;
+#[cfg(any(feature = "assembler", feature = "disassembler", feature = "optimizer"))]
+pub mod analysis;
+#[cfg(any(feature = "assembler", feature = "disassembler"))]
+pub mod verify;
+#[cfg(feature = "compiled")]
+pub mod compiled;
+pub mod heap;
+pub mod profile;
+#[cfg(feature = "cli")]
+pub mod trace_diff;
+#[cfg(feature = "debugger")]
+pub mod canvas;
+pub mod input;
+#[cfg(feature = "cli")]
+pub mod manifest;
+#[cfg(feature = "cli")]
+pub mod bindiff;
+#[cfg(feature = "devices")]
+pub mod hooks;
+#[cfg(feature = "devices")]
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+mod toml;
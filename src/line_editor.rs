@@ -0,0 +1,124 @@
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::env;
+use std::path::PathBuf;
+
+/// Line editing with history and tab completion for the interactive prompts.
+///
+/// History is loaded from and saved to a file in the user's home directory so it
+/// survives between sessions. The words offered for completion can change as the
+/// session goes on (e.g. when new labels are defined), see [`LineEditor::set_completions`].
+pub struct LineEditor {
+    editor: Editor<WordCompleter, FileHistory>,
+    history_path: Option<PathBuf>,
+}
+
+impl LineEditor {
+    /// Creates an editor whose history is kept in `~/<history_file>`.
+    ///
+    /// History is only kept in memory if the home directory is unknown.
+    pub fn new(history_file: &str) -> Result<Self, String> {
+        let mut editor = Editor::new().map_err(|e| format!("Failed to start line editor: {}", e))?;
+        editor.set_helper(Some(WordCompleter { words: Vec::new() }));
+
+        let history_path = env::var_os("HOME").map(|home| PathBuf::from(home).join(history_file));
+        if let Some(path) = &history_path {
+            // A missing history file just means this is the first session
+            let _ = editor.load_history(path);
+        }
+
+        Ok(LineEditor { editor, history_path })
+    }
+
+    /// Replaces the words offered when the user presses tab.
+    pub fn set_completions(&mut self, words: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.words = words;
+        }
+    }
+
+    /// Reads one line, returning `None` at end of input or when the user presses Ctrl-D.
+    ///
+    /// Ctrl-C discards the current line and returns an empty one.
+    pub fn read_line(&mut self, prompt: &str) -> Option<String> {
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    let _ = self.editor.add_history_entry(line.as_str());
+                    if let Some(path) = &self.history_path {
+                        let _ = self.editor.save_history(path);
+                    }
+                }
+                Some(line)
+            }
+            Err(ReadlineError::Interrupted) => Some(String::new()),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Completes the word under the cursor from a fixed list of words.
+struct WordCompleter {
+    words: Vec<String>,
+}
+
+impl Completer for WordCompleter {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_word(line, pos, &self.words))
+    }
+}
+
+impl Hinter for WordCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for WordCompleter {}
+
+impl Validator for WordCompleter {}
+
+impl Helper for WordCompleter {}
+
+/// Returns where the word ending at `pos` starts and the words it could be completed to.
+///
+/// Matching ignores case since mnemonics are case-insensitive.
+fn complete_word(line: &str, pos: usize, words: &[String]) -> (usize, Vec<String>) {
+    let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let prefix = line[start..pos].to_lowercase();
+
+    let candidates = words
+        .iter()
+        .filter(|word| word.to_lowercase().starts_with(&prefix))
+        .cloned()
+        .collect();
+    (start, candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words() -> Vec<String> {
+        ["MEMREAD", "MEMWRITE", "MEMWRITES", "MULT", "main"].iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_completes_word_under_cursor() {
+        let (start, candidates) = complete_word("memw", 4, &words());
+        assert_eq!(start, 0);
+        assert_eq!(candidates, vec!["MEMWRITE", "MEMWRITES"]);
+    }
+
+    #[test]
+    fn test_completes_operands() {
+        let (start, candidates) = complete_word("CALL ma", 7, &words());
+        assert_eq!(start, 5);
+        assert_eq!(candidates, vec!["main"]);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::analysis::{may_push_nothing, stack_effect, successors, unreachable_code};
+use crate::instruction::Instruction;
+use crate::program::Program;
+use crate::spliter::tokenize_line;
+use crate::verify::{check_program, VerifyError};
+use std::collections::HashSet;
+use std::fmt;
+
+/// What a [`Lint`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintKind {
+    /// A label that no jump, call or `TRY` refers to.
+    UnusedLabel,
+    /// Instructions that can never run, e.g. after a `RET`.
+    UnreachableCode,
+    /// A jump or call to an index past the end of the program.
+    JumpPastEnd,
+    /// A memory operand or data beyond the end of memory.
+    MemoryOutOfRange,
+    /// An instruction that takes more values than the stack ever holds there.
+    StackUnderflow,
+    /// A `JIZ` or `JNZ` that some path reaches with nothing on the stack to test.
+    EmptyStackBranch,
+}
+
+impl LintKind {
+    /// The name the lint is reported under.
+    pub fn name(self) -> &'static str {
+        match self {
+            LintKind::UnusedLabel => "unused-label",
+            LintKind::UnreachableCode => "unreachable-code",
+            LintKind::JumpPastEnd => "jump-past-end",
+            LintKind::MemoryOutOfRange => "memory-out-of-range",
+            LintKind::StackUnderflow => "stack-underflow",
+            LintKind::EmptyStackBranch => "empty-stack-branch",
+        }
+    }
+}
+
+/// A warning about a program that assembles but probably does not do what was meant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub kind: LintKind,
+    /// The 1-based source line the lint is about, if it is about one.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "warning[{}]: {}", self.kind.name(), self.message)
+    }
+}
+
+/// Looks for likely mistakes in `program`, assembled from `source`: labels nothing refers
+/// to, code that can never run, jumps past the end, memory operands beyond `memory_size`
+/// cells, certain stack underflows and conditional jumps that may find the stack empty.
+/// Lints come in the order of their lines.
+///
+/// The label of the first instruction is the entry point and never counts as unused.
+///
+/// ```
+/// use vortex_vm::lint::{lint, LintKind};
+/// use vortex_vm::spliter::parse_program;
+///
+/// let source = "main:\nPUSH 1\nRET\nPUSH 2\nunused:\nMEMWRITE 5000 1";
+/// let lints = lint(&parse_program(source).unwrap(), source, 2048);
+/// let found: Vec<_> = lints.iter().map(|lint| (lint.kind, lint.line)).collect();
+/// assert_eq!(found, [
+///     (LintKind::UnreachableCode, Some(4)),
+///     (LintKind::UnusedLabel, Some(5)),
+///     (LintKind::MemoryOutOfRange, Some(6)),
+/// ]);
+/// ```
+pub fn lint(program: &Program, source: &str, memory_size: usize) -> Vec<Lint> {
+    let line_of = |pc: usize| program.debug_info.source_line(pc);
+    let mut lints = unused_labels(program, source);
+
+    for run in unreachable_code(program) {
+        let message = format!("{} instruction(s) can never run", run.len());
+        lints.push(Lint { kind: LintKind::UnreachableCode, line: line_of(run.start), message });
+    }
+
+    let mut underflows = HashSet::new();
+    for error in check_program(program, memory_size).err().unwrap_or_default() {
+        let kind = match error {
+            VerifyError::JumpOutOfRange { .. } => LintKind::JumpPastEnd,
+            VerifyError::MemoryOutOfRange { .. } | VerifyError::MemoryTooSmall { .. } => LintKind::MemoryOutOfRange,
+            VerifyError::CertainUnderflow { pc, .. } => {
+                underflows.insert(pc);
+                LintKind::StackUnderflow
+            }
+            _ => continue,
+        };
+        lints.push(Lint { kind, line: error.pc().and_then(line_of), message: error.describe(program) });
+    }
+
+    for pc in empty_stack_branches(&program.instructions).into_iter().filter(|pc| !underflows.contains(pc)) {
+        let instruction = program.instructions[pc].to_string();
+        let mnemonic = instruction.split_whitespace().next().unwrap_or_default();
+        let message = format!("{} at instruction {} may run with nothing on the stack to test", mnemonic, pc);
+        lints.push(Lint { kind: LintKind::EmptyStackBranch, line: line_of(pc), message });
+    }
+
+    lints.sort_by_key(|lint| (lint.line, lint.kind));
+    lints
+}
+
+/// Finds the labels defined in the code of `source` that no jump, call or `TRY` names.
+fn unused_labels(program: &Program, source: &str) -> Vec<Lint> {
+    let mut referenced = HashSet::new();
+    let mut definitions = Vec::new();
+    let mut in_data = false;
+    for (index, line) in source.lines().enumerate() {
+        let parts = tokenize_line(line).parts;
+        match parts.as_slice() {
+            [section, ..] if section.eq_ignore_ascii_case(".data") => in_data = true,
+            [section] if section.eq_ignore_ascii_case(".text") => in_data = false,
+            [label] if !in_data && label.ends_with(':') => definitions.push((label.trim_end_matches(':').to_string(), index + 1)),
+            [mnemonic, target] if ["JIZ", "JNZ", "JMP", "CALL", "TRY"].iter().any(|jump| mnemonic.eq_ignore_ascii_case(jump)) => {
+                referenced.insert(target.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    let labels = &program.debug_info.labels;
+    definitions
+        .into_iter()
+        .filter(|(name, _)| !referenced.contains(name))
+        .filter(|(name, _)| labels.iter().any(|(label, index)| label == name && *index != 0))
+        .map(|(name, line)| Lint { kind: LintKind::UnusedLabel, line: Some(line), message: format!("label '{}' is never used", name) })
+        .collect()
+}
+
+/// Finds the `JIZ`s and `JNZ`s that some path from the entry reaches with an empty stack.
+///
+/// Paths are not followed through calls, host calls or `TRY` handlers, where the depth of
+/// the stack depends on code elsewhere, nor in programs using `JMPDYN` or `CODEWRITE`.
+fn empty_stack_branches(instructions: &[Instruction]) -> Vec<usize> {
+    let opaque = instructions.iter().any(|instruction| matches!(instruction, Instruction::JmpDyn | Instruction::CodeWrite));
+    if opaque {
+        return Vec::new();
+    }
+
+    // The shallowest the stack gets before each instruction; only a shallower depth
+    // than seen before is worth following again, so this ends
+    let mut depths: Vec<Option<usize>> = vec![None; instructions.len()];
+    let mut work = vec![(0, 0)];
+    while let Some((pc, depth)) = work.pop() {
+        let Some(instruction) = instructions.get(pc) else {
+            continue;
+        };
+        if depths[pc].is_some_and(|known| known <= depth) {
+            continue;
+        }
+        depths[pc] = Some(depth);
+
+        let (pops, pushes) = stack_effect(instruction);
+        let after = match instruction {
+            Instruction::Call(_) | Instruction::HostCall(_) => continue,
+            Instruction::Try(_) => {
+                work.push((pc + 1, depth));
+                continue;
+            }
+            Instruction::Clear => 0,
+            // An instruction that underflows is skipped
+            _ if pops as usize > depth => depth,
+            // The shallowest case is a division by zero, which pushes nothing
+            _ if may_push_nothing(instruction) => depth - pops as usize,
+            _ => depth - pops as usize + pushes as usize,
+        };
+        work.extend(successors(instruction, pc).into_iter().map(|next| (next, after)));
+    }
+
+    (0..instructions.len())
+        .filter(|&pc| matches!(instructions[pc], Instruction::Jiz(_) | Instruction::Jnz(_)) && depths[pc] == Some(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spliter::parse_program;
+
+    fn kinds(source: &str) -> Vec<(LintKind, Option<usize>)> {
+        lint(&parse_program(source).unwrap(), source, 2048).iter().map(|lint| (lint.kind, lint.line)).collect()
+    }
+
+    #[test]
+    fn test_clean_program_has_no_lints() {
+        let source = ".data 0\nmsg:\n.string \"hi\"\n.text\nmain:\nPUSH 3\nloop:\nSUBS 1\nJNZ loop\nPRINT msg 2\nCALL done\nRET\ndone:\nRET";
+        assert_eq!(kinds(source), []);
+    }
+
+    #[test]
+    fn test_jumps_and_branches() {
+        // The JNZ finds the stack empty only after the POP
+        let source = "PUSH 1\nJIZ zero\nPOP\nzero:\nJNZ 0\nJMP 2\nPOP";
+        let mut program = parse_program(source).unwrap();
+        // The parser refuses targets past the end, but bytecode can still hold them
+        program.instructions[4] = Instruction::Jmp(99);
+        let found: Vec<_> = lint(&program, source, 2048).iter().map(|lint| (lint.kind, lint.line)).collect();
+        assert_eq!(found, [
+            (LintKind::EmptyStackBranch, Some(5)),
+            (LintKind::JumpPastEnd, Some(6)),
+            (LintKind::UnreachableCode, Some(7)),
+        ]);
+
+        // A branch that always underflows is only reported once
+        assert_eq!(kinds("PUSH 1\nPOP\nJIZ 0"), [(LintKind::StackUnderflow, Some(3))]);
+        // Depths after calls are not known, so nothing is reported there
+        assert_eq!(kinds("CALL f\nJNZ 0\nRET\nf:\nPUSH 1\nRET"), []);
+    }
+}
@@ -1,8 +1,35 @@
-use vortex_vm::assembler::load_bytecode_file;
-use vortex_vm::run::execute;
+use vortex_vm::analysis::analyze;
+use vortex_vm::canvas::StackCanvas;
+use vortex_vm::bindiff::{Change, Hunk, Patch};
+use vortex_vm::assembler::{assemble_program, compile_expanded, disassemble_program, is_bytecode, AssembleOptions, BYTECODE_MAGIC};
+use vortex_vm::debugger::{Debugger, ReloadOutcome, StopReason};
+use vortex_vm::diagnostic;
+use vortex_vm::diagnostics::{self, Level};
+use vortex_vm::events::{Event, EventStream};
+use vortex_vm::formatter::format_source;
+use vortex_vm::hooks::Hooks;
+use vortex_vm::include::{expand_source, read_source, ExpandedSource};
+use vortex_vm::dump::CrashDump;
+use vortex_vm::line_editor::LineEditor;
+use vortex_vm::manifest::{program_hash, RunManifest, RunOutcome};
+use vortex_vm::lint::lint;
+use vortex_vm::input::{FileInput, InputSource, RecordingInput, StdinInput, StringInput};
+use vortex_vm::instruction::Word;
+use vortex_vm::isa::{required_capabilities, Capability, IsaVersion};
+use vortex_vm::run::{ArithmeticMode, OutputEncoding, TraceEvent, TraceSink, UninitReadMode, Vm, VmConfig, VmError, MEMORY_SIZE, POISON_PATTERN};
+use vortex_vm::program::Program;
+use vortex_vm::render::Notebook;
+use vortex_vm::repl::Repl;
+use vortex_vm::size::SizeReport;
+use vortex_vm::spliter::{split_instructions_with_lines, try_parse_program_with};
+use vortex_vm::verify::check_program;
+use vortex_vm::trace_diff::{diff_traces, TraceDiff};
 use std::env;
 use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::SystemTime;
 fn print_usage() {
     println!("Vortex VM - Stack-Based Virtual Machine");
     println!("Version: {}", env!("CARGO_PKG_VERSION"));
@@ -11,18 +38,82 @@ fn print_usage() {
     println!("    vortex-vm <COMMAND> [OPTIONS]");
     println!();
     println!("COMMANDS:");
-    println!("    run <file>     Execute a .vvm or .asv file (.asv files are assembled first)");
-    println!("    assemble <input.asv> <output.vvm>    Assemble .asv file to .vvm bytecode");
+    println!("    run <file|->   Execute a program, assembling it first unless it is bytecode (- reads it from standard input)");
+    println!("    assemble <input.vvm> <output.asv>    Assemble .vvm source to .asv bytecode");
+    println!("    disassemble <file>    Print bytecode as assembly source, with the labels kept by 'assemble --debug'");
+    println!("    debug <file>   Step through a .vvm or .asv file interactively");
+    println!("    repl           Execute assembly one line at a time, showing the stack after each line");
+    println!("    info <file>    Summarize a program without running it: stack depth, memory, loops and calls");
+    println!("    size <file>    Break down the size of a program's bytecode by instruction class and suggest cheaper encodings");
+    println!("    inspect-dump <file>    Pretty-print a crash dump written by 'run --crash-dump'");
+    println!("    rerun <manifest>       Repeat a run recorded with 'run --emit-manifest' and check that it ends the same way");
+    println!("    verify <file>  Check a program for jumps outside it, memory beyond --mem-size and certain stack underflows");
+    println!("    fmt <file>...  Format assembly sources in place: casing, indentation, operand spacing and comment alignment");
+    println!("    check <file>   Assemble and verify a program without writing any files, reporting every problem and exiting non-zero if there are any");
+    println!("    lint <file>    Warn about unused labels, unreachable code, jumps past the end, memory beyond --mem-size and branches on an empty stack");
+    println!("    test <dir>     Run every .vvm and .asv program in <dir> (with <name>.in as input) and report those that trap or HALT with a non-zero code (takes the VM options of 'run')");
+    println!("    trace-diff <old> <new>    Run two programs on the same input and report the first step where their stacks differ (takes the VM options of 'run')");
+    println!("    bindiff <old> <new> [--output <patch>]    List the instructions added, removed and changed between two programs and optionally write a patch");
+    println!("    patch <program> <patch> <output.asv>      Apply a patch written by 'bindiff --output' and write the result as bytecode");
+    println!("    render <file.vvm> --html [--output <path>]    Run an annotated program section by section into an HTML report");
     println!("    help           Show this help message");
     println!();
     println!("OPTIONS:");
     println!("    --version      Show version information");
     println!("    --help         Show this help message");
+    println!("    --crash-dump <path>    (run) Write a crash dump to <path> if the program traps");
+    println!("    --trace                (run) Print every executed instruction with the stack before and after to stderr");
+    println!("    --stack-canvas         (run) Draw the stack after every instruction to stderr, marking the values it pushed");
+    println!("    --exit-summary         (run) Report where and why the program stopped, by label when running a .vvm source");
+    println!("    --profile              (run) Report the steps and time spent in each PROFBEGIN/PROFEND phase to stderr");
+    println!("    --hooks <file>         (run) Define HOSTCALL functions and Print destinations from a TOML hook file");
+    println!("    --emit-manifest <path> (run) Record the program hash, configuration and input read to <path> for 'rerun'");
+    println!("    --input <file>         (run, trace-diff) Give READLINE/READINT the lines of <file> instead of stdin");
+    println!("    --events-json <path>   (run, debug) Write newline-delimited JSON events to <path> ('-' for stderr)");
+    println!("    --max-steps <n>        (run, debug, repl) Trap once <n> instructions have executed");
+    println!("    --max-stack <n>        (run, debug, repl) Trap once the stack holds more than <n> values");
+    println!("    --mem-size <cells>     (run, debug, repl, verify, check, lint) Give the program <cells> memory cells instead of 2048 (.requires mem can raise it)");
+    println!("    --max-mem-size <cells> (run, debug, repl) Let GROW add memory up to <cells> cells (default 4194304)");
+    println!("    --mem-init <value>     (run, debug, repl) Initialize memory to <value>, 'zero' (default) or 'poison' (0xDEADBEEF)");
+    println!("    --self-modifying       (run, debug, repl) Allow CODEREAD/CODEWRITE to read and replace instructions");
+    println!("    --overflow <mode>      (run, debug, repl) Arithmetic overflow: wrap (default), saturate or trap");
+    println!("    --output-encoding <e>  (run, debug, repl) How PRINT writes bytes: latin1 (default), raw, utf8 (raw, checked) or hex");
+    println!("    --strict               (run, debug, repl) Trap on stack underflow, division by zero and out-of-bounds memory access");
+    println!("    --protect-data         (run, debug, repl) Make .data read-only, so writes to it trap");
+    println!("    --catch-traps          (run, debug, repl) Send runtime traps inside a TRY to its handler with a negative code");
+    println!("    --uninit-reads <mode>  (run, debug, repl) Report reads of never-written memory: off (default), warn or trap");
+    println!("    --target-isa <version> (assemble) Only accept instructions from ISA <version> (1.0, 1.1 or 1.2, the default)");
+    println!("    --check                (fmt) Only list the files that are not formatted, exiting with status 1 if there are any");
+    println!("    --optimize             (assemble) Remove code that can never run instead of warning about it");
+    println!("    --debug                (assemble) Keep source lines and label names in the bytecode for traces and the debugger");
+    println!("    --compress             (assemble) Compress the instructions and data of the bytecode (zlib)");
+    println!("    -I <dir>               Also look for %include'd files in <dir> (may be repeated)");
+    println!("    -v, -vv, -vvv          Report loader/assembler progress (info), details (debug) or every instruction (trace)");
+    println!();
+    println!("ENVIRONMENT:");
+    println!("    VORTEX_LOG=<level>     Diagnostics level when no -v flag is given: error, warn (default), info, debug, trace");
     println!();
     println!("EXAMPLES:");
     println!("    vortex-vm run program.vvm");
-    println!("    vortex-vm run program.asv    # Assembles first, then runs");
-    println!("    vortex-vm assemble program.asv program.vvm");
+    println!("    vortex-vm run program.asv    # Loads bytecode");
+    println!("    vortex-vm run - < program.vvm    # Assembles first, then runs");
+    println!("    vortex-vm assemble program.vvm program.asv");
+    println!("    vortex-vm run program.vvm --crash-dump crash.txt");
+    println!("    vortex-vm run program.vvm --mem-init poison");
+    println!("    vortex-vm run program.vvm --hooks hooks.toml");
+    println!("    vortex-vm run program.vvm --emit-manifest run.toml");
+    println!("    vortex-vm rerun run.toml");
+    println!("    vortex-vm run program.vvm --mem-size 65536");
+    println!("    vortex-vm render lesson.vvm --html --output lesson.html");
+    println!("    vortex-vm verify untrusted.asv --mem-size 4096");
+    println!("    vortex-vm test examples --strict");
+    println!("    vortex-vm trace-diff old.vvm new.vvm < input.txt");
+    println!("    vortex-vm run program.vvm -vv");
+    println!("    vortex-vm run program.vvm -I lib");
+    println!("    vortex-vm assemble program.vvm program.asv --target-isa 1.0");
+    println!("    vortex-vm assemble program.vvm program.asv --optimize");
+    println!("    vortex-vm assemble program.vvm program.asv --debug");
+    println!("    vortex-vm assemble program.vvm program.asv --compress");
     println!("    vortex-vm --help");
 }
 
@@ -30,8 +121,8 @@ fn print_version() {
     println!("Vortex VM version {}", env!("CARGO_PKG_VERSION"));
 }
 
-fn assemble_file_to_path(input_file: &str, output_file: &str) {
-    match vortex_vm::assembler::assemble_file(input_file, output_file) {
+fn assemble_file_to_path(input_file: &str, output_file: &str, include_paths: &[PathBuf], options: &AssembleOptions) {
+    match vortex_vm::assembler::assemble_file_with(input_file, output_file, include_paths, options) {
         Ok(()) => {
             println!("Successfully assembled '{}' to '{}'", input_file, output_file);
         }
@@ -42,62 +133,1158 @@ fn assemble_file_to_path(input_file: &str, output_file: &str) {
     }
 }
 
-fn run_file(filename: &str) {
-    let instructions = if filename.ends_with(".vvm") {
-        // For .asv files, assemble them first to a temporary .vvm file
-        println!("Assembling '{}' to bytecode...", filename);
-        let temp_filename = filename.replace(".vvm", "_temp.asv");
+/// The name standard input goes by in messages when a program is read from `-`.
+const STDIN_NAME: &str = "<stdin>";
+
+/// The contents of a program file, told apart by the bytecode header rather than the
+/// file's extension.
+enum ProgramFile {
+    Bytecode(Program),
+    /// Assembly source with its includes expanded.
+    Source(ExpandedSource),
+}
+
+/// Reads the program in `filename`, or on standard input if it is `-`. A file starting with
+/// the bytecode header is loaded as bytecode and anything else is read as assembly source,
+/// whatever its name.
+fn read_program_file(filename: &str, include_paths: &[PathBuf]) -> Result<ProgramFile, String> {
+    let (bytes, path) = if filename == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).map_err(|e| format!("Failed to read standard input: {}", e))?;
+        (bytes, Path::new(STDIN_NAME))
+    } else {
+        let bytes = fs::read(filename).map_err(|e| format!("Failed to read file '{}': {}", filename, e))?;
+        (bytes, Path::new(filename))
+    };
+
+    if is_bytecode(&bytes) {
+        let program = disassemble_program(&bytes).map_err(|e| format!("Failed to load bytecode file '{}': {}", filename, e))?;
+        diagnostic!(Level::Info, "Loaded {} instructions from '{}'", program.instructions.len(), path.display());
+        return Ok(ProgramFile::Bytecode(program));
+    }
+    let text = String::from_utf8(bytes).map_err(|_| format!("'{}' is neither bytecode nor assembly source", path.display()))?;
+    expand_source(&text, path, include_paths).map(ProgramFile::Source)
+}
+
+/// Loads the program in `filename` for running, compiling it in memory if it is source. The
+/// expanded source comes along for the lines crash dumps quote.
+fn load_program(filename: &str, include_paths: &[PathBuf]) -> (Program, Option<ExpandedSource>) {
+    let file = read_program_file(filename, include_paths).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    match file {
+        ProgramFile::Bytecode(program) => (program, None),
+        ProgramFile::Source(source) => {
+            diagnostic!(Level::Info, "Assembling '{}'...", filename);
+            match compile_expanded(&source, &AssembleOptions::default()) {
+                Ok(mut program) => {
+                    program.debug_info.file = source_file(filename, &source);
+                    (program, Some(source))
+                }
+                Err(e) => {
+                    eprintln!("Error: Failed to assemble file '{}': {}", filename, e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+/// Options shared by the `run`, `debug` and `repl` commands.
+#[derive(Default)]
+struct RunOptions {
+    crash_dump_path: Option<String>,
+    trace: bool,
+    stack_canvas: bool,
+    exit_summary: bool,
+    profile: bool,
+    events_path: Option<String>,
+    input_path: Option<String>,
+    manifest_path: Option<String>,
+    hooks_path: Option<String>,
+    /// The run `rerun` repeats, whose input and configuration replace the usual ones.
+    replay: Option<RunManifest>,
+    include_paths: Vec<PathBuf>,
+    config: VmConfig,
+}
+
+fn parse_run_options(command: &str, args: &[String]) -> RunOptions {
+    let mut run_options = RunOptions::default();
+    // These only look at the program, so of the VM settings only the memory size applies
+    let checks_only = matches!(command, "verify" | "check" | "lint");
 
-        match vortex_vm::assembler::assemble_file(filename, &temp_filename) {
-            Ok(()) => {
-                // Now load and run the assembled bytecode
-                match load_bytecode_file(&temp_filename) {
-                    Ok(instructions) => {
-                        // Clean up the temporary file
-                        let _ = fs::remove_file(&temp_filename);
-                        instructions
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--crash-dump" if command == "run" => {
+                run_options.crash_dump_path = Some(option_value(option, options.next()).to_string());
+            }
+            "--trace" if command == "run" => run_options.trace = true,
+            "--stack-canvas" if command == "run" => run_options.stack_canvas = true,
+            "--exit-summary" if command == "run" => run_options.exit_summary = true,
+            "--profile" if command == "run" => run_options.profile = true,
+            "--hooks" if command == "run" => {
+                run_options.hooks_path = Some(option_value(option, options.next()).to_string());
+            }
+            "--emit-manifest" if command == "run" => {
+                run_options.manifest_path = Some(option_value(option, options.next()).to_string());
+            }
+            "--input" if command == "run" || command == "trace-diff" => {
+                run_options.input_path = Some(option_value(option, options.next()).to_string());
+            }
+            "--events-json" if command == "run" || command == "debug" => {
+                run_options.events_path = Some(option_value(option, options.next()).to_string());
+            }
+            "--self-modifying" if !checks_only => run_options.config.self_modifying = true,
+            "--strict" if !checks_only => run_options.config.strict = true,
+            "--protect-data" if !checks_only => run_options.config.protect_data = true,
+            "--catch-traps" if !checks_only => run_options.config.catch_traps = true,
+            "--max-steps" if !checks_only => {
+                let value = option_value(option, options.next());
+                match value.parse::<u64>() {
+                    Ok(max_steps) => run_options.config.max_steps = Some(max_steps),
+                    Err(_) => {
+                        eprintln!("Error: Invalid step limit '{}'", value);
+                        process::exit(1);
                     }
-                    Err(e) => {
-                        let _ = fs::remove_file(&temp_filename);
-                        eprintln!("Error: Failed to load assembled bytecode: {}", e);
+                }
+            }
+            "--max-stack" if !checks_only => {
+                let value = option_value(option, options.next());
+                match value.parse::<usize>() {
+                    Ok(depth) => run_options.config.max_stack_depth = Some(depth),
+                    Err(_) => {
+                        eprintln!("Error: Invalid stack depth limit '{}'", value);
                         process::exit(1);
                     }
                 }
             }
+            "--mem-size" => {
+                let value = option_value(option, options.next());
+                match value.parse::<usize>() {
+                    Ok(cells) if cells > 0 => run_options.config.memory_size = Some(cells),
+                    _ => {
+                        eprintln!("Error: Invalid memory size '{}'", value);
+                        process::exit(1);
+                    }
+                }
+            }
+            "--max-mem-size" if !checks_only => {
+                let value = option_value(option, options.next());
+                match value.parse::<usize>() {
+                    Ok(cells) => run_options.config.max_memory_size = Some(cells),
+                    Err(_) => {
+                        eprintln!("Error: Invalid maximum memory size '{}'", value);
+                        process::exit(1);
+                    }
+                }
+            }
+            "--mem-init" if !checks_only => {
+                run_options.config.memory_fill = parse_memory_fill(option_value(option, options.next()));
+            }
+            "--overflow" if !checks_only => {
+                run_options.config.arithmetic = match option_value(option, options.next()) {
+                    "wrap" => ArithmeticMode::Wrapping,
+                    "saturate" => ArithmeticMode::Saturating,
+                    "trap" => ArithmeticMode::Checked,
+                    mode => {
+                        eprintln!("Error: Invalid overflow mode '{}'. Use wrap, saturate or trap", mode);
+                        process::exit(1);
+                    }
+                };
+            }
+            "--output-encoding" if !checks_only => {
+                run_options.config.output_encoding = match option_value(option, options.next()) {
+                    "latin1" => OutputEncoding::Latin1,
+                    "raw" => OutputEncoding::Raw,
+                    "utf8" => OutputEncoding::Utf8,
+                    "hex" => OutputEncoding::Hex,
+                    encoding => {
+                        eprintln!("Error: Invalid output encoding '{}'. Use latin1, raw, utf8 or hex", encoding);
+                        process::exit(1);
+                    }
+                };
+            }
+            "--uninit-reads" if !checks_only => {
+                run_options.config.uninit_reads = match option_value(option, options.next()) {
+                    "off" => UninitReadMode::Off,
+                    "warn" => UninitReadMode::Warn,
+                    "trap" => UninitReadMode::Trap,
+                    mode => {
+                        eprintln!("Error: Invalid uninitialized read mode '{}'. Use off, warn or trap", mode);
+                        process::exit(1);
+                    }
+                };
+            }
+            _ => {
+                eprintln!("Error: Unknown option '{}' for '{}'", option, command);
+                if checks_only {
+                    eprintln!("Usage: vortex-vm {} <file> [--mem-size <cells>]", command);
+                }
+                process::exit(1);
+            }
+        }
+    }
+
+    run_options
+}
+
+fn option_value<'a>(option: &str, value: Option<&'a String>) -> &'a str {
+    match value {
+        Some(value) => value,
+        None => {
+            eprintln!("Error: '{}' requires a value", option);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_memory_fill(value: &str) -> Word {
+    let parsed = match value {
+        "zero" => Some(0),
+        "poison" => Some(POISON_PATTERN),
+        _ => match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16).ok().map(|v| v as Word),
+            None => value.parse::<Word>().ok(),
+        },
+    };
+
+    match parsed {
+        Some(fill) => fill,
+        None => {
+            eprintln!("Error: Invalid memory init value '{}'. Use a number, 0x-prefixed hex, 'zero' or 'poison'", value);
+            process::exit(1);
+        }
+    }
+}
+
+fn open_event_stream(options: &RunOptions) -> Option<EventStream> {
+    let path = options.events_path.as_ref()?;
+    match EventStream::open(path) {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_file(filename: &str, options: &RunOptions) {
+    let (program, source) = load_program(filename, &options.include_paths);
+    let mut events = open_event_stream(options);
+    if let Some(events) = &mut events {
+        events.emit(&Event::Assembled { file: filename.to_string(), instructions: program.instructions.len() });
+        events.emit(&Event::Started);
+    }
+
+    // Manifests pin the program down by its hash
+    let program_hash = (options.manifest_path.is_some() || options.replay.is_some()).then(|| {
+        program_hash(&program).unwrap_or_else(|e| {
+            eprintln!("Error: Failed to hash '{}': {}", filename, e);
+            process::exit(1);
+        })
+    });
+    if let Some(replay) = &options.replay
+        && program_hash != Some(replay.program_hash)
+    {
+        eprintln!("Error: '{}' is not the program the manifest was recorded with", filename);
+        process::exit(1);
+    }
+
+    // step 2: run the instructions
+    let mut output_buffer = Vec::new();
+    // The program's .requires directives fill in what the command line left open
+    let mut config = options.config.clone();
+    program.requirements.apply(&mut config);
+    let mut vm = Vm::with_config(program.instructions, config.clone());
+    vm.load_data(&program.data);
+    let input: Box<dyn InputSource> = match (&options.replay, &options.input_path) {
+        (Some(replay), _) => Box::new(StringInput::new(replay.input.clone())),
+        (None, Some(path)) => match FileInput::open(path) {
+            Ok(input) => Box::new(input),
             Err(e) => {
-                eprintln!("Error: Failed to assemble file '{}': {}", filename, e);
+                eprintln!("Error: Failed to open input file '{}': {}", path, e);
                 process::exit(1);
             }
+        },
+        (None, None) => Box::new(StdinInput::stdin()),
+    };
+    let input = RecordingInput::new(input);
+    let recorded_input = input.recorded();
+    vm.set_input_source(input);
+    // Phase names and budgets are only kept in the source
+    let debug_info = program.debug_info;
+    for (&phase, &steps) in &debug_info.budgets {
+        vm.set_phase_budget(phase, steps);
+    }
+    if let Some(path) = &options.hooks_path
+        && let Err(e) = Hooks::read_file(path).and_then(|hooks| hooks.install(&mut vm, &debug_info))
+    {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+    let mut canvas = StackCanvas::new(io::stderr());
+    let mut trace = |event: &TraceEvent| {
+        if options.trace {
+            match debug_info.symbol(event.pc) {
+                Some(symbol) => eprintln!("{}  ({})", event, symbol),
+                None => eprintln!("{}", event),
+            }
+        }
+        if options.stack_canvas {
+            canvas.trace(event);
+        }
+    };
+    let sink: Option<&mut dyn TraceSink> = if options.trace || options.stack_canvas { Some(&mut trace) } else { None };
+    let result = match (&mut events, sink) {
+        // Step by hand so every Print can be reported as it happens
+        (Some(events), sink) => run_with_events(&mut vm, &mut output_buffer, sink, events),
+        (None, Some(sink)) => vm.run_traced(&mut output_buffer, sink),
+        (None, None) => vm.run(&mut output_buffer),
+    };
+
+    diagnostic!(
+        Level::Info,
+        "Executed {} instructions (peak stack depth {}, peak call depth {})",
+        vm.steps,
+        vm.high_water.stack,
+        vm.high_water.call_depth
+    );
+
+    // Print any output from Print instructions, byte for byte
+    if !output_buffer.is_empty() {
+        let mut stdout = io::stdout().lock();
+        if let Err(e) = stdout.write_all(&output_buffer).and_then(|_| stdout.flush()) {
+            eprintln!("Error: Failed to write output: {}", e);
+        }
+    }
+
+    if let Some(events) = &mut events {
+        if let Err(e) = &result {
+            events.emit(&Event::Trapped { pc: e.pc(), message: e.to_string() });
+        }
+        events.emit(&Event::Finished { stack: vm.stack.clone(), steps: vm.steps, exit_code: vm.exit_code.unwrap_or(0) });
+    }
+
+    if options.exit_summary
+        && let Some(summary) = vm.exit_summary(&result)
+    {
+        let program = Program { debug_info: debug_info.clone(), ..Program::new(vm.program.to_vec()) };
+        println!("Exited with {}", summary.describe(&program));
+    }
+
+    if options.profile {
+        eprint!("{}", vm.profile().report(&debug_info.phases));
+    }
+
+    let outcome = RunOutcome::capture(&vm, &result, &output_buffer);
+    if let Some(path) = &options.manifest_path {
+        let manifest = RunManifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            program: filename.to_string(),
+            program_hash: program_hash.unwrap_or_default(),
+            include_paths: options.include_paths.clone(),
+            hooks: options.hooks_path.as_ref().map(PathBuf::from),
+            config,
+            input: recorded_input.lock().map(|input| input.clone()).unwrap_or_default(),
+            outcome: outcome.clone(),
+        };
+        match manifest.write_file(path) {
+            Ok(()) => eprintln!("Run manifest written to '{}'", path),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+    let mut reproduced = true;
+    if let Some(replay) = &options.replay {
+        let differences = replay.outcome.differences(&outcome);
+        if differences.is_empty() {
+            eprintln!("Reproduced the recorded run");
+        } else {
+            eprintln!("Error: The run differs from the recorded one:");
+            for difference in &differences {
+                eprintln!("    {}", difference);
+            }
+            reproduced = false;
+        }
+    }
+
+    if let Err(e) = result {
+        eprintln!("Error: Program trapped: {}", e);
+        if let Some(path) = &options.crash_dump_path {
+            write_crash_dump(source.as_ref(), &vm, &e, path);
+        }
+        println!("Final stack: {:?}", vm.stack);
+        process::exit(1);
+    }
+
+    println!("Final stack: {:?}", vm.stack);
+    if !reproduced {
+        process::exit(1);
+    }
+    if let Some(code) = vm.exit_code {
+        process::exit(code);
+    }
+}
+
+/// Repeats the run recorded in the manifest at `path`, see `run --emit-manifest`.
+fn rerun_manifest(path: &str) {
+    let manifest = RunManifest::read_file(path).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    if manifest.version != env!("CARGO_PKG_VERSION") {
+        diagnostic!(Level::Warn, "The manifest was recorded with version {} of the VM, this is {}", manifest.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    let options = RunOptions {
+        include_paths: manifest.include_paths.clone(),
+        hooks_path: manifest.hooks.as_ref().map(|path| path.to_string_lossy().into_owned()),
+        config: manifest.config.clone(),
+        ..RunOptions::default()
+    };
+    let program = manifest.program.clone();
+    run_file(&program, &RunOptions { replay: Some(manifest), ..options });
+}
+
+/// Returns `filename` if `source` includes nothing, so that line numbers in the expanded
+/// text are line numbers in the file. Standard input cannot be read again, so it has none.
+fn source_file(filename: &str, source: &ExpandedSource) -> Option<PathBuf> {
+    if filename == "-" {
+        return None;
+    }
+    let first = source.origins.first().map(|(path, _)| path)?;
+    let unchanged = source.origins.iter().enumerate().all(|(index, (path, line))| path == first && *line == index + 1);
+    unchanged.then(|| PathBuf::from(filename))
+}
+
+fn run_with_events(vm: &mut Vm, output_buffer: &mut Vec<u8>, mut sink: Option<&mut dyn TraceSink>, events: &mut EventStream) -> Result<(), VmError> {
+    while vm.is_running() {
+        let output_before = output_buffer.len();
+        let result = match &mut sink {
+            Some(sink) => vm.step_traced(output_buffer, *sink),
+            None => vm.step(output_buffer),
+        };
+
+        if output_buffer.len() > output_before {
+            let text = String::from_utf8_lossy(&output_buffer[output_before..]).into_owned();
+            events.emit(&Event::OutputChunk { text });
+        }
+        result?;
+    }
+    Ok(())
+}
+
+fn write_crash_dump(source: Option<&ExpandedSource>, vm: &Vm, error: &VmError, path: &str) {
+    let mut dump = CrashDump::capture(vm, error);
+
+    // Source lines are only available when running an assembly source file
+    if let Some(source) = source
+        && let Ok((_, lines)) = split_instructions_with_lines(&source.text)
+        && let Some(&line_number) = lines.get(error.pc())
+        && let Some(line) = source.text.lines().nth(line_number - 1)
+    {
+        dump = dump.with_source_line(format!("{}: {}", source.describe_line(line_number), line.trim()));
+    }
+
+    match dump.write_file(path) {
+        Ok(()) => eprintln!("Crash dump written to '{}'", path),
+        Err(e) => eprintln!("Error: {}", e),
+    }
+}
+
+fn debug_file(filename: &str, options: &RunOptions) {
+    // Region names and source lines are only available when debugging an assembly source file
+    let file = read_program_file(filename, &options.include_paths).and_then(|file| match file {
+        ProgramFile::Bytecode(program) => Ok(program),
+        ProgramFile::Source(source) => parse_expanded(&source),
+    });
+    let program = match file {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut config = options.config.clone();
+    program.requirements.apply(&mut config);
+    let mut debugger = Debugger::with_config(program, config);
+    let mut editor = match LineEditor::new(".vortex_vm_debug_history") {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    editor.set_completions(debugger.completion_words());
+
+    let mut events = open_event_stream(options);
+    if let Some(events) = &mut events {
+        events.emit(&Event::Assembled { file: filename.to_string(), instructions: debugger.vm.program.len() });
+        events.emit(&Event::Started);
+    }
+    let mut reported_output = 0;
+
+    println!("Debugging '{}'. Type 'help' for a list of commands.", filename);
+    println!("{}", debugger.handle_command("where").unwrap_or_default());
+
+    // Edits to an assembly source are picked up before the next command runs
+    let mut source_modified = source_modified_time(filename);
+
+    while let Some(line) = editor.read_line("(vvm) ") {
+        let modified = source_modified_time(filename);
+        if modified != source_modified {
+            source_modified = modified;
+            reload_source(&mut debugger, filename, &options.include_paths);
+            editor.set_completions(debugger.completion_words());
+            reported_output = reported_output.min(debugger.output.len());
+        }
+
+        match debugger.handle_command(&line) {
+            Some(response) if response.is_empty() => {}
+            Some(response) => println!("{}", response),
+            None => break,
+        }
+
+        if let Some(events) = &mut events {
+            if debugger.output.len() > reported_output {
+                let text = String::from_utf8_lossy(&debugger.output[reported_output..]).into_owned();
+                events.emit(&Event::OutputChunk { text });
+                reported_output = debugger.output.len();
+            }
+            match debugger.take_last_stop() {
+                Some(StopReason::Breakpoint(pc)) => events.emit(&Event::BreakpointHit { pc }),
+                Some(StopReason::OutputMatch(_)) => events.emit(&Event::BreakpointHit { pc: debugger.vm.pc }),
+                Some(StopReason::Trapped(e)) => {
+                    events.emit(&Event::Trapped { pc: e.pc(), message: e.to_string() });
+                    events.emit(&Event::Finished {
+                        stack: debugger.vm.stack.clone(),
+                        steps: debugger.vm.steps,
+                        exit_code: debugger.vm.exit_code.unwrap_or(0),
+                    });
+                }
+                Some(StopReason::Halted) => {
+                    events.emit(&Event::Finished {
+                        stack: debugger.vm.stack.clone(),
+                        steps: debugger.vm.steps,
+                        exit_code: debugger.vm.exit_code.unwrap_or(0),
+                    });
+                }
+                Some(StopReason::Step) | None => {}
+            }
+        }
+    }
+}
+
+fn repl(options: &RunOptions) {
+    let mut repl = Repl::with_config(options.config.clone());
+    let mut editor = match LineEditor::new(".vortex_vm_repl_history") {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    println!("Vortex VM {} REPL. Type ':help' for a list of commands.", env!("CARGO_PKG_VERSION"));
+
+    loop {
+        editor.set_completions(repl.completion_words());
+        let Some(line) = editor.read_line("vvm> ") else {
+            break;
+        };
+
+        match repl.handle_line(&line) {
+            Some(response) => {
+                if !response.is_empty() {
+                    println!("{}", response);
+                }
+                // Meta-commands print their own results
+                let line = line.trim();
+                if !line.is_empty() && !line.starts_with(':') && !line.starts_with(';') {
+                    println!("{:?}", repl.vm.stack);
+                }
+            }
+            None => break,
+        }
+    }
+}
+
+/// Returns when an assembly source was last modified; `None` for bytecode files.
+fn source_modified_time(filename: &str) -> Option<SystemTime> {
+    let mut magic = Vec::new();
+    fs::File::open(filename).ok()?.take(BYTECODE_MAGIC.len() as u64).read_to_end(&mut magic).ok()?;
+    if is_bytecode(&magic) {
+        return None;
+    }
+    fs::metadata(filename).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Parses source read by [`read_source`], reporting errors by file and line.
+fn parse_expanded(source: &ExpandedSource) -> Result<Program, String> {
+    try_parse_program_with(&source.text, |line| source.describe_line(line))
+}
+
+fn reload_source(debugger: &mut Debugger, filename: &str, include_paths: &[PathBuf]) {
+    let program = match read_source(filename, include_paths).and_then(|source| parse_expanded(&source)) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("Error: Failed to reload '{}': {}", filename, e);
+            return;
+        }
+    };
+
+    match debugger.reload(program) {
+        ReloadOutcome::Unchanged => {}
+        ReloadOutcome::HotSwapped { first_change } => {
+            println!("Reloaded '{}' in place (first change at instruction {})", filename, first_change);
+        }
+        ReloadOutcome::Restarted { first_change } => {
+            println!("Reloaded '{}' from the start: code the program already reached changed (instruction {})", filename, first_change);
+        }
+    }
+}
+
+/// Formats assembly sources in place, or with `--check` only reports those that are not
+/// formatted and exits with status 1 if there are any.
+fn format_files(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let mut unformatted = 0;
+    for filename in args.iter().filter(|arg| *arg != "--check") {
+        if filename.starts_with('-') {
+            eprintln!("Error: Unknown option '{}' for 'fmt'", filename);
+            process::exit(1);
+        }
+        let source = match fs::read_to_string(filename) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error: Failed to read file '{}': {}", filename, e);
+                process::exit(1);
+            }
+        };
+
+        let formatted = format_source(&source);
+        if formatted == source {
+            continue;
+        }
+        unformatted += 1;
+        if check {
+            println!("Would reformat '{}'", filename);
+        } else if let Err(e) = fs::write(filename, formatted) {
+            eprintln!("Error: Failed to write '{}': {}", filename, e);
+            process::exit(1);
+        } else {
+            println!("Formatted '{}'", filename);
+        }
+    }
+    if check && unformatted > 0 {
+        process::exit(1);
+    }
+}
+
+fn render_file(filename: &str, args: &[String], include_paths: &[PathBuf]) {
+    let mut output_path = None;
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--html" => {}
+            "--output" | "-o" => output_path = Some(option_value(option, options.next()).to_string()),
+            _ => {
+                eprintln!("Error: Unknown option '{}' for 'render'", option);
+                process::exit(1);
+            }
+        }
+    }
+
+    let source = match read_program_file(filename, include_paths) {
+        Ok(ProgramFile::Source(source)) => source,
+        Ok(ProgramFile::Bytecode(_)) => {
+            eprintln!("Error: '{}' is bytecode; render needs the assembly source", filename);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let html = match Notebook::run(&source.text, VmConfig::default()) {
+        Ok(notebook) => notebook.to_html(filename),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    match output_path {
+        Some(path) => match fs::write(&path, html) {
+            Ok(()) => println!("Report written to '{}'", path),
+            Err(e) => {
+                eprintln!("Error: Failed to write '{}': {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => print!("{}", html),
+    }
+}
+
+/// Loads a program for a report; source files keep their labels and line numbers.
+fn load_annotated_program(filename: &str, include_paths: &[PathBuf]) -> Program {
+    match read_annotated_program(filename, include_paths) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Parses a source file keeping its labels and line numbers, or loads a bytecode file.
+fn read_annotated_program(filename: &str, include_paths: &[PathBuf]) -> Result<Program, String> {
+    match read_program_file(filename, include_paths)? {
+        ProgramFile::Bytecode(program) => Ok(program),
+        ProgramFile::Source(source) => {
+            let mut program = parse_expanded(&source)?;
+            program.debug_info.file = source_file(filename, &source);
+            Ok(program)
+        }
+    }
+}
+
+fn verify_file(filename: &str, options: &RunOptions) {
+    let program = load_annotated_program(filename, &options.include_paths);
+    let mut config = options.config.clone();
+    program.requirements.apply(&mut config);
+
+    match check_program(&program, config.memory_size.unwrap_or(MEMORY_SIZE)) {
+        Ok(()) => println!("{}: no problems found", filename),
+        Err(errors) => {
+            println!("{}: {} problem(s)", filename, errors.len());
+            for error in &errors {
+                match error.pc().and_then(|pc| program.symbolize(pc)) {
+                    Some(symbol) => println!("    {} ({})", error.describe(&program), symbol),
+                    None => println!("    {}", error.describe(&program)),
+                }
+            }
+            process::exit(1);
         }
-    } else if filename.ends_with(".asv") {
-        // For .asv files, load them directly
-        match load_bytecode_file(filename) {
-            Ok(instructions) => instructions,
+    }
+}
+
+/// Assembles a program in memory like `assemble` and verifies it like `verify`, writing
+/// nothing, and reports every problem found.
+fn check_file(filename: &str, options: &RunOptions) {
+    let (program, source) = match read_program_file(filename, &options.include_paths) {
+        Ok(ProgramFile::Bytecode(program)) => (program, None),
+        Ok(ProgramFile::Source(source)) => match compile_expanded(&source, &AssembleOptions::default()) {
+            Ok(program) => (program, Some(source)),
             Err(e) => {
-                eprintln!("Error: Failed to load bytecode file '{}': {}", filename, e);
+                eprintln!("Error: {}", e);
                 process::exit(1);
             }
+        },
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    let mut config = options.config.clone();
+    program.requirements.apply(&mut config);
+
+    let Err(errors) = check_program(&program, config.memory_size.unwrap_or(MEMORY_SIZE)) else {
+        println!("{}: ok", filename);
+        return;
+    };
+    for error in &errors {
+        let line = error.pc().and_then(|pc| program.debug_info.source_line(pc));
+        match (line, &source) {
+            (Some(line), Some(source)) => eprintln!("Error: {} at {}", error.describe(&program), source.describe_line(line)),
+            _ => eprintln!("Error: {}", error.describe(&program)),
+        }
+    }
+    eprintln!("{}: {} problem(s)", filename, errors.len());
+    process::exit(1);
+}
+
+fn lint_file(filename: &str, options: &RunOptions) {
+    let source = match read_program_file(filename, &options.include_paths) {
+        Ok(ProgramFile::Source(source)) => source,
+        Ok(ProgramFile::Bytecode(_)) => {
+            eprintln!("Error: '{}' is bytecode; lint needs the assembly source", filename);
+            process::exit(1);
         }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    // Parse without assembling, which would warn about unreachable code a second time
+    let program = try_parse_program_with(&source.text, |line| source.describe_line(line)).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+    let mut config = options.config.clone();
+    program.requirements.apply(&mut config);
+
+    let lints = lint(&program, &source.text, config.memory_size.unwrap_or(MEMORY_SIZE));
+    if lints.is_empty() {
+        println!("{}: ok", filename);
+        return;
+    }
+    for found in &lints {
+        match found.line {
+            Some(line) => println!("{}: {}", source.describe_line(line), found),
+            None => println!("{}: {}", filename, found),
+        }
+    }
+    eprintln!("{}: {} warning(s)", filename, lints.len());
+    process::exit(1);
+}
+
+fn disassemble_file(filename: &str, include_paths: &[PathBuf]) {
+    match read_program_file(filename, include_paths) {
+        Ok(ProgramFile::Bytecode(program)) => print!("{}", program.to_source()),
+        Ok(ProgramFile::Source(_)) => {
+            eprintln!("Error: '{}' is already assembly source", filename);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn size_file(filename: &str, include_paths: &[PathBuf]) {
+    let program = load_annotated_program(filename, include_paths);
+    match SizeReport::new(&program) {
+        Ok(report) => print!("{}", report.describe(&program)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn info_file(filename: &str, include_paths: &[PathBuf]) {
+    let program = load_annotated_program(filename, include_paths);
+    let stats = analyze(&program);
+    let location = |index: usize| program.debug_info.describe_location(index);
+
+    println!("Program: {}", filename);
+    println!("Instructions: {}", program.instructions.len());
+    let cells: usize = program.data.iter().map(|block| block.values.len()).sum();
+    println!("Data: {} block(s), {} cell(s)", program.data.len(), cells);
+    let requirements = &program.requirements;
+    println!(
+        "Requires: memory {}, fuel {}",
+        requirements.memory.map_or("-".to_string(), |memory| memory.to_string()),
+        requirements.fuel.map_or("-".to_string(), |fuel| fuel.to_string())
+    );
+    let mask = required_capabilities(&program.instructions);
+    let capabilities: Vec<&str> = Capability::ALL.iter().filter(|capability| mask & capability.bit() != 0).map(|capability| capability.name()).collect();
+    println!("Capabilities: {}", if capabilities.is_empty() { "none".to_string() } else { capabilities.join(", ") });
+
+    match stats.max_stack {
+        Some(depth) => println!("Max stack depth: {}", depth),
+        None => println!("Max stack depth: unbounded or unknown"),
+    }
+    if let Some(index) = stats.underflow {
+        println!("Possible stack underflow: {}", location(index));
+    }
+    match &stats.memory {
+        Some(range) => println!("Memory referenced: {}..{}", range.start, range.end),
+        None => println!("Memory referenced: none"),
+    }
+    if stats.back_edges.is_empty() {
+        println!("Loops: none");
     } else {
-        eprintln!("Error: Unsupported file extension for '{}'. Supported: .vvm, .asv", filename);
+        println!("Loops: {} back edge(s), may not terminate", stats.back_edges.len());
+        for (from, to) in &stats.back_edges {
+            println!("    {} -> {}", location(*from), location(*to));
+        }
+    }
+    if stats.dynamic_jumps > 0 {
+        println!("Dynamic jumps: {}", stats.dynamic_jumps);
+    }
+    println!("Subroutines: {}{}", stats.calls.len() - 1, if stats.recursive { " (recursive)" } else { "" });
+    for (entry, callees) in stats.calls.iter().filter(|(_, callees)| !callees.is_empty()) {
+        let callees: Vec<String> = callees.iter().map(|callee| location(*callee)).collect();
+        println!("    {} calls {}", location(*entry), callees.join(", "));
+    }
+}
+
+fn trace_diff_files(old_file: &str, new_file: &str, options: &RunOptions) {
+    let old = load_annotated_program(old_file, &options.include_paths);
+    let new = load_annotated_program(new_file, &options.include_paths);
+
+    // Both programs read the same input, so it is read once up front, but only if needed
+    let mask = required_capabilities(&old.instructions) | required_capabilities(&new.instructions);
+    let input = match &options.input_path {
+        Some(path) => fs::read(path),
+        None if mask & Capability::Input.bit() != 0 => {
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input).map(|_| input)
+        }
+        None => Ok(Vec::new()),
+    };
+    let input = input.unwrap_or_else(|e| {
+        eprintln!("Error: Failed to read input: {}", e);
         process::exit(1);
+    });
+
+    match diff_traces(&old, &new, &options.config, &input) {
+        TraceDiff::Same { steps, trap: None } => println!("Traces match for all {} steps", steps),
+        TraceDiff::Same { steps, trap: Some(trap) } => println!("Traces match for {} steps, then both trap: {}", steps, trap),
+        TraceDiff::Diverged { step, old: old_side, new: new_side } => {
+            println!("Traces diverge at step {}:", step);
+            println!("    {}: {}", old_file, old_side.describe(&old));
+            println!("    {}: {}", new_file, new_side.describe(&new));
+            process::exit(1);
+        }
+    }
+}
+
+fn bindiff_files(old_file: &str, new_file: &str, args: &[String], include_paths: &[PathBuf]) {
+    let mut output_path = None;
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--output" | "-o" => output_path = Some(option_value(option, options.next()).to_string()),
+            _ => {
+                eprintln!("Error: Unknown option '{}' for 'bindiff'", option);
+                process::exit(1);
+            }
+        }
+    }
+
+    let old = load_annotated_program(old_file, include_paths);
+    let new = load_annotated_program(new_file, include_paths);
+    let patch = Patch::between(&old, &new).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    });
+
+    let changes: Vec<Change> = patch.hunks.iter().flat_map(Hunk::changes).collect();
+    for change in &changes {
+        println!("{}", change.describe(&old, &new));
+    }
+    if let Some(requirements) = &patch.requirements {
+        println!("requirements changed: {:?} -> {:?}", old.requirements, requirements);
+    }
+    if patch.data.is_some() {
+        let cells = |program: &Program| program.data.iter().map(|block| block.values.len()).sum::<usize>();
+        println!("data changed: {} block(s), {} cell(s) -> {} block(s), {} cell(s)", old.data.len(), cells(&old), new.data.len(), cells(&new));
+    }
+    let count = |kind: fn(&Change) -> bool| changes.iter().filter(|change| kind(change)).count();
+    println!(
+        "{} changed, {} removed, {} added",
+        count(|change| matches!(change, Change::Changed { .. })),
+        count(|change| matches!(change, Change::Removed { .. })),
+        count(|change| matches!(change, Change::Added { .. }))
+    );
+
+    if let Some(path) = output_path {
+        match patch.write_file(&path) {
+            Ok(()) => println!("Patch written to '{}'", path),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    if !patch.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn patch_file(program_file: &str, patch_file: &str, output_file: &str, include_paths: &[PathBuf]) {
+    let program = load_annotated_program(program_file, include_paths);
+    let patched = Patch::read_file(patch_file)
+        .and_then(|patch| patch.apply(&program))
+        .and_then(|patched| assemble_program(&patched))
+        .and_then(|bytecode| fs::write(output_file, bytecode).map_err(|e| format!("Failed to write bytecode file '{}': {}", output_file, e)));
+    match patched {
+        Ok(()) => println!("Patched '{}' into '{}'", program_file, output_file),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Step limit for each program `test` runs without `--max-steps` or `.requires fuel`, so
+/// a program that never stops fails instead of hanging the suite.
+const TEST_STEP_LIMIT: u64 = 10_000_000;
+
+fn test_dir(dir: &str, options: &RunOptions) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error: Failed to read directory '{}': {}", dir, e);
+            process::exit(1);
+        }
     };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| matches!(path.extension().and_then(|extension| extension.to_str()), Some("vvm" | "asv")))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        eprintln!("Error: No .vvm or .asv programs in '{}'", dir);
+        process::exit(1);
+    }
 
-    // step 2: run the instructions
-    let mut output_buffer = Vec::new();
-    let (stack, _mem) = execute(&instructions, &mut output_buffer);
+    let mut failures = Vec::new();
+    for path in &files {
+        let filename = path.to_string_lossy();
+        match run_test(path, options) {
+            Ok(()) => println!("test {} ... ok", filename),
+            Err(reason) => {
+                println!("test {} ... FAILED", filename);
+                failures.push((filename, reason));
+            }
+        }
+    }
 
-    // Print any output from Print instructions
-    if !output_buffer.is_empty() {
-        let output = String::from_utf8_lossy(&output_buffer);
-        print!("{}", output);
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for (filename, reason) in &failures {
+            println!("    {}: {}", filename, reason);
+        }
+    }
+    println!();
+    let status = if failures.is_empty() { "ok" } else { "FAILED" };
+    println!("test result: {}. {} passed; {} failed", status, files.len() - failures.len(), failures.len());
+    if !failures.is_empty() {
+        process::exit(1);
     }
+}
 
-    println!("Final stack: {:?}", stack);
+/// Runs one program of a `test` suite, with the file of the same name ending in `.in` as
+/// its input if there is one. It passes if it stops without a trap, failed assertions
+/// included, and with exit code 0.
+fn run_test(path: &Path, options: &RunOptions) -> Result<(), String> {
+    let program = read_annotated_program(&path.to_string_lossy(), &options.include_paths)?;
+    let mut config = options.config.clone();
+    program.requirements.apply(&mut config);
+    config.max_steps.get_or_insert(TEST_STEP_LIMIT);
+
+    let mut vm = Vm::with_config(program.instructions.clone(), config);
+    vm.load_data(&program.data);
+    let input_path = path.with_extension("in");
+    if input_path.exists() {
+        let input = FileInput::open(&input_path).map_err(|e| format!("Failed to read '{}': {}", input_path.display(), e))?;
+        vm.set_input_source(input);
+    }
+    if let Err(e) = vm.run(&mut Vec::new()) {
+        return Err(match program.symbolize(e.pc()) {
+            Some(symbol) => format!("{} ({})", e, symbol),
+            None => e.to_string(),
+        });
+    }
+    match vm.exit_code {
+        Some(code) if code != 0 => Err(format!("halted with exit code {}", code)),
+        _ => Ok(()),
+    }
+}
+
+fn inspect_dump(path: &str) {
+    match CrashDump::read_file(path) {
+        Ok(dump) => print!("{}", dump),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Removes `-I <dir>` and `-I<dir>` from `args` and returns the directories in order.
+fn take_include_paths(args: &mut Vec<String>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut index = 1;
+    while index < args.len() {
+        if args[index] == "-I" {
+            args.remove(index);
+            if index == args.len() {
+                eprintln!("Error: Option '-I' requires a directory");
+                process::exit(1);
+            }
+            paths.push(PathBuf::from(args.remove(index)));
+        } else if let Some(path) = args[index].strip_prefix("-I") {
+            paths.push(PathBuf::from(path));
+            args.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+    paths
+}
+
+/// Parses the options that follow `assemble <input> <output>`, returning the target ISA.
+fn parse_assemble_options(args: &[String]) -> AssembleOptions {
+    let mut options = AssembleOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target-isa" => {
+                let Some(version) = args.next() else {
+                    eprintln!("Error: Option '--target-isa' requires a version");
+                    process::exit(1);
+                };
+                options.isa = IsaVersion::parse(version).unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                });
+            }
+            "--optimize" => options.optimize = true,
+            "--debug" => options.debug = true,
+            "--compress" => options.compress = true,
+            _ => {
+                eprintln!("Error: Unknown option '{}' for 'assemble'", arg);
+                process::exit(1);
+            }
+        }
+    }
+    options
+}
+
+/// Removes `-v`, `-vv`, `-vvv` and `--verbose` from `args` and returns how many `v`s were given.
+///
+/// A lone `-v` is left alone since it doubles as `--version`.
+fn take_verbosity(args: &mut Vec<String>) -> Option<usize> {
+    if args.len() == 2 && args[1] == "-v" {
+        return None;
+    }
+
+    let mut count = None;
+    let mut index = 1;
+    while index < args.len() {
+        let arg = &args[index];
+        let vs = match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() && flags.chars().all(|c| c == 'v') => flags.len(),
+            _ if arg == "--verbose" => 1,
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        *count.get_or_insert(0) += vs;
+        args.remove(index);
+    }
+    count
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // Command-line verbosity wins over VORTEX_LOG
+    diagnostics::init_from_env();
+    if let Some(count) = take_verbosity(&mut args) {
+        diagnostics::set_level(Level::from_verbosity(count));
+    }
+    let include_paths = take_include_paths(&mut args);
 
     // Handle case with no arguments
     if args.len() == 1 {
@@ -111,13 +1298,180 @@ fn main() {
         "run" | "--run" | "-r" => {
             if args.len() < 3 {
                 eprintln!("Error: 'run' command requires a filename");
-                eprintln!("Usage: vortex-vm run <filename>");
+                eprintln!("Usage: vortex-vm run <filename|-> [OPTIONS]");
+                process::exit(1);
+            }
+
+            let mut options = parse_run_options("run", &args[3..]);
+            options.include_paths = include_paths;
+            run_file(&args[2], &options);
+        }
+
+        "debug" | "--debug" | "-d" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'debug' command requires a filename");
+                eprintln!("Usage: vortex-vm debug <filename> [OPTIONS]");
+                process::exit(1);
+            }
+
+            let mut options = parse_run_options("debug", &args[3..]);
+            options.include_paths = include_paths;
+            debug_file(&args[2], &options);
+        }
+
+        "repl" => {
+            let options = parse_run_options("repl", &args[2..]);
+            repl(&options);
+        }
+
+        "info" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'info' command requires a filename");
+                eprintln!("Usage: vortex-vm info <file>");
+                process::exit(1);
+            }
+
+            info_file(&args[2], &include_paths);
+        }
+
+        "disassemble" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'disassemble' command requires a filename");
+                eprintln!("Usage: vortex-vm disassemble <file.asv>");
+                process::exit(1);
+            }
+
+            disassemble_file(&args[2], &include_paths);
+        }
+
+        "size" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'size' command requires a filename");
+                eprintln!("Usage: vortex-vm size <file>");
+                process::exit(1);
+            }
+
+            size_file(&args[2], &include_paths);
+        }
+
+        "verify" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'verify' command requires a filename");
+                eprintln!("Usage: vortex-vm verify <file> [--mem-size <cells>]");
                 process::exit(1);
             }
 
-            let filename = &args[2];
+            let mut options = parse_run_options("verify", &args[3..]);
+            options.include_paths = include_paths;
+            verify_file(&args[2], &options);
+        }
+
+        "check" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'check' command requires a filename");
+                eprintln!("Usage: vortex-vm check <file> [--mem-size <cells>]");
+                process::exit(1);
+            }
+
+            let mut options = parse_run_options("check", &args[3..]);
+            options.include_paths = include_paths;
+            check_file(&args[2], &options);
+        }
+
+        "lint" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'lint' command requires a filename");
+                eprintln!("Usage: vortex-vm lint <file> [--mem-size <cells>]");
+                process::exit(1);
+            }
+
+            let mut options = parse_run_options("lint", &args[3..]);
+            options.include_paths = include_paths;
+            lint_file(&args[2], &options);
+        }
+
+        "test" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'test' command requires a directory");
+                eprintln!("Usage: vortex-vm test <dir> [OPTIONS]");
+                process::exit(1);
+            }
+
+            let mut options = parse_run_options("test", &args[3..]);
+            options.include_paths = include_paths;
+            test_dir(&args[2], &options);
+        }
+
+        "trace-diff" => {
+            if args.len() < 4 {
+                eprintln!("Error: 'trace-diff' command requires two filenames");
+                eprintln!("Usage: vortex-vm trace-diff <old> <new> [OPTIONS]");
+                process::exit(1);
+            }
+
+            let mut options = parse_run_options("trace-diff", &args[4..]);
+            options.include_paths = include_paths;
+            trace_diff_files(&args[2], &args[3], &options);
+        }
+
+        "bindiff" => {
+            if args.len() < 4 {
+                eprintln!("Error: 'bindiff' command requires two filenames");
+                eprintln!("Usage: vortex-vm bindiff <old> <new> [--output <patch>]");
+                process::exit(1);
+            }
+
+            bindiff_files(&args[2], &args[3], &args[4..], &include_paths);
+        }
+
+        "patch" => {
+            if args.len() < 5 {
+                eprintln!("Error: 'patch' command requires a program, a patch and an output filename");
+                eprintln!("Usage: vortex-vm patch <program> <patch> <output.asv>");
+                process::exit(1);
+            }
+
+            patch_file(&args[2], &args[3], &args[4], &include_paths);
+        }
+
+        "rerun" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'rerun' command requires a manifest");
+                eprintln!("Usage: vortex-vm rerun <manifest>");
+                process::exit(1);
+            }
+
+            rerun_manifest(&args[2]);
+        }
+
+        "inspect-dump" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'inspect-dump' command requires a dump file");
+                eprintln!("Usage: vortex-vm inspect-dump <file>");
+                process::exit(1);
+            }
+
+            inspect_dump(&args[2]);
+        }
+
+        "fmt" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'fmt' command requires at least one filename");
+                eprintln!("Usage: vortex-vm fmt <file>... [--check]");
+                process::exit(1);
+            }
+
+            format_files(&args[2..]);
+        }
+
+        "render" => {
+            if args.len() < 3 {
+                eprintln!("Error: 'render' command requires a filename");
+                eprintln!("Usage: vortex-vm render <file.vvm> --html [--output <path>]");
+                process::exit(1);
+            }
 
-            run_file(filename);
+            render_file(&args[2], &args[3..], &include_paths);
         }
 
         "assemble" | "--assemble" | "-a" => {
@@ -142,7 +1496,8 @@ fn main() {
                 process::exit(1);
             }
 
-            assemble_file_to_path(input_file, output_file);
+            let options = parse_assemble_options(&args[4..]);
+            assemble_file_to_path(input_file, output_file, &include_paths, &options);
         }
 
         "help" | "--help" | "-h" => {
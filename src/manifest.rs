@@ -0,0 +1,363 @@
+use crate::assembler::assemble_program;
+use crate::instruction::Word;
+use crate::program::Program;
+use crate::run::{ArithmeticMode, OutputEncoding, UninitReadMode, Vm, VmConfig, VmError};
+use crate::toml::{self, quote, Entry, Value};
+use std::fs;
+use std::path::PathBuf;
+
+const MANIFEST_HEADER: &str = "# Vortex VM run manifest, replay it with `vortex-vm rerun <file>`";
+
+/// Everything needed to repeat a run exactly, and what the run did, so that a bug report
+/// can be reproduced on another machine with `vortex-vm rerun`.
+///
+/// Manifests are small TOML files. The VM has no source of randomness, so the program,
+/// its configuration and the input it read decide everything it does.
+///
+/// ```
+/// use vortex_vm::manifest::{RunManifest, RunOutcome};
+/// use vortex_vm::run::VmConfig;
+///
+/// let manifest = RunManifest {
+///     version: "0.2.0".to_string(),
+///     program: "sum.vvm".to_string(),
+///     program_hash: 0x1234,
+///     include_paths: Vec::new(),
+///     hooks: None,
+///     config: VmConfig { strict: true, max_steps: Some(100), ..VmConfig::default() },
+///     input: b"20\n22\n".to_vec(),
+///     outcome: RunOutcome { steps: 5, stack: vec![42], ..RunOutcome::default() },
+/// };
+/// assert_eq!(RunManifest::parse(&manifest.to_toml()).unwrap(), manifest);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunManifest {
+    /// The version of the VM that made the run.
+    pub version: String,
+    /// The program file, as given on the command line.
+    pub program: String,
+    /// The [`program_hash`] of the program that ran.
+    pub program_hash: u64,
+    /// Directories searched for `%include`d files.
+    pub include_paths: Vec<PathBuf>,
+    /// The hook file given with `--hooks`, see [`Hooks`](crate::hooks::Hooks).
+    pub hooks: Option<PathBuf>,
+    /// The configuration the program ran with, after its `.requires` directives applied.
+    pub config: VmConfig,
+    /// Every line `READLINE` and `READINT` read, each ending in `\n`.
+    pub input: Vec<u8>,
+    pub outcome: RunOutcome,
+}
+
+/// How a run ended, compared by `vortex-vm rerun` to tell whether it was reproduced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOutcome {
+    /// Instructions executed.
+    pub steps: u64,
+    /// The stack when the program stopped.
+    pub stack: Vec<Word>,
+    /// The code passed to `HALT`, if the program halted.
+    pub exit_code: Option<i32>,
+    /// The trap that stopped the program, if any.
+    pub trap: Option<String>,
+    /// A hash of everything the program printed.
+    pub output_hash: u64,
+}
+
+impl RunOutcome {
+    /// Captures how `vm` ended with `result` after printing `output`.
+    pub fn capture(vm: &Vm, result: &Result<(), VmError>, output: &[u8]) -> Self {
+        RunOutcome {
+            steps: vm.steps,
+            stack: vm.stack.clone(),
+            exit_code: vm.exit_code,
+            trap: result.as_ref().err().map(|e| e.to_string()),
+            output_hash: hash_bytes(output),
+        }
+    }
+
+    /// Describes every way `actual` differs from this outcome; empty if it does not.
+    pub fn differences(&self, actual: &RunOutcome) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.steps != actual.steps {
+            differences.push(format!("executed {} instructions instead of {}", actual.steps, self.steps));
+        }
+        if self.stack != actual.stack {
+            differences.push(format!("final stack {:?} instead of {:?}", actual.stack, self.stack));
+        }
+        if self.exit_code != actual.exit_code {
+            differences.push(format!("exit code {:?} instead of {:?}", actual.exit_code, self.exit_code));
+        }
+        if self.trap != actual.trap {
+            let describe = |trap: &Option<String>| trap.clone().unwrap_or_else(|| "no trap".to_string());
+            differences.push(format!("{} instead of {}", describe(&actual.trap), describe(&self.trap)));
+        }
+        if self.output_hash != actual.output_hash {
+            differences.push("different output".to_string());
+        }
+        differences
+    }
+}
+
+/// Hashes the bytecode `program` assembles to, so that edits to comments or layout of its
+/// source do not count as changes but any change to what it does does.
+pub fn program_hash(program: &Program) -> Result<u64, String> {
+    assemble_program(program).map(|bytecode| hash_bytes(&bytecode))
+}
+
+/// Hashes `bytes` with 64-bit FNV-1a.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+impl RunManifest {
+    /// Serializes the manifest to TOML.
+    pub fn to_toml(&self) -> String {
+        let mut text = format!("{}\n", MANIFEST_HEADER);
+        text.push_str(&format!("version = {}\n", quote(&self.version)));
+        text.push_str(&format!("program = {}\n", quote(&self.program)));
+        text.push_str(&format!("program_hash = \"{:016x}\"\n", self.program_hash));
+        let include_paths: Vec<String> = self.include_paths.iter().map(|path| quote(&path.to_string_lossy())).collect();
+        text.push_str(&format!("include_paths = [{}]\n", include_paths.join(", ")));
+        if let Some(hooks) = &self.hooks {
+            text.push_str(&format!("hooks = {}\n", quote(&hooks.to_string_lossy())));
+        }
+        match std::str::from_utf8(&self.input) {
+            Ok(input) => text.push_str(&format!("input = {}\n", quote(input))),
+            Err(_) => text.push_str(&format!("input_bytes = [{}]\n", join(&self.input))),
+        }
+
+        let config = &self.config;
+        text.push_str("\n[config]\n");
+        text.push_str(&format!("memory_fill = {}\n", config.memory_fill));
+        let limits = [
+            ("memory_size", config.memory_size.map(|size| size as u64)),
+            ("max_memory_size", config.max_memory_size.map(|size| size as u64)),
+            ("max_steps", config.max_steps),
+            ("max_stack_depth", config.max_stack_depth.map(|depth| depth as u64)),
+        ];
+        for (key, limit) in limits {
+            if let Some(limit) = limit {
+                text.push_str(&format!("{} = {}\n", key, limit));
+            }
+        }
+        text.push_str(&format!("uninit_reads = \"{}\"\n", uninit_read_name(config.uninit_reads)));
+        text.push_str(&format!("arithmetic = \"{}\"\n", arithmetic_name(config.arithmetic)));
+        text.push_str(&format!("output_encoding = \"{}\"\n", output_encoding_name(config.output_encoding)));
+        let flags = [
+            ("track_provenance", config.track_provenance),
+            ("self_modifying", config.self_modifying),
+            ("strict", config.strict),
+            ("protect_data", config.protect_data),
+            ("catch_traps", config.catch_traps),
+        ];
+        for (key, flag) in flags {
+            text.push_str(&format!("{} = {}\n", key, flag));
+        }
+
+        let outcome = &self.outcome;
+        text.push_str("\n[outcome]\n");
+        text.push_str(&format!("steps = {}\n", outcome.steps));
+        text.push_str(&format!("stack = [{}]\n", join(&outcome.stack)));
+        if let Some(code) = outcome.exit_code {
+            text.push_str(&format!("exit_code = {}\n", code));
+        }
+        if let Some(trap) = &outcome.trap {
+            text.push_str(&format!("trap = {}\n", quote(trap)));
+        }
+        text.push_str(&format!("output_hash = \"{:016x}\"\n", outcome.output_hash));
+        text
+    }
+
+    /// Parses a manifest previously produced by [`RunManifest::to_toml`].
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut manifest = RunManifest {
+            version: String::new(),
+            program: String::new(),
+            program_hash: 0,
+            include_paths: Vec::new(),
+            hooks: None,
+            config: VmConfig::default(),
+            input: Vec::new(),
+            outcome: RunOutcome::default(),
+        };
+        let mut has_program = false;
+        let mut has_hash = false;
+
+        for Entry { line, table, key, value } in toml::parse(text).map_err(|e| format!("Invalid manifest: {}", e))? {
+            match manifest.set(&table, &key, value) {
+                Ok(true) => {}
+                Ok(false) => return Err(format!("Unknown manifest field '{}' at line {}", key, line)),
+                Err(e) => return Err(format!("Invalid manifest value for '{}' at line {}: {}", key, line, e)),
+            }
+            has_program |= table.is_empty() && key == "program";
+            has_hash |= table.is_empty() && key == "program_hash";
+        }
+
+        if !has_program || !has_hash {
+            return Err("Manifest does not name a program and its hash".to_string());
+        }
+        Ok(manifest)
+    }
+
+    /// Sets the field `key` of `table` to `value`, returning false for unknown fields.
+    fn set(&mut self, table: &str, key: &str, value: Value) -> Result<bool, String> {
+        let config = &mut self.config;
+        let outcome = &mut self.outcome;
+        match (table, key) {
+            ("", "version") => self.version = value.string()?,
+            ("", "program") => self.program = value.string()?,
+            ("", "program_hash") => self.program_hash = value.hash()?,
+            ("", "include_paths") => {
+                self.include_paths = value.array()?.into_iter().map(|path| path.string().map(PathBuf::from)).collect::<Result<_, _>>()?;
+            }
+            ("", "hooks") => self.hooks = Some(PathBuf::from(value.string()?)),
+            ("", "input") => self.input = value.string()?.into_bytes(),
+            ("", "input_bytes") => {
+                self.input = value.array()?.into_iter().map(|byte| byte.integer()).collect::<Result<_, _>>()?;
+            }
+            ("config", "memory_fill") => config.memory_fill = value.integer()?,
+            ("config", "memory_size") => config.memory_size = Some(value.integer()?),
+            ("config", "max_memory_size") => config.max_memory_size = Some(value.integer()?),
+            ("config", "max_steps") => config.max_steps = Some(value.integer()?),
+            ("config", "max_stack_depth") => config.max_stack_depth = Some(value.integer()?),
+            ("config", "uninit_reads") => {
+                let name = value.string()?;
+                config.uninit_reads = [UninitReadMode::Off, UninitReadMode::Warn, UninitReadMode::Trap]
+                    .into_iter()
+                    .find(|&mode| uninit_read_name(mode) == name)
+                    .ok_or_else(|| format!("Unknown uninit_reads mode '{}'", name))?;
+            }
+            ("config", "arithmetic") => {
+                let name = value.string()?;
+                config.arithmetic = [ArithmeticMode::Wrapping, ArithmeticMode::Saturating, ArithmeticMode::Checked]
+                    .into_iter()
+                    .find(|&mode| arithmetic_name(mode) == name)
+                    .ok_or_else(|| format!("Unknown arithmetic mode '{}'", name))?;
+            }
+            ("config", "output_encoding") => {
+                let name = value.string()?;
+                config.output_encoding = [OutputEncoding::Latin1, OutputEncoding::Raw, OutputEncoding::Utf8, OutputEncoding::Hex]
+                    .into_iter()
+                    .find(|&encoding| output_encoding_name(encoding) == name)
+                    .ok_or_else(|| format!("Unknown output encoding '{}'", name))?;
+            }
+            ("config", "track_provenance") => config.track_provenance = value.boolean()?,
+            ("config", "self_modifying") => config.self_modifying = value.boolean()?,
+            ("config", "strict") => config.strict = value.boolean()?,
+            ("config", "protect_data") => config.protect_data = value.boolean()?,
+            ("config", "catch_traps") => config.catch_traps = value.boolean()?,
+            ("outcome", "steps") => outcome.steps = value.integer()?,
+            ("outcome", "stack") => outcome.stack = value.array()?.into_iter().map(|value| value.integer()).collect::<Result<_, _>>()?,
+            ("outcome", "exit_code") => outcome.exit_code = Some(value.integer()?),
+            ("outcome", "trap") => outcome.trap = Some(value.string()?),
+            ("outcome", "output_hash") => outcome.output_hash = value.hash()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Writes the manifest to `path`.
+    pub fn write_file(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_toml()).map_err(|e| format!("Failed to write manifest '{}': {}", path, e))
+    }
+
+    /// Reads a manifest from `path`.
+    pub fn read_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read manifest '{}': {}", path, e))?;
+        Self::parse(&text)
+    }
+}
+
+/// The names the command line uses for each mode, see `--uninit-reads`, `--overflow` and
+/// `--output-encoding`.
+fn uninit_read_name(mode: UninitReadMode) -> &'static str {
+    match mode {
+        UninitReadMode::Off => "off",
+        UninitReadMode::Warn => "warn",
+        UninitReadMode::Trap => "trap",
+    }
+}
+
+fn arithmetic_name(mode: ArithmeticMode) -> &'static str {
+    match mode {
+        ArithmeticMode::Wrapping => "wrap",
+        ArithmeticMode::Saturating => "saturate",
+        ArithmeticMode::Checked => "trap",
+    }
+}
+
+fn output_encoding_name(encoding: OutputEncoding) -> &'static str {
+    match encoding {
+        OutputEncoding::Latin1 => "latin1",
+        OutputEncoding::Raw => "raw",
+        OutputEncoding::Utf8 => "utf8",
+        OutputEncoding::Hex => "hex",
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> RunManifest {
+        RunManifest {
+            version: "0.2.0".to_string(),
+            program: "dir/prog \"one\".vvm".to_string(),
+            program_hash: u64::MAX,
+            include_paths: vec![PathBuf::from("lib")],
+            hooks: Some(PathBuf::from("hooks.toml")),
+            config: VmConfig {
+                memory_fill: -1,
+                memory_size: Some(4096),
+                arithmetic: ArithmeticMode::Checked,
+                output_encoding: OutputEncoding::Hex,
+                uninit_reads: UninitReadMode::Warn,
+                catch_traps: true,
+                ..VmConfig::default()
+            },
+            input: b"tab\there\n\x01\n".to_vec(),
+            outcome: RunOutcome { steps: 9, stack: vec![-3, 4], exit_code: Some(2), trap: Some("stack underflow".to_string()), output_hash: 7 },
+        }
+    }
+
+    #[test]
+    fn test_manifest_round_trips() {
+        let manifest = manifest();
+        let text = manifest.to_toml();
+        assert!(text.contains("program = \"dir/prog \\\"one\\\".vvm\"\n"));
+        assert!(text.contains("input = \"tab\\there\\n\\u0001\\n\"\n"));
+        assert!(text.contains("arithmetic = \"trap\"\n"));
+        assert_eq!(RunManifest::parse(&text).unwrap(), manifest);
+
+        // Input that is not UTF-8 is kept byte for byte
+        let binary = RunManifest { input: vec![0xff, b'\n'], ..manifest };
+        let text = binary.to_toml();
+        assert!(text.contains("input_bytes = [255, 10]\n"));
+        assert_eq!(RunManifest::parse(&text).unwrap(), binary);
+    }
+
+    #[test]
+    fn test_invalid_manifests_are_rejected() {
+        assert!(RunManifest::parse("version = \"0.2.0\"").is_err());
+        assert!(RunManifest::parse("program = \"a.vvm\"\nprogram_hash = \"00\"\nspeed = 3").is_err());
+        assert!(RunManifest::parse("program = \"a.vvm\"\nprogram_hash = \"00\"\n[config]\narithmetic = \"fast\"").is_err());
+        assert!(RunManifest::parse("program = \"a.vvm\nprogram_hash = \"00\"").is_err());
+
+        let manifest = RunManifest::parse("program = \"a.vvm\" # comment\nprogram_hash = \"0f\"").unwrap();
+        assert_eq!((manifest.program.as_str(), manifest.program_hash), ("a.vvm", 15));
+    }
+
+    #[test]
+    fn test_outcome_differences() {
+        let recorded = RunOutcome { steps: 3, stack: vec![1], ..RunOutcome::default() };
+        assert!(recorded.differences(&recorded).is_empty());
+
+        let actual = RunOutcome { steps: 4, trap: Some("division by zero".to_string()), ..recorded.clone() };
+        assert_eq!(recorded.differences(&actual), ["executed 4 instructions instead of 3", "division by zero instead of no trap"]);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::diagnostic;
+use crate::diagnostics::Level;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// What a [`Profile`] measured for one phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseStats {
+    /// How many times the phase was entered and left again.
+    pub entries: u64,
+    /// Instructions executed inside the phase, including the `PROFEND` that closed it.
+    pub steps: u64,
+    /// Wall-clock time spent inside the phase.
+    pub time: Duration,
+}
+
+/// Steps and time per phase, for the phases guest code delimits with `PROFBEGIN` and
+/// `PROFEND`.
+///
+/// Phases may nest and overlap, and each one counts everything that ran while it was open.
+/// A phase entered again before it ends, as a recursive routine does, is only measured
+/// from its outermost entry, so its time is not counted twice.
+///
+/// A phase may be given a budget of steps; [`Profile::end`] reports when the steps the
+/// phase took in total first go over it.
+///
+/// ```
+/// use vortex_vm::profile::Profile;
+///
+/// let mut profile = Profile::default();
+/// profile.begin(0, 1);
+/// profile.begin(1, 2);
+/// profile.end(1, 5);
+/// profile.end(0, 10);
+///
+/// assert_eq!(profile.stats(0).unwrap().steps, 9);
+/// assert_eq!(profile.stats(1).unwrap().steps, 3);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    phases: BTreeMap<u32, PhaseStats>,
+    /// Phases entered and not yet left, with the step count and time they were entered at.
+    open: Vec<(u32, u64, Instant)>,
+    budgets: BTreeMap<u32, u64>,
+}
+
+impl Profile {
+    /// Enters `phase` after `steps` instructions have executed.
+    pub fn begin(&mut self, phase: u32, steps: u64) {
+        self.open.push((phase, steps, Instant::now()));
+    }
+
+    /// Leaves the innermost open entry of `phase` after `steps` instructions have executed.
+    /// Leaving a phase that is not open is reported and ignored.
+    ///
+    /// Returns the phase's budget if this entry took it over, which happens at most once
+    /// per phase.
+    pub fn end(&mut self, phase: u32, steps: u64) -> Option<u64> {
+        let Some(index) = self.open.iter().rposition(|&(open, _, _)| open == phase) else {
+            diagnostic!(Level::Warn, "PROFEND of phase {} that was never begun", phase);
+            return None;
+        };
+        let (_, begun_at, started) = self.open.remove(index);
+
+        let stats = self.phases.entry(phase).or_default();
+        let steps_before = stats.steps;
+        stats.entries += 1;
+        if !self.open.iter().any(|&(open, _, _)| open == phase) {
+            stats.steps += steps - begun_at;
+            stats.time += started.elapsed();
+        }
+
+        let budget = *self.budgets.get(&phase)?;
+        (steps_before <= budget && stats.steps > budget).then_some(budget)
+    }
+
+    /// Allows `phase` at most `steps` instructions in total.
+    pub fn set_budget(&mut self, phase: u32, steps: u64) {
+        self.budgets.insert(phase, steps);
+    }
+
+    /// Returns the budget of `phase`, if it has one.
+    pub fn budget(&self, phase: u32) -> Option<u64> {
+        self.budgets.get(&phase).copied()
+    }
+
+    /// Returns what was measured for `phase`, if it was ever left.
+    pub fn stats(&self, phase: u32) -> Option<&PhaseStats> {
+        self.phases.get(&phase)
+    }
+
+    /// Every phase that was left at least once, in id order.
+    pub fn phases(&self) -> impl Iterator<Item = (u32, &PhaseStats)> + '_ {
+        self.phases.iter().map(|(&phase, stats)| (phase, stats))
+    }
+
+    /// Returns whether no phase was ever left.
+    pub fn is_empty(&self) -> bool {
+        self.phases.is_empty()
+    }
+
+    /// Formats a table of every phase, naming them with `names` indexed by phase id, as
+    /// kept in [`DebugInfo::phases`](crate::program::DebugInfo::phases).
+    #[cfg(feature = "cli")]
+    pub fn report(&self, names: &[String]) -> String {
+        let name = |phase: u32| names.get(phase as usize).cloned().unwrap_or_else(|| format!("phase {}", phase));
+        let width = self.phases.keys().map(|&phase| name(phase).len()).chain([5]).max().unwrap_or(5);
+
+        let mut report = format!("{:<width$}  {:>8}  {:>10}  {:>12}\n", "phase", "entries", "steps", "time");
+        for (&phase, stats) in &self.phases {
+            let millis = stats.time.as_secs_f64() * 1000.0;
+            report.push_str(&format!(
+                "{:<width$}  {:>8}  {:>10}  {:>9.3} ms\n",
+                name(phase),
+                stats.entries,
+                stats.steps,
+                millis
+            ));
+        }
+        report
+    }
+
+    /// Forgets every measurement and open phase. Budgets are kept.
+    pub fn clear(&mut self) {
+        self.phases.clear();
+        self.open.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recursive_entries_are_measured_once() {
+        let mut profile = Profile::default();
+        profile.begin(0, 0);
+        profile.begin(0, 4);
+        profile.end(0, 6);
+        profile.end(0, 10);
+
+        assert_eq!(profile.stats(0).map(|stats| (stats.entries, stats.steps)), Some((2, 10)));
+    }
+
+    #[test]
+    fn test_unmatched_end_is_ignored() {
+        let mut profile = Profile::default();
+        profile.end(3, 5);
+        assert!(profile.is_empty());
+
+        // Overlapping phases each close their own entry
+        profile.begin(0, 0);
+        profile.begin(1, 1);
+        profile.end(0, 4);
+        profile.end(1, 7);
+        assert_eq!(profile.stats(0).unwrap().steps, 4);
+        assert_eq!(profile.stats(1).unwrap().steps, 6);
+    }
+
+    #[test]
+    fn test_going_over_budget_is_reported_once() {
+        let mut profile = Profile::default();
+        profile.set_budget(0, 10);
+        profile.begin(0, 0);
+        assert_eq!(profile.end(0, 10), None);
+        profile.begin(0, 20);
+        assert_eq!(profile.end(0, 21), Some(10));
+        profile.begin(0, 30);
+        assert_eq!(profile.end(0, 40), None);
+
+        profile.clear();
+        assert_eq!(profile.budget(0), Some(10));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_report_names_phases() {
+        let mut profile = Profile::default();
+        profile.begin(0, 0);
+        profile.end(0, 12);
+        profile.begin(7, 12);
+        profile.end(7, 13);
+
+        let report = profile.report(&["parse".to_string()]);
+        let lines: Vec<Vec<&str>> = report.lines().map(|line| line.split_whitespace().collect()).collect();
+        assert_eq!(lines[0], ["phase", "entries", "steps", "time"]);
+        assert_eq!(lines[1][..3], ["parse", "1", "12"]);
+        assert_eq!(lines[2][..4], ["phase", "7", "1", "1"]);
+    }
+}
@@ -0,0 +1,696 @@
+use crate::instruction::{Instruction, Word};
+use crate::run::{VmConfig, MEMORY_SIZE};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Resources a program declares with `.requires`, so it does not depend on the defaults
+/// of whichever runner loads it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Requirements {
+    /// Memory cells the program needs (`.requires mem N`).
+    pub memory: Option<usize>,
+    /// How many instructions the program should be allowed to execute (`.requires fuel N`).
+    pub fuel: Option<u64>,
+}
+
+impl Requirements {
+    /// Adjusts `config` to the requirements: memory grows to at least the required size,
+    /// and the fuel becomes the step limit unless one was already set.
+    ///
+    /// ```
+    /// use vortex_vm::program::Requirements;
+    /// use vortex_vm::run::VmConfig;
+    ///
+    /// let requirements = Requirements { memory: Some(8192), fuel: Some(1000) };
+    /// let mut config = VmConfig { max_steps: Some(50), ..VmConfig::default() };
+    /// requirements.apply(&mut config);
+    ///
+    /// assert_eq!((config.memory_size, config.max_steps), (Some(8192), Some(50)));
+    /// ```
+    pub fn apply(&self, config: &mut VmConfig) {
+        if let Some(memory) = self.memory
+            && memory > config.memory_size.unwrap_or(MEMORY_SIZE)
+        {
+            config.memory_size = Some(memory);
+        }
+        if config.max_steps.is_none() {
+            config.max_steps = self.fuel;
+        }
+    }
+
+    /// Combines two sets of requirements, keeping the larger of each.
+    pub fn merge(&self, other: &Requirements) -> Requirements {
+        Requirements { memory: self.memory.max(other.memory), fuel: self.fuel.max(other.fuel) }
+    }
+}
+
+/// Values declared in a `.data` section, copied into memory starting at `start` before
+/// the program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataBlock {
+    pub start: usize,
+    pub values: Vec<Word>,
+}
+
+impl DataBlock {
+    /// Returns the first address past the end of the block.
+    pub fn end(&self) -> usize {
+        self.start + self.values.len()
+    }
+}
+
+/// A named range of memory declared with `.region NAME start len`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Region {
+    /// Returns the first address past the end of the region.
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// Returns true if `addr` lies inside the region.
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr < self.end()
+    }
+}
+
+/// Information about a program that is not needed to execute it but helps tools
+/// present it to humans.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugInfo {
+    pub regions: Vec<Region>,
+    /// The 1-based source line of each instruction, indexed by instruction.
+    pub lines: Vec<usize>,
+    /// The `NULL`s inserted by `.align`. They reserve room for patching code in place, so
+    /// tools that rewrite programs should keep them and their position.
+    pub padding: Vec<Range<usize>>,
+    /// Label names with the instruction index they mark, in index order.
+    pub labels: Vec<(String, usize)>,
+    /// The source file the program was parsed from, if it came from a file.
+    pub file: Option<PathBuf>,
+    /// The names of the profiling phases `PROFBEGIN`/`PROFEND` name, indexed by phase id.
+    pub phases: Vec<String>,
+    /// The most steps each phase may take in total, declared with `.budget PHASE STEPS`
+    /// and keyed by phase id.
+    pub budgets: BTreeMap<u32, u64>,
+}
+
+impl DebugInfo {
+    /// Looks up a region by name (case-sensitive).
+    pub fn region(&self, name: &str) -> Option<&Region> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+
+    /// Returns the region containing `addr`, if any.
+    pub fn region_at(&self, addr: usize) -> Option<&Region> {
+        self.regions.iter().find(|region| region.contains(addr))
+    }
+
+    /// Names `addr` by the region containing it, as `NAME` for its first cell and
+    /// `NAME+OFFSET` for the others.
+    ///
+    /// ```
+    /// use vortex_vm::program::{DebugInfo, Region};
+    ///
+    /// let debug_info = DebugInfo { regions: vec![Region { name: "pair".to_string(), start: 4, len: 2 }], ..DebugInfo::default() };
+    /// assert_eq!(debug_info.name_address(4).as_deref(), Some("pair"));
+    /// assert_eq!(debug_info.name_address(5).as_deref(), Some("pair+1"));
+    /// assert_eq!(debug_info.name_address(6), None);
+    /// ```
+    pub fn name_address(&self, addr: usize) -> Option<String> {
+        let region = self.region_at(addr)?;
+        Some(match addr - region.start {
+            0 => region.name.clone(),
+            offset => format!("{}+{}", region.name, offset),
+        })
+    }
+
+    /// Returns true if the instruction at `index` is alignment padding.
+    pub fn is_padding(&self, index: usize) -> bool {
+        self.padding.iter().any(|range| range.contains(&index))
+    }
+
+    /// Returns the source line the instruction at `index` was parsed from, if known.
+    pub fn source_line(&self, index: usize) -> Option<usize> {
+        self.lines.get(index).copied()
+    }
+
+    /// Describes the location of the instruction at `index`, preferring its source line.
+    pub fn describe_location(&self, index: usize) -> String {
+        match self.source_line(index) {
+            Some(line) => format!("line {}", line),
+            None => format!("instruction {}", index),
+        }
+    }
+
+    /// Names the instruction at `index` by the closest label at or before it, if there is
+    /// one, e.g. `loop+1 at main.vvm:5` for the debugger and traces.
+    pub fn symbol(&self, index: usize) -> Option<Symbol> {
+        self.labels.iter().any(|(_, label)| *label <= index).then(|| self.symbol_with(&self.labels, index))
+    }
+
+    fn symbol_with(&self, labels: &[(String, usize)], index: usize) -> Symbol {
+        let label = labels.iter().filter(|(_, at)| *at <= index).max_by_key(|(_, at)| *at);
+        Symbol {
+            label: label.map(|(name, _)| name.clone()),
+            offset: index - label.map_or(0, |(_, at)| *at),
+            file: self.file.clone(),
+            line: self.source_line(index),
+        }
+    }
+}
+
+/// A human-readable location of an instruction, see [`Program::symbolize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The closest label at or before the instruction, if any.
+    pub label: Option<String>,
+    /// How many instructions past `label` (or the start of the program) the instruction is.
+    pub offset: usize,
+    /// The source file, if known.
+    pub file: Option<PathBuf>,
+    /// The 1-based source line, if known.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Symbol {
+    /// Formats the symbol as `label+offset at file:line`, leaving out what is unknown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.label {
+            Some(label) if self.offset == 0 => write!(f, "{}", label)?,
+            Some(label) => write!(f, "{}+{}", label, self.offset)?,
+            None => write!(f, "instruction {}", self.offset)?,
+        }
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, " at {}:{}", file.display(), line),
+            (Some(file), None) => write!(f, " in {}", file.display()),
+            (None, Some(line)) => write!(f, " at line {}", line),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// A parsed program together with its initial memory image and debug information.
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::program::{Program, Region};
+///
+/// let mut program = Program::new(vec![Instruction::MemWrite(0, vec![72, 105]), Instruction::Ret]);
+/// program.debug_info.regions.push(Region { name: "greeting".to_string(), start: 0, len: 5 });
+/// let region = program.debug_info.region("greeting").unwrap();
+///
+/// assert_eq!((region.start, region.len), (0, 5));
+/// assert_eq!(program.instructions.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    /// The initial memory image, in ascending address order without overlaps.
+    pub data: Vec<DataBlock>,
+    pub requirements: Requirements,
+    pub debug_info: DebugInfo,
+}
+
+impl Program {
+    /// Creates a program without data or debug information.
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Program { instructions, data: Vec::new(), requirements: Requirements::default(), debug_info: DebugInfo::default() }
+    }
+
+    /// Returns the label and source location of the instruction at `pc`, or `None` if `pc`
+    /// lies past the end of the program.
+    ///
+    /// Without label names in the debug information, such as for a program loaded from
+    /// bytecode assembled without `--debug`, labels are reconstructed from the code: `start` for the first instruction,
+    /// `sub_N` for call targets and `loc_N` for jump targets.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::program::Program;
+    ///
+    /// let mut program = Program::new(vec![
+    ///     Instruction::Call(2),
+    ///     Instruction::Ret,
+    ///     Instruction::Push(1),
+    ///     Instruction::Ret,
+    /// ]);
+    /// assert_eq!(program.symbolize(3).unwrap().to_string(), "sub_2+1");
+    ///
+    /// // As parsed from "CALL work\nRET\nwork:\nPUSH 1\nRET"
+    /// program.debug_info.labels = vec![("work".to_string(), 2)];
+    /// program.debug_info.lines = vec![1, 2, 4, 5];
+    /// assert_eq!(program.symbolize(3).unwrap().to_string(), "work+1 at line 5");
+    /// ```
+    pub fn symbolize(&self, pc: usize) -> Option<Symbol> {
+        if pc > self.instructions.len() {
+            return None;
+        }
+
+        let reconstructed;
+        let labels = if self.debug_info.labels.is_empty() {
+            reconstructed = self.reconstruct_labels();
+            &reconstructed
+        } else {
+            &self.debug_info.labels
+        };
+        Some(self.debug_info.symbol_with(labels, pc))
+    }
+
+    /// Writes the program as assembly source that assembles back to the same instructions,
+    /// data and requirements. Jumps and calls name their targets by the labels of the debug
+    /// information, or by labels reconstructed as for [`symbolize`](Program::symbolize).
+    /// Declared regions are kept, and memory operands inside one are named in a comment.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::program::Program;
+    ///
+    /// let mut program = Program::new(vec![Instruction::Push(3), Instruction::SubS(1), Instruction::Jnz(1)]);
+    /// assert_eq!(program.to_source(), ".text\nstart:\n    PUSH 3\nloc_1:\n    SUBS 1\n    JNZ loc_1\n");
+    ///
+    /// program.debug_info.labels = vec![("loop".to_string(), 1)];
+    /// assert_eq!(program.to_source(), ".text\n    PUSH 3\nloop:\n    SUBS 1\n    JNZ loop\n");
+    /// ```
+    pub fn to_source(&self) -> String {
+        let mut source = String::new();
+        if let Some(memory) = self.requirements.memory {
+            source.push_str(&format!(".requires mem {}\n", memory));
+        }
+        if let Some(fuel) = self.requirements.fuel {
+            source.push_str(&format!(".requires fuel {}\n", fuel));
+        }
+        for region in &self.debug_info.regions {
+            source.push_str(&format!(".region {} {} {}\n", region.name, region.start, region.len));
+        }
+        for block in &self.data {
+            let directive = if block.values.iter().all(|value| (0..=255).contains(value)) { ".byte" } else { ".word" };
+            let values: Vec<String> = block.values.iter().map(|value| value.to_string()).collect();
+            source.push_str(&format!(".data {}\n    {} {}\n", block.start, directive, values.join(" ")));
+        }
+        source.push_str(".text\n");
+
+        let reconstructed;
+        let labels = if self.debug_info.labels.is_empty() {
+            reconstructed = self.reconstruct_labels();
+            &reconstructed
+        } else {
+            &self.debug_info.labels
+        };
+        let label_at = |index: usize| labels.iter().filter(move |(_, at)| *at == index).map(|(name, _)| name);
+
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            for name in label_at(index) {
+                source.push_str(&format!("{}:\n", name));
+            }
+            let line = match instruction.jump_target().and_then(|target| label_at(target as usize).next()) {
+                Some(name) => format!("{} {}", instruction.to_string().split(' ').next().unwrap_or_default(), name),
+                None => instruction.to_string(),
+            };
+            let regions: Vec<String> =
+                instruction.memory_addresses().into_iter().filter_map(|addr| usize::try_from(addr).ok().and_then(|addr| self.debug_info.name_address(addr))).collect();
+            if regions.is_empty() {
+                source.push_str(&format!("    {}\n", line));
+            } else {
+                source.push_str(&format!("    {} ; {}\n", line, regions.join(", ")));
+            }
+        }
+        for name in label_at(self.instructions.len()) {
+            source.push_str(&format!("{}:\n", name));
+        }
+        source
+    }
+
+    /// Names the entry point and every call and jump target, in index order.
+    fn reconstruct_labels(&self) -> Vec<(String, usize)> {
+        let mut targets = BTreeMap::new();
+        for instruction in &self.instructions {
+            match instruction.jump_target().map(|target| target as usize) {
+                Some(target) if target < self.instructions.len() => {
+                    // A target that is both called and jumped to is named as a subroutine
+                    let is_call = matches!(instruction, Instruction::Call(_));
+                    *targets.entry(target).or_insert(is_call) |= is_call;
+                }
+                _ => {}
+            }
+        }
+
+        let named = targets.into_iter().filter(|&(index, _)| index != 0).map(|(index, is_call)| {
+            let prefix = if is_call { "sub" } else { "loc" };
+            (format!("{}_{}", prefix, index), index)
+        });
+        std::iter::once(("start".to_string(), 0)).chain(named).collect()
+    }
+
+    /// Appends `other` after the last instruction and returns the index it now starts at.
+    ///
+    /// Jump and call targets in `other` are relocated by that offset, and its regions and
+    /// data are merged with ours. Both fragments share one memory, so a region declared
+    /// twice must have the same bounds, and differently named regions as well as data
+    /// blocks must not overlap; otherwise nothing is appended and an error is returned.
+    /// The combined program requires the larger of both fragments' resources, and
+    /// profiling phases with the same name become one phase with the tighter budget.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::program::Program;
+    ///
+    /// let mut program = Program::new(vec![Instruction::Call(2), Instruction::Ret]);
+    /// let helper = Program::new(vec![Instruction::Push(1), Instruction::Jmp(0)]);
+    ///
+    /// assert_eq!(program.append(helper), Ok(2));
+    /// assert_eq!(program.instructions[3], Instruction::Jmp(2));
+    /// ```
+    pub fn append(&mut self, other: Program) -> Result<usize, String> {
+        let offset = self.instructions.len();
+        u32::try_from(offset + other.instructions.len()).map_err(|_| "Program has too many instructions".to_string())?;
+
+        let mut new_regions = Vec::new();
+        for region in other.debug_info.regions {
+            match self.debug_info.region(&region.name) {
+                Some(existing) if *existing == region => continue,
+                Some(existing) => {
+                    return Err(format!(
+                        "Region '{}' is declared as {}..{} and {}..{}",
+                        region.name,
+                        existing.start,
+                        existing.end(),
+                        region.start,
+                        region.end()
+                    ));
+                }
+                None => {}
+            }
+            let overlapping = self.debug_info.regions.iter().chain(&new_regions).find(|existing| overlaps(existing, &region));
+            if let Some(existing) = overlapping {
+                return Err(format!("Region '{}' overlaps region '{}'", region.name, existing.name));
+            }
+            new_regions.push(region);
+        }
+        for block in &other.data {
+            if let Some(existing) = self.data.iter().find(|existing| existing.start < block.end() && block.start < existing.end()) {
+                return Err(format!("Data at {}..{} overlaps data at {}..{}", block.start, block.end(), existing.start, existing.end()));
+            }
+        }
+        self.debug_info.regions.extend(new_regions);
+        self.data.extend(other.data);
+        self.data.sort_by_key(|block| block.start);
+        self.requirements = self.requirements.merge(&other.requirements);
+
+        // Source lines only stay meaningful while every instruction has one
+        if self.debug_info.lines.len() == offset && other.debug_info.lines.len() == other.instructions.len() {
+            self.debug_info.lines.extend(other.debug_info.lines);
+        }
+        let padding = other.debug_info.padding.into_iter().map(|range| range.start + offset..range.end + offset);
+        self.debug_info.padding.extend(padding);
+        let labels = other.debug_info.labels.into_iter().map(|(name, index)| (name, index + offset));
+        self.debug_info.labels.extend(labels);
+        if self.debug_info.file != other.debug_info.file {
+            self.debug_info.file = None;
+        }
+
+        // Phases with the same name are the same phase; the others get new ids
+        let phases: Vec<u32> = other
+            .debug_info
+            .phases
+            .into_iter()
+            .map(|name| match self.debug_info.phases.iter().position(|existing| *existing == name) {
+                Some(id) => id as u32,
+                None => {
+                    self.debug_info.phases.push(name);
+                    self.debug_info.phases.len() as u32 - 1
+                }
+            })
+            .collect();
+        for (phase, steps) in other.debug_info.budgets {
+            let phase = phases.get(phase as usize).copied().unwrap_or(phase);
+            let budget = self.debug_info.budgets.entry(phase).or_insert(steps);
+            *budget = (*budget).min(steps);
+        }
+
+        for mut instruction in other.instructions {
+            if let Some(target) = instruction.jump_target_mut() {
+                *target = target.saturating_add(offset as u32);
+            }
+            if let Instruction::ProfBegin(phase) | Instruction::ProfEnd(phase) = &mut instruction
+                && let Some(&id) = phases.get(*phase as usize)
+            {
+                *phase = id;
+            }
+            self.instructions.push(instruction);
+        }
+        Ok(offset)
+    }
+
+    /// Removes the instructions that can never run, see
+    /// [`reachable`](crate::analysis::reachable), and returns how many were removed.
+    ///
+    /// Jump targets, labels and source lines follow the instructions that are kept; labels
+    /// of removed code are dropped. A program with `.align` padding keeps its layout and is
+    /// left unchanged.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::program::Program;
+    ///
+    /// let mut program = Program::new(vec![Instruction::Jmp(2), Instruction::Push(1), Instruction::Push(2)]);
+    /// assert_eq!(program.eliminate_dead_code(), 1);
+    /// assert_eq!(program.instructions, vec![Instruction::Jmp(1), Instruction::Push(2)]);
+    /// ```
+    #[cfg(feature = "optimizer")]
+    pub fn eliminate_dead_code(&mut self) -> usize {
+        if !self.debug_info.padding.is_empty() {
+            return 0;
+        }
+        let reached = crate::analysis::reachable(&self.instructions);
+        let removed = reached.iter().filter(|&&reached| !reached).count();
+        if removed == 0 {
+            return 0;
+        }
+
+        // The new index of every instruction, and of the end of the program
+        let mut new_index = Vec::with_capacity(reached.len() + 1);
+        let mut kept = 0;
+        for &reached in &reached {
+            new_index.push(kept);
+            kept += usize::from(reached);
+        }
+        new_index.push(kept);
+        let relocate = |index: usize| new_index.get(index).copied().unwrap_or_else(|| index - removed);
+
+        let instructions = std::mem::take(&mut self.instructions);
+        for (mut instruction, _) in instructions.into_iter().zip(&reached).filter(|&(_, &reached)| reached) {
+            if let Some(target) = instruction.jump_target_mut() {
+                *target = relocate(*target as usize) as u32;
+            }
+            self.instructions.push(instruction);
+        }
+        if self.debug_info.lines.len() == reached.len() {
+            let lines = std::mem::take(&mut self.debug_info.lines);
+            self.debug_info.lines = lines.into_iter().zip(&reached).filter(|&(_, &reached)| reached).map(|(line, _)| line).collect();
+        }
+        self.debug_info.labels.retain(|&(_, index)| reached.get(index).copied().unwrap_or(true));
+        for (_, index) in &mut self.debug_info.labels {
+            *index = relocate(*index);
+        }
+        removed
+    }
+}
+
+fn overlaps(a: &Region, b: &Region) -> bool {
+    a.start < b.end() && b.start < a.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debug_info() -> DebugInfo {
+        DebugInfo {
+            regions: vec![
+                Region { name: "input".to_string(), start: 0, len: 16 },
+                Region { name: "screen".to_string(), start: 100, len: 80 },
+            ],
+            lines: vec![2, 3, 5],
+            padding: Vec::new(),
+            labels: vec![("start".to_string(), 0), ("loop".to_string(), 1)],
+            file: Some(PathBuf::from("main.vvm")),
+            phases: vec!["setup".to_string()],
+            budgets: BTreeMap::from([(0, 100)]),
+        }
+    }
+
+    #[test]
+    fn test_region_lookup_by_name() {
+        let info = debug_info();
+        assert_eq!(info.region("screen").map(|r| r.start), Some(100));
+        assert!(info.region("Screen").is_none());
+    }
+
+    #[test]
+    fn test_region_lookup_by_address() {
+        let info = debug_info();
+        assert_eq!(info.region_at(15).map(|r| r.name.as_str()), Some("input"));
+        assert_eq!(info.region_at(179).map(|r| r.name.as_str()), Some("screen"));
+        assert!(info.region_at(16).is_none());
+        assert!(info.region_at(180).is_none());
+    }
+
+    #[test]
+    fn test_describe_location() {
+        let info = debug_info();
+        assert_eq!(info.describe_location(2), "line 5");
+        assert_eq!(info.describe_location(3), "instruction 3");
+    }
+
+
+    #[test]
+    fn test_symbolize_uses_debug_labels() {
+        let program = Program { debug_info: debug_info(), ..Program::new(vec![Instruction::Ret; 4]) };
+        let symbol = program.symbolize(2).unwrap();
+        assert_eq!(symbol.label.as_deref(), Some("loop"));
+        assert_eq!((symbol.offset, symbol.line), (1, Some(5)));
+        assert_eq!(symbol.to_string(), "loop+1 at main.vvm:5");
+        assert_eq!(program.symbolize(3).unwrap().to_string(), "loop+2 in main.vvm");
+        assert_eq!(program.symbolize(5), None);
+    }
+
+    #[test]
+    fn test_symbolize_reconstructs_labels() {
+        let program = Program::new(vec![
+            Instruction::Jmp(3),
+            Instruction::Call(4),
+            Instruction::Ret,
+            Instruction::Call(4),
+            Instruction::Jiz(4),
+            Instruction::Jmp(0),
+            Instruction::Jmp(99),
+        ]);
+        let labels: Vec<String> = (0..7).map(|pc| program.symbolize(pc).unwrap().to_string()).collect();
+        assert_eq!(labels, ["start", "start+1", "start+2", "loc_3", "sub_4", "sub_4+1", "sub_4+2"]);
+    }
+
+    #[test]
+    fn test_append_relocates_targets() {
+        let mut program = Program::new(vec![Instruction::Push(0), Instruction::Jiz(1)]);
+        let fragment = Program::new(vec![Instruction::Jnz(1), Instruction::Call(0), Instruction::JmpDyn]);
+
+        assert_eq!(program.append(fragment), Ok(2));
+        assert_eq!(
+            program.instructions[2..],
+            [Instruction::Jnz(3), Instruction::Call(2), Instruction::JmpDyn]
+        );
+    }
+
+    #[test]
+    fn test_append_merges_regions_and_lines() {
+        let mut program = Program { debug_info: debug_info(), ..Program::new(vec![Instruction::Ret; 3]) };
+        let fragment = Program {
+            instructions: vec![Instruction::Null, Instruction::ProfEnd(1), Instruction::Null],
+            data: Vec::new(),
+            requirements: Requirements::default(),
+            debug_info: DebugInfo {
+                regions: vec![
+                    Region { name: "input".to_string(), start: 0, len: 16 },
+                    Region { name: "scratch".to_string(), start: 16, len: 4 },
+                ],
+                lines: vec![6, 7, 8],
+                padding: vec![0..1, 2..3],
+                labels: vec![("helper".to_string(), 1)],
+                file: None,
+                phases: vec!["load".to_string(), "setup".to_string()],
+                budgets: BTreeMap::from([(0, 20), (1, 50)]),
+            },
+        };
+
+        program.append(fragment).unwrap();
+        assert_eq!(program.debug_info.regions.len(), 3);
+        assert_eq!(program.debug_info.phases, ["setup", "load"]);
+        assert_eq!(program.debug_info.budgets, BTreeMap::from([(0, 50), (1, 20)]));
+        assert_eq!(program.instructions[4], Instruction::ProfEnd(0));
+        assert_eq!(program.debug_info.source_line(4), Some(7));
+        assert!(program.debug_info.is_padding(3) && program.debug_info.is_padding(5));
+        assert!(!program.debug_info.is_padding(4));
+        assert_eq!(program.symbolize(5).unwrap().to_string(), "helper+1 at line 8");
+    }
+
+    #[test]
+    fn test_append_rejects_conflicting_regions() {
+        let mut program = Program { debug_info: debug_info(), ..Program::new(vec![Instruction::Ret; 3]) };
+        let conflict = |name: &str, start| Program {
+            instructions: vec![Instruction::Ret],
+            data: Vec::new(),
+            requirements: Requirements::default(),
+            debug_info: DebugInfo { regions: vec![Region { name: name.to_string(), start, len: 8 }], ..DebugInfo::default() },
+        };
+
+        assert_eq!(program.append(conflict("input", 4)), Err("Region 'input' is declared as 0..16 and 4..12".to_string()));
+        assert_eq!(program.append(conflict("buffer", 96)), Err("Region 'buffer' overlaps region 'screen'".to_string()));
+        assert_eq!(program.instructions.len(), 3);
+    }
+
+    #[test]
+    fn test_requirements_only_grow_memory() {
+        let requirements = Requirements { memory: Some(100), fuel: Some(10) };
+        let mut config = VmConfig::default();
+        requirements.apply(&mut config);
+        assert_eq!((config.memory_size, config.max_steps), (None, Some(10)));
+
+        let merged = requirements.merge(&Requirements { memory: Some(4096), fuel: None });
+        assert_eq!(merged, Requirements { memory: Some(4096), fuel: Some(10) });
+    }
+
+    #[test]
+    #[cfg(all(feature = "assembler", feature = "optimizer"))]
+    fn test_dead_code_elimination_relocates_debug_info() {
+        let source = "CALL f\nJMP end\nunused:\nPUSH 1\nRET\nf:\nPUSH 2\nJIZ f\nRET\nend:";
+        let mut program = crate::spliter::parse_program(source).unwrap();
+        assert_eq!(program.eliminate_dead_code(), 2);
+
+        assert_eq!(program.instructions, vec![
+            Instruction::Call(2),
+            Instruction::Jmp(5),
+            Instruction::Push(2),
+            Instruction::Jiz(2),
+            Instruction::Ret,
+        ]);
+        assert_eq!(program.debug_info.lines, vec![1, 2, 7, 8, 9]);
+        let labels: Vec<(&str, usize)> = program.debug_info.labels.iter().map(|(name, index)| (name.as_str(), *index)).collect();
+        assert_eq!(labels, [("f", 2), ("end", 5)]);
+
+        let mut aligned = crate::spliter::parse_program("HALT 0\n.align 2\nPUSH 1").unwrap();
+        assert_eq!(aligned.eliminate_dead_code(), 0);
+        assert_eq!(aligned.instructions.len(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "assembler")]
+    fn test_source_names_regions() {
+        let program = crate::spliter::parse_program(".region buf 4 4\nMEMSET 4 4 0\nMEMCOPY 10 6 2\nMEMREAD 20").unwrap();
+        let source = program.to_source();
+        assert_eq!(source, ".region buf 4 4\n.text\nstart:\n    MEMSET 4 4 0 ; buf\n    MEMCOPY 10 6 2 ; buf+2\n    MEMREAD 20\n");
+        let reparsed = crate::spliter::parse_program(&source).unwrap();
+        assert_eq!((reparsed.instructions, reparsed.debug_info.regions), (program.instructions, program.debug_info.regions));
+    }
+
+    #[test]
+    fn test_append_merges_data() {
+        let block = |start, values: &[Word]| DataBlock { start, values: values.to_vec() };
+        let mut program = Program { data: vec![block(10, &[1, 2])], ..Program::new(vec![Instruction::Ret]) };
+
+        let fragment = Program { data: vec![block(0, &[3])], ..Program::new(vec![Instruction::Ret]) };
+        program.append(fragment).unwrap();
+        assert_eq!(program.data, vec![block(0, &[3]), block(10, &[1, 2])]);
+
+        let overlapping = Program { data: vec![block(11, &[4])], ..Program::new(vec![Instruction::Ret]) };
+        assert_eq!(program.append(overlapping), Err("Data at 11..12 overlaps data at 10..12".to_string()));
+        assert_eq!(program.instructions.len(), 2);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::instruction::Word;
+use crate::run::{Vm, VmConfig};
+use crate::spliter::split_instructions_with_lines;
+
+/// Maximum number of instructions executed while rendering a program.
+pub const RENDER_STEP_LIMIT: usize = 1_000_000;
+
+/// Maximum number of snapshots shown for a section that runs more than once (e.g. a loop body).
+pub const MAX_SNAPSHOTS_PER_SECTION: usize = 5;
+
+/// The state of the VM when execution left a section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub stack: Vec<Word>,
+    pub output: String,
+}
+
+/// A part of an annotated program: the `;;` notes that introduce it, its source lines,
+/// and what the VM looked like each time execution passed through it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub notes: Vec<String>,
+    pub source: Vec<String>,
+    pub snapshots: Vec<Snapshot>,
+    pub visits: usize,
+}
+
+/// The result of executing an annotated program section by section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notebook {
+    pub sections: Vec<Section>,
+    pub error: Option<String>,
+}
+
+impl Notebook {
+    /// Splits `source` into sections and runs it, recording a snapshot whenever execution
+    /// moves from one section to another or jumps back within a section.
+    ///
+    /// A line starting with `;;` begins a new section; consecutive `;;` lines are the notes
+    /// describing it. Code before the first such line forms an untitled section.
+    ///
+    /// Fails if the program jumps to a label that is never defined, as
+    /// [`split_instructions`](crate::spliter::split_instructions) does.
+    ///
+    /// ```
+    /// use vortex_vm::render::Notebook;
+    /// use vortex_vm::run::VmConfig;
+    ///
+    /// let source = ";; Push two numbers\nPUSH 2\nPUSH 3\n;; Add them\nADD\nRET";
+    /// let notebook = Notebook::run(source, VmConfig::default()).unwrap();
+    ///
+    /// assert_eq!(notebook.sections.len(), 2);
+    /// assert_eq!(notebook.sections[0].snapshots[0].stack, vec![2, 3]);
+    /// assert_eq!(notebook.sections[1].snapshots[0].stack, vec![5]);
+    /// ```
+    pub fn run(source: &str, config: VmConfig) -> Result<Self, String> {
+        let (mut sections, section_starts) = split_sections(source);
+        let (instructions, lines) = split_instructions_with_lines(source)?;
+
+        // The section each instruction belongs to
+        let section_of: Vec<usize> = lines
+            .iter()
+            .map(|&line| section_starts.iter().rposition(|&start| start <= line).unwrap_or(0))
+            .collect();
+
+        let mut vm = Vm::with_config(instructions, config);
+        let mut output = Vec::new();
+        let mut shown_output = 0;
+        let mut error = None;
+        let mut steps = 0;
+
+        while vm.is_running() {
+            if steps == RENDER_STEP_LIMIT {
+                error = Some(format!("Stopped after {} steps", RENDER_STEP_LIMIT));
+                break;
+            }
+
+            let executed = vm.pc;
+            let current = section_of[executed];
+            let result = vm.step(&mut output);
+            steps += 1;
+
+            // Jumping backwards within a section starts another pass through it
+            let left_section = !vm.is_running() || section_of.get(vm.pc) != Some(&current) || vm.pc <= executed;
+            if left_section || result.is_err() {
+                let section = &mut sections[current];
+                section.visits += 1;
+                if section.snapshots.len() < MAX_SNAPSHOTS_PER_SECTION {
+                    section.snapshots.push(Snapshot {
+                        stack: vm.stack.clone(),
+                        output: String::from_utf8_lossy(&output[shown_output..]).into_owned(),
+                    });
+                }
+                shown_output = output.len();
+            }
+
+            if let Err(e) = result {
+                error = Some(format!("Program trapped: {}", e));
+                break;
+            }
+        }
+
+        Ok(Notebook { sections, error })
+    }
+
+    /// Renders the notebook as a self-contained HTML page.
+    pub fn to_html(&self, title: &str) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+        html.push_str(STYLE);
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&format!("<h1>{}</h1>\n", escape_html(title)));
+
+        for section in &self.sections {
+            html.push_str("<section>\n");
+            for note in &section.notes {
+                html.push_str(&format!("<p>{}</p>\n", escape_html(note)));
+            }
+            if !section.source.is_empty() {
+                html.push_str(&format!("<pre class=\"source\">{}</pre>\n", escape_html(&section.source.join("\n"))));
+            }
+
+            if section.visits == 0 && !section.source.is_empty() {
+                html.push_str("<p class=\"skipped\">Not executed</p>\n");
+            }
+            for snapshot in &section.snapshots {
+                html.push_str(&format!("<div class=\"stack\">Stack: {}</div>\n", escape_html(&format!("{:?}", snapshot.stack))));
+                if !snapshot.output.is_empty() {
+                    html.push_str(&format!("<pre class=\"output\">{}</pre>\n", escape_html(&snapshot.output)));
+                }
+            }
+            if section.visits > section.snapshots.len() {
+                html.push_str(&format!("<p class=\"skipped\">Executed {} times, showing the first {}</p>\n", section.visits, section.snapshots.len()));
+            }
+            html.push_str("</section>\n");
+        }
+
+        if let Some(error) = &self.error {
+            html.push_str(&format!("<p class=\"error\">{}</p>\n", escape_html(error)));
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+/// Splits `source` into sections, returning them along with the 1-based line each starts at.
+fn split_sections(source: &str) -> (Vec<Section>, Vec<usize>) {
+    let mut sections = vec![empty_section()];
+    let mut starts = vec![1];
+    let mut in_notes = false;
+
+    for (index, line) in source.lines().enumerate() {
+        match line.trim().strip_prefix(";;") {
+            Some(note) => {
+                if !in_notes {
+                    sections.push(empty_section());
+                    starts.push(index + 1);
+                    in_notes = true;
+                }
+                let note = note.trim();
+                if !note.is_empty() {
+                    sections.last_mut().unwrap().notes.push(note.to_string());
+                }
+            }
+            None => {
+                in_notes = false;
+                sections.last_mut().unwrap().source.push(line.to_string());
+            }
+        }
+    }
+
+    // Drop a leading section that only holds blank lines
+    if sections.len() > 1 && sections[0].source.iter().all(|line| line.trim().is_empty()) {
+        sections.remove(0);
+        starts.remove(0);
+    }
+
+    for section in &mut sections {
+        while section.source.last().is_some_and(|line| line.trim().is_empty()) {
+            section.source.pop();
+        }
+    }
+
+    (sections, starts)
+}
+
+fn empty_section() -> Section {
+    Section { notes: Vec::new(), source: Vec::new(), snapshots: Vec::new(), visits: 0 }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const STYLE: &str = "<style>
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; }
+section { border-top: 1px solid #ccc; padding: 0.5em 0; }
+pre.source { background: #f4f4f4; padding: 0.5em; }
+pre.output { background: #fffbe6; padding: 0.5em; }
+.stack { font-family: monospace; color: #225; }
+.skipped { color: #888; font-style: italic; }
+.error { color: #b00; font-weight: bold; }
+</style>
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOOP: &str = "\
+;; Count down from 3
+PUSH 3
+;; Loop body
+loop:
+SUBS 1
+JNZ loop
+;; Print the result
+MemWrite 0 111 107
+Print 0 2
+RET
+";
+
+    #[test]
+    fn test_sections_and_snapshots() {
+        let notebook = Notebook::run(LOOP, VmConfig::default()).unwrap();
+
+        assert_eq!(notebook.sections.len(), 3);
+        assert_eq!(notebook.sections[0].notes, vec!["Count down from 3"]);
+        assert_eq!(notebook.sections[1].source, vec!["loop:", "SUBS 1", "JNZ loop"]);
+        assert_eq!(notebook.sections[1].visits, 3);
+        assert_eq!(notebook.sections[1].snapshots[0].stack, vec![2]);
+        assert_eq!(notebook.sections[2].snapshots[0].output, "ok");
+        assert_eq!(notebook.error, None);
+    }
+
+    #[test]
+    fn test_html_escapes_and_reports_traps() {
+        let notebook = Notebook::run(";; a < b\nPUSH 1\nPUSH 99\nJMPDYN", VmConfig::default()).unwrap();
+        let html = notebook.to_html("demo");
+
+        assert!(html.contains("<p>a &lt; b</p>"));
+        assert!(html.contains("Stack: [1, 99]"));
+        assert!(html.contains("Program trapped: invalid jump target 99 at instruction 2"));
+    }
+}
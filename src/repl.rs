@@ -0,0 +1,329 @@
+use crate::assembler::load_program_file;
+use crate::include::read_source;
+use crate::instruction::Instruction;
+use crate::run::{Vm, VmConfig};
+use crate::spliter::{label_definition, parse_labels, parse_line_with_labels, parse_program, MNEMONICS};
+use std::collections::HashMap;
+use std::fs;
+
+/// Maximum number of instructions a single REPL line may execute.
+pub const REPL_STEP_LIMIT: usize = 100_000;
+
+/// The meta-commands understood by [`Repl::handle_line`].
+pub const META_COMMANDS: &[&str] = &[":load", ":reset", ":stack", ":mem", ":save", ":help", ":quit"];
+
+/// An interactive session that executes assembly one line at a time against a
+/// persistent [`Vm`].
+///
+/// Every instruction typed is appended to the session program and run straight away, so
+/// labels defined earlier can be jumped to or called later. Lines starting with `:` are
+/// meta-commands (see [`Repl::handle_line`]).
+///
+/// ```
+/// use vortex_vm::repl::Repl;
+///
+/// let mut repl = Repl::new();
+/// repl.handle_line("PUSH 2");
+/// repl.handle_line("PUSH 3");
+/// repl.handle_line("ADD");
+///
+/// assert_eq!(repl.vm.stack, vec![5]);
+/// assert_eq!(repl.handle_line(":stack").unwrap(), "Stack: [5]");
+/// ```
+pub struct Repl {
+    pub vm: Vm,
+    pub output: Vec<u8>,
+    labels: HashMap<String, usize>,
+}
+
+impl Repl {
+    /// Creates a session with an empty program.
+    pub fn new() -> Self {
+        Self::with_config(VmConfig::default())
+    }
+
+    /// Creates a session whose VM runs with the given configuration.
+    pub fn with_config(config: VmConfig) -> Self {
+        Repl {
+            vm: Vm::with_config(Vec::new(), config),
+            output: Vec::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Handles one line of input and returns the text to show the user, or `None` when
+    /// the user asked to quit.
+    ///
+    /// Assembly lines are executed immediately. Supported meta-commands: `:load <file>`,
+    /// `:reset`, `:stack`, `:mem <addr> [len]`, `:save <file>`, `:help`, `:quit`.
+    pub fn handle_line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+
+        let Some(meta) = line.strip_prefix(':') else {
+            return Some(self.execute_line(line));
+        };
+
+        let (command, argument) = meta.split_once(char::is_whitespace).unwrap_or((meta, ""));
+        let argument = argument.trim();
+
+        let response = match command {
+            "load" => match self.load(argument) {
+                Ok(count) => format!("Loaded {} instructions from '{}'", count, argument),
+                Err(e) => e,
+            },
+            "reset" => {
+                *self = Self::with_config(self.vm.config.clone());
+                "Session reset".to_string()
+            }
+            "stack" => format!("Stack: {:?}", self.vm.stack),
+            "mem" => self.format_memory(argument),
+            "save" => match self.save(argument) {
+                Ok(()) => format!("Session saved to '{}'", argument),
+                Err(e) => e,
+            },
+            "help" => HELP.to_string(),
+            "quit" | "q" => return None,
+            _ => format!("Unknown command ':{}'. Type ':help' for a list of commands.", command),
+        };
+
+        Some(response)
+    }
+
+    /// Returns the words worth offering for tab completion: mnemonics, meta-commands and
+    /// the labels defined so far.
+    pub fn completion_words(&self) -> Vec<String> {
+        let mut labels: Vec<String> = self.labels.keys().cloned().collect();
+        labels.sort();
+
+        MNEMONICS.iter().chain(META_COMMANDS).map(|word| word.to_string()).chain(labels).collect()
+    }
+
+    /// Appends a library to the session program without running it, so its labels can be
+    /// called from later lines. Returns the number of instructions loaded.
+    ///
+    /// Assembly sources (`.vvm`) bring their labels along; bytecode files (`.asv`) can only
+    /// be reached by instruction index. The library's data is copied into memory right away.
+    pub fn load(&mut self, path: &str) -> Result<usize, String> {
+        if path.is_empty() {
+            return Err("Usage: :load <file>".to_string());
+        }
+
+        let (program, labels) = if path.ends_with(".asv") {
+            (load_program_file(path)?, HashMap::new())
+        } else {
+            let source = read_source(path, &[])?.text;
+            (parse_program(&source)?, parse_labels(&source))
+        };
+        let instructions = program.instructions;
+        self.vm.load_data(&program.data);
+
+        // Jump over the library so the session program stays runnable from the start
+        let jump_index = self.vm.program.len();
+        let offset = jump_index + 1;
+        let end = offset + instructions.len();
+        self.vm.program_mut().push(Instruction::Jmp(end as u32));
+
+        for mut instruction in instructions.iter().cloned() {
+            if let Some(target) = instruction.jump_target_mut() {
+                *target = target.saturating_add(offset as u32);
+            }
+            self.vm.program_mut().push(instruction);
+        }
+        for (name, index) in labels {
+            self.labels.insert(name, index + offset);
+        }
+
+        self.vm.pc = self.vm.program.len();
+        Ok(instructions.len())
+    }
+
+    /// Writes the session program, including loaded libraries, as an assembly source file
+    /// that runs the same instructions from the start.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        if path.is_empty() {
+            return Err("Usage: :save <file>".to_string());
+        }
+        fs::write(path, self.to_source()).map_err(|e| format!("Failed to write '{}': {}", path, e))
+    }
+
+    /// Returns the session program as assembly source.
+    pub fn to_source(&self) -> String {
+        let mut labels: Vec<(&String, &usize)> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, index)| (**index, name.as_str()));
+
+        let mut source = String::new();
+        for (index, instruction) in self.vm.program.iter().enumerate() {
+            for (name, _) in labels.iter().filter(|(_, label_index)| **label_index == index) {
+                source.push_str(&format!("{}:\n", name));
+            }
+            source.push_str(&format!("    {}\n", instruction));
+        }
+        for (name, _) in labels.iter().filter(|(_, label_index)| **label_index >= self.vm.program.len()) {
+            source.push_str(&format!("{}:\n", name));
+        }
+        source
+    }
+
+    /// Appends an instruction or label typed by the user and runs it.
+    fn execute_line(&mut self, line: &str) -> String {
+        if let Some(name) = label_definition(line) {
+            self.labels.insert(name.clone(), self.vm.program.len());
+            return format!("Label '{}' defined at instruction {}", name, self.vm.program.len());
+        }
+
+        let Some(instruction) = parse_line_with_labels(line, &self.labels) else {
+            if line.is_empty() || line.starts_with(';') {
+                return String::new();
+            }
+            return format!("Invalid instruction: {}", line);
+        };
+
+        self.vm.program_mut().push(instruction);
+        self.vm.pc = self.vm.program.len() - 1;
+
+        let output_before = self.output.len();
+        let mut response = String::new();
+        let mut steps = 0;
+        while self.vm.is_running() {
+            if steps == REPL_STEP_LIMIT {
+                response = format!("Stopped after {} steps", REPL_STEP_LIMIT);
+                break;
+            }
+            if let Err(e) = self.vm.step(&mut self.output) {
+                response = format!("Trapped: {}", e);
+                break;
+            }
+            steps += 1;
+        }
+
+        // A RET outside of a call, a HALT or a trap should not end the session
+        if let Some(code) = self.vm.exit_code.take() {
+            response = format!("Halted with exit code {}", code);
+        }
+        self.vm.halted = false;
+        self.vm.pc = self.vm.program.len();
+
+        if self.output.len() > output_before {
+            let output = String::from_utf8_lossy(&self.output[output_before..]).into_owned();
+            response = if response.is_empty() { output } else { format!("{}\n{}", output, response) };
+        }
+        response
+    }
+
+    fn format_memory(&self, argument: &str) -> String {
+        let parts: Vec<&str> = argument.split_whitespace().collect();
+        let (Some(start), len) = (parts.first().and_then(|p| p.parse::<usize>().ok()), parts.get(1)) else {
+            return "Usage: :mem <addr> [len]".to_string();
+        };
+        let len = len.and_then(|l| l.parse::<usize>().ok()).unwrap_or(8);
+
+        if start >= self.vm.mem.len() {
+            return format!("Address {} is out of bounds", start);
+        }
+        let end = (start + len).min(self.vm.mem.len());
+        format!("Memory {}..{}: {:?}", start, end, &self.vm.mem[start..end])
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const HELP: &str = "\
+Type an instruction or a label to run it. Meta-commands:
+  :load <file>        Add a .vvm or .asv library to the session without running it
+  :reset              Start over with an empty program and fresh VM state
+  :stack              Show the stack
+  :mem <addr> [len]   Show memory cells
+  :save <file>        Write the session as a runnable .vvm program
+  :help               Show this help
+  :quit               Leave the session";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spliter::split_instructions;
+    use std::env;
+
+    fn temp_path(name: &str) -> String {
+        env::temp_dir().join(format!("vortex_repl_{}_{}", std::process::id(), name)).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_lines_run_against_persistent_state() {
+        let mut repl = Repl::new();
+        repl.handle_line("MemWrite 0 104 105");
+        assert_eq!(repl.handle_line("Print 0 2").unwrap(), "hi");
+        repl.handle_line("MemRead 1");
+
+        assert_eq!(repl.vm.stack, vec![105]);
+        assert_eq!(repl.handle_line(":mem 0 2").unwrap(), "Memory 0..2: [104, 105]");
+    }
+
+    #[test]
+    fn test_ret_and_traps_keep_the_session_alive() {
+        let mut repl = Repl::new();
+        repl.handle_line("RET");
+        assert_eq!(repl.handle_line("HALT 2").unwrap(), "Halted with exit code 2");
+        assert!(repl.handle_line("CALL 99").unwrap().starts_with("Trapped: invalid call target"));
+
+        repl.handle_line("PUSH 1");
+        assert_eq!(repl.vm.stack, vec![1]);
+    }
+
+    #[test]
+    fn test_load_library_and_call_it() {
+        let library = temp_path("lib.vvm");
+        fs::write(&library, "double:\n    DUP\n    ADD\n    RET\n").unwrap();
+
+        let mut repl = Repl::new();
+        repl.handle_line("PUSH 21");
+        assert_eq!(repl.handle_line(&format!(":load {}", library)).unwrap(), format!("Loaded 3 instructions from '{}'", library));
+        repl.handle_line("CALL double");
+        fs::remove_file(&library).unwrap();
+
+        assert_eq!(repl.vm.stack, vec![42]);
+    }
+
+    #[test]
+    fn test_saved_session_runs_the_same() {
+        let library = temp_path("square.vvm");
+        fs::write(&library, "square:\n    DUP\n    MULT\n    RET\n").unwrap();
+
+        let mut repl = Repl::new();
+        repl.handle_line(&format!(":load {}", library));
+        repl.handle_line("PUSH 7");
+        repl.handle_line("CALL square");
+        fs::remove_file(&library).unwrap();
+
+        let program = split_instructions(&repl.to_source()).unwrap();
+        let mut vm = Vm::new(program);
+        vm.run(&mut Vec::new()).unwrap();
+        assert_eq!(vm.stack, vec![49]);
+    }
+
+    #[test]
+    fn test_completion_words_include_labels() {
+        let mut repl = Repl::new();
+        repl.handle_line("square:");
+
+        let words = repl.completion_words();
+        assert!(words.iter().any(|w| w == "MEMWRITES"));
+        assert!(words.iter().any(|w| w == ":load"));
+        assert_eq!(words.last().map(String::as_str), Some("square"));
+    }
+
+    #[test]
+    fn test_reset_and_unknown_commands() {
+        let mut repl = Repl::new();
+        repl.handle_line("loop:");
+        repl.handle_line("PUSH 3");
+
+        assert_eq!(repl.handle_line(":reset").unwrap(), "Session reset");
+        assert!(repl.vm.stack.is_empty() && repl.vm.program.is_empty());
+        assert!(repl.handle_line(":frob").unwrap().starts_with("Unknown command"));
+        assert_eq!(repl.handle_line(":quit"), None);
+    }
+}
@@ -1,456 +1,3664 @@
-use crate::instruction::Instruction;
-use std::io::Write;
+use crate::encoding::{decode_word, encode_word};
+use crate::diagnostic;
+use crate::diagnostics::Level;
+use crate::heap::Heap;
+use crate::input::{InputSource, ReaderInput};
+use crate::instruction::{Instruction, Word};
+use crate::isa::{IsaVersion, SUPPORTED_CAPABILITIES};
+use crate::profile::Profile;
+use crate::program::{DataBlock, Program};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
-/// Executes a program of instructions and returns the final state of the stack and memory.
-///
-/// This is the main entry point for running Vortex VM programs. It processes each instruction
-/// in sequence, maintaining a stack for data manipulation and a memory space for data storage.
-///
-/// # Examples
-///
-/// Basic stack operations:
+/// Number of memory cells available to a program unless [`VmConfig::memory_size`] says otherwise.
+pub const MEMORY_SIZE: usize = 2048;
+
+/// Number of memory cells `GROW` adds per page.
+pub const PAGE_SIZE: usize = 1024;
+
+/// The most memory cells `GROW` can reach unless [`VmConfig::max_memory_size`] says otherwise.
+pub const MAX_MEMORY_SIZE: usize = 1 << 22;
+
+/// Maximum number of nested `Call` frames before execution traps.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// Number of local variable slots each call frame has for `LOADLOCAL` and `STORELOCAL`.
+pub const MAX_LOCALS: usize = 256;
+
+/// Number of recently executed instruction indices kept for crash reports.
+pub const TRACE_RING_SIZE: usize = 16;
+
+/// Number of instructions [`Vm::run_with_cancel`] executes between checks of its token.
+pub const CANCEL_CHECK_INTERVAL: u64 = 1024;
+
+/// A memory fill value that makes reads of uninitialized memory easy to spot.
+pub const POISON_PATTERN: Word = 0xDEADBEEF;
+
+/// `PERFREAD` counter: instructions executed so far, including the `PERFREAD` itself.
+pub const PERF_STEPS: i32 = 0;
+/// `PERFREAD` counter: values on the stack before the counter is pushed.
+pub const PERF_STACK_DEPTH: i32 = 1;
+/// `PERFREAD` counter: subroutine calls that have not returned yet.
+pub const PERF_CALL_DEPTH: i32 = 2;
+/// `PERFREAD` counter: instructions left before `max_steps` traps, or -1 without a limit.
+pub const PERF_STEPS_LEFT: i32 = 3;
+/// `PERFREAD` counter: memory cells currently allocated with `ALLOC`.
+pub const PERF_HEAP_CELLS: i32 = 4;
+
+/// `VMINFO` field: the version of this VM as `major * 10000 + minor * 100 + patch`.
+pub const VMINFO_VERSION: i32 = 0;
+/// `VMINFO` field: the instruction set version as `major * 100 + minor`.
+pub const VMINFO_ISA: i32 = 1;
+/// `VMINFO` field: memory cells, including any added by `GROW`.
+pub const VMINFO_MEMORY: i32 = 2;
+/// `VMINFO` field: the [capability](crate::isa::Capability) mask of this build.
+pub const VMINFO_CAPABILITIES: i32 = 3;
+/// `VMINFO` field: bits in a stack value.
+pub const VMINFO_WORD_BITS: i32 = 4;
+
+/// Settings that control how a [`Vm`] executes a program.
 ///
 /// ```
 /// use vortex_vm::instruction::Instruction;
-/// use vortex_vm::run::execute;
-///
-/// let program = vec![
-///     Instruction::Push(5),
-///     Instruction::Push(3),
-///     Instruction::Add,
-///     Instruction::Ret,
-/// ];
+/// use vortex_vm::run::{Vm, VmConfig, POISON_PATTERN};
 ///
-/// let mut output = Vec::new();
-/// let (stack, memory) = execute(&program, &mut output);
+/// let config = VmConfig { memory_fill: POISON_PATTERN, ..VmConfig::default() };
+/// let mut vm = Vm::with_config(vec![Instruction::MemRead(7)], config);
+/// vm.run(&mut Vec::new()).unwrap();
 ///
-/// assert_eq!(stack, vec![8]);
-/// assert_eq!(memory[0], 0); // Memory is initialized to zeros
+/// assert_eq!(vm.stack, vec![POISON_PATTERN]);
 /// ```
-///
-/// Memory operations:
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VmConfig {
+    /// The value every memory cell holds before the program writes to it.
+    pub memory_fill: Word,
+    /// Number of memory cells, or `None` for [`MEMORY_SIZE`].
+    pub memory_size: Option<usize>,
+    /// The most memory cells `GROW` may grow memory to, or `None` for [`MAX_MEMORY_SIZE`].
+    pub max_memory_size: Option<usize>,
+    /// What to do when `MemRead` or `Print` touches a cell the program never wrote.
+    pub uninit_reads: UninitReadMode,
+    /// Remember which instruction produced each stack value so traps can point at it.
+    pub track_provenance: bool,
+    /// Stop with [`VmError::FuelExhausted`] after executing this many instructions.
+    pub max_steps: Option<u64>,
+    /// Stop with [`VmError::StackOverflow`] once the operand stack holds more than this many
+    /// values, so a runaway `DUP` loop traps instead of exhausting host memory.
+    pub max_stack_depth: Option<usize>,
+    /// Let `CODEREAD`/`CODEWRITE` read and replace instructions of the running program.
+    pub self_modifying: bool,
+    /// How `ADD`, `SUB`, `MULT`, `DIV` and their immediate forms handle overflow.
+    pub arithmetic: ArithmeticMode,
+    /// Trap on stack underflow, division by zero and out-of-bounds memory access instead
+    /// of skipping the instruction.
+    pub strict: bool,
+    /// How `PRINT` and `PRINTSTR` turn memory cells into output bytes.
+    pub output_encoding: OutputEncoding,
+    /// Make the data [`Vm::load_data`] copies into memory read-only, see [`Vm::protect`].
+    pub protect_data: bool,
+    /// Send traps that have a [`VmError::code`] to the innermost `TRY` handler, like a
+    /// `THROW` of that code, instead of stopping the program.
+    pub catch_traps: bool,
+}
+
+/// How `PRINT` and `PRINTSTR` turn memory cells into output bytes. Every mode uses the low
+/// byte of each cell.
 ///
 /// ```
 /// use vortex_vm::instruction::Instruction;
-/// use vortex_vm::run::execute;
-///
-/// let program = vec![
-///     Instruction::MemWrite(0, vec![72, 101, 108, 108, 111]), // "Hello"
-///     Instruction::Print(0, 5),
-///     Instruction::Ret,
-/// ];
+/// use vortex_vm::run::{OutputEncoding, Vm, VmConfig};
 ///
-/// let mut output = Vec::new();
-/// let (_stack, _memory) = execute(&program, &mut output);
+/// let program = vec![Instruction::MemWrite(0, vec![0xC3, 0xA9]), Instruction::Print(0, 2)];
+/// let print = |output_encoding| {
+///     let mut vm = Vm::with_config(program.clone(), VmConfig { output_encoding, ..VmConfig::default() });
+///     let mut output = Vec::new();
+///     vm.run(&mut output).map(|_| output)
+/// };
 ///
-/// let printed = String::from_utf8(output).unwrap();
-/// assert_eq!(printed, "Hello");
+/// assert_eq!(print(OutputEncoding::Latin1).unwrap(), "Ã©".as_bytes());
+/// assert_eq!(print(OutputEncoding::Raw).unwrap(), "é".as_bytes());
 /// ```
-///
-/// Jump instructions:
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// Each byte is a character from U+0000 to U+00FF and is written UTF-8 encoded, so bytes
+    /// from 128 up take two output bytes.
+    #[default]
+    Latin1,
+    /// Bytes are written unchanged, for binary output or text the program encodes itself.
+    Raw,
+    /// Like [`OutputEncoding::Raw`], but each print must be valid UTF-8 or it stops with
+    /// [`VmError::InvalidOutput`].
+    Utf8,
+    /// Each print becomes a line of space-separated two-digit hex bytes, for inspecting
+    /// binary output.
+    Hex,
+}
+
+/// How arithmetic instructions handle results that do not fit in a [`Word`].
 ///
 /// ```
-/// use vortex_vm::instruction::Instruction;
-/// use vortex_vm::run::execute;
-///
-/// let program = vec![
-///     Instruction::Push(3),
-///     Instruction::SubS(1),
-///     Instruction::Jnz("1".to_string()), // Jump back to start if not zero
-///     Instruction::Ret,
-/// ];
+/// use vortex_vm::instruction::{Instruction, Word};
+/// use vortex_vm::run::{ArithmeticMode, Vm, VmConfig, VmError};
 ///
-/// let mut output = Vec::new();
-/// let (stack, _memory) = execute(&program, &mut output);
+/// let program = vec![Instruction::Push(Word::MAX), Instruction::AddS(1)];
+/// let run = |arithmetic| {
+///     let mut vm = Vm::with_config(program.clone(), VmConfig { arithmetic, ..VmConfig::default() });
+///     vm.run(&mut Vec::new()).map(|_| vm.stack)
+/// };
 ///
-/// assert_eq!(stack, vec![0]); // Should decrement from 3 to 0
+/// assert_eq!(run(ArithmeticMode::Wrapping), Ok(vec![Word::MIN]));
+/// assert_eq!(run(ArithmeticMode::Saturating), Ok(vec![Word::MAX]));
+/// assert_eq!(run(ArithmeticMode::Checked), Err(VmError::ArithmeticOverflow { pc: 1 }));
 /// ```
-pub fn execute(instructions: &[Instruction], output_buffer: &mut Vec<u8>) -> (Vec<i32>, Vec<i32>) {
-    let mut stack: Vec<i32> = Vec::new();
-    let mut mem: Vec<i32> = vec![0; 2048];
-    let mut i: usize = 0;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    /// Wrap around in two's complement.
+    #[default]
+    Wrapping,
+    /// Clamp to [`Word::MIN`] or [`Word::MAX`].
+    Saturating,
+    /// Stop execution with [`VmError::ArithmeticOverflow`].
+    Checked,
+}
 
-    while i < instructions.len() {
-        match &instructions[i] {
-            Instruction::Null => {
-                i += 1;
-            }
-            Instruction::Push(value) => {
-                stack.push(*value);
-                i += 1;
-            }
-            Instruction::Pop => {
-                stack.pop();
-                i += 1;
-            }
-            Instruction::Ret => {
-                break;
-            }
-            Instruction::Jiz(target) => {
-                i = execute_jiz(&stack, instructions, i, target);
-            }
-            Instruction::Jnz(target) => {
-                i = execute_jnz(&stack, instructions, i, target);
+/// How reads of never-written memory cells are reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UninitReadMode {
+    /// Reads are not tracked.
+    #[default]
+    Off,
+    /// Print a warning with the instruction index and address, then continue.
+    Warn,
+    /// Stop execution with [`VmError::UninitializedRead`].
+    Trap,
+}
+
+/// A trap raised while executing a program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError {
+    /// A `Call` referenced a target outside the program.
+    InvalidCallTarget { pc: usize, target: u32 },
+    /// A `JmpDyn` popped an address outside the program.
+    InvalidJumpTarget { pc: usize, target: Word },
+    /// An instruction needed more values than the stack held.
+    StackUnderflow { pc: usize },
+    /// A memory cell was read before anything was written to it.
+    UninitializedRead { pc: usize, addr: usize },
+    /// A `THROW`, or a trap sent to the handlers by [`VmConfig::catch_traps`], found no
+    /// `TRY` handler installed.
+    UnhandledException { pc: usize, code: Word },
+    /// The program gave up with `ABORT`, reporting `message` from its memory.
+    GuestAbort { pc: usize, message: String },
+    /// An `ASSERT` or `ASSERTEQ` found `actual` where the program expected `expected`.
+    AssertionFailed { pc: usize, expected: Word, actual: Word },
+    /// A profiling phase took more steps than its budget in [strict](VmConfig::strict) mode,
+    /// see [`Vm::set_phase_budget`].
+    BudgetExceeded { pc: usize, phase: u32, steps: u64, budget: u64 },
+    /// Nested calls exceeded `MAX_CALL_DEPTH`.
+    CallStackOverflow { pc: usize, depth: usize },
+    /// The operand stack grew past `VmConfig::max_stack_depth` values.
+    StackOverflow { pc: usize, depth: usize },
+    /// The program used up its `VmConfig::max_steps` budget before finishing.
+    FuelExhausted { pc: usize, stack: Vec<Word> },
+    /// A `HostCall` named a function that was never registered.
+    UnknownHostFunction { pc: usize, name: String },
+    /// A host function reported an error.
+    HostFunctionFailed { pc: usize, name: String, message: String },
+    /// A `CodeRead` or `CodeWrite` was refused.
+    InvalidCodeAccess { pc: usize, index: Word, reason: String },
+    /// Reading input failed, or `ReadInt` did not get a number.
+    InvalidInput { pc: usize, reason: String },
+    /// The host cancelled the run through a [`CancellationToken`] before instruction `pc`.
+    Cancelled { pc: usize },
+    /// An arithmetic result did not fit in a [`Word`] in [`ArithmeticMode::Checked`] mode.
+    ArithmeticOverflow { pc: usize },
+    /// A division or modulo by zero in [strict](VmConfig::strict) mode.
+    DivisionByZero { pc: usize },
+    /// A memory operand outside memory in [strict](VmConfig::strict) mode.
+    MemoryOutOfBounds { pc: usize, addr: i32, len: i32 },
+    /// A `Load` or `Store` popped an address outside memory.
+    InvalidAddress { pc: usize, addr: Word },
+    /// An `ALLOC` asked for fewer than one cell, or for more than fits below
+    /// [`VmConfig::max_memory_size`].
+    AllocationFailed { pc: usize, size: Word },
+    /// A `FREE` popped an address that is not the start of an allocated block.
+    InvalidFree { pc: usize, addr: Word },
+    /// An instruction tried to write to memory made read-only with [`Vm::protect`].
+    ProtectedWrite { pc: usize, addr: usize },
+    /// A print was not valid UTF-8 in [`OutputEncoding::Utf8`] mode.
+    InvalidOutput { pc: usize, reason: String },
+}
+
+impl VmError {
+    /// Returns the index of the instruction that trapped.
+    pub fn pc(&self) -> usize {
+        match self {
+            VmError::InvalidCallTarget { pc, .. }
+            | VmError::InvalidJumpTarget { pc, .. }
+            | VmError::StackUnderflow { pc }
+            | VmError::UninitializedRead { pc, .. }
+            | VmError::CallStackOverflow { pc, .. }
+            | VmError::StackOverflow { pc, .. }
+            | VmError::UnhandledException { pc, .. }
+            | VmError::GuestAbort { pc, .. }
+            | VmError::AssertionFailed { pc, .. }
+            | VmError::BudgetExceeded { pc, .. }
+            | VmError::FuelExhausted { pc, .. }
+            | VmError::UnknownHostFunction { pc, .. }
+            | VmError::HostFunctionFailed { pc, .. }
+            | VmError::InvalidCodeAccess { pc, .. }
+            | VmError::InvalidInput { pc, .. }
+            | VmError::Cancelled { pc }
+            | VmError::ArithmeticOverflow { pc }
+            | VmError::DivisionByZero { pc }
+            | VmError::MemoryOutOfBounds { pc, .. }
+            | VmError::InvalidAddress { pc, .. }
+            | VmError::AllocationFailed { pc, .. }
+            | VmError::InvalidFree { pc, .. }
+            | VmError::ProtectedWrite { pc, .. }
+            | VmError::InvalidOutput { pc, .. } => *pc,
+        }
+    }
+
+    /// Returns the code a `TRY` handler receives for this trap with
+    /// [`VmConfig::catch_traps`], or `None` for traps that always stop the program.
+    ///
+    /// Traps use negative codes so they do not clash with the codes programs `THROW`.
+    pub fn code(&self) -> Option<Word> {
+        let code = match self {
+            VmError::StackUnderflow { .. } => -1,
+            VmError::DivisionByZero { .. } => -2,
+            VmError::ArithmeticOverflow { .. } => -3,
+            VmError::MemoryOutOfBounds { .. } => -4,
+            VmError::InvalidAddress { .. } => -5,
+            VmError::UninitializedRead { .. } => -6,
+            VmError::AllocationFailed { .. } => -7,
+            VmError::InvalidFree { .. } => -8,
+            VmError::ProtectedWrite { .. } => -9,
+            VmError::InvalidInput { .. } => -10,
+            VmError::HostFunctionFailed { .. } => -11,
+            _ => return None,
+        };
+        Some(code)
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::InvalidCallTarget { pc, target } => {
+                write!(f, "invalid call target '{}' at instruction {}", target, pc)
             }
-            Instruction::AddS(n) => {
-                i = execute_adds(&mut stack, i, *n);
+            VmError::InvalidJumpTarget { pc, target } => {
+                write!(f, "invalid jump target {} at instruction {}", target, pc)
             }
-            Instruction::Add => {
-                i = execute_add(&mut stack, i);
+            VmError::StackUnderflow { pc } => write!(f, "stack underflow at instruction {}", pc),
+            VmError::UninitializedRead { pc, addr } => {
+                write!(f, "read of uninitialized memory at address {} by instruction {}", addr, pc)
             }
-            Instruction::SubS(n) => {
-                i = execute_subs(&mut stack, i, *n);
+            VmError::CallStackOverflow { pc, depth } => {
+                write!(f, "call stack overflow (depth {}) at instruction {}", depth, pc)
             }
-            Instruction::Sub => {
-                i = execute_sub(&mut stack, i);
+            VmError::UnhandledException { pc, code } => {
+                write!(f, "unhandled exception {} at instruction {}", code, pc)
             }
-            Instruction::Dup => {
-                i = execute_dup(&mut stack, i);
+            VmError::GuestAbort { pc, message } => write!(f, "program aborted at instruction {}: {}", pc, message),
+            VmError::AssertionFailed { pc, expected, actual } => {
+                write!(f, "assertion failed at instruction {}: expected {}, found {}", pc, expected, actual)
             }
-            Instruction::Swap => {
-                i = execute_swap(&mut stack, i);
+            VmError::BudgetExceeded { pc, phase, steps, budget } => {
+                write!(f, "phase {} took {} steps, over its budget of {}, at instruction {}", phase, steps, budget, pc)
             }
-            Instruction::DivS(n) => {
-                i = execute_divs(&mut stack, i, *n);
+            VmError::StackOverflow { pc, depth } => {
+                write!(f, "stack overflow (depth {}) at instruction {}", depth, pc)
             }
-            Instruction::Div => {
-                i = execute_div(&mut stack, i);
+            VmError::FuelExhausted { pc, stack } => {
+                write!(f, "step limit reached before instruction {} (stack: {:?})", pc, stack)
             }
-            Instruction::MultS(n) => {
-                i = execute_mults(&mut stack, i, *n);
+            VmError::UnknownHostFunction { pc, name } => {
+                write!(f, "unknown host function '{}' at instruction {}", name, pc)
             }
-            Instruction::Mult => {
-                i = execute_mult(&mut stack, i);
+            VmError::HostFunctionFailed { pc, name, message } => {
+                write!(f, "host function '{}' failed at instruction {}: {}", name, pc, message)
             }
-            Instruction::MemWrite(start_addr, values) => {
-                i = execute_memwrite(&mut mem, i, *start_addr, values);
+            VmError::InvalidCodeAccess { pc, index, reason } => {
+                write!(f, "invalid code access at index {} by instruction {}: {}", index, pc, reason)
             }
-            Instruction::Print(start_addr, length) => {
-                i = execute_print(output_buffer, &mem, i, *start_addr, *length);
+            VmError::InvalidInput { pc, reason } => write!(f, "invalid input at instruction {}: {}", pc, reason),
+            VmError::Cancelled { pc } => write!(f, "cancelled before instruction {}", pc),
+            VmError::ArithmeticOverflow { pc } => write!(f, "arithmetic overflow at instruction {}", pc),
+            VmError::DivisionByZero { pc } => write!(f, "division by zero at instruction {}", pc),
+            VmError::MemoryOutOfBounds { pc, addr, len } => {
+                write!(f, "memory access of {} cell(s) at address {} is out of bounds at instruction {}", len, addr, pc)
             }
-            Instruction::MemRead(index) => {
-                i = execute_memread(&mut stack, &mem, i, *index);
+            VmError::InvalidAddress { pc, addr } => write!(f, "invalid memory address {} at instruction {}", addr, pc),
+            VmError::AllocationFailed { pc, size } => {
+                write!(f, "cannot allocate {} memory cells at instruction {}", size, pc)
             }
-            Instruction::MemWriteS(memory_index, write_len) => {
-                i = execute_memwrites(&mut stack, &mut mem, i, *memory_index, *write_len);
+            VmError::InvalidFree { pc, addr } => write!(f, "free of unallocated address {} at instruction {}", addr, pc),
+            VmError::ProtectedWrite { pc, addr } => {
+                write!(f, "write to read-only memory at address {} at instruction {}", addr, pc)
             }
+            VmError::InvalidOutput { pc, reason } => write!(f, "invalid output at instruction {}: {}", pc, reason),
         }
     }
-
-    (stack, mem)
 }
 
-// Jump instructions
-fn execute_jiz(stack: &[i32], instructions: &[Instruction], current_i: usize, target: &str) -> usize {
-    if let Some(&val) = stack.last()
-        && val == 0
-        && let Ok(addr) = target.parse::<usize>()
-        && addr < instructions.len()
-    {
-        addr
-    } else {
-        current_i + 1
+impl std::error::Error for VmError {}
+
+/// Lets a host stop a [`Vm::run_with_cancel`] running on another thread.
+///
+/// Clones share the same flag, so the host keeps one clone and hands another to the run.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
     }
-}
 
-fn execute_jnz(stack: &[i32], instructions: &[Instruction], current_i: usize, target: &str) -> usize {
-    if let Some(&val) = stack.last()
-        && val != 0
-        && let Ok(addr) = target.parse::<usize>()
-        && addr < instructions.len()
-    {
-        addr
-    } else {
-        current_i + 1
+    /// Asks every run holding this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
     }
-}
 
-// Arithmetic instructions
-fn execute_adds(stack: &mut Vec<i32>, current_i: usize, n: i32) -> usize {
-    if let Some(val) = stack.pop() {
-        stack.push(val + n);
+    /// Returns true once [`CancellationToken::cancel`] was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
     }
-    current_i + 1
 }
 
-fn execute_add(stack: &mut Vec<i32>, current_i: usize) -> usize {
-    if stack.len() >= 2 {
-        let a = stack.pop().unwrap();
-        let b = stack.pop().unwrap();
-        stack.push(b + a);
-    }
-    current_i + 1
+/// One executed instruction, as reported to a [`TraceSink`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent<'a> {
+    pub pc: usize,
+    pub instruction: &'a Instruction,
+    pub stack_before: &'a [Word],
+    pub stack_after: &'a [Word],
 }
 
-fn execute_subs(stack: &mut Vec<i32>, current_i: usize, n: i32) -> usize {
-    if let Some(val) = stack.pop() {
-        stack.push(val - n);
+impl fmt::Display for TraceEvent<'_> {
+    /// Formats the event as `index: instruction  stack-before -> stack-after`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5}: {:<20} {:?} -> {:?}", self.pc, self.instruction.to_string(), self.stack_before, self.stack_after)
     }
-    current_i + 1
 }
 
-fn execute_sub(stack: &mut Vec<i32>, current_i: usize) -> usize {
-    if stack.len() >= 2 {
-        let a = stack.pop().unwrap();
-        let b = stack.pop().unwrap();
-        stack.push(b - a);
-    }
-    current_i + 1
+/// Receives an event for every instruction executed by [`Vm::run_traced`].
+///
+/// Any `FnMut(&TraceEvent)` closure can be used as a sink.
+pub trait TraceSink {
+    fn trace(&mut self, event: &TraceEvent);
 }
 
-fn execute_divs(stack: &mut [i32], current_i: usize, n: i32) -> usize {
-    if let Some(val) = stack.last_mut() && n != 0 {
-        *val /= n;
+impl<F: FnMut(&TraceEvent)> TraceSink for F {
+    fn trace(&mut self, event: &TraceEvent) {
+        self(event)
     }
-    current_i + 1
 }
 
-fn execute_div(stack: &mut Vec<i32>, current_i: usize) -> usize {
-    if stack.len() >= 2 {
-        let a = stack.pop().unwrap();
-        let b = stack.pop().unwrap();
-        if a != 0 {
-            stack.push(b / a);
+/// A destination for output that should not go to the regular output buffer.
+pub type OutputSink = Arc<Mutex<dyn Write + Send>>;
+
+/// Routes `Print`s of a memory range to their own sink, see [`Vm::intercept_print`].
+#[derive(Clone)]
+pub struct PrintInterceptor {
+    pub range: Range<usize>,
+    sink: OutputSink,
+}
+
+impl PrintInterceptor {
+    /// Writes the output of the instruction at `pc` to the sink.
+    fn write(&self, bytes: &[u8], pc: usize) {
+        let mut sink = self.sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(e) = sink.write_all(bytes) {
+            diagnostic!(Level::Warn, "Intercepted output of instruction {} was lost: {}", pc, e);
         }
     }
-    current_i + 1
 }
 
-fn execute_mults(stack: &mut [i32], current_i: usize, n: i32) -> usize {
-    if let Some(val) = stack.last_mut() {
-        *val *= n;
+/// Returns the first interceptor whose range contains `start_addr`.
+fn find_interceptor(interceptors: &[PrintInterceptor], start_addr: i32) -> Option<&PrintInterceptor> {
+    let start = usize::try_from(start_addr).ok()?;
+    interceptors.iter().find(|interceptor| interceptor.range.contains(&start))
+}
+
+impl fmt::Debug for PrintInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrintInterceptor").field("range", &self.range).finish_non_exhaustive()
     }
-    current_i + 1
 }
 
-fn execute_mult(stack: &mut Vec<i32>, current_i: usize) -> usize {
-    if stack.len() >= 2 {
-        let a = stack.pop().unwrap();
-        let b = stack.pop().unwrap();
-        stack.push(b * a);
+/// Where `ReadLine` and `ReadInt` take their input from, see [`Vm::set_input_source`].
+pub type InputReader = Arc<Mutex<dyn InputSource>>;
+
+/// The input of a [`Vm`]; without a source every read sees the end of input.
+#[derive(Clone, Default)]
+struct VmInput(Option<InputReader>);
+
+impl fmt::Debug for VmInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VmInput").field(&self.0.is_some()).finish()
     }
-    current_i + 1
 }
 
-// Stack manipulation instructions
-fn execute_dup(stack: &mut Vec<i32>, current_i: usize) -> usize {
-    if let Some(&val) = stack.last() {
-        stack.push(val);
+/// A Rust function callable from assembly with `HOSTCALL name`. It receives the VM stack
+/// and may pop arguments and push results; returning an error traps the program.
+pub type HostFn = Arc<Mutex<dyn FnMut(&mut Vec<Word>) -> Result<(), String> + Send>>;
+
+/// The complete state of a running program.
+///
+/// `Vm` executes one instruction at a time via [`Vm::step`], which lets tools such as
+/// crash reporting inspect the state after a trap. Most callers can use [`execute`] instead.
+#[derive(Debug, Clone)]
+pub struct Vm {
+    pub config: VmConfig,
+    /// The instructions being run, shared with other VMs running the same program; use
+    /// [`Vm::program_mut`] to change them.
+    pub program: Arc<Vec<Instruction>>,
+    pub stack: Vec<Word>,
+    pub mem: Vec<Word>,
+    pub call_stack: Vec<usize>,
+    /// The local variables of every active call frame, innermost last.
+    pub locals: Vec<Word>,
+    /// Where the locals of the current frame start in [`Vm::locals`].
+    pub frame_pointer: usize,
+    /// The frame pointers of the callers, restored by `RET`.
+    saved_frames: Vec<usize>,
+    pub pc: usize,
+    pub halted: bool,
+    /// The code passed to the `HALT` that stopped the program, if any.
+    pub exit_code: Option<i32>,
+    pub steps: u64,
+    /// Incremented whenever `CODEWRITE` replaces an instruction, so tools holding on to
+    /// a decoded or annotated copy of the program know to refresh it.
+    pub code_version: u64,
+    /// The deepest the stacks have been since the VM was created or last reset.
+    pub high_water: HighWater,
+    trace: VecDeque<usize>,
+    initialized: Vec<bool>,
+    origins: Vec<Option<usize>>,
+    interceptors: Vec<PrintInterceptor>,
+    host_fns: HostFunctions,
+    input: VmInput,
+    heap: Heap,
+    protected: Vec<Range<usize>>,
+    handlers: Vec<Handler>,
+    profile: Profile,
+}
+
+/// A handler installed by `TRY`, with the state to unwind to when something is thrown.
+#[derive(Debug, Clone, Copy)]
+struct Handler {
+    target: usize,
+    stack_depth: usize,
+    call_depth: usize,
+    frame_pointer: usize,
+    locals: usize,
+}
+
+/// The largest stack and call stack a [`Vm`] has needed, see [`Vm::high_water`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HighWater {
+    /// The most values the operand stack held after an instruction.
+    pub stack: usize,
+    /// The most subroutine calls that were nested at once.
+    pub call_depth: usize,
+    /// The most memory cells that were allocated with `ALLOC` at once.
+    pub heap: usize,
+}
+
+/// Why a [`Vm`] stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExitReason {
+    /// A `RET` outside any subroutine.
+    Ret,
+    /// A `HALT` with its exit code.
+    Halt(i32),
+    /// Execution ran past the last instruction.
+    End,
+    /// The step limit ran out, see [`VmConfig::max_steps`].
+    FuelExhausted,
+    /// An instruction trapped.
+    Trap(VmError),
+}
+
+/// Where and why a run ended, see [`Vm::exit_summary`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExitSummary {
+    /// The index of the instruction execution ended at, or the program length if it ran
+    /// past the last instruction.
+    pub pc: usize,
+    pub reason: ExitReason,
+}
+
+impl ExitSummary {
+    /// Describes the exit, naming the instruction by its [symbol](Program::symbolize) in
+    /// `program`.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::program::Program;
+    /// use vortex_vm::run::Vm;
+    ///
+    /// let mut program = Program::new(vec![Instruction::Push(1), Instruction::Push(2), Instruction::Halt(3)]);
+    /// // As parsed from "PUSH 1\nmain:\nPUSH 2\nHALT 3"
+    /// program.debug_info.labels = vec![("main".to_string(), 1)];
+    /// program.debug_info.lines = vec![1, 3, 4];
+    /// let mut vm = Vm::new(program.instructions.clone());
+    /// let result = vm.run(&mut Vec::new());
+    ///
+    /// let summary = vm.exit_summary(&result).unwrap();
+    /// assert_eq!(summary.describe(&program), "HALT 3 at instruction 2 (main+1 at line 4)");
+    /// ```
+    pub fn describe(&self, program: &Program) -> String {
+        let location = match program.symbolize(self.pc) {
+            Some(symbol) => format!("instruction {} ({})", self.pc, symbol),
+            None => format!("instruction {}", self.pc),
+        };
+        match &self.reason {
+            ExitReason::Ret => format!("RET at {}", location),
+            ExitReason::Halt(code) => format!("HALT {} at {}", code, location),
+            ExitReason::End => format!("end of program at {}", location),
+            ExitReason::FuelExhausted => format!("out of fuel at {}", location),
+            ExitReason::Trap(error) => format!("trap at {}: {}", location, error),
+        }
     }
-    current_i + 1
 }
 
-fn execute_swap(stack: &mut Vec<i32>, current_i: usize) -> usize {
-    if stack.len() >= 2 {
-        let a = stack.pop().unwrap();
-        let b = stack.pop().unwrap();
-        stack.push(a);
-        stack.push(b);
+/// The host functions registered on a [`Vm`], shared between clones.
+#[derive(Clone, Default)]
+struct HostFunctions(HashMap<String, HostFn>);
+
+impl fmt::Debug for HostFunctions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.0.keys().collect();
+        names.sort();
+        f.debug_set().entries(names).finish()
     }
-    current_i + 1
 }
 
-// Memory instructions
-fn execute_memwrite(mem: &mut [i32], current_i: usize, start_addr: i32, values: &[i32]) -> usize {
-    if start_addr < 2048 {
-        for j in 0..values.len() {
-            if (start_addr as usize + j) < mem.len() {
-                mem[start_addr as usize + j] = values[j];
+impl Vm {
+    /// Creates a VM with an empty stack and zeroed memory, ready to run `program`.
+    pub fn new(program: impl Into<Arc<Vec<Instruction>>>) -> Self {
+        Self::with_config(program, VmConfig::default())
+    }
+
+    /// Creates a VM ready to run `program` with the given configuration.
+    ///
+    /// Passing an `Arc` lets many VMs, on any number of threads, run the same program
+    /// without each holding a copy of it.
+    pub fn with_config(program: impl Into<Arc<Vec<Instruction>>>, config: VmConfig) -> Self {
+        let tracks_writes = config.uninit_reads != UninitReadMode::Off;
+        let memory_size = config.memory_size.unwrap_or(MEMORY_SIZE);
+
+        Vm {
+            mem: vec![config.memory_fill; memory_size],
+            config,
+            program: program.into(),
+            stack: Vec::new(),
+            call_stack: Vec::new(),
+            locals: Vec::new(),
+            frame_pointer: 0,
+            saved_frames: Vec::new(),
+            pc: 0,
+            halted: false,
+            exit_code: None,
+            steps: 0,
+            code_version: 0,
+            high_water: HighWater::default(),
+            trace: VecDeque::with_capacity(TRACE_RING_SIZE),
+            initialized: if tracks_writes { vec![false; memory_size] } else { Vec::new() },
+            origins: Vec::new(),
+            interceptors: Vec::new(),
+            host_fns: HostFunctions::default(),
+            input: VmInput::default(),
+            heap: Heap::default(),
+            protected: Vec::new(),
+            handlers: Vec::new(),
+            profile: Profile::default(),
+        }
+    }
+
+    /// Returns the program for changing it, first copying it if other VMs share it.
+    pub fn program_mut(&mut self) -> &mut Vec<Instruction> {
+        Arc::make_mut(&mut self.program)
+    }
+
+    /// Returns true while there are instructions left to execute.
+    pub fn is_running(&self) -> bool {
+        !self.halted && self.pc < self.program.len()
+    }
+
+    /// Summarizes how the run that returned `result` ended, or returns `None` if the VM
+    /// can still continue.
+    pub fn exit_summary(&self, result: &Result<(), VmError>) -> Option<ExitSummary> {
+        let (pc, reason) = match result {
+            Err(error @ VmError::FuelExhausted { .. }) => (error.pc(), ExitReason::FuelExhausted),
+            Err(error) => (error.pc(), ExitReason::Trap(error.clone())),
+            Ok(()) if self.is_running() => return None,
+            Ok(()) => match self.exit_code {
+                Some(code) => (self.pc, ExitReason::Halt(code)),
+                None if self.halted => (self.pc, ExitReason::Ret),
+                None => (self.pc, ExitReason::End),
+            },
+        };
+        Some(ExitSummary { pc, reason })
+    }
+
+    /// Returns the most recently executed instruction indices, oldest first.
+    pub fn recent_trace(&self) -> Vec<usize> {
+        self.trace.iter().copied().collect()
+    }
+
+    /// Returns the index of the instruction that pushed the stack value `depth` slots below
+    /// the top, if [`VmConfig::track_provenance`] is enabled and the origin is known.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::{Vm, VmConfig};
+    ///
+    /// let config = VmConfig { track_provenance: true, ..VmConfig::default() };
+    /// let program = vec![Instruction::Push(4), Instruction::Push(5), Instruction::AddS(1)];
+    /// let mut vm = Vm::with_config(program, config);
+    /// vm.run(&mut Vec::new()).unwrap();
+    ///
+    /// assert_eq!(vm.stack_origin(0), Some(2));
+    /// assert_eq!(vm.stack_origin(1), Some(0));
+    /// ```
+    pub fn stack_origin(&self, depth: usize) -> Option<usize> {
+        if self.origins.len() != self.stack.len() {
+            return None;
+        }
+        let slot = self.origins.len().checked_sub(depth + 1)?;
+        self.origins[slot]
+    }
+
+    /// Replaces the stack with values that were not produced by the program, forgetting
+    /// where the previous values came from.
+    pub fn replace_stack(&mut self, stack: Vec<Word>) {
+        self.origins = vec![None; if self.config.track_provenance { stack.len() } else { 0 }];
+        self.stack = stack;
+    }
+
+    /// Describes the stack value a trap was caused by, as a name and the index of the
+    /// instruction that pushed it.
+    pub fn trap_operand(&self, error: &VmError) -> Option<(&'static str, usize)> {
+        match error {
+            VmError::InvalidJumpTarget { .. } => self.stack_origin(0).map(|origin| ("jump target", origin)),
+            _ => None,
+        }
+    }
+
+    /// Sends the output of every `Print` whose start address lies in `range` to `sink`
+    /// instead of the output buffer passed to [`Vm::run`].
+    ///
+    /// When ranges overlap, the interceptor registered first wins.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::Vm;
+    ///
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    /// let mut vm = Vm::new(vec![
+    ///     Instruction::MemWrite(0, vec![104, 105]),
+    ///     Instruction::MemWrite(100, vec![111, 107]),
+    ///     Instruction::Print(0, 2),
+    ///     Instruction::Print(100, 2),
+    /// ]);
+    /// vm.intercept_print(100..116, log.clone());
+    ///
+    /// let mut output = Vec::new();
+    /// vm.run(&mut output).unwrap();
+    /// assert_eq!(output, b"hi");
+    /// assert_eq!(*log.lock().unwrap(), b"ok");
+    /// ```
+    pub fn intercept_print(&mut self, range: Range<usize>, sink: OutputSink) {
+        self.interceptors.push(PrintInterceptor { range, sink });
+    }
+
+    /// Makes `function` callable from assembly as `HOSTCALL name`, replacing any function
+    /// previously registered under the same name.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::Vm;
+    ///
+    /// let mut vm = Vm::new(vec![
+    ///     Instruction::Push(20),
+    ///     Instruction::HostCall("double".to_string()),
+    ///     Instruction::HostCall("answer".to_string()),
+    /// ]);
+    /// vm.register_host_fn("double", |stack| {
+    ///     let value = stack.pop().ok_or("double needs an argument")?;
+    ///     stack.push(value * 2);
+    ///     Ok(())
+    /// });
+    /// vm.register_host_fn("answer", |stack| {
+    ///     stack.push(42);
+    ///     Ok(())
+    /// });
+    ///
+    /// vm.run(&mut Vec::new()).unwrap();
+    /// assert_eq!(vm.stack, vec![40, 42]);
+    /// ```
+    pub fn register_host_fn<F>(&mut self, name: &str, function: F)
+    where
+        F: FnMut(&mut Vec<Word>) -> Result<(), String> + Send + 'static,
+    {
+        self.host_fns.0.insert(name.to_string(), Arc::new(Mutex::new(function)));
+    }
+
+    /// Makes `ReadLine` and `ReadInt` read lines from `input`, e.g. a `Cursor` over test
+    /// data. See [`Vm::set_input_source`] for other kinds of input.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::Vm;
+    ///
+    /// let mut vm = Vm::new(vec![Instruction::ReadInt, Instruction::ReadInt, Instruction::Add]);
+    /// vm.set_input(Cursor::new("2\n40\n"));
+    ///
+    /// vm.run(&mut Vec::new()).unwrap();
+    /// assert_eq!(vm.stack, vec![42]);
+    /// ```
+    pub fn set_input<R>(&mut self, input: R)
+    where
+        R: BufRead + Send + 'static,
+    {
+        self.set_input_source(ReaderInput(input));
+    }
+
+    /// Makes `ReadLine` and `ReadInt` read from `source`, such as a
+    /// [`FileInput`](crate::input::FileInput) or a [`ScriptedInput`](crate::input::ScriptedInput).
+    pub fn set_input_source<S>(&mut self, source: S)
+    where
+        S: InputSource + 'static,
+    {
+        self.input = VmInput(Some(Arc::new(Mutex::new(source))));
+    }
+
+    /// Copies a program's initial memory image into memory; call it before running.
+    ///
+    /// Loaded cells count as written. Values that do not fit in memory are dropped with a warning.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::program::DataBlock;
+    /// use vortex_vm::run::Vm;
+    ///
+    /// let mut vm = Vm::new(vec![Instruction::PrintStr(100)]);
+    /// vm.load_data(&[DataBlock { start: 100, values: vec![104, 105, 0] }]);
+    ///
+    /// let mut output = Vec::new();
+    /// vm.run(&mut output).unwrap();
+    /// assert_eq!(output, b"hi");
+    /// ```
+    pub fn load_data(&mut self, data: &[DataBlock]) {
+        for block in data {
+            let end = block.end().min(self.mem.len());
+            if end < block.end() {
+                diagnostic!(Level::Warn, "Data at {}..{} does not fit in memory of {} cells", block.start, block.end(), self.mem.len());
+            }
+            if block.start >= end {
+                continue;
+            }
+
+            self.mem[block.start..end].copy_from_slice(&block.values[..end - block.start]);
+            if !self.initialized.is_empty() {
+                self.initialized[block.start..end].fill(true);
+            }
+            if self.config.protect_data {
+                self.protect(block.start..end);
             }
         }
     }
-    current_i + 1
-}
 
-fn execute_memwrites(stack: &mut Vec<i32>, mem: &mut [i32], current_i: usize, memory_index: i32, write_len: i32) -> usize {
-    if memory_index as usize + write_len as usize <= mem.len() {
-        let mut writes = Vec::with_capacity(write_len as usize);
-        for _ in 0..write_len {
-            if let Some(val) = stack.pop() {
-                writes.push(val);
-            } else {
-                eprintln!("Stack underflow on MemWriteS");
-                break;
+    /// Makes `cells` read-only until the next [`reset`](Vm::reset): instructions that write
+    /// to them trap with [`VmError::ProtectedWrite`] instead. The host can still change them
+    /// through [`Vm::mem`].
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::{Vm, VmError};
+    ///
+    /// let mut vm = Vm::new(vec![Instruction::MemWrite(8, vec![1, 2, 3])]);
+    /// vm.protect(10..12);
+    /// assert_eq!(vm.run(&mut Vec::new()), Err(VmError::ProtectedWrite { pc: 0, addr: 10 }));
+    /// ```
+    pub fn protect(&mut self, cells: Range<usize>) {
+        if !cells.is_empty() {
+            self.protected.push(cells);
+        }
+    }
+
+    /// Returns the read-only ranges of memory, in the order they were protected.
+    pub fn protected(&self) -> &[Range<usize>] {
+        &self.protected
+    }
+
+    /// Returns the steps and time measured for the phases the program delimited with
+    /// `PROFBEGIN` and `PROFEND`.
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Allows the profiling phase `phase` at most `steps` instructions in total, as
+    /// `.budget` directives declare in [`DebugInfo::budgets`](crate::program::DebugInfo::budgets).
+    /// A phase that goes over its budget is reported when it ends, or traps with
+    /// [`VmError::BudgetExceeded`] in [strict](VmConfig::strict) mode.
+    pub fn set_phase_budget(&mut self, phase: u32, steps: u64) {
+        self.profile.set_budget(phase, steps);
+    }
+
+    /// Puts the VM back into the state [`Vm::with_config`] created it in, keeping its program,
+    /// configuration, host functions, print interceptors, input and phase budgets.
+    ///
+    /// Memory and the stacks are cleared in place, so running many short programs on one
+    /// VM does not allocate for each run.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::Vm;
+    ///
+    /// let mut vm = Vm::new(vec![Instruction::MemWrite(0, vec![7]), Instruction::MemRead(0)]);
+    /// vm.run(&mut Vec::new()).unwrap();
+    ///
+    /// vm.reset();
+    /// assert_eq!((vm.pc, vm.stack.len(), vm.mem[0]), (0, 0, 0));
+    /// ```
+    pub fn reset(&mut self) {
+        let memory_size = self.config.memory_size.unwrap_or(MEMORY_SIZE);
+        self.stack.clear();
+        // Undo any GROW
+        self.mem.truncate(memory_size);
+        self.mem.fill(self.config.memory_fill);
+        self.call_stack.clear();
+        self.locals.clear();
+        self.frame_pointer = 0;
+        self.saved_frames.clear();
+        self.pc = 0;
+        self.halted = false;
+        self.exit_code = None;
+        self.steps = 0;
+        self.code_version = 0;
+        self.high_water = HighWater::default();
+        self.trace.clear();
+        self.initialized.truncate(memory_size);
+        self.initialized.fill(false);
+        self.origins.clear();
+        self.heap.clear();
+        self.protected.clear();
+        self.handlers.clear();
+        self.profile.clear();
+    }
+
+    /// Releases the capacity of the stacks and of memory beyond what they currently hold and
+    /// returns the number of bytes freed.
+    ///
+    /// A long-lived VM that runs many programs can call this between runs so that one deep
+    /// recursion, or memory one run grew and [`Vm::reset`] shrank again, does not stay
+    /// allocated for good.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::Vm;
+    ///
+    /// let mut vm = Vm::new(vec![Instruction::Push(1); 1000]);
+    /// vm.run(&mut Vec::new()).unwrap();
+    /// vm.stack.clear();
+    ///
+    /// assert!(vm.compact() >= 1000 * 8);
+    /// assert_eq!(vm.high_water.stack, 1000);
+    /// ```
+    pub fn compact(&mut self) -> usize {
+        let before = self.reserved_bytes();
+        self.stack.shrink_to_fit();
+        self.call_stack.shrink_to_fit();
+        self.locals.shrink_to_fit();
+        self.saved_frames.shrink_to_fit();
+        self.origins.shrink_to_fit();
+        self.mem.shrink_to_fit();
+        self.initialized.shrink_to_fit();
+        before - self.reserved_bytes()
+    }
+
+    /// Bytes allocated for the stacks and memory, used or not.
+    fn reserved_bytes(&self) -> usize {
+        self.stack.capacity() * size_of::<Word>()
+            + self.call_stack.capacity() * size_of::<usize>()
+            + self.locals.capacity() * size_of::<Word>()
+            + self.saved_frames.capacity() * size_of::<usize>()
+            + self.origins.capacity() * size_of::<Option<usize>>()
+            + self.mem.capacity() * size_of::<Word>()
+            + self.initialized.capacity() * size_of::<bool>()
+    }
+
+    /// Runs the program until it returns, falls off the end, or traps.
+    pub fn run(&mut self, output_buffer: &mut Vec<u8>) -> Result<(), VmError> {
+        while self.is_running() {
+            self.step(output_buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a program that [`verify_program`](crate::verify::verify_program) accepted.
+    ///
+    /// The common stack, arithmetic and jump instructions run directly, without the trace
+    /// ring, provenance or high-water marks; verification already ruled out what those would
+    /// catch. Everything else goes through [`Vm::step`], and traps on either path go to
+    /// [`VmConfig::catch_traps`] handlers alike. A config that asks for per-step checks, see
+    /// [`Vm::needs_step_checks`], runs the whole program through [`Vm::run`] instead.
+    #[cfg(any(feature = "assembler", feature = "disassembler"))]
+    pub(crate) fn run_verified(&mut self, output_buffer: &mut Vec<u8>) -> Result<(), VmError> {
+        if self.needs_step_checks() {
+            return self.run(output_buffer);
+        }
+
+        while self.is_running() {
+            let i = self.pc;
+            let arithmetic = self.config.arithmetic;
+            let stack = &mut self.stack;
+            let next = match &self.program[i] {
+                Instruction::Null => Ok(i + 1),
+                Instruction::Push(value) => {
+                    stack.push(*value);
+                    Ok(i + 1)
+                }
+                Instruction::Pop => {
+                    stack.pop();
+                    Ok(i + 1)
+                }
+                Instruction::Dup => Ok(execute_dup(stack, i)),
+                Instruction::Swap => Ok(execute_swap(stack, i)),
+                Instruction::Jiz(target) => Ok(execute_jiz(stack, &self.program, i, *target)),
+                Instruction::Jnz(target) => Ok(execute_jnz(stack, &self.program, i, *target)),
+                Instruction::Jmp(target) => Ok(execute_jmp(&self.program, i, *target)),
+                Instruction::AddS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Add, *n),
+                Instruction::Add => execute_binary(stack, i, arithmetic, ArithOp::Add),
+                Instruction::SubS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Sub, *n),
+                Instruction::Sub => execute_binary(stack, i, arithmetic, ArithOp::Sub),
+                Instruction::MultS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Mul, *n),
+                Instruction::Mult => execute_binary(stack, i, arithmetic, ArithOp::Mul),
+                Instruction::Eq => Ok(execute_compare(stack, i, |b, a| b == a)),
+                Instruction::Neq => Ok(execute_compare(stack, i, |b, a| b != a)),
+                Instruction::Lt => Ok(execute_compare(stack, i, |b, a| b < a)),
+                Instruction::Gt => Ok(execute_compare(stack, i, |b, a| b > a)),
+                Instruction::Lte => Ok(execute_compare(stack, i, |b, a| b <= a)),
+                Instruction::Gte => Ok(execute_compare(stack, i, |b, a| b >= a)),
+                Instruction::MemRead(index) => Ok(execute_memread(stack, &self.mem, i, *index)),
+                _ => {
+                    self.step(output_buffer)?;
+                    continue;
+                }
+            };
+            // A trapping instruction counts as a step, as it does in `step`
+            self.steps += 1;
+            match next {
+                Ok(next) => self.pc = next,
+                Err(error) => self.catch(error)?,
             }
         }
-        // Reverse because stack pop order is backwards
-        writes.reverse();
+        Ok(())
+    }
+
+    /// Returns whether the config asks for checks made before or after every step, which
+    /// the verified fast paths leave out: the step and stack depth limits, strict mode,
+    /// uninitialized-read tracking, provenance and protected memory.
+    #[cfg(any(feature = "assembler", feature = "disassembler"))]
+    fn needs_step_checks(&self) -> bool {
+        self.config.max_steps.is_some()
+            || self.config.max_stack_depth.is_some()
+            || self.config.strict
+            || self.config.uninit_reads != UninitReadMode::Off
+            || self.config.track_provenance
+            || !self.protected.is_empty()
+    }
 
-        for (offset, val) in writes.into_iter().enumerate() {
-            mem[memory_index as usize + offset] = val;
+    /// Runs a verified program like [`Vm::run_verified`], but reads stack operands, jump
+    /// targets and `MEMREAD` addresses without checking them.
+    ///
+    /// # Safety
+    ///
+    /// Every instruction must find the operands it takes on the stack, every jump target
+    /// must be at most the program length and every `MEMREAD` address must lie inside
+    /// memory, as [`verify_program`](crate::verify::verify_program) establishes.
+    #[cfg(any(feature = "assembler", feature = "disassembler"))]
+    pub(crate) unsafe fn run_verified_unchecked(&mut self, output_buffer: &mut Vec<u8>) -> Result<(), VmError> {
+        if self.needs_step_checks() {
+            return self.run(output_buffer);
         }
-    } else {
-        eprintln!("MemWriteS out of bounds at index {}", memory_index);
+
+        let len = self.program.len();
+        while !self.halted && self.pc < len {
+            let i = self.pc;
+            let arithmetic = self.config.arithmetic;
+            let stack = &mut self.stack;
+            let depth = stack.len();
+            // SAFETY: the loop condition keeps `i` inside the program
+            let next = match unsafe { self.program.get_unchecked(i) } {
+                Instruction::Null => i + 1,
+                Instruction::Push(value) => {
+                    stack.push(*value);
+                    i + 1
+                }
+                Instruction::Pop => {
+                    debug_assert!(depth >= 1, "POP at {} on an empty stack", i);
+                    // SAFETY: the caller guarantees a value to pop; words need no drop
+                    unsafe { stack.set_len(depth - 1) };
+                    i + 1
+                }
+                Instruction::Dup => {
+                    debug_assert!(depth >= 1, "DUP at {} on an empty stack", i);
+                    // SAFETY: the caller guarantees a value to copy
+                    let top = unsafe { *stack.get_unchecked(depth - 1) };
+                    stack.push(top);
+                    i + 1
+                }
+                Instruction::Jiz(target) | Instruction::Jnz(target) => {
+                    debug_assert!(depth >= 1, "conditional jump at {} on an empty stack", i);
+                    // SAFETY: the caller guarantees a value to test
+                    let top = unsafe { *stack.get_unchecked(depth - 1) };
+                    let jump = (top == 0) == matches!(self.program[i], Instruction::Jiz(_));
+                    if jump { *target as usize } else { i + 1 }
+                }
+                Instruction::Jmp(target) => *target as usize,
+                Instruction::Add | Instruction::Sub | Instruction::Mult => {
+                    let op = match self.program[i] {
+                        Instruction::Add => ArithOp::Add,
+                        Instruction::Sub => ArithOp::Sub,
+                        _ => ArithOp::Mul,
+                    };
+                    debug_assert!(depth >= 2, "arithmetic at {} with {} value(s) on the stack", i, depth);
+                    // SAFETY: the caller guarantees two operands
+                    let (b, a) = unsafe { (*stack.get_unchecked(depth - 2), *stack.get_unchecked(depth - 1)) };
+                    let Some(result) = arithmetic.apply(op, b, a) else {
+                        self.steps += 1;
+                        self.catch(VmError::ArithmeticOverflow { pc: i })?;
+                        continue;
+                    };
+                    // SAFETY: shrinking past the two operands just read
+                    unsafe { stack.set_len(depth - 2) };
+                    stack.push(result);
+                    i + 1
+                }
+                Instruction::MemRead(addr) => {
+                    debug_assert!((*addr as usize) < self.mem.len(), "MEMREAD {} at {} outside memory", addr, i);
+                    // SAFETY: the caller guarantees the address is inside memory
+                    stack.push(unsafe { *self.mem.get_unchecked(*addr as usize) });
+                    i + 1
+                }
+                _ => {
+                    self.step(output_buffer)?;
+                    continue;
+                }
+            };
+            self.pc = next;
+            self.steps += 1;
+        }
+        Ok(())
     }
-    current_i + 1
-}
 
-fn execute_memread(stack: &mut Vec<i32>, mem: &[i32], current_i: usize, index: i32) -> usize {
-    if index >= mem.len() as i32 {
-        eprintln!("MemRead out of bounds: {}", index);
-    } else {
-        stack.push(mem[index as usize]);
+    /// Runs the program like [`Vm::run`] until `token` is cancelled.
+    ///
+    /// The token is checked before the first instruction and then every
+    /// [`CANCEL_CHECK_INTERVAL`] instructions; a cancelled run stops with
+    /// [`VmError::Cancelled`] and can be resumed by running again. A `READLINE` or `READINT`
+    /// waiting for input is not interrupted.
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::{CancellationToken, Vm, VmError};
+    ///
+    /// let token = CancellationToken::new();
+    /// let canceller = token.clone();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_millis(10));
+    ///     canceller.cancel();
+    /// });
+    ///
+    /// let mut vm = Vm::new(vec![Instruction::Jmp(0)]);
+    /// let result = vm.run_with_cancel(&mut Vec::new(), &token);
+    /// assert_eq!(result, Err(VmError::Cancelled { pc: 0 }));
+    /// ```
+    pub fn run_with_cancel(&mut self, output_buffer: &mut Vec<u8>, token: &CancellationToken) -> Result<(), VmError> {
+        let mut until_check = 0;
+        while self.is_running() {
+            if until_check == 0 {
+                if token.is_cancelled() {
+                    return Err(VmError::Cancelled { pc: self.pc });
+                }
+                until_check = CANCEL_CHECK_INTERVAL;
+            }
+            until_check -= 1;
+            self.step(output_buffer)?;
+        }
+        Ok(())
     }
-    current_i + 1
-}
 
-fn execute_print(output_buffer: &mut Vec<u8>, mem: &[i32], current_i: usize, start_addr: i32, length: i32) -> usize {
-    let start = start_addr as usize;
-    let end = start + length as usize;
-    if end <= mem.len() {
-        for &byte_val in mem.iter().take(end).skip(start) {
-            write!(output_buffer, "{}", byte_val as u8 as char).unwrap();
+    /// Runs the program like [`Vm::run`], reporting every executed instruction to `sink`.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::run::{TraceEvent, Vm};
+    ///
+    /// let mut vm = Vm::new(vec![Instruction::Push(2), Instruction::Dup, Instruction::Add]);
+    /// let mut lines = Vec::new();
+    /// vm.run_traced(&mut Vec::new(), &mut |event: &TraceEvent| lines.push(event.to_string())).unwrap();
+    ///
+    /// assert_eq!(lines[2], "    2: ADD                  [2, 2] -> [4]");
+    /// ```
+    pub fn run_traced(&mut self, output_buffer: &mut Vec<u8>, sink: &mut dyn TraceSink) -> Result<(), VmError> {
+        while self.is_running() {
+            self.step_traced(output_buffer, sink)?;
         }
-    } else {
-        eprintln!("Print out of bounds: {}..{}", start, end);
+        Ok(())
     }
-    current_i + 1
-}
 
-#[cfg(test)]
-mod tests {
-    use std::vec;
+    /// Executes one instruction like [`Vm::step`] and reports it to `sink`.
+    ///
+    /// An instruction that traps is not reported since it did not complete.
+    pub fn step_traced(&mut self, output_buffer: &mut Vec<u8>, sink: &mut dyn TraceSink) -> Result<(), VmError> {
+        if !self.is_running() {
+            return Ok(());
+        }
 
-    use super::*;
-    use crate::instruction::Instruction;
+        let pc = self.pc;
+        let stack_before = self.stack.clone();
+        self.step(output_buffer)?;
 
-    mod stack_operations {
-        use super::*;
+        sink.trace(&TraceEvent {
+            pc,
+            instruction: &self.program[pc],
+            stack_before: &stack_before,
+            stack_after: &self.stack,
+        });
+        Ok(())
+    }
 
-        #[test]
-        fn test_null_instruction() {
-            let program = vec![
-                Instruction::Push(42),
-                Instruction::Null, // Should do nothing
-                Instruction::Ret,
-            ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![42]); // Stack should remain unchanged
+    /// Executes the instruction at the current program counter.
+    ///
+    /// On a trap the program counter is left pointing at the faulting instruction, unless
+    /// [`VmConfig::catch_traps`] sent it to a `TRY` handler.
+    pub fn step(&mut self, output_buffer: &mut Vec<u8>) -> Result<(), VmError> {
+        self.execute_step(output_buffer).or_else(|error| self.catch(error))
+    }
+
+    /// Sends `error` to the innermost `TRY` handler if [`VmConfig::catch_traps`] is set
+    /// and the trap has a code, or returns it.
+    pub(crate) fn catch(&mut self, error: VmError) -> Result<(), VmError> {
+        match error.code() {
+            Some(code) if self.config.catch_traps && !self.handlers.is_empty() => {
+                self.pc = self.unwind(code).expect("a handler is installed");
+                Ok(())
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Unwinds to the innermost `TRY` handler, pushes `code` for it and returns where it
+    /// starts, or returns `None` if no handler is installed.
+    fn unwind(&mut self, code: Word) -> Option<usize> {
+        let handler = self.handlers.pop()?;
+        self.stack.truncate(handler.stack_depth);
+        self.stack.push(code);
+        self.call_stack.truncate(handler.call_depth);
+        self.saved_frames.truncate(handler.call_depth);
+        self.locals.truncate(handler.locals);
+        self.frame_pointer = handler.frame_pointer;
+        Some(handler.target)
+    }
+
+    fn execute_step(&mut self, output_buffer: &mut Vec<u8>) -> Result<(), VmError> {
+        if !self.is_running() {
+            return Ok(());
+        }
+
+        let i = self.pc;
+        self.begin_step(i)?;
+
+        let depth_before = self.stack.len();
+        let arithmetic = self.config.arithmetic;
+        let encoding = self.config.output_encoding;
+        let stack = &mut self.stack;
+        let mem = &mut self.mem;
+        let interceptors = &self.interceptors;
+        let host_fns = &self.host_fns;
+        let instructions = &self.program;
+        let mut code_write = None;
+        let mut thrown = None;
+
+        self.pc = match &instructions[i] {
+            Instruction::Null => i + 1,
+            Instruction::Push(value) => {
+                stack.push(*value);
+                i + 1
+            }
+            Instruction::Pop => {
+                stack.pop();
+                i + 1
+            }
+            Instruction::Ret => {
+                // Return to the caller if inside a subroutine, otherwise halt
+                if let Some(return_addr) = self.call_stack.pop() {
+                    // The callee's locals go away with its frame
+                    self.locals.truncate(self.frame_pointer);
+                    self.frame_pointer = self.saved_frames.pop().unwrap_or(0);
+                    // So do the handlers it installed and did not remove
+                    while self.handlers.last().is_some_and(|handler| handler.call_depth > self.call_stack.len()) {
+                        self.handlers.pop();
+                    }
+                    return_addr
+                } else {
+                    self.halted = true;
+                    i
+                }
+            }
+            Instruction::Halt(code) => {
+                // Stops the whole program, even from inside a subroutine
+                self.exit_code = Some(*code);
+                self.halted = true;
+                i
+            }
+            Instruction::Abort(start_addr, length) => {
+                // Like a Print, the part of the message outside memory is left out
+                let cells = clamped_range(*start_addr, usize::try_from(*length).unwrap_or(0), mem.len()).map_or(&[][..], |range| &mem[range]);
+                let bytes: Vec<u8> = cells.iter().map(|&cell| cell as u8).collect();
+                return Err(VmError::GuestAbort { pc: i, message: String::from_utf8_lossy(&bytes).into_owned() });
+            }
+            Instruction::Try(target) => {
+                // Like jumps, a handler may be the end of the program
+                let target = *target as usize;
+                if target > instructions.len() {
+                    return Err(VmError::InvalidJumpTarget { pc: i, target: target as Word });
+                }
+                self.handlers.push(Handler {
+                    target,
+                    stack_depth: stack.len(),
+                    call_depth: self.call_stack.len(),
+                    frame_pointer: self.frame_pointer,
+                    locals: self.locals.len(),
+                });
+                i + 1
+            }
+            Instruction::EndTry => {
+                self.handlers.pop();
+                i + 1
+            }
+            Instruction::Throw => {
+                thrown = Some(stack.pop().ok_or(VmError::StackUnderflow { pc: i })?);
+                i
+            }
+            Instruction::Jiz(target) => execute_jiz(stack, instructions, i, *target),
+            Instruction::Jnz(target) => execute_jnz(stack, instructions, i, *target),
+            Instruction::Jmp(target) => execute_jmp(instructions, i, *target),
+            Instruction::JmpDyn => execute_jmp_dyn(stack, instructions, i)?,
+            Instruction::Call(target) => {
+                let target = execute_call(&mut self.call_stack, instructions, i, *target)?;
+                self.saved_frames.push(self.frame_pointer);
+                self.frame_pointer = self.locals.len();
+                target
+            }
+            Instruction::HostCall(name) => execute_host_call(stack, host_fns, i, name)?,
+            Instruction::AddS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Add, *n)?,
+            Instruction::Add => execute_binary(stack, i, arithmetic, ArithOp::Add)?,
+            Instruction::SubS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Sub, *n)?,
+            Instruction::Sub => execute_binary(stack, i, arithmetic, ArithOp::Sub)?,
+            Instruction::Dup => execute_dup(stack, i),
+            Instruction::Swap => execute_swap(stack, i),
+            Instruction::Rot => execute_rot(stack, i),
+            Instruction::Over => execute_pick(stack, i, 1),
+            Instruction::Pick(depth) => execute_pick(stack, i, *depth),
+            Instruction::Depth => {
+                stack.push(stack.len() as Word);
+                i + 1
+            }
+            Instruction::Clear => {
+                stack.clear();
+                i + 1
+            }
+            Instruction::DivS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Div, *n)?,
+            Instruction::Div => execute_binary(stack, i, arithmetic, ArithOp::Div)?,
+            Instruction::MultS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Mul, *n)?,
+            Instruction::Mult => execute_binary(stack, i, arithmetic, ArithOp::Mul)?,
+            Instruction::ModS(n) => execute_immediate(stack, i, arithmetic, ArithOp::Rem, *n)?,
+            Instruction::Mod => execute_binary(stack, i, arithmetic, ArithOp::Rem)?,
+            Instruction::Eq => execute_compare(stack, i, |b, a| b == a),
+            Instruction::Neq => execute_compare(stack, i, |b, a| b != a),
+            Instruction::Lt => execute_compare(stack, i, |b, a| b < a),
+            Instruction::Gt => execute_compare(stack, i, |b, a| b > a),
+            Instruction::Lte => execute_compare(stack, i, |b, a| b <= a),
+            Instruction::Gte => execute_compare(stack, i, |b, a| b >= a),
+            Instruction::MemWrite(start_addr, values) => execute_memwrite(mem, i, *start_addr, values),
+            Instruction::Print(start_addr, length) => match find_interceptor(interceptors, *start_addr) {
+                Some(interceptor) => {
+                    let mut bytes = Vec::new();
+                    let next = execute_print(&mut bytes, mem, i, encoding, *start_addr, *length)?;
+                    interceptor.write(&bytes, i);
+                    next
+                }
+                None => execute_print(output_buffer, mem, i, encoding, *start_addr, *length)?,
+            },
+            Instruction::PrintStr(start_addr) => match find_interceptor(interceptors, *start_addr) {
+                Some(interceptor) => {
+                    let mut bytes = Vec::new();
+                    let next = execute_print_str(&mut bytes, mem, i, encoding, *start_addr)?;
+                    interceptor.write(&bytes, i);
+                    next
+                }
+                None => execute_print_str(output_buffer, mem, i, encoding, *start_addr)?,
+            },
+            Instruction::PrintInt => execute_print_int(output_buffer, stack, i)?,
+            Instruction::PerfRead(counter) => {
+                let value = match *counter {
+                    PERF_STEPS => self.steps as Word,
+                    PERF_STACK_DEPTH => stack.len() as Word,
+                    PERF_CALL_DEPTH => self.call_stack.len() as Word,
+                    PERF_STEPS_LEFT => self.config.max_steps.map_or(-1, |max_steps| max_steps.saturating_sub(self.steps) as Word),
+                    PERF_HEAP_CELLS => self.heap.allocated() as Word,
+                    // Unknown counters read as unavailable so programs work across VM versions
+                    _ => -1,
+                };
+                stack.push(value);
+                i + 1
+            }
+            Instruction::VmInfo(field) => {
+                let value = match *field {
+                    VMINFO_VERSION => vm_version(),
+                    VMINFO_ISA => IsaVersion::LATEST.number(),
+                    VMINFO_MEMORY => mem.len() as Word,
+                    VMINFO_CAPABILITIES => Word::from(SUPPORTED_CAPABILITIES),
+                    VMINFO_WORD_BITS => Word::from(Word::BITS),
+                    // Like PERFREAD, so newer fields read as unavailable on older VMs
+                    _ => -1,
+                };
+                stack.push(value);
+                i + 1
+            }
+            Instruction::ProfBegin(phase) => {
+                self.profile.begin(*phase, self.steps);
+                i + 1
+            }
+            Instruction::ProfEnd(phase) => {
+                if let Some(budget) = self.profile.end(*phase, self.steps) {
+                    let steps = self.profile.stats(*phase).map_or(0, |stats| stats.steps);
+                    if self.config.strict {
+                        return Err(VmError::BudgetExceeded { pc: i, phase: *phase, steps, budget });
+                    }
+                    diagnostic!(Level::Warn, "Phase {} took {} steps, over its budget of {}, at instruction {}", phase, steps, budget, i);
+                }
+                i + 1
+            }
+            Instruction::Assert(expected) => {
+                let actual = *stack.last().ok_or(VmError::StackUnderflow { pc: i })?;
+                if actual != *expected {
+                    return Err(VmError::AssertionFailed { pc: i, expected: *expected, actual });
+                }
+                i + 1
+            }
+            Instruction::AssertEq => {
+                // The expected value is pushed last, so `PUSH 3` `ASSERTEQ` checks like `ASSERT 3`
+                let [actual, expected] = stack.last_chunk::<2>().copied().ok_or(VmError::StackUnderflow { pc: i })?;
+                if actual != expected {
+                    return Err(VmError::AssertionFailed { pc: i, expected, actual });
+                }
+                stack.pop();
+                i + 1
+            }
+            Instruction::MemRead(index) => execute_memread(stack, mem, i, *index),
+            Instruction::Load => execute_load(stack, mem, i)?,
+            Instruction::Store => execute_store(stack, mem, i)?,
+            Instruction::LoadLocal(index) => {
+                match local_slot(*index) {
+                    // Slots the frame never stored to read as zero
+                    Some(slot) => stack.push(self.locals.get(self.frame_pointer + slot).copied().unwrap_or(0)),
+                    None => diagnostic!(Level::Warn, "LoadLocal slot {} out of range", index),
+                }
+                i + 1
+            }
+            Instruction::StoreLocal(index) => {
+                match local_slot(*index) {
+                    Some(slot) => {
+                        let value = stack.pop().ok_or(VmError::StackUnderflow { pc: i })?;
+                        let cell = self.frame_pointer + slot;
+                        if cell >= self.locals.len() {
+                            self.locals.resize(cell + 1, 0);
+                        }
+                        self.locals[cell] = value;
+                    }
+                    None => diagnostic!(Level::Warn, "StoreLocal slot {} out of range", index),
+                }
+                i + 1
+            }
+            Instruction::MemCopy(dst, src, len) => execute_memcopy(mem, i, *dst, *src, *len),
+            Instruction::MemSet(addr, len, value) => execute_memset(mem, i, *addr, *len, *value),
+            Instruction::MemCmp(lhs, rhs, len) => execute_memcmp(stack, mem, i, *lhs, *rhs, *len),
+            Instruction::Grow(pages) => {
+                let max_memory_size = self.config.max_memory_size.unwrap_or(MAX_MEMORY_SIZE);
+                let previous = execute_grow(mem, self.config.memory_fill, max_memory_size, *pages);
+                if previous.is_some() && !self.initialized.is_empty() {
+                    self.initialized.resize(mem.len(), false);
+                }
+                stack.push(previous.map_or(-1, |size| size as Word));
+                i + 1
+            }
+            Instruction::MemSize => {
+                stack.push(mem.len() as Word);
+                i + 1
+            }
+            Instruction::Alloc => {
+                let size = stack.pop().ok_or(VmError::StackUnderflow { pc: i })?;
+                let max_memory_size = self.config.max_memory_size.unwrap_or(MAX_MEMORY_SIZE);
+                let block = usize::try_from(size).ok().and_then(|size| self.heap.alloc(size, mem, self.config.memory_fill, max_memory_size));
+                let Some(block) = block else {
+                    stack.push(size);
+                    return Err(VmError::AllocationFailed { pc: i, size });
+                };
+                if !self.initialized.is_empty() {
+                    // Reused cells hold whatever the previous owner left there
+                    self.initialized.resize(mem.len(), false);
+                    self.initialized[block.clone()].fill(false);
+                }
+                self.high_water.heap = self.high_water.heap.max(self.heap.allocated());
+                stack.push(block.start as Word);
+                i + 1
+            }
+            Instruction::Protect(addr, len) => {
+                match checked_range(*addr, *len, mem.len()) {
+                    Some(cells) if !cells.is_empty() => self.protected.push(cells),
+                    Some(_) => {}
+                    None => diagnostic!(Level::Warn, "Protect out of bounds: {} ({} cells)", addr, len),
+                }
+                i + 1
+            }
+            Instruction::Free => {
+                let addr = stack.pop().ok_or(VmError::StackUnderflow { pc: i })?;
+                if usize::try_from(addr).ok().and_then(|addr| self.heap.free(addr)).is_none() {
+                    stack.push(addr);
+                    return Err(VmError::InvalidFree { pc: i, addr });
+                }
+                i + 1
+            }
+            Instruction::ReadLine(addr, max_len) => {
+                let written = execute_read_line(stack, mem, &self.input, i, *addr, *max_len)?;
+                if let Some(written) = written
+                    && !self.initialized.is_empty()
+                {
+                    self.initialized[written].fill(true);
+                }
+                i + 1
+            }
+            Instruction::ReadInt => execute_read_int(stack, &self.input, i)?,
+            Instruction::MemWriteS(memory_index, write_len) => {
+                execute_memwrites(stack, mem, i, *memory_index, *write_len)
+            }
+            Instruction::CodeRead => execute_code_read(stack, instructions, &self.config, i)?,
+            Instruction::CodeWrite => {
+                let (index, instruction) = execute_code_write(stack, instructions, &self.config, i)?;
+                code_write = Some((index, instruction));
+                i + 1
+            }
+        };
+
+        if let Some(code) = thrown {
+            match self.unwind(code) {
+                Some(target) => self.pc = target,
+                None => {
+                    // Left on the stack like the operands of other traps
+                    self.stack.push(code);
+                    return Err(VmError::UnhandledException { pc: i, code });
+                }
+            }
+        }
+
+        self.finish_step(i, depth_before)?;
+
+        // Applied last so the steps above still see the instruction that ran
+        if let Some((index, instruction)) = code_write {
+            self.program_mut()[index] = instruction;
+            self.code_version += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Does the bookkeeping that comes before executing the instruction at `pc`: the step
+    /// limit, the trace ring, and the strict, read-only and uninitialized-read checks.
+    pub(crate) fn begin_step(&mut self, pc: usize) -> Result<(), VmError> {
+        if let Some(max_steps) = self.config.max_steps
+            && self.steps >= max_steps
+        {
+            return Err(VmError::FuelExhausted { pc, stack: self.stack.clone() });
+        }
+        self.steps += 1;
+
+        if self.trace.len() == TRACE_RING_SIZE {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(pc);
+        diagnostic!(Level::Trace, "{:>5}: {}", pc, self.program[pc]);
+
+        if self.config.strict {
+            self.check_strict(pc)?;
+        }
+        if !self.protected.is_empty() {
+            self.check_protected(pc)?;
+        }
+        if self.config.uninit_reads != UninitReadMode::Off {
+            self.check_initialized_reads(pc)?;
+            self.mark_written(pc);
+        }
+        Ok(())
+    }
+
+    /// Updates provenance and the high-water marks after the instruction at `pc` ran on a
+    /// stack that held `depth_before` values, and enforces the stack depth limit.
+    pub(crate) fn finish_step(&mut self, pc: usize, depth_before: usize) -> Result<(), VmError> {
+        if self.config.track_provenance {
+            self.record_origins(pc, depth_before);
+        }
+        self.high_water.stack = self.high_water.stack.max(self.stack.len());
+        self.high_water.call_depth = self.high_water.call_depth.max(self.call_stack.len());
+
+        if let Some(limit) = self.config.max_stack_depth
+            && self.stack.len() > limit
+        {
+            // Point back at the instruction that overflowed, like any other trap
+            self.pc = pc;
+            return Err(VmError::StackOverflow { pc, depth: self.stack.len() });
+        }
+        Ok(())
+    }
+
+    /// Updates the origin of each stack slot after the instruction at `pc` ran.
+    fn record_origins(&mut self, pc: usize, depth_before: usize) {
+        let depth = self.stack.len();
+
+        // The stack may have been replaced from outside the VM
+        if self.origins.len() != depth_before {
+            self.origins.resize(depth_before, None);
+        }
+
+        match self.program[pc] {
+            // Copies and reorders keep the origin of the values they move
+            Instruction::Dup if depth > depth_before => {
+                let origin = self.origins.last().copied().flatten();
+                self.origins.push(origin);
+            }
+            Instruction::Swap if depth >= 2 => {
+                self.origins.swap(depth - 1, depth - 2);
+            }
+            Instruction::Rot if depth >= 3 => {
+                self.origins[depth - 3..].rotate_left(1);
+            }
+            Instruction::Over | Instruction::Pick(_) if depth > depth_before => {
+                let below = match self.program[pc] {
+                    Instruction::Pick(depth) => depth as usize,
+                    _ => 1,
+                };
+                let origin = self.origins[depth_before - 1 - below];
+                self.origins.push(origin);
+            }
+            ref instruction => {
+                let consumed = match instruction {
+                    Instruction::MemWriteS(..) | Instruction::HostCall(_) => depth_before.saturating_sub(depth),
+                    _ => stack_operands(instruction),
+                };
+                // Instructions that lack operands leave the stack untouched
+                let untouched = if depth_before >= consumed { depth_before - consumed } else { depth_before };
+                self.origins.truncate(untouched.min(depth));
+                self.origins.resize(depth, Some(pc));
+            }
+        }
+    }
+
+    /// Traps if the instruction at `pc` would be skipped for lack of operands, divide by
+    /// zero or touch memory outside `mem`; the permissive mode ignores these.
+    fn check_strict(&self, pc: usize) -> Result<(), VmError> {
+        let instruction = &self.program[pc];
+        let needed = match instruction {
+            Instruction::Dup | Instruction::Jiz(_) | Instruction::Jnz(_) => 1,
+            Instruction::Swap | Instruction::Over => 2,
+            Instruction::Rot => 3,
+            Instruction::Pick(depth) => usize::try_from(*depth).map_or(0, |depth| depth + 1),
+            Instruction::MemWriteS(_, len) => usize::try_from(*len).unwrap_or(0),
+            _ => stack_operands(instruction),
+        };
+        if self.stack.len() < needed {
+            return Err(VmError::StackUnderflow { pc });
+        }
+
+        let divisor = match instruction {
+            Instruction::DivS(n) | Instruction::ModS(n) => Some(*n),
+            Instruction::Div | Instruction::Mod => self.stack.last().copied(),
+            _ => None,
+        };
+        if divisor == Some(0) {
+            return Err(VmError::DivisionByZero { pc });
+        }
+
+        let accesses = match instruction {
+            Instruction::MemWrite(addr, values) => [Some((*addr, values.len() as i32)), None],
+            Instruction::MemWriteS(addr, len)
+            | Instruction::Print(addr, len)
+            | Instruction::Abort(addr, len)
+            | Instruction::ReadLine(addr, len)
+            | Instruction::Protect(addr, len)
+            | Instruction::MemSet(addr, len, _) => [Some((*addr, *len)), None],
+            Instruction::MemRead(addr) | Instruction::PrintStr(addr) => [Some((*addr, 1)), None],
+            Instruction::MemCopy(a, b, len) | Instruction::MemCmp(a, b, len) => [Some((*a, *len)), Some((*b, *len))],
+            _ => [None, None],
+        };
+        for (addr, len) in accesses.into_iter().flatten() {
+            if checked_range(addr, len, self.mem.len()).is_none() {
+                return Err(VmError::MemoryOutOfBounds { pc, addr, len });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports reads by the instruction at `pc` that touch never-written memory.
+    fn check_initialized_reads(&self, pc: usize) -> Result<(), VmError> {
+        let mem_len = self.mem.len();
+        let (reads, more_reads) = match self.program[pc] {
+            Instruction::MemRead(addr) => (checked_range(addr, 1, mem_len), None),
+            Instruction::Load => (self.stack_address().map(|addr| addr..addr + 1), None),
+            Instruction::Print(addr, len) | Instruction::Abort(addr, len) => (checked_range(addr, len, mem_len), None),
+            Instruction::PrintStr(addr) => (string_range(&self.mem, addr), None),
+            // Copies out of range do nothing, so only check the source when both fit
+            Instruction::MemCopy(dst, src, len) => (checked_range(dst, len, mem_len).and(checked_range(src, len, mem_len)), None),
+            Instruction::MemCmp(lhs, rhs, len) => match (checked_range(lhs, len, mem_len), checked_range(rhs, len, mem_len)) {
+                (Some(lhs), Some(rhs)) => (Some(lhs), Some(rhs)),
+                _ => (None, None),
+            },
+            _ => (None, None),
+        };
+
+        if let Some(addr) = reads.into_iter().chain(more_reads).flatten().find(|&addr| !self.initialized[addr]) {
+            let error = VmError::UninitializedRead { pc, addr };
+            if self.config.uninit_reads == UninitReadMode::Trap {
+                return Err(error);
+            }
+            diagnostic!(Level::Warn, "{}", error);
+        }
+        Ok(())
+    }
+
+    /// Traps if the instruction at `pc` would write to read-only memory. `READLINE` counts
+    /// as writing its whole buffer, however long the line turns out to be.
+    fn check_protected(&self, pc: usize) -> Result<(), VmError> {
+        let writes = match self.program[pc] {
+            Instruction::ReadLine(addr, max_len) => checked_range(addr, max_len, self.mem.len()),
+            _ => self.write_range(pc),
+        };
+        let Some(writes) = writes else {
+            return Ok(());
+        };
+
+        let first = self
+            .protected
+            .iter()
+            .filter(|cells| cells.start < writes.end && writes.start < cells.end)
+            .map(|cells| cells.start.max(writes.start))
+            .min();
+        match first {
+            Some(addr) => Err(VmError::ProtectedWrite { pc, addr }),
+            None => Ok(()),
+        }
+    }
+
+    /// Records the memory cells the instruction at `pc` is about to write.
+    fn mark_written(&mut self, pc: usize) {
+        if let Some(writes) = self.write_range(pc) {
+            self.initialized[writes].fill(true);
+        }
+    }
+
+    /// Returns the memory cells the instruction at `pc` is about to write. `READLINE` is left
+    /// out since how much it writes depends on the input.
+    fn write_range(&self, pc: usize) -> Option<Range<usize>> {
+        match &self.program[pc] {
+            Instruction::MemWrite(addr, values) => clamped_range(*addr, values.len(), self.mem.len()),
+            Instruction::MemWriteS(addr, len) => {
+                // MemWriteS stops early when the stack runs out
+                let written = (*len).max(0).min(self.stack.len() as i32);
+                checked_range(*addr, *len, self.mem.len()).map(|range| range.start..range.start + written as usize)
+            }
+            Instruction::Store if self.stack.len() >= 2 => self.stack_address().map(|addr| addr..addr + 1),
+            Instruction::MemSet(addr, len, _) => checked_range(*addr, *len, self.mem.len()),
+            Instruction::MemCopy(dst, src, len) => {
+                checked_range(*src, *len, self.mem.len()).and(checked_range(*dst, *len, self.mem.len()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the memory cell named by the address on top of the stack, if it is valid.
+    fn stack_address(&self) -> Option<usize> {
+        let addr = usize::try_from(*self.stack.last()?).ok()?;
+        (addr < self.mem.len()).then_some(addr)
+    }
+}
+
+/// Keeps finished VMs around so servers running many short programs can reuse their
+/// memory and stacks instead of allocating new ones for every run.
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::{VmConfig, VmPool};
+///
+/// let mut pool = VmPool::new(VmConfig::default(), 4);
+/// for value in 1..=3 {
+///     let mut vm = pool.get(vec![Instruction::Push(value)]);
+///     vm.run(&mut Vec::new()).unwrap();
+///     assert_eq!(vm.stack, vec![value]);
+///     pool.put(vm);
+/// }
+/// assert_eq!(pool.idle(), 1);
+/// ```
+#[derive(Debug)]
+pub struct VmPool {
+    config: VmConfig,
+    capacity: usize,
+    idle: Vec<Vm>,
+}
+
+impl VmPool {
+    /// Creates an empty pool of VMs with `config` that keeps at most `capacity` idle VMs.
+    pub fn new(config: VmConfig, capacity: usize) -> Self {
+        VmPool { config, capacity, idle: Vec::new() }
+    }
+
+    /// Returns a VM ready to run `program`, reusing an idle one if there is one.
+    ///
+    /// The VM has the pool's configuration and no host functions, print interceptors, input
+    /// or phase budgets.
+    pub fn get(&mut self, program: impl Into<Arc<Vec<Instruction>>>) -> Vm {
+        match self.idle.pop() {
+            Some(mut vm) => {
+                vm.program = program.into();
+                vm
+            }
+            None => Vm::with_config(program, self.config.clone()),
+        }
+    }
+
+    /// Resets `vm` and keeps it for a later [`VmPool::get`]. VMs with a different
+    /// configuration, or beyond the pool's capacity, are dropped.
+    pub fn put(&mut self, mut vm: Vm) {
+        if vm.config != self.config || self.idle.len() >= self.capacity {
+            return;
+        }
+
+        vm.reset();
+        vm.program = Arc::default();
+        vm.interceptors.clear();
+        vm.host_fns = HostFunctions::default();
+        vm.input = VmInput::default();
+        vm.profile = Profile::default();
+        self.idle.push(vm);
+    }
+
+    /// Returns how many VMs are waiting to be reused.
+    pub fn idle(&self) -> usize {
+        self.idle.len()
+    }
+}
+
+/// Returns how many values `instruction` takes off the stack when it has enough operands.
+fn stack_operands(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Pop
+        | Instruction::JmpDyn
+        | Instruction::Load
+        | Instruction::Alloc
+        | Instruction::Free
+        | Instruction::Throw
+        | Instruction::AssertEq
+        | Instruction::CodeRead
+        | Instruction::PrintInt
+        | Instruction::AddS(_)
+        | Instruction::SubS(_)
+        | Instruction::MultS(_)
+        | Instruction::DivS(_)
+        | Instruction::ModS(_) => 1,
+        Instruction::Add
+        | Instruction::Sub
+        | Instruction::Mult
+        | Instruction::Div
+        | Instruction::Mod
+        | Instruction::Eq
+        | Instruction::Neq
+        | Instruction::Lt
+        | Instruction::Gt
+        | Instruction::Lte
+        | Instruction::Gte
+        | Instruction::Store
+        | Instruction::CodeWrite => 2,
+        Instruction::StoreLocal(index) if local_slot(*index).is_some() => 1,
+        _ => 0,
+    }
+}
+
+/// Returns `addr..addr + len` if the whole range lies inside memory of size `mem_len`.
+fn checked_range(addr: i32, len: i32, mem_len: usize) -> Option<std::ops::Range<usize>> {
+    let start = usize::try_from(addr).ok()?;
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    (end <= mem_len).then_some(start..end)
+}
+
+/// Returns the part of `addr..addr + len` that lies inside memory of size `mem_len`.
+fn clamped_range(addr: i32, len: usize, mem_len: usize) -> Option<std::ops::Range<usize>> {
+    let start = usize::try_from(addr).ok()?.min(mem_len);
+    Some(start..start.saturating_add(len).min(mem_len))
+}
+
+/// Executes a program of instructions and returns the final state of the stack and memory.
+///
+/// This is the main entry point for running Vortex VM programs. It processes each instruction
+/// in sequence, maintaining a stack for data manipulation and a memory space for data storage.
+/// If the program traps, the error is reported on stderr and the state at the trap is returned;
+/// use [`Vm`] directly to handle traps programmatically.
+///
+/// # Examples
+///
+/// Basic stack operations:
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::execute;
+///
+/// let program = vec![
+///     Instruction::Push(5),
+///     Instruction::Push(3),
+///     Instruction::Add,
+///     Instruction::Ret,
+/// ];
+///
+/// let mut output = Vec::new();
+/// let (stack, memory) = execute(&program, &mut output);
+///
+/// assert_eq!(stack, vec![8]);
+/// assert_eq!(memory[0], 0); // Memory is initialized to zeros
+/// ```
+///
+/// Memory operations:
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::execute;
+///
+/// let program = vec![
+///     Instruction::MemWrite(0, vec![72, 101, 108, 108, 111]), // "Hello"
+///     Instruction::Print(0, 5),
+///     Instruction::Ret,
+/// ];
+///
+/// let mut output = Vec::new();
+/// let (_stack, _memory) = execute(&program, &mut output);
+///
+/// let printed = String::from_utf8(output).unwrap();
+/// assert_eq!(printed, "Hello");
+/// ```
+///
+/// Jump instructions:
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::execute;
+///
+/// let program = vec![
+///     Instruction::Push(3),
+///     Instruction::SubS(1),
+///     Instruction::Jnz(1), // Jump back to start if not zero
+///     Instruction::Ret,
+/// ];
+///
+/// let mut output = Vec::new();
+/// let (stack, _memory) = execute(&program, &mut output);
+///
+/// assert_eq!(stack, vec![0]); // Should decrement from 3 to 0
+/// ```
+pub fn execute(instructions: &[Instruction], output_buffer: &mut Vec<u8>) -> (Vec<Word>, Vec<Word>) {
+    execute_with_config(instructions, VmConfig::default(), output_buffer)
+}
+
+/// Executes a program like [`execute`] on a VM with the given configuration, for example
+/// with more or less memory than [`MEMORY_SIZE`].
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::run::{execute_with_config, VmConfig};
+///
+/// let config = VmConfig { memory_size: Some(8192), ..VmConfig::default() };
+/// let (_stack, memory) = execute_with_config(&[Instruction::MemWrite(8000, vec![1])], config, &mut Vec::new());
+/// assert_eq!((memory.len(), memory[8000]), (8192, 1));
+/// ```
+pub fn execute_with_config(instructions: &[Instruction], config: VmConfig, output_buffer: &mut Vec<u8>) -> (Vec<Word>, Vec<Word>) {
+    let mut vm = Vm::with_config(instructions.to_vec(), config);
+
+    if let Err(e) = vm.run(output_buffer) {
+        diagnostic!(Level::Error, "{}", e);
+    }
+
+    (vm.stack, vm.mem)
+}
+
+// Jump instructions. A target just past the last instruction ends the program; other
+// out-of-range targets fall through.
+fn execute_jiz(stack: &[Word], instructions: &[Instruction], current_i: usize, target: u32) -> usize {
+    if let Some(&val) = stack.last()
+        && val == 0
+        && (target as usize) <= instructions.len()
+    {
+        target as usize
+    } else {
+        current_i + 1
+    }
+}
+
+fn execute_jnz(stack: &[Word], instructions: &[Instruction], current_i: usize, target: u32) -> usize {
+    if let Some(&val) = stack.last()
+        && val != 0
+        && (target as usize) <= instructions.len()
+    {
+        target as usize
+    } else {
+        current_i + 1
+    }
+}
+
+fn execute_jmp(instructions: &[Instruction], current_i: usize, target: u32) -> usize {
+    if (target as usize) <= instructions.len() {
+        target as usize
+    } else {
+        current_i + 1
+    }
+}
+
+fn execute_jmp_dyn(stack: &mut Vec<Word>, instructions: &[Instruction], current_i: usize) -> Result<usize, VmError> {
+    let target = stack.pop().ok_or(VmError::StackUnderflow { pc: current_i })?;
+
+    match usize::try_from(target) {
+        Ok(addr) if addr < instructions.len() => Ok(addr),
+        _ => {
+            // Restore the operand so the trap state shows what was popped
+            stack.push(target);
+            Err(VmError::InvalidJumpTarget { pc: current_i, target })
+        }
+    }
+}
+
+/// Returns the slot of a call frame that `LOADLOCAL`/`STORELOCAL` `index` names, or `None`
+/// if it is outside `0..MAX_LOCALS`.
+pub(crate) fn local_slot(index: i32) -> Option<usize> {
+    usize::try_from(index).ok().filter(|&slot| slot < MAX_LOCALS)
+}
+
+fn execute_call(call_stack: &mut Vec<usize>, instructions: &[Instruction], current_i: usize, target: u32) -> Result<usize, VmError> {
+    let addr = target as usize;
+    if addr >= instructions.len() {
+        return Err(VmError::InvalidCallTarget { pc: current_i, target });
+    }
+
+    if call_stack.len() >= MAX_CALL_DEPTH {
+        return Err(VmError::CallStackOverflow { pc: current_i, depth: call_stack.len() });
+    }
+
+    call_stack.push(current_i + 1);
+    Ok(addr)
+}
+
+fn execute_host_call(stack: &mut Vec<Word>, host_fns: &HostFunctions, current_i: usize, name: &str) -> Result<usize, VmError> {
+    let Some(function) = host_fns.0.get(name) else {
+        return Err(VmError::UnknownHostFunction { pc: current_i, name: name.to_string() });
+    };
+
+    let mut function = function.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    function(stack).map_err(|message| VmError::HostFunctionFailed { pc: current_i, name: name.to_string(), message })?;
+    Ok(current_i + 1)
+}
+
+// Self-modifying code
+fn execute_code_read(stack: &mut Vec<Word>, instructions: &[Instruction], config: &VmConfig, current_i: usize) -> Result<usize, VmError> {
+    let index = stack.pop().ok_or(VmError::StackUnderflow { pc: current_i })?;
+    let instruction = code_slot(instructions, config, current_i, index)?;
+
+    let word = encode_word(&instructions[instruction]).ok_or_else(|| VmError::InvalidCodeAccess {
+        pc: current_i,
+        index,
+        reason: format!("'{}' cannot be encoded as a word", instructions[instruction]),
+    })?;
+    stack.push(word);
+    Ok(current_i + 1)
+}
+
+/// Decodes and verifies the instruction `CODEWRITE` is about to store, returning it with its index.
+fn execute_code_write(
+    stack: &mut Vec<Word>,
+    instructions: &[Instruction],
+    config: &VmConfig,
+    current_i: usize,
+) -> Result<(usize, Instruction), VmError> {
+    if stack.len() < 2 {
+        return Err(VmError::StackUnderflow { pc: current_i });
+    }
+    let word = stack.pop().unwrap();
+    let index = stack.pop().unwrap();
+    let slot = code_slot(instructions, config, current_i, index)?;
+
+    let mut instruction = decode_word(word).map_err(|reason| VmError::InvalidCodeAccess { pc: current_i, index, reason })?;
+    if let Some(target) = instruction.jump_target_mut()
+        && *target as usize >= instructions.len()
+    {
+        return Err(VmError::InvalidCodeAccess {
+            pc: current_i,
+            index,
+            reason: format!("'{}' jumps outside the program", instruction),
+        });
+    }
+    Ok((slot, instruction))
+}
+
+/// Checks that self-modifying code is enabled and `index` names an instruction.
+fn code_slot(instructions: &[Instruction], config: &VmConfig, current_i: usize, index: Word) -> Result<usize, VmError> {
+    if !config.self_modifying {
+        let reason = "self-modifying code is disabled".to_string();
+        return Err(VmError::InvalidCodeAccess { pc: current_i, index, reason });
+    }
+    match usize::try_from(index) {
+        Ok(slot) if slot < instructions.len() => Ok(slot),
+        _ => Err(VmError::InvalidCodeAccess { pc: current_i, index, reason: "outside the program".to_string() }),
+    }
+}
+
+// Arithmetic instructions
+
+/// The operation an arithmetic instruction performs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl ArithOp {
+    fn divides(self) -> bool {
+        matches!(self, ArithOp::Div | ArithOp::Rem)
+    }
+}
+
+impl ArithmeticMode {
+    /// Computes `lhs op rhs`, or `None` if it overflows in [`ArithmeticMode::Checked`] mode.
+    /// `rhs` must not be zero for division and remainder.
+    fn apply(self, op: ArithOp, lhs: Word, rhs: Word) -> Option<Word> {
+        match (self, op) {
+            // The remainder of MIN / -1 is 0, which fits, so every mode agrees
+            (_, ArithOp::Rem) => Some(lhs.wrapping_rem(rhs)),
+            (ArithmeticMode::Wrapping, ArithOp::Add) => Some(lhs.wrapping_add(rhs)),
+            (ArithmeticMode::Wrapping, ArithOp::Sub) => Some(lhs.wrapping_sub(rhs)),
+            (ArithmeticMode::Wrapping, ArithOp::Mul) => Some(lhs.wrapping_mul(rhs)),
+            (ArithmeticMode::Wrapping, ArithOp::Div) => Some(lhs.wrapping_div(rhs)),
+            (ArithmeticMode::Saturating, ArithOp::Add) => Some(lhs.saturating_add(rhs)),
+            (ArithmeticMode::Saturating, ArithOp::Sub) => Some(lhs.saturating_sub(rhs)),
+            (ArithmeticMode::Saturating, ArithOp::Mul) => Some(lhs.saturating_mul(rhs)),
+            (ArithmeticMode::Saturating, ArithOp::Div) => Some(lhs.saturating_div(rhs)),
+            (ArithmeticMode::Checked, ArithOp::Add) => lhs.checked_add(rhs),
+            (ArithmeticMode::Checked, ArithOp::Sub) => lhs.checked_sub(rhs),
+            (ArithmeticMode::Checked, ArithOp::Mul) => lhs.checked_mul(rhs),
+            (ArithmeticMode::Checked, ArithOp::Div) => lhs.checked_div(rhs),
+        }
+    }
+}
+
+/// Applies `op` to the top of the stack and `n`, for `ADDS`, `SUBS`, `MULTS`, `DIVS` and
+/// `MODS`. An empty stack or a zero divisor leaves the stack unchanged.
+pub(crate) fn execute_immediate(stack: &mut [Word], current_i: usize, mode: ArithmeticMode, op: ArithOp, n: Word) -> Result<usize, VmError> {
+    if let Some(val) = stack.last_mut()
+        && !(op.divides() && n == 0)
+    {
+        *val = mode.apply(op, *val, n).ok_or(VmError::ArithmeticOverflow { pc: current_i })?;
+    }
+    Ok(current_i + 1)
+}
+
+/// Replaces the top two values with `second op top`, for `ADD`, `SUB`, `MULT`, `DIV` and
+/// `MOD`. Dividing by zero pops both values and pushes nothing.
+pub(crate) fn execute_binary(stack: &mut Vec<Word>, current_i: usize, mode: ArithmeticMode, op: ArithOp) -> Result<usize, VmError> {
+    if stack.len() >= 2 {
+        let (b, a) = (stack[stack.len() - 2], stack[stack.len() - 1]);
+        let result = if op.divides() && a == 0 {
+            None
+        } else {
+            // Checked overflow traps before popping so the operands stay visible
+            Some(mode.apply(op, b, a).ok_or(VmError::ArithmeticOverflow { pc: current_i })?)
+        };
+        stack.truncate(stack.len() - 2);
+        stack.extend(result);
+    }
+    Ok(current_i + 1)
+}
+
+// Comparison instructions
+pub(crate) fn execute_compare(stack: &mut Vec<Word>, current_i: usize, compare: fn(Word, Word) -> bool) -> usize {
+    if stack.len() >= 2 {
+        let a = stack.pop().unwrap();
+        let b = stack.pop().unwrap();
+        stack.push(compare(b, a) as Word);
+    }
+    current_i + 1
+}
+
+// Stack manipulation instructions
+pub(crate) fn execute_dup(stack: &mut Vec<Word>, current_i: usize) -> usize {
+    if let Some(&val) = stack.last() {
+        stack.push(val);
+    }
+    current_i + 1
+}
+
+pub(crate) fn execute_swap(stack: &mut Vec<Word>, current_i: usize) -> usize {
+    if stack.len() >= 2 {
+        let a = stack.pop().unwrap();
+        let b = stack.pop().unwrap();
+        stack.push(a);
+        stack.push(b);
+    }
+    current_i + 1
+}
+
+/// Moves the third value from the top to the top.
+pub(crate) fn execute_rot(stack: &mut [Word], current_i: usize) -> usize {
+    if let Some(start) = stack.len().checked_sub(3) {
+        stack[start..].rotate_left(1);
+    }
+    current_i + 1
+}
+
+/// Pushes a copy of the value `depth` places below the top, so `PICK 0` is `DUP`.
+pub(crate) fn execute_pick(stack: &mut Vec<Word>, current_i: usize, depth: i32) -> usize {
+    let index = usize::try_from(depth).ok().and_then(|depth| stack.len().checked_sub(depth + 1));
+    if let Some(index) = index {
+        stack.push(stack[index]);
+    }
+    current_i + 1
+}
+
+// Memory instructions
+fn execute_memwrite(mem: &mut [Word], current_i: usize, start_addr: i32, values: &[Word]) -> usize {
+    // Values past the end of memory are dropped
+    if let Some(range) = clamped_range(start_addr, values.len(), mem.len()) {
+        let len = range.len();
+        mem[range].copy_from_slice(&values[..len]);
+    }
+    current_i + 1
+}
+
+fn execute_memwrites(stack: &mut Vec<Word>, mem: &mut [Word], current_i: usize, memory_index: i32, write_len: i32) -> usize {
+    if let Some(range) = checked_range(memory_index, write_len, mem.len()) {
+        let mut writes = Vec::with_capacity(range.len());
+        for _ in 0..write_len {
+            if let Some(val) = stack.pop() {
+                writes.push(val);
+            } else {
+                diagnostic!(Level::Warn, "Stack underflow on MemWriteS");
+                break;
+            }
+        }
+        // Reverse because stack pop order is backwards
+        writes.reverse();
+
+        mem[range.start..range.start + writes.len()].copy_from_slice(&writes);
+    } else {
+        diagnostic!(Level::Warn, "MemWriteS out of bounds at index {}", memory_index);
+    }
+    current_i + 1
+}
+
+pub(crate) fn execute_memread(stack: &mut Vec<Word>, mem: &[Word], current_i: usize, index: i32) -> usize {
+    match checked_range(index, 1, mem.len()) {
+        Some(range) => stack.push(mem[range.start]),
+        None => diagnostic!(Level::Warn, "MemRead out of bounds: {}", index),
+    }
+    current_i + 1
+}
+
+/// Returns the memory cell `addr` names, restoring it to the stack and trapping if it is
+/// outside memory.
+fn memory_cell(stack: &mut Vec<Word>, mem: &[Word], current_i: usize, addr: Word) -> Result<usize, VmError> {
+    match usize::try_from(addr) {
+        Ok(cell) if cell < mem.len() => Ok(cell),
+        _ => {
+            stack.push(addr);
+            Err(VmError::InvalidAddress { pc: current_i, addr })
+        }
+    }
+}
+
+pub(crate) fn execute_load(stack: &mut Vec<Word>, mem: &[Word], current_i: usize) -> Result<usize, VmError> {
+    let addr = stack.pop().ok_or(VmError::StackUnderflow { pc: current_i })?;
+    let cell = memory_cell(stack, mem, current_i, addr)?;
+    stack.push(mem[cell]);
+    Ok(current_i + 1)
+}
+
+/// Pops an address and then the value to store there.
+pub(crate) fn execute_store(stack: &mut Vec<Word>, mem: &mut [Word], current_i: usize) -> Result<usize, VmError> {
+    if stack.len() < 2 {
+        return Err(VmError::StackUnderflow { pc: current_i });
+    }
+    let addr = stack.pop().unwrap();
+    let cell = memory_cell(stack, mem, current_i, addr)?;
+    mem[cell] = stack.pop().unwrap();
+    Ok(current_i + 1)
+}
+
+/// Copies `len` cells from `src` to `dst`; the ranges may overlap.
+fn execute_memcopy(mem: &mut [Word], current_i: usize, dst: i32, src: i32, len: i32) -> usize {
+    match (checked_range(dst, len, mem.len()), checked_range(src, len, mem.len())) {
+        (Some(dst), Some(src)) => mem.copy_within(src, dst.start),
+        _ => diagnostic!(Level::Warn, "MemCopy out of bounds: {} <- {} ({} cells)", dst, src, len),
+    }
+    current_i + 1
+}
+
+fn execute_memset(mem: &mut [Word], current_i: usize, addr: i32, len: i32, value: Word) -> usize {
+    match checked_range(addr, len, mem.len()) {
+        Some(range) => mem[range].fill(value),
+        None => diagnostic!(Level::Warn, "MemSet out of bounds: {} ({} cells)", addr, len),
+    }
+    current_i + 1
+}
+
+/// Adds `pages` pages of memory filled with `fill` and returns the previous size in cells,
+/// or `None` if memory would grow beyond `max_memory_size` cells.
+fn execute_grow(mem: &mut Vec<Word>, fill: Word, max_memory_size: usize, pages: i32) -> Option<usize> {
+    let previous = mem.len();
+    let size = usize::try_from(pages).ok()?.checked_mul(PAGE_SIZE)?.checked_add(previous)?;
+    if size > max_memory_size {
+        return None;
+    }
+    mem.resize(size, fill);
+    Some(previous)
+}
+
+/// Compares two ranges cell by cell and pushes -1, 0 or 1 as the first is less than, equal
+/// to or greater than the second.
+fn execute_memcmp(stack: &mut Vec<Word>, mem: &[Word], current_i: usize, lhs: i32, rhs: i32, len: i32) -> usize {
+    match (checked_range(lhs, len, mem.len()), checked_range(rhs, len, mem.len())) {
+        (Some(lhs), Some(rhs)) => stack.push(mem[lhs].cmp(&mem[rhs]) as Word),
+        _ => diagnostic!(Level::Warn, "MemCmp out of bounds: {} and {} ({} cells)", lhs, rhs, len),
+    }
+    current_i + 1
+}
+
+fn execute_print(
+    output_buffer: &mut Vec<u8>,
+    mem: &[Word],
+    current_i: usize,
+    encoding: OutputEncoding,
+    start_addr: i32,
+    length: i32,
+) -> Result<usize, VmError> {
+    match checked_range(start_addr, length, mem.len()) {
+        Some(range) => write_cells(output_buffer, &mem[range], encoding, current_i)?,
+        None => diagnostic!(Level::Warn, "Print out of bounds: {}..{}", start_addr, i64::from(start_addr) + i64::from(length)),
+    }
+    Ok(current_i + 1)
+}
+
+/// The crate version as `VMINFO` reports it, e.g. 200 for 0.2.0.
+fn vm_version() -> Word {
+    let mut parts = env!("CARGO_PKG_VERSION").split('.').map(|part| part.parse::<Word>().unwrap_or(0));
+    let mut next = || parts.next().unwrap_or(0);
+    next() * 10000 + next() * 100 + next()
+}
+
+fn execute_print_int(output_buffer: &mut Vec<u8>, stack: &mut Vec<Word>, current_i: usize) -> Result<usize, VmError> {
+    let value = stack.pop().ok_or(VmError::StackUnderflow { pc: current_i })?;
+    write!(output_buffer, "{}", value).unwrap();
+    Ok(current_i + 1)
+}
+
+// Input instructions
+
+/// Reads one line into memory and pushes how many bytes were stored, or -1 at the end of
+/// input. Returns the cells written.
+fn execute_read_line(
+    stack: &mut Vec<Word>,
+    mem: &mut [Word],
+    input: &VmInput,
+    current_i: usize,
+    addr: i32,
+    max_len: i32,
+) -> Result<Option<Range<usize>>, VmError> {
+    let Some(range) = checked_range(addr, max_len, mem.len()) else {
+        diagnostic!(Level::Warn, "ReadLine out of bounds: {}..{}", addr, i64::from(addr) + i64::from(max_len));
+        stack.push(0);
+        return Ok(None);
+    };
+
+    let Some(line) = read_input_line(input, current_i)? else {
+        stack.push(-1);
+        return Ok(None);
+    };
+
+    // Bytes that do not fit are dropped along with the rest of the line
+    let written = range.start..range.start + line.len().min(range.len());
+    for (cell, &byte) in mem[written.clone()].iter_mut().zip(&line) {
+        *cell = Word::from(byte);
+    }
+    stack.push(written.len() as Word);
+    Ok(Some(written))
+}
+
+fn execute_read_int(stack: &mut Vec<Word>, input: &VmInput, current_i: usize) -> Result<usize, VmError> {
+    let Some(line) = read_input_line(input, current_i)? else {
+        return Err(VmError::InvalidInput { pc: current_i, reason: "end of input".to_string() });
+    };
+
+    let text = String::from_utf8_lossy(&line);
+    let value = text.trim().parse::<Word>().map_err(|_| VmError::InvalidInput {
+        pc: current_i,
+        reason: format!("'{}' is not a number", text.trim()),
+    })?;
+    stack.push(value);
+    Ok(current_i + 1)
+}
+
+/// Reads a line without its line ending, or `None` at the end of input.
+fn read_input_line(input: &VmInput, current_i: usize) -> Result<Option<Vec<u8>>, VmError> {
+    let Some(source) = &input.0 else {
+        return Ok(None);
+    };
+
+    let mut source = source.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    source.read_line().map_err(|e| VmError::InvalidInput { pc: current_i, reason: e.to_string() })
+}
+
+/// Returns the cells of the zero-terminated string at `start_addr`, including the
+/// terminator when there is one before the end of memory.
+fn string_range(mem: &[Word], start_addr: i32) -> Option<Range<usize>> {
+    let start = usize::try_from(start_addr).ok().filter(|&start| start < mem.len())?;
+    let end = mem[start..].iter().position(|&cell| cell == 0).map_or(mem.len(), |len| start + len + 1);
+    Some(start..end)
+}
+
+fn execute_print_str(
+    output_buffer: &mut Vec<u8>,
+    mem: &[Word],
+    current_i: usize,
+    encoding: OutputEncoding,
+    start_addr: i32,
+) -> Result<usize, VmError> {
+    let Some(range) = string_range(mem, start_addr) else {
+        diagnostic!(Level::Warn, "PrintStr out of bounds: {}", start_addr);
+        return Ok(current_i + 1);
+    };
+
+    let string = &mem[range];
+    let text = string.strip_suffix(&[0]).unwrap_or_else(|| {
+        diagnostic!(Level::Warn, "PrintStr at {} ran to the end of memory without a 0 terminator", start_addr);
+        string
+    });
+    write_cells(output_buffer, text, encoding, current_i)?;
+    Ok(current_i + 1)
+}
+
+/// Appends the low byte of each cell in `encoding`, without going through the formatting
+/// machinery for every cell. Nothing is written if the cells are rejected.
+fn write_cells(output_buffer: &mut Vec<u8>, cells: &[Word], encoding: OutputEncoding, current_i: usize) -> Result<(), VmError> {
+    let bytes = cells.iter().map(|&cell| cell as u8);
+    match encoding {
+        OutputEncoding::Latin1 => {
+            output_buffer.reserve(cells.len());
+            for byte in bytes {
+                if byte.is_ascii() {
+                    output_buffer.push(byte);
+                } else {
+                    output_buffer.extend_from_slice(char::from(byte).encode_utf8(&mut [0; 2]).as_bytes());
+                }
+            }
+        }
+        OutputEncoding::Raw => output_buffer.extend(bytes),
+        OutputEncoding::Hex => {
+            let line: Vec<String> = bytes.map(|byte| format!("{:02x}", byte)).collect();
+            output_buffer.extend_from_slice(line.join(" ").as_bytes());
+            output_buffer.push(b'\n');
+        }
+        OutputEncoding::Utf8 => {
+            let start = output_buffer.len();
+            output_buffer.extend(bytes);
+            if let Err(e) = std::str::from_utf8(&output_buffer[start..]) {
+                output_buffer.truncate(start);
+                return Err(VmError::InvalidOutput { pc: current_i, reason: e.to_string() });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+    use crate::instruction::Instruction;
+
+    mod stack_operations {
+        use super::*;
+
+        #[test]
+        fn test_null_instruction() {
+            let program = vec![
+                Instruction::Push(42),
+                Instruction::Null, // Should do nothing
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![42]); // Stack should remain unchanged
+        }
+
+        #[test]
+        fn test_push_and_add() {
+            let program = vec![Instruction::Push(5), Instruction::AddS(3), Instruction::Ret];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![8]);
+        }
+
+        #[test]
+        fn test_push_pop() {
+            let program = vec![Instruction::Push(10), Instruction::Pop, Instruction::Ret];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert!(stack.is_empty());
+        }
+
+        #[test]
+        fn test_dup_and_swap() {
+            let program = vec![
+                Instruction::Push(1),
+                Instruction::Push(2),
+                Instruction::Swap, // stack: [2,1]
+                Instruction::Dup,  // stack: [2,1,1]
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![2, 1, 1]);
+        }
+
+        #[test]
+        fn test_rot_over_pick() {
+            let program = vec![
+                Instruction::Push(1),
+                Instruction::Push(2),
+                Instruction::Push(3),
+                Instruction::Rot,     // stack: [2,3,1]
+                Instruction::Over,    // stack: [2,3,1,3]
+                Instruction::Pick(3), // stack: [2,3,1,3,2]
+                Instruction::Pick(0), // stack: [2,3,1,3,2,2]
+                Instruction::Ret,
+            ];
+            let (stack, _) = execute(&program, &mut Vec::new());
+            assert_eq!(stack, vec![2, 3, 1, 3, 2, 2]);
+        }
+
+        #[test]
+        fn test_depth_and_clear() {
+            let program = vec![
+                Instruction::Depth,
+                Instruction::Push(7),
+                Instruction::Depth, // stack: [0,7,2]
+                Instruction::Clear,
+                Instruction::Depth,
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::with_config(program, VmConfig { track_provenance: true, ..VmConfig::default() });
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![0]);
+            assert_eq!(vm.high_water.stack, 3);
+        }
+
+        #[test]
+        fn test_shuffles_without_enough_values_are_skipped() {
+            let program = vec![
+                Instruction::Push(1),
+                Instruction::Push(2),
+                Instruction::Rot,
+                Instruction::Pick(2),
+                Instruction::Pick(-1),
+                Instruction::Ret,
+            ];
+            let (stack, _) = execute(&program, &mut Vec::new());
+            assert_eq!(stack, vec![1, 2]);
+
+            let mut vm = Vm::with_config(program, VmConfig { strict: true, ..VmConfig::default() });
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::StackUnderflow { pc: 2 }));
+        }
+
+        #[test]
+        fn test_subtract() {
+            let program = vec![
+                Instruction::Push(10),
+                Instruction::Push(3),
+                Instruction::Sub, // 10 - 3 = 7
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![7]);
+        }
+    }
+
+    mod arithmetic_operations {
+        use super::*;
+
+        fn run_with(arithmetic: ArithmeticMode, program: Vec<Instruction>) -> Result<Vec<Word>, VmError> {
+            let mut vm = Vm::with_config(program, VmConfig { arithmetic, ..VmConfig::default() });
+            vm.run(&mut Vec::new()).map(|_| vm.stack)
+        }
+
+        #[test]
+        fn test_overflow_modes() {
+            let mult = vec![Instruction::Push(Word::MIN), Instruction::MultS(2)];
+            assert_eq!(run_with(ArithmeticMode::Wrapping, mult.clone()), Ok(vec![0]));
+            assert_eq!(run_with(ArithmeticMode::Saturating, mult.clone()), Ok(vec![Word::MIN]));
+            assert_eq!(run_with(ArithmeticMode::Checked, mult), Err(VmError::ArithmeticOverflow { pc: 1 }));
+
+            let sub = vec![Instruction::Push(Word::MIN), Instruction::Push(1), Instruction::Sub];
+            assert_eq!(run_with(ArithmeticMode::Wrapping, sub.clone()), Ok(vec![Word::MAX]));
+            assert_eq!(run_with(ArithmeticMode::Saturating, sub), Ok(vec![Word::MIN]));
+        }
+
+        #[test]
+        fn test_dividing_min_by_minus_one() {
+            let div = vec![Instruction::Push(Word::MIN), Instruction::Push(-1), Instruction::Div];
+            assert_eq!(run_with(ArithmeticMode::Wrapping, div.clone()), Ok(vec![Word::MIN]));
+            assert_eq!(run_with(ArithmeticMode::Saturating, div.clone()), Ok(vec![Word::MAX]));
+            assert_eq!(run_with(ArithmeticMode::Checked, div), Err(VmError::ArithmeticOverflow { pc: 2 }));
+
+            let rem = vec![Instruction::Push(Word::MIN), Instruction::ModS(-1)];
+            assert_eq!(run_with(ArithmeticMode::Checked, rem), Ok(vec![0]));
+        }
+
+        #[test]
+        fn test_checked_overflow_keeps_operands() {
+            let program = vec![Instruction::Push(Word::MAX), Instruction::Push(1), Instruction::Add];
+            let mut vm = Vm::with_config(program, VmConfig { arithmetic: ArithmeticMode::Checked, ..VmConfig::default() });
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::ArithmeticOverflow { pc: 2 }));
+            assert_eq!(vm.stack, vec![Word::MAX, 1]);
+        }
+
+        #[test]
+        fn test_mult_and_div() {
+            let program = vec![
+                Instruction::Push(1),
+                Instruction::Push(25),
+                Instruction::Mult, // [25]
+                Instruction::Dup,  // [25,25]
+                Instruction::Div,  // [1]
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![1]);
+        }
+
+        #[test]
+        fn test_values_beyond_32_bits() {
+            let program = vec![
+                Instruction::Push(1_700_000_000),
+                Instruction::MultS(1000), // Milliseconds timestamp, overflows i32
+                Instruction::AddS(5),
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![1_700_000_000_005]);
+        }
+
+        #[test]
+        fn test_mults_and_divs() {
+            let program = vec![
+                Instruction::Push(2),
+                Instruction::MultS(2), // [4]
+                Instruction::Dup,      // [4,4]
+                Instruction::DivS(2),  // [4,2]
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![4, 2]);
+        }
+    }
+
+    mod modulo_operations {
+        use super::*;
+
+        #[test]
+        fn test_mod_and_mods() {
+            let program = vec![
+                Instruction::Push(17),
+                Instruction::Push(5),
+                Instruction::Mod,     // [2]
+                Instruction::Push(23),
+                Instruction::ModS(4), // [2, 3]
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![2, 3]);
+        }
+
+        #[test]
+        fn test_mod_by_zero_is_ignored() {
+            let program = vec![
+                Instruction::Push(9),
+                Instruction::ModS(0), // [9]
+                Instruction::Push(0),
+                Instruction::Mod,     // Both operands consumed, like DIV
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert!(stack.is_empty());
+        }
+
+        #[test]
+        fn test_mod_keeps_dividend_sign() {
+            let program = vec![Instruction::Push(-7), Instruction::ModS(3), Instruction::Ret];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![-1]);
+        }
+    }
+
+    mod comparison_operations {
+        use super::*;
+
+        fn compare(a: Word, b: Word, op: Instruction) -> Vec<Word> {
+            let program = vec![Instruction::Push(a), Instruction::Push(b), op, Instruction::Ret];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            stack
+        }
+
+        #[test]
+        fn test_eq_and_neq() {
+            assert_eq!(compare(4, 4, Instruction::Eq), vec![1]);
+            assert_eq!(compare(4, 5, Instruction::Eq), vec![0]);
+            assert_eq!(compare(4, 4, Instruction::Neq), vec![0]);
+            assert_eq!(compare(4, 5, Instruction::Neq), vec![1]);
+        }
+
+        #[test]
+        fn test_ordering_uses_second_operand_first() {
+            // Like SUB, comparisons evaluate (second op first): 3 < 8
+            assert_eq!(compare(3, 8, Instruction::Lt), vec![1]);
+            assert_eq!(compare(3, 8, Instruction::Gt), vec![0]);
+            assert_eq!(compare(8, 8, Instruction::Lte), vec![1]);
+            assert_eq!(compare(-2, 8, Instruction::Gte), vec![0]);
+        }
+
+        #[test]
+        fn test_compare_with_single_value_is_ignored() {
+            let program = vec![Instruction::Push(1), Instruction::Lt, Instruction::Ret];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![1]);
+        }
+    }
+
+    #[cfg(feature = "assembler")]
+    mod control_flow {
+        use super::*;
+        use crate::spliter::{parse_program, split_instructions};
+
+        #[test]
+        fn test_exit_summary_reasons() {
+            let summary = |program: Vec<Instruction>, config: VmConfig| {
+                let mut vm = Vm::with_config(program, config);
+                let result = vm.run(&mut Vec::new());
+                vm.exit_summary(&result).unwrap()
+            };
+            let config = VmConfig::default();
+
+            let cases = [
+                (vec![Instruction::Call(2), Instruction::Ret, Instruction::Ret], 1, ExitReason::Ret),
+                (vec![Instruction::Call(2), Instruction::Ret, Instruction::Halt(5)], 2, ExitReason::Halt(5)),
+                (vec![Instruction::Push(1), Instruction::Pop], 2, ExitReason::End),
+                (vec![Instruction::Load], 0, ExitReason::Trap(VmError::StackUnderflow { pc: 0 })),
+            ];
+            for (program, pc, reason) in cases {
+                assert_eq!(summary(program, config.clone()), ExitSummary { pc, reason });
+            }
+
+            let fuel = VmConfig { max_steps: Some(3), ..VmConfig::default() };
+            let looping = summary(vec![Instruction::Null, Instruction::Jmp(0)], fuel);
+            assert_eq!((looping.pc, looping.reason), (1, ExitReason::FuelExhausted));
+        }
+
+        #[test]
+        fn test_paused_vm_has_no_exit_summary() {
+            let mut vm = Vm::new(vec![Instruction::Push(1), Instruction::Ret]);
+            vm.step(&mut Vec::new()).unwrap();
+            assert_eq!(vm.exit_summary(&Ok(())), None);
+        }
+
+        #[test]
+        fn test_perf_read_counters() {
+            let program = vec![
+                Instruction::PerfRead(PERF_STEPS),
+                Instruction::Call(3),
+                Instruction::Ret,
+                Instruction::PerfRead(PERF_CALL_DEPTH),
+                Instruction::PerfRead(PERF_STACK_DEPTH),
+                Instruction::PerfRead(PERF_STEPS_LEFT),
+                Instruction::PerfRead(99),
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::with_config(program.clone(), VmConfig { max_steps: Some(10), ..VmConfig::default() });
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![1, 1, 2, 5, -1]);
+
+            let mut vm = Vm::new(program);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack[3], -1);
+        }
+
+        #[test]
+        fn test_vm_info_fields() {
+            let program = vec![
+                Instruction::VmInfo(VMINFO_VERSION),
+                Instruction::VmInfo(VMINFO_ISA),
+                Instruction::Grow(1),
+                Instruction::Pop,
+                Instruction::VmInfo(VMINFO_MEMORY),
+                Instruction::VmInfo(VMINFO_CAPABILITIES),
+                Instruction::VmInfo(VMINFO_WORD_BITS),
+                Instruction::VmInfo(-7),
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::with_config(program, VmConfig { memory_size: Some(16), ..VmConfig::default() });
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![200, 102, 16 + PAGE_SIZE as Word, 0b111, 64, -1]);
+        }
+
+        #[test]
+        fn test_halt_stops_inside_subroutine() {
+            let program = vec![
+                Instruction::Call(3),
+                Instruction::Push(1),
+                Instruction::Ret,
+                Instruction::Push(7),
+                Instruction::Halt(3),
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::new(program);
+            vm.run(&mut Vec::new()).unwrap();
+
+            assert_eq!(vm.exit_code, Some(3));
+            assert_eq!(vm.stack, vec![7]);
+            assert_eq!(vm.pc, 4);
+        }
+
+        #[test]
+        fn test_loop_program() {
+            let program = vec![
+                Instruction::Push(5),
+                Instruction::SubS(1),
+                Instruction::Jnz(1),
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![0]);
+        }
+
+        #[test]
+        fn test_jiz_jump() {
+            let program = vec![
+                Instruction::Push(0),
+                Instruction::Jiz(3), // Jump to RET if zero (which it is)
+                Instruction::Push(99), // This should be skipped
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![0]); // Should not push 99
+        }
+
+        #[test]
+        fn test_jiz_no_jump() {
+            let program = vec![
+                Instruction::Push(1),
+                Instruction::Jiz(3), // Don't jump if not zero
+                Instruction::Push(99), // This should execute
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![1, 99]); // Should push 99
+        }
+
+        #[test]
+        fn test_jmp_is_unconditional() {
+            let program = vec![
+                Instruction::Push(0),
+                Instruction::Jmp(3),
+                Instruction::Push(99), // Skipped
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![0]);
+        }
+
+        #[test]
+        fn test_jmp_dyn_jump_table() {
+            // Jump to the address computed on the stack: 2 * 2 + 1 = 5
+            let program = vec![
+                Instruction::Push(2),
+                Instruction::MultS(2),
+                Instruction::AddS(1),
+                Instruction::JmpDyn,
+                Instruction::Push(10), // Skipped
+                Instruction::Push(20),
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![20]);
+        }
+
+        #[test]
+        fn test_jmp_dyn_out_of_range_traps() {
+            let mut vm = Vm::new(vec![Instruction::Push(-1), Instruction::JmpDyn, Instruction::Ret]);
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::InvalidJumpTarget { pc: 1, target: -1 });
+
+            let mut vm = Vm::new(vec![Instruction::Push(3), Instruction::JmpDyn, Instruction::Ret]);
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::InvalidJumpTarget { pc: 1, target: 3 });
+            assert_eq!(vm.stack, vec![3]);
+        }
+
+        #[test]
+        fn test_jmp_dyn_empty_stack_traps() {
+            let mut vm = Vm::new(vec![Instruction::JmpDyn]);
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::StackUnderflow { pc: 0 });
+        }
+
+        #[test]
+        fn test_call_and_ret() {
+            let program = vec![
+                Instruction::Push(2),
+                Instruction::Call(4), // Call the doubling routine
+                Instruction::AddS(1),               // Runs after the routine returns
+                Instruction::Ret,
+                Instruction::MultS(2),              // routine: double the top value
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![5]);
+        }
+
+        #[test]
+        fn test_nested_calls() {
+            let program = vec![
+                Instruction::Call(3),
+                Instruction::Push(3),
+                Instruction::Ret,
+                Instruction::Call(5), // outer routine calls inner routine
+                Instruction::Ret,
+                Instruction::Push(1),               // inner routine
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            let (stack, _) = execute(&program, &mut output);
+            assert_eq!(stack, vec![1, 3]);
+        }
+
+        #[test]
+        fn test_call_invalid_target_traps() {
+            let program = vec![
+                Instruction::Push(7),
+                Instruction::Call(99), // Out of range
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::new(program);
+            let mut output = Vec::new();
+            let err = vm.run(&mut output).unwrap_err();
+            assert_eq!(err, VmError::InvalidCallTarget { pc: 1, target: 99 });
+            assert_eq!(vm.pc, 1); // Left pointing at the faulting instruction
+            assert_eq!(vm.stack, vec![7]);
+        }
+
+        #[test]
+        fn test_unbounded_recursion_traps() {
+            let program = vec![Instruction::Call(0)];
+            let mut vm = Vm::new(program);
+            let mut output = Vec::new();
+            let err = vm.run(&mut output).unwrap_err();
+            assert_eq!(err, VmError::CallStackOverflow { pc: 0, depth: MAX_CALL_DEPTH });
+        }
+
+        #[test]
+        fn test_locals_belong_to_their_frame() {
+            let program = vec![
+                Instruction::Push(7),
+                Instruction::StoreLocal(0),
+                Instruction::Call(5),
+                Instruction::LoadLocal(0),
+                Instruction::Ret,
+                // The callee's slot 0 is its own, and slot 1 was never stored to
+                Instruction::Push(9),
+                Instruction::StoreLocal(0),
+                Instruction::LoadLocal(1),
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::new(program);
+            vm.run(&mut Vec::new()).unwrap();
+
+            assert_eq!(vm.stack, vec![0, 7]);
+            assert_eq!((vm.locals.as_slice(), vm.frame_pointer), (&[7][..], 0));
+
+            vm.reset();
+            assert!(vm.locals.is_empty());
+        }
+
+        #[test]
+        fn test_recursive_calls_keep_separate_locals() {
+            // fact(n) = n == 0 ? 1 : n * fact(n - 1), with n kept in a local
+            let program = vec![
+                Instruction::Push(5),
+                Instruction::Call(3),
+                Instruction::Ret,
+                Instruction::StoreLocal(0),
+                Instruction::LoadLocal(0),
+                Instruction::Jiz(11),
+                Instruction::SubS(1),
+                Instruction::Call(3),
+                Instruction::LoadLocal(0),
+                Instruction::Mult,
+                Instruction::Ret,
+                Instruction::Pop,
+                Instruction::Push(1),
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::new(program);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![120]);
+        }
+
+        #[test]
+        fn test_store_local_needs_a_value() {
+            let mut vm = Vm::new(vec![Instruction::StoreLocal(0)]);
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::StackUnderflow { pc: 0 }));
+        }
+
+        #[test]
+        fn test_runaway_dup_loop_overflows_the_stack() {
+            let program = vec![Instruction::Push(1), Instruction::Dup, Instruction::Jmp(1)];
+            let config = VmConfig { max_stack_depth: Some(8), ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+
+            assert_eq!(err, VmError::StackOverflow { pc: 1, depth: 9 });
+            assert_eq!(vm.pc, 1);
+            assert_eq!(vm.high_water.stack, 9);
+        }
+
+        #[test]
+        fn test_stack_at_the_limit_is_allowed() {
+            let program = vec![Instruction::Push(1), Instruction::Push(2), Instruction::Pop, Instruction::Push(3)];
+            let config = VmConfig { max_stack_depth: Some(2), ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![1, 3]);
+        }
+
+        #[test]
+        fn test_infinite_loop_runs_out_of_fuel() {
+            let program = vec![Instruction::Push(1), Instruction::Jnz(1)];
+            let config = VmConfig { max_steps: Some(10), ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+
+            assert_eq!(err, VmError::FuelExhausted { pc: 1, stack: vec![1] });
+            assert_eq!(vm.steps, 10);
+        }
+
+        #[test]
+        fn test_program_within_budget_finishes() {
+            let program = vec![Instruction::Push(1), Instruction::Ret];
+            let config = VmConfig { max_steps: Some(2), ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            assert_eq!(vm.run(&mut Vec::new()), Ok(()));
+        }
+
+        #[test]
+        fn test_high_water_and_compact() {
+            // Recurse 50 calls deep, pushing a value per call, then unwind
+            let program = vec![
+                Instruction::Push(50),
+                Instruction::Call(3),
+                Instruction::Ret,
+                Instruction::Dup,
+                Instruction::SubS(1),
+                Instruction::Jiz(7),
+                Instruction::Call(3),
+                Instruction::Pop,
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::new(program);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.high_water, HighWater { stack: 51, call_depth: 50, heap: 0 });
+
+            vm.stack.clear();
+            assert!(vm.compact() > 0);
+            assert_eq!((vm.stack.capacity(), vm.call_stack.capacity()), (0, 0));
+            assert_eq!(vm.compact(), 0);
+            assert_eq!(vm.high_water.call_depth, 50);
+        }
+
+        #[test]
+        fn test_cancelled_run_stops_at_next_check() {
+            let program = vec![Instruction::HostCall("cancel".to_string()), Instruction::Jmp(1)];
+            let config = VmConfig { max_steps: Some(2000), ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            let token = CancellationToken::new();
+            let canceller = token.clone();
+            vm.register_host_fn("cancel", move |_| {
+                canceller.cancel();
+                Ok(())
+            });
+
+            let err = vm.run_with_cancel(&mut Vec::new(), &token).unwrap_err();
+            assert_eq!(err, VmError::Cancelled { pc: 1 });
+            assert_eq!(vm.steps, CANCEL_CHECK_INTERVAL);
+
+            // Cancelling again before resuming stops without running anything
+            assert_eq!(vm.run_with_cancel(&mut Vec::new(), &token), Err(VmError::Cancelled { pc: 1 }));
+            assert_eq!(vm.steps, CANCEL_CHECK_INTERVAL);
+
+            let err = vm.run_with_cancel(&mut Vec::new(), &CancellationToken::new()).unwrap_err();
+            assert_eq!(err, VmError::FuelExhausted { pc: 1, stack: vec![] });
+        }
+
+        #[test]
+        fn test_host_functions_keep_state() {
+            let program = vec![
+                Instruction::HostCall("counter".to_string()),
+                Instruction::HostCall("counter".to_string()),
+                Instruction::Add,
+            ];
+            let mut vm = Vm::new(program);
+            let mut calls = 0;
+            vm.register_host_fn("counter", move |stack| {
+                calls += 1;
+                stack.push(calls);
+                Ok(())
+            });
+
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![3]);
+        }
+
+        #[test]
+        fn test_host_call_errors_trap() {
+            let mut vm = Vm::new(vec![Instruction::HostCall("missing".to_string())]);
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::UnknownHostFunction { pc: 0, name: "missing".to_string() });
+
+            let mut vm = Vm::new(vec![Instruction::Push(1), Instruction::HostCall("fail".to_string())]);
+            vm.register_host_fn("fail", |_| Err("no entropy".to_string()));
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err.to_string(), "host function 'fail' failed at instruction 1: no entropy");
+        }
+
+        #[test]
+        fn test_recent_trace_is_bounded() {
+            let program = vec![
+                Instruction::Push(40),
+                Instruction::SubS(1),
+                Instruction::Jnz(1),
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::new(program);
+            let mut output = Vec::new();
+            vm.run(&mut output).unwrap();
+            let trace = vm.recent_trace();
+            assert_eq!(trace.len(), TRACE_RING_SIZE);
+            assert_eq!(trace.last(), Some(&3));
+        }
+
+        #[test]
+        fn test_passing_assertions_keep_the_checked_value() {
+            let mut vm = Vm::new(split_instructions("PUSH 6\nMULTS 7\nASSERT 42\nPUSH 42\nASSERTEQ").unwrap());
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![42]);
+        }
+
+        #[test]
+        fn test_failed_assertions_stop_the_program() {
+            let mut vm = Vm::new(split_instructions("PUSH 5\nASSERT 4").unwrap());
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::AssertionFailed { pc: 1, expected: 4, actual: 5 });
+            assert_eq!(err.to_string(), "assertion failed at instruction 1: expected 4, found 5");
+
+            // Handlers do not get to hide a failed assertion
+            let config = VmConfig { catch_traps: true, ..VmConfig::default() };
+            let mut vm = Vm::with_config(split_instructions("TRY done\nPUSH 1\nPUSH 2\nASSERTEQ\ndone:").unwrap(), config);
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::AssertionFailed { pc: 3, expected: 2, actual: 1 }));
+            assert_eq!(vm.stack, vec![1, 2]);
+
+            let mut vm = Vm::new(split_instructions("PUSH 1\nASSERTEQ").unwrap());
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::StackUnderflow { pc: 1 }));
+        }
+
+        #[test]
+        fn test_abort_reports_its_message() {
+            let source = ".data 0\nmsg:\n.string \"bad input\"\n.text\nTRY done\nCALL fail\nPUSH 1\nfail:\nABORT msg 9\ndone:";
+            let program = parse_program(source).unwrap();
+            let config = VmConfig { catch_traps: true, ..VmConfig::default() };
+            let mut vm = Vm::with_config(program.instructions, config);
+            vm.load_data(&program.data);
+
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::GuestAbort { pc: 3, message: "bad input".to_string() });
+            assert_eq!(err.to_string(), "program aborted at instruction 3: bad input");
+            assert_eq!(err.code(), None);
+
+            // The part of the message outside memory is left out
+            let end = Vm::new(Vec::new()).mem.len() as i32;
+            let mut vm = Vm::new(vec![Instruction::MemWrite(end - 2, vec![111, 104]), Instruction::Abort(end - 2, 1000)]);
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::GuestAbort { pc: 1, message: "oh".to_string() }));
+        }
+    }
+
+    #[cfg(feature = "assembler")]
+    mod exceptions {
+        use super::*;
+        use crate::spliter::split_instructions;
+
+        fn run(source: &str, config: VmConfig) -> (Vm, Result<(), VmError>) {
+            let mut vm = Vm::with_config(split_instructions(source).unwrap(), config);
+            let result = vm.run(&mut Vec::new());
+            (vm, result)
+        }
+
+        #[test]
+        fn test_throw_unwinds_to_the_handler() {
+            let source = "PUSH 1\nTRY handler\nPUSH 2\nPUSH 3\nPUSH 42\nTHROW\nPUSH 99\nhandler:\nADDS 1";
+            let (vm, result) = run(source, VmConfig::default());
+            result.unwrap();
+            assert_eq!(vm.stack, vec![1, 43]);
+        }
+
+        #[test]
+        fn test_throw_unwinds_call_frames() {
+            let source = "PUSH 5\nSTORELOCAL 0\nTRY handler\nCALL f\nRET\nhandler:\nLOADLOCAL 0\nRET\nf:\nPUSH 8\nSTORELOCAL 0\nCALL g\ng:\nPUSH 7\nTHROW";
+            let (vm, result) = run(source, VmConfig::default());
+            result.unwrap();
+
+            assert_eq!(vm.stack, vec![7, 5]);
+            assert!(vm.call_stack.is_empty());
+            assert_eq!((vm.locals.as_slice(), vm.frame_pointer), (&[5][..], 0));
+        }
+
+        #[test]
+        fn test_unhandled_throw_traps() {
+            let (vm, result) = run("PUSH 1\nPUSH 7\nTHROW", VmConfig::default());
+            assert_eq!(result, Err(VmError::UnhandledException { pc: 2, code: 7 }));
+            assert_eq!((vm.pc, vm.stack), (2, vec![1, 7]));
+
+            // ENDTRY removes the handler, and so does returning from the routine that installed it
+            let (_, result) = run("TRY handler\nENDTRY\nPUSH 1\nTHROW\nhandler:", VmConfig::default());
+            assert_eq!(result, Err(VmError::UnhandledException { pc: 3, code: 1 }));
+            let (_, result) = run("CALL f\nPUSH 2\nTHROW\nf:\nTRY handler\nRET\nhandler:", VmConfig::default());
+            assert_eq!(result, Err(VmError::UnhandledException { pc: 2, code: 2 }));
+        }
+
+        #[test]
+        fn test_traps_reach_handlers_when_enabled() {
+            let source = "TRY handler\nPUSH 1\nDIVS 0\nhandler:";
+            let config = VmConfig { strict: true, ..VmConfig::default() };
+            let (_, result) = run(source, config.clone());
+            assert_eq!(result, Err(VmError::DivisionByZero { pc: 2 }));
+
+            let (vm, result) = run(source, VmConfig { catch_traps: true, ..config });
+            result.unwrap();
+            assert_eq!(vm.stack, vec![-2]);
+
+            // Traps without a code, and traps outside any TRY, still stop the program
+            let config = VmConfig { catch_traps: true, max_steps: Some(3), ..VmConfig::default() };
+            let (_, result) = run("TRY handler\nloop:\nJMP loop\nhandler:", config);
+            assert!(matches!(result, Err(VmError::FuelExhausted { .. })));
+            let (_, result) = run("LOAD", VmConfig { catch_traps: true, ..VmConfig::default() });
+            assert_eq!(result, Err(VmError::StackUnderflow { pc: 0 }));
+        }
+
+        #[test]
+        fn test_verified_runs_trap_like_run() {
+            // The overflow happens on the fast path, and the step limit is checked by `step`
+            let source = "TRY handler\nPUSH 9223372036854775807\nADDS 1\nPUSH 1\nhandler:\nPUSH 7";
+            let checked = VmConfig { arithmetic: ArithmeticMode::Checked, ..VmConfig::default() };
+            let configs = [
+                checked.clone(),
+                VmConfig { catch_traps: true, ..checked.clone() },
+                VmConfig { catch_traps: true, max_steps: Some(2), ..checked },
+            ];
+            for config in configs {
+                let (expected, expected_result) = run(source, config.clone());
+                let outcome = |vm: &Vm, result| (result, vm.pc, vm.steps, vm.stack.clone());
+
+                let mut vm = Vm::with_config(split_instructions(source).unwrap(), config.clone());
+                let result = vm.run_verified(&mut Vec::new());
+                assert_eq!(outcome(&vm, result), outcome(&expected, expected_result.clone()));
+
+                let mut vm = Vm::with_config(split_instructions(source).unwrap(), config);
+                // SAFETY: every operand, target and address of the program is in range
+                let result = unsafe { vm.run_verified_unchecked(&mut Vec::new()) };
+                assert_eq!(outcome(&vm, result), outcome(&expected, expected_result));
+            }
+
+            let config = VmConfig { arithmetic: ArithmeticMode::Checked, catch_traps: true, ..VmConfig::default() };
+            let mut vm = Vm::with_config(split_instructions(source).unwrap(), config);
+            vm.run_verified(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![VmError::ArithmeticOverflow { pc: 2 }.code().unwrap(), 7]);
+        }
+    }
+
+    mod memory_operations {
+        use super::*;
+
+        fn uninit_config(mode: UninitReadMode) -> VmConfig {
+            VmConfig { uninit_reads: mode, ..VmConfig::default() }
+        }
+
+        #[test]
+        fn test_uninitialized_read_traps() {
+            let program = vec![
+                Instruction::MemWrite(0, vec![72, 105]),
+                Instruction::Print(0, 3), // Cell 2 was never written
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            let mut output = Vec::new();
+            let err = vm.run(&mut output).unwrap_err();
+            assert_eq!(err, VmError::UninitializedRead { pc: 1, addr: 2 });
+            assert!(output.is_empty());
+        }
+
+        #[test]
+        fn test_load_and_store_use_addresses_from_the_stack() {
+            let program = vec![
+                Instruction::Push(42),
+                Instruction::Push(10),
+                Instruction::Store,
+                Instruction::Push(10),
+                Instruction::Load,
+                Instruction::Ret,
+            ];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![42]);
+            assert_eq!(vm.mem[10], 42);
+        }
+
+        #[test]
+        fn test_load_and_store_trap_on_invalid_addresses() {
+            let mut vm = Vm::new(vec![Instruction::Push(7), Instruction::Push(-1), Instruction::Store]);
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::InvalidAddress { pc: 2, addr: -1 }));
+            assert_eq!(vm.stack, vec![7, -1]);
+
+            let mut vm = Vm::new(vec![Instruction::Push(MEMORY_SIZE as Word), Instruction::Load]);
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::InvalidAddress { pc: 1, addr: MEMORY_SIZE as Word }));
+
+            let mut vm = Vm::new(vec![Instruction::Push(0), Instruction::Store]);
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::StackUnderflow { pc: 1 }));
+        }
+
+        #[test]
+        fn test_bounds_follow_the_configured_memory_size() {
+            let program = vec![
+                Instruction::MemWrite(4000, vec![7]),
+                Instruction::MemRead(4000),
+                Instruction::Push(1),
+                Instruction::MemWriteS(4095, 1),
+                Instruction::MemRead(4096), // Past the end, skipped
+                Instruction::MemRead(-1),
+                Instruction::Print(-1, 2),
+                Instruction::MemWriteS(-1, 1),
+            ];
+            let mut vm = Vm::with_config(program, VmConfig { memory_size: Some(4096), ..VmConfig::default() });
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![7]);
+            assert_eq!((vm.mem.len(), vm.mem[4095]), (4096, 1));
+
+            let mut vm = Vm::with_config(vec![Instruction::MemRead(16)], VmConfig { memory_size: Some(16), strict: true, ..VmConfig::default() });
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::MemoryOutOfBounds { pc: 0, addr: 16, len: 1 }));
+        }
+
+        #[test]
+        fn test_grow_adds_pages_up_to_the_maximum() {
+            let program = vec![
+                Instruction::MemSize,
+                Instruction::Grow(2),
+                Instruction::MemWrite(MEMORY_SIZE as i32 + 2 * PAGE_SIZE as i32 - 1, vec![5]),
+                Instruction::MemSize,
+                Instruction::Grow(1), // Over the maximum
+                Instruction::Grow(-1),
+                Instruction::MemSize,
+            ];
+            let config = VmConfig { max_memory_size: Some(MEMORY_SIZE + 2 * PAGE_SIZE), memory_fill: 9, ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            vm.run(&mut Vec::new()).unwrap();
+
+            let grown = (MEMORY_SIZE + 2 * PAGE_SIZE) as Word;
+            assert_eq!(vm.stack, vec![MEMORY_SIZE as Word, MEMORY_SIZE as Word, grown, -1, -1, grown]);
+            assert_eq!((vm.mem[MEMORY_SIZE], vm.mem[grown as usize - 1]), (9, 5));
+
+            vm.reset();
+            assert_eq!(vm.mem.len(), MEMORY_SIZE);
+        }
+
+        #[test]
+        fn test_compact_releases_grown_memory() {
+            let program = vec![Instruction::Grow(4)];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Warn));
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.mem.len(), MEMORY_SIZE + 4 * PAGE_SIZE);
+
+            vm.reset();
+            let grown = 4 * PAGE_SIZE * (size_of::<Word>() + size_of::<bool>());
+            assert!(vm.compact() >= grown);
+            assert_eq!((vm.mem.capacity(), vm.initialized.capacity()), (MEMORY_SIZE, MEMORY_SIZE));
+            assert_eq!(vm.compact(), 0);
+        }
+
+        #[test]
+        fn test_grown_memory_is_tracked_as_unwritten() {
+            let program = vec![Instruction::Grow(1), Instruction::MemRead(MEMORY_SIZE as i32)];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::UninitializedRead { pc: 1, addr: MEMORY_SIZE }));
+        }
+
+        #[test]
+        fn test_writes_to_protected_memory_trap() {
+            let cases = [
+                (vec![Instruction::MemWrite(6, vec![1, 2, 3])], 8),
+                (vec![Instruction::Push(1), Instruction::Push(12), Instruction::Store], 12),
+                (vec![Instruction::MemCopy(12, 0, 4)], 12),
+                (vec![Instruction::MemSet(0, 20, 0)], 8),
+                (vec![Instruction::Push(1), Instruction::MemWriteS(11, 1)], 11),
+            ];
+            for (mut program, addr) in cases {
+                program.insert(0, Instruction::Protect(8, 5));
+                let pc = program.len() - 1;
+                let mut vm = Vm::new(program);
+                assert_eq!(vm.run(&mut Vec::new()), Err(VmError::ProtectedWrite { pc, addr }));
+                assert!(vm.mem.iter().all(|&cell| cell == 0));
+            }
+        }
+
+        #[test]
+        fn test_protected_memory_can_be_read() {
+            let program = vec![
+                Instruction::Protect(0, 2),
+                Instruction::Protect(-1, 4), // Out of bounds, skipped
+                Instruction::MemRead(0),
+                Instruction::MemCopy(2, 0, 2),
+                Instruction::MemWrite(2, vec![3]),
+            ];
+            let mut vm = Vm::new(program);
+            vm.load_data(&[DataBlock { start: 0, values: vec![7, 8] }]);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!((vm.stack[0], &vm.mem[..4]), (7, &[7, 8, 3, 8][..]));
+            assert_eq!(vm.protected(), vec![0..2]);
+
+            vm.reset();
+            assert!(vm.protected().is_empty());
+        }
+
+        #[test]
+        fn test_protect_data_makes_data_read_only() {
+            let program = vec![Instruction::MemRead(4), Instruction::MemWrite(4, vec![0])];
+            let data = [DataBlock { start: 4, values: vec![9] }];
+
+            let mut vm = Vm::new(program.clone());
+            vm.load_data(&data);
+            vm.run(&mut Vec::new()).unwrap();
+
+            let mut vm = Vm::with_config(program, VmConfig { protect_data: true, ..VmConfig::default() });
+            vm.load_data(&data);
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::ProtectedWrite { pc: 1, addr: 4 }));
+            assert_eq!((vm.stack.as_slice(), vm.mem[4]), (&[9][..], 9));
+        }
+
+        #[test]
+        fn test_alloc_and_free_reuse_blocks() {
+            let program = vec![
+                Instruction::Push(3),
+                Instruction::Alloc,
+                Instruction::Dup,
+                Instruction::Push(42),
+                Instruction::Swap,
+                Instruction::Store,
+                Instruction::Dup,
+                Instruction::Load,
+                Instruction::Swap,
+                Instruction::Free,
+                Instruction::Push(2),
+                Instruction::Alloc, // Fits in the freed block
+                Instruction::PerfRead(PERF_HEAP_CELLS),
+            ];
+            let mut vm = Vm::new(program);
+            vm.run(&mut Vec::new()).unwrap();
+
+            let base = MEMORY_SIZE as Word;
+            assert_eq!(vm.stack, vec![42, base, 2]);
+            assert_eq!(vm.mem.len(), MEMORY_SIZE + 3);
+            assert_eq!(vm.high_water.heap, 3);
+
+            vm.reset();
+            assert_eq!(vm.mem.len(), MEMORY_SIZE);
+        }
+
+        #[test]
+        fn test_alloc_traps_when_out_of_memory() {
+            let config = VmConfig { max_memory_size: Some(MEMORY_SIZE + 8), ..VmConfig::default() };
+            for size in [0, -1, 9] {
+                let mut vm = Vm::with_config(vec![Instruction::Push(size), Instruction::Alloc], config.clone());
+                assert_eq!(vm.run(&mut Vec::new()), Err(VmError::AllocationFailed { pc: 1, size }));
+                assert_eq!(vm.stack, vec![size]);
+            }
+
+            // Two free holes of 4 cannot hold 8 once the tail is taken
+            let program = vec![
+                Instruction::Push(4),
+                Instruction::Alloc,
+                Instruction::Push(4),
+                Instruction::Alloc,
+                Instruction::Swap,
+                Instruction::Free,
+                Instruction::Push(8),
+                Instruction::Alloc,
+            ];
+            let mut vm = Vm::with_config(program, VmConfig { max_memory_size: Some(MEMORY_SIZE + 12), ..VmConfig::default() });
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::AllocationFailed { pc: 7, size: 8 }));
         }
 
         #[test]
-        fn test_push_and_add() {
-            let program = vec![Instruction::Push(5), Instruction::AddS(3), Instruction::Ret];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![8]);
+        fn test_invalid_free_traps() {
+            let program = vec![Instruction::Push(1), Instruction::Alloc, Instruction::Dup, Instruction::Free, Instruction::Free];
+            let mut vm = Vm::new(program);
+            let addr = MEMORY_SIZE as Word;
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::InvalidFree { pc: 4, addr }));
+            assert_eq!(vm.stack, vec![addr]);
         }
 
         #[test]
-        fn test_push_pop() {
-            let program = vec![Instruction::Push(10), Instruction::Pop, Instruction::Ret];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert!(stack.is_empty());
+        fn test_reused_heap_cells_are_tracked_as_unwritten() {
+            let program = vec![
+                Instruction::Push(1),
+                Instruction::Alloc,
+                Instruction::Push(5),
+                Instruction::Swap,
+                Instruction::Store,
+                Instruction::Push(MEMORY_SIZE as Word),
+                Instruction::Free,
+                Instruction::Push(1),
+                Instruction::Alloc,
+                Instruction::Load,
+            ];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::UninitializedRead { pc: 9, addr: MEMORY_SIZE }));
         }
 
         #[test]
-        fn test_dup_and_swap() {
+        fn test_bulk_memory_operations() {
             let program = vec![
-                Instruction::Push(1),
-                Instruction::Push(2),
-                Instruction::Swap, // stack: [2,1]
-                Instruction::Dup,  // stack: [2,1,1]
+                Instruction::MemWrite(0, vec![1, 2, 3, 4]),
+                Instruction::MemCopy(1, 0, 3), // Overlapping copy
+                Instruction::MemSet(10, 3, 7),
+                Instruction::MemCmp(0, 1, 2),
+                Instruction::MemCmp(1, 0, 2),
+                Instruction::MemCmp(10, 11, 2),
                 Instruction::Ret,
             ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![2, 1, 1]);
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.mem[..4], [1, 1, 2, 3]);
+            assert_eq!(vm.mem[9..14], [0, 7, 7, 7, 0]);
+            assert_eq!(vm.stack, vec![-1, 1, 0]);
         }
 
         #[test]
-        fn test_subtract() {
+        fn test_bulk_memory_out_of_bounds_is_skipped() {
+            let end = MEMORY_SIZE as i32;
             let program = vec![
-                Instruction::Push(10),
-                Instruction::Push(3),
-                Instruction::Sub, // 10 - 3 = 7
-                Instruction::Ret,
+                Instruction::MemWrite(0, vec![5]),
+                Instruction::MemCopy(end - 1, 0, 2),
+                Instruction::MemSet(-1, 2, 9),
+                Instruction::MemCmp(0, end, 1),
             ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![7]);
+            let mut vm = Vm::new(program);
+            vm.run(&mut Vec::new()).unwrap();
+            assert!(vm.stack.is_empty());
+            assert_eq!(vm.mem[0], 5);
+            assert_eq!(vm.mem[MEMORY_SIZE - 1], 0);
+
+            let mut vm = Vm::with_config(vec![Instruction::MemCopy(0, end - 1, 2)], VmConfig { strict: true, ..VmConfig::default() });
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::MemoryOutOfBounds { pc: 0, addr: end - 1, len: 2 }));
         }
-    }
 
-    mod arithmetic_operations {
-        use super::*;
+        #[test]
+        fn test_memcmp_of_unwritten_cells_traps() {
+            let program = vec![Instruction::MemWrite(0, vec![1, 2]), Instruction::MemCopy(4, 0, 2), Instruction::MemCmp(0, 5, 2)];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::UninitializedRead { pc: 2, addr: 6 }));
+        }
 
         #[test]
-        fn test_mult_and_div() {
-            let program = vec![
-                Instruction::Push(1),
-                Instruction::Push(25),
-                Instruction::Mult, // [25]
-                Instruction::Dup,  // [25,25]
-                Instruction::Div,  // [1]
-                Instruction::Ret,
-            ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![1]);
+        fn test_load_of_unwritten_cell_traps() {
+            let program = vec![Instruction::Push(3), Instruction::Load];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::UninitializedRead { pc: 1, addr: 3 }));
         }
 
         #[test]
-        fn test_mults_and_divs() {
+        fn test_initialized_reads_pass() {
             let program = vec![
+                Instruction::MemWrite(4, vec![1]),
                 Instruction::Push(2),
-                Instruction::MultS(2), // [4]
-                Instruction::Dup,      // [4,4]
-                Instruction::DivS(2),  // [4,2]
+                Instruction::Push(3),
+                Instruction::MemWriteS(5, 2),
+                Instruction::MemRead(4),
+                Instruction::MemRead(6),
+                Instruction::Print(4, 3),
                 Instruction::Ret,
             ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![4, 2]);
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![1, 3]);
         }
-    }
-
-    mod control_flow {
-        use super::*;
 
         #[test]
-        fn test_loop_program() {
+        fn test_partial_memwrites_only_marks_written_cells() {
             let program = vec![
-                Instruction::Push(5),
-                Instruction::SubS(1),
-                Instruction::Jnz("1".to_string()),
+                Instruction::Push(9),
+                Instruction::MemWriteS(0, 2), // Stack runs out after one value
+                Instruction::MemRead(0),
+                Instruction::MemRead(1),
                 Instruction::Ret,
             ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![0]);
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::UninitializedRead { pc: 3, addr: 1 });
         }
 
         #[test]
-        fn test_jiz_jump() {
-            let program = vec![
-                Instruction::Push(0),
-                Instruction::Jiz("3".to_string()), // Jump to RET if zero (which it is)
-                Instruction::Push(99), // This should be skipped
-                Instruction::Ret,
+        fn test_uninitialized_read_warn_continues() {
+            let program = vec![Instruction::MemRead(9), Instruction::Ret];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Warn));
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![0]);
+        }
+
+        #[test]
+        fn test_load_data_counts_as_written() {
+            let data = [
+                DataBlock { start: 0, values: vec![7, 8] },
+                DataBlock { start: MEMORY_SIZE - 1, values: vec![1, 2, 3] },
             ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![0]); // Should not push 99
+            let program = vec![Instruction::MemRead(1), Instruction::MemRead(MEMORY_SIZE as i32 - 1), Instruction::MemRead(2)];
+            let mut vm = Vm::with_config(program, uninit_config(UninitReadMode::Trap));
+            vm.load_data(&data);
+
+            // The cells past the end of memory are dropped, the rest are initialized
+            let error = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(vm.stack, vec![8, 1]);
+            assert_eq!(error, VmError::UninitializedRead { pc: 2, addr: 2 });
         }
 
         #[test]
-        fn test_jiz_no_jump() {
+        fn test_default_memory_is_zeroed() {
+            let vm = Vm::new(vec![]);
+            assert!(vm.mem.iter().all(|&cell| cell == 0));
+        }
+
+        #[test]
+        fn test_poisoned_memory_fill() {
+            let config = VmConfig { memory_fill: POISON_PATTERN, ..VmConfig::default() };
             let program = vec![
-                Instruction::Push(1),
-                Instruction::Jiz("3".to_string()), // Don't jump if not zero
-                Instruction::Push(99), // This should execute
+                Instruction::MemWrite(1, vec![5]),
+                Instruction::MemRead(0),
+                Instruction::MemRead(1),
                 Instruction::Ret,
             ];
-            let mut output = Vec::new();
-            let (stack, _) = execute(&program, &mut output);
-            assert_eq!(stack, vec![1, 99]); // Should push 99
+            let mut vm = Vm::with_config(program, config);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![POISON_PATTERN, 5]);
+            assert_eq!(vm.mem.len(), MEMORY_SIZE);
         }
-    }
-
-    mod memory_operations {
-        use super::*;
 
         #[test]
         fn test_memwrites() {
@@ -525,5 +3733,416 @@ mod tests {
             let printed = String::from_utf8(output).unwrap();
             assert_eq!(printed, "Hello!");
         }
+
+        #[test]
+        fn test_print_encodes_high_bytes_as_utf8() {
+            let program = vec![Instruction::MemWrite(0, vec![0xE9, 0x41, 0x1FF]), Instruction::Print(0, 3)];
+            let mut output = Vec::new();
+            execute(&program, &mut output);
+            assert_eq!(String::from_utf8(output).unwrap(), "éAÿ");
+        }
+
+        #[test]
+        fn test_raw_and_utf8_output() {
+            let program = vec![
+                Instruction::MemWrite(0, vec![0xE2, 0x82, 0xAC, 0, 0xFF]), // "€" then an invalid byte
+                Instruction::PrintStr(0),
+                Instruction::Print(0, 5),
+            ];
+            let run = |output_encoding| {
+                let mut vm = Vm::with_config(program.clone(), VmConfig { output_encoding, ..VmConfig::default() });
+                let mut output = Vec::new();
+                (vm.run(&mut output), output)
+            };
+
+            assert_eq!(run(OutputEncoding::Raw), (Ok(()), vec![0xE2, 0x82, 0xAC, 0xE2, 0x82, 0xAC, 0, 0xFF]));
+
+            let (result, output) = run(OutputEncoding::Utf8);
+            assert!(matches!(result, Err(VmError::InvalidOutput { pc: 2, .. })), "{:?}", result);
+            assert_eq!(output, "€".as_bytes());
+
+            assert_eq!(run(OutputEncoding::Hex), (Ok(()), b"e2 82 ac\ne2 82 ac 00 ff\n".to_vec()));
+        }
+
+        #[test]
+        fn test_memwrite_drops_values_past_the_end() {
+            let end = MEMORY_SIZE as i32;
+            let (_stack, mem) = execute(&[Instruction::MemWrite(end - 2, vec![1, 2, 3])], &mut Vec::new());
+            assert_eq!(mem[MEMORY_SIZE - 2..], [1, 2]);
+        }
+
+        #[test]
+        fn test_print_str() {
+            let program = vec![
+                Instruction::MemWrite(10, vec![104, 105, 0, 120]), // "hi\0x"
+                Instruction::PrintStr(10),
+                Instruction::PrintStr(12),
+                Instruction::Ret,
+            ];
+            let mut output = Vec::new();
+            execute(&program, &mut output);
+            assert_eq!(output, b"hi");
+
+            // Without a terminator the rest of memory is printed
+            let mut vm = Vm::with_config(vec![Instruction::PrintStr(2046)], VmConfig { memory_fill: 65, ..VmConfig::default() });
+            let mut output = Vec::new();
+            vm.run(&mut output).unwrap();
+            assert_eq!(output, b"AA");
+        }
+
+        #[test]
+        fn test_print_int() {
+            let program = vec![Instruction::Push(7), Instruction::Push(-42), Instruction::PrintInt, Instruction::PrintInt];
+            let mut vm = Vm::new(program);
+            let mut output = Vec::new();
+
+            assert_eq!(vm.run(&mut output), Ok(()));
+            assert_eq!(output, b"-427");
+            assert!(vm.stack.is_empty());
+
+            let mut vm = Vm::new(vec![Instruction::PrintInt]);
+            assert_eq!(vm.run(&mut output), Err(VmError::StackUnderflow { pc: 0 }));
+        }
+    }
+
+    mod provenance {
+        use super::*;
+
+        fn tracked_vm(program: Vec<Instruction>) -> Vm {
+            let config = VmConfig { track_provenance: true, ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            let _ = vm.run(&mut Vec::new());
+            vm
+        }
+
+        #[test]
+        fn test_origins_follow_stack_operations() {
+            let vm = tracked_vm(vec![
+                Instruction::Push(1),
+                Instruction::Push(2),
+                Instruction::Push(3),
+                Instruction::Swap,
+                Instruction::Dup,
+                Instruction::Add,
+                Instruction::MemRead(0),
+            ]);
+
+            assert_eq!(vm.stack, vec![1, 3, 4, 0]);
+            assert_eq!(vm.stack_origin(0), Some(6));
+            assert_eq!(vm.stack_origin(1), Some(5));
+            assert_eq!(vm.stack_origin(2), Some(2));
+            assert_eq!(vm.stack_origin(3), Some(0));
+            assert_eq!(vm.stack_origin(4), None);
+        }
+
+        #[test]
+        fn test_missing_operands_keep_origins() {
+            let vm = tracked_vm(vec![Instruction::Push(1), Instruction::Add, Instruction::MemWriteS(4000, 1)]);
+
+            assert_eq!(vm.stack, vec![1]);
+            assert_eq!(vm.stack_origin(0), Some(0));
+        }
+
+        #[test]
+        fn test_trap_operand_names_jump_target() {
+            let vm = tracked_vm(vec![Instruction::Push(9), Instruction::Null, Instruction::JmpDyn]);
+            let err = VmError::InvalidJumpTarget { pc: 2, target: 9 };
+            assert_eq!(vm.trap_operand(&err), Some(("jump target", 0)));
+        }
+
+        #[test]
+        fn test_untracked_and_replaced_stacks_have_no_origins() {
+            let mut vm = Vm::new(vec![Instruction::Push(1)]);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack_origin(0), None);
+
+            let mut vm = tracked_vm(vec![Instruction::Push(1)]);
+            vm.replace_stack(vec![5, 6]);
+            assert_eq!(vm.stack_origin(0), None);
+        }
+    }
+
+    mod tracing {
+        use super::*;
+
+        #[test]
+        fn test_trace_reports_every_step() {
+            let program = vec![Instruction::Push(1), Instruction::Jmp(3), Instruction::Push(2), Instruction::Ret];
+            let mut vm = Vm::new(program);
+            let mut steps = Vec::new();
+            vm.run_traced(&mut Vec::new(), &mut |event: &TraceEvent| {
+                steps.push((event.pc, event.stack_after.to_vec()));
+            })
+            .unwrap();
+
+            assert_eq!(steps, vec![(0, vec![1]), (1, vec![1]), (3, vec![1])]);
+        }
+
+        #[test]
+        fn test_trapping_step_is_not_traced() {
+            let mut vm = Vm::new(vec![Instruction::Push(1), Instruction::Call(7)]);
+            let mut traced = Vec::new();
+            let err = vm.run_traced(&mut Vec::new(), &mut |event: &TraceEvent| traced.push(event.pc)).unwrap_err();
+
+            assert_eq!(err.pc(), 1);
+            assert_eq!(traced, vec![0]);
+        }
+
+        #[test]
+        #[cfg(feature = "assembler")]
+        fn test_profile_counts_steps_per_phase() {
+            let program = crate::spliter::parse_program("PROFBEGIN all\nPUSH 3\nloop:\nPROFBEGIN body\nSUBS 1\nPROFEND body\nJNZ loop\nPROFEND all").unwrap();
+            let mut vm = Vm::new(program.instructions);
+            vm.run(&mut Vec::new()).unwrap();
+
+            let stats: Vec<_> = vm.profile().phases().map(|(phase, stats)| (phase, stats.entries, stats.steps)).collect();
+            assert_eq!(stats, vec![(0, 1, 14), (1, 3, 6)]);
+
+            vm.reset();
+            assert!(vm.profile().is_empty());
+        }
+
+        #[test]
+        #[cfg(feature = "assembler")]
+        fn test_phase_over_budget_warns_or_traps() {
+            let program = crate::spliter::parse_program(".budget body 5\nPUSH 3\nloop:\nPROFBEGIN body\nSUBS 1\nPROFEND body\nJNZ loop").unwrap();
+            let budgets = &program.debug_info.budgets;
+
+            let mut vm = Vm::new(program.instructions.clone());
+            for (&phase, &steps) in budgets {
+                vm.set_phase_budget(phase, steps);
+            }
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.profile().stats(0).map(|stats| stats.steps), Some(6));
+
+            let mut vm = Vm::with_config(program.instructions.clone(), VmConfig { strict: true, ..VmConfig::default() });
+            for (&phase, &steps) in budgets {
+                vm.set_phase_budget(phase, steps);
+            }
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err, VmError::BudgetExceeded { pc: 3, phase: 0, steps: 6, budget: 5 });
+        }
+    }
+
+    mod print_interceptors {
+        use super::*;
+
+        #[test]
+        fn test_interceptors_route_by_start_address() {
+            let program = vec![
+                Instruction::MemWrite(0, vec![97, 98, 99, 100]),
+                Instruction::Print(0, 1),
+                Instruction::Print(1, 2),
+                Instruction::Print(3, 1),
+            ];
+            let first: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+            let overlapping: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+
+            let mut vm = Vm::new(program);
+            vm.intercept_print(1..3, first.clone());
+            vm.intercept_print(0..3, overlapping.clone());
+            let mut output = Vec::new();
+            vm.run(&mut output).unwrap();
+
+            // Print 1 2 starts inside the first range and keeps going past its end
+            assert_eq!(*first.lock().unwrap(), b"bc");
+            assert_eq!(*overlapping.lock().unwrap(), b"a");
+            assert_eq!(output, b"d");
+        }
+    }
+
+    #[cfg(feature = "assembler")]
+    mod self_modifying {
+        use super::*;
+        use crate::encoding::encode_word;
+        use crate::spliter::split_instructions;
+
+        fn config() -> VmConfig {
+            VmConfig { self_modifying: true, ..VmConfig::default() }
+        }
+
+        #[test]
+        fn test_code_write_patches_later_instruction() {
+            let word = encode_word(&Instruction::Push(42)).unwrap();
+            let source = format!("PUSH 3\nPUSH {}\nCODEWRITE\nPUSH 1\nRET", word);
+            let mut vm = Vm::with_config(split_instructions(&source).unwrap(), config());
+            vm.run(&mut Vec::new()).unwrap();
+
+            assert_eq!(vm.stack, vec![42]);
+            assert_eq!(vm.program[3], Instruction::Push(42));
+            assert_eq!(vm.code_version, 1);
+        }
+
+        #[test]
+        fn test_code_read_copies_instruction_word() {
+            let program = split_instructions("PUSH 2\nCODEREAD\nSUBS 5").unwrap();
+            let mut vm = Vm::with_config(program, config());
+            vm.run(&mut Vec::new()).unwrap();
+
+            assert_eq!(vm.stack, vec![encode_word(&Instruction::SubS(5)).unwrap() - 5]);
+        }
+
+        #[test]
+        fn test_code_access_is_opt_in() {
+            let mut vm = Vm::new(split_instructions("PUSH 0\nCODEREAD").unwrap());
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+
+            assert_eq!(
+                err,
+                VmError::InvalidCodeAccess { pc: 1, index: 0, reason: "self-modifying code is disabled".to_string() }
+            );
+        }
+
+        #[test]
+        fn test_code_write_is_verified() {
+            let jump = encode_word(&Instruction::Jmp(50)).unwrap();
+            let source = format!("PUSH 0\nPUSH {}\nCODEWRITE", jump);
+            let mut vm = Vm::with_config(split_instructions(&source).unwrap(), config());
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert!(err.to_string().ends_with("'JMP 50' jumps outside the program"));
+
+            let mut vm = Vm::with_config(split_instructions("PUSH 0\nPUSH -1\nCODEWRITE").unwrap(), config());
+            assert!(matches!(vm.run(&mut Vec::new()), Err(VmError::InvalidCodeAccess { index: 0, .. })));
+            assert_eq!(vm.program[0], Instruction::Push(0));
+        }
+    }
+
+    #[cfg(feature = "assembler")]
+    mod input {
+        use super::*;
+        use crate::spliter::split_instructions;
+        use std::io::Cursor;
+
+        #[test]
+        fn test_read_line_stores_bytes_and_pushes_length() {
+            let mut vm = Vm::new(split_instructions("READLINE 10 4\nREADLINE 20 8\nREADLINE 30 8").unwrap());
+            vm.set_input(Cursor::new("hello\r\nok\n"));
+            vm.run(&mut Vec::new()).unwrap();
+
+            // Long lines are cut to the buffer; the end of input reads as -1
+            assert_eq!(vm.stack, vec![4, 2, -1]);
+            assert_eq!(vm.mem[10..15], [104, 101, 108, 108, 0]);
+            assert_eq!(vm.mem[20..23], [111, 107, 0]);
+        }
+
+        #[test]
+        fn test_read_line_marks_cells_initialized() {
+            let config = VmConfig { uninit_reads: UninitReadMode::Trap, ..VmConfig::default() };
+            let mut vm = Vm::with_config(split_instructions("READLINE 0 8\nPRINT 0 2\nPRINT 0 3").unwrap(), config);
+            vm.set_input(Cursor::new("hi"));
+            let mut output = Vec::new();
+
+            assert_eq!(vm.run(&mut output), Err(VmError::UninitializedRead { pc: 2, addr: 2 }));
+            assert_eq!(output, b"hi");
+        }
+
+        #[test]
+        fn test_read_int_traps_on_bad_input() {
+            let mut vm = Vm::new(split_instructions("READINT\nREADINT").unwrap());
+            vm.set_input(Cursor::new(" -7 \nseven\n"));
+
+            let err = vm.run(&mut Vec::new()).unwrap_err();
+            assert_eq!(err.to_string(), "invalid input at instruction 1: 'seven' is not a number");
+            assert_eq!(vm.stack, vec![-7]);
+
+            let mut vm = Vm::new(split_instructions("READINT").unwrap());
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::InvalidInput { pc: 0, reason: "end of input".to_string() }));
+        }
+    }
+
+    mod reuse {
+        use super::*;
+
+        #[test]
+        fn test_reset_keeps_host_setup() {
+            let program = vec![Instruction::MemWrite(3, vec![1]), Instruction::HostCall("seven".to_string()), Instruction::MemRead(4)];
+            let config = VmConfig { memory_fill: 9, uninit_reads: UninitReadMode::Trap, ..VmConfig::default() };
+            let mut vm = Vm::with_config(program, config);
+            vm.register_host_fn("seven", |stack| {
+                stack.push(7);
+                Ok(())
+            });
+            assert_eq!(vm.run(&mut Vec::new()), Err(VmError::UninitializedRead { pc: 2, addr: 4 }));
+
+            vm.reset();
+            assert_eq!((vm.pc, vm.steps, vm.high_water), (0, 0, HighWater::default()));
+            assert!(vm.mem.iter().all(|&cell| cell == 9));
+            assert!(vm.initialized.iter().all(|&written| !written));
+
+            vm.program_mut()[2] = Instruction::MemRead(3);
+            vm.run(&mut Vec::new()).unwrap();
+            assert_eq!(vm.stack, vec![7, 1]);
+        }
+
+        #[test]
+        fn test_pool_reuses_clean_vms() {
+            let mut pool = VmPool::new(VmConfig::default(), 1);
+            let mut vm = pool.get(vec![Instruction::HostCall("seven".to_string())]);
+            vm.register_host_fn("seven", |stack| {
+                stack.push(7);
+                Ok(())
+            });
+            vm.run(&mut Vec::new()).unwrap();
+            let mem_ptr = vm.mem.as_ptr();
+            pool.put(vm);
+
+            // The reused VM has the same memory but none of the previous host functions
+            let mut vm = pool.get(vec![Instruction::HostCall("seven".to_string())]);
+            assert_eq!(vm.mem.as_ptr(), mem_ptr);
+            assert!(matches!(vm.run(&mut Vec::new()), Err(VmError::UnknownHostFunction { .. })));
+
+            pool.put(Vm::new(vec![]));
+            pool.put(vm);
+            assert_eq!(pool.idle(), 1);
+            pool.put(Vm::with_config(vec![], VmConfig { memory_fill: 1, ..VmConfig::default() }));
+            assert_eq!(pool.idle(), 1);
+        }
+    }
+
+    mod strict {
+        use super::*;
+
+        fn run_strict(program: Vec<Instruction>) -> (Vm, Result<(), VmError>) {
+            let mut vm = Vm::with_config(program, VmConfig { strict: true, ..VmConfig::default() });
+            let result = vm.run(&mut Vec::new());
+            (vm, result)
+        }
+
+        #[test]
+        fn test_underflow_traps() {
+            let (vm, result) = run_strict(vec![Instruction::Push(1), Instruction::Add]);
+            assert_eq!(result, Err(VmError::StackUnderflow { pc: 1 }));
+            assert_eq!((vm.pc, vm.stack), (1, vec![1]));
+
+            let (_, result) = run_strict(vec![Instruction::Jiz(0)]);
+            assert_eq!(result, Err(VmError::StackUnderflow { pc: 0 }));
+        }
+
+        #[test]
+        fn test_division_by_zero_traps() {
+            let (vm, result) = run_strict(vec![Instruction::Push(6), Instruction::Push(0), Instruction::Div]);
+            assert_eq!(result, Err(VmError::DivisionByZero { pc: 2 }));
+            assert_eq!(vm.stack, vec![6, 0]);
+
+            let (_, result) = run_strict(vec![Instruction::Push(6), Instruction::ModS(0)]);
+            assert_eq!(result, Err(VmError::DivisionByZero { pc: 1 }));
+        }
+
+        #[test]
+        fn test_out_of_bounds_memory_traps() {
+            let end = MEMORY_SIZE as i32;
+            let (_, result) = run_strict(vec![Instruction::MemWrite(end - 1, vec![1, 2])]);
+            assert_eq!(result, Err(VmError::MemoryOutOfBounds { pc: 0, addr: end - 1, len: 2 }));
+
+            let (_, result) = run_strict(vec![Instruction::MemRead(-1)]);
+            assert_eq!(result, Err(VmError::MemoryOutOfBounds { pc: 0, addr: -1, len: 1 }));
+        }
+
+        #[test]
+        fn test_valid_programs_run_unchanged() {
+            let (vm, result) = run_strict(vec![Instruction::Push(7), Instruction::DivS(2), Instruction::MemWrite(0, vec![5]), Instruction::MemRead(0), Instruction::Add]);
+            assert_eq!(result, Ok(()));
+            assert_eq!(vm.stack, vec![8]);
+        }
     }
 }
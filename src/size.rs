@@ -0,0 +1,240 @@
+use crate::assembler::{assemble_program, encoded_size};
+use crate::instruction::Instruction;
+use crate::program::Program;
+use std::collections::HashSet;
+
+/// How many of the largest `MEMWRITE`s a report lists.
+const LARGEST_WRITES: usize = 5;
+
+/// The instructions of one group of the instruction set and the bytes they take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSize {
+    pub class: &'static str,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// A change to the instruction at `index` that would make the bytecode smaller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Saving {
+    pub index: usize,
+    pub bytes: usize,
+    pub suggestion: String,
+}
+
+/// Where the bytes of a program's bytecode go, see [`SizeReport::new`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    /// The size of the whole bytecode file.
+    pub total: usize,
+    /// The header with the requirements and capabilities that follow it.
+    pub header: usize,
+    pub instructions: usize,
+    pub data: usize,
+    /// The bytes of each group of instructions, largest first.
+    pub classes: Vec<ClassSize>,
+    /// The indices of the largest `MEMWRITE`s and their sizes, largest first.
+    pub largest_writes: Vec<(usize, usize)>,
+    /// Places where a cheaper construct does the same, largest saving first.
+    pub savings: Vec<Saving>,
+}
+
+impl SizeReport {
+    /// Measures the bytecode `program` assembles to.
+    ///
+    /// Two kinds of savings are suggested: a `MEMWRITE` of one value repeated, which a
+    /// `MEMSET` does in a few bytes, and a `MEMWRITE` continuing where the one before it
+    /// stopped, which could be merged into it unless something jumps to it.
+    ///
+    /// ```
+    /// use vortex_vm::size::SizeReport;
+    /// use vortex_vm::spliter::parse_program;
+    ///
+    /// let program = parse_program("MEMWRITE 0 7 7 7 7 7 7 7 7 7 7\nPRINT 0 10").unwrap();
+    /// let report = SizeReport::new(&program).unwrap();
+    /// assert_eq!((report.total, report.header, report.instructions, report.data), (28, 12, 16, 0));
+    /// assert_eq!(report.savings[0].bytes, 9);
+    /// ```
+    pub fn new(program: &Program) -> Result<Self, String> {
+        let total = assemble_program(program)?.len();
+        let without_data = Program { data: Vec::new(), ..program.clone() };
+        let data = total - assemble_program(&without_data)?.len();
+        let sizes: Vec<usize> = program.instructions.iter().map(encoded_size).collect();
+        let instructions: usize = sizes.iter().sum();
+
+        let mut classes: Vec<ClassSize> = Vec::new();
+        for (instruction, &bytes) in program.instructions.iter().zip(&sizes) {
+            let class = class_of(instruction);
+            match classes.iter_mut().find(|size| size.class == class) {
+                Some(size) => {
+                    size.count += 1;
+                    size.bytes += bytes;
+                }
+                None => classes.push(ClassSize { class, count: 1, bytes }),
+            }
+        }
+        classes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.class.cmp(b.class)));
+
+        let mut largest_writes: Vec<(usize, usize)> =
+            (0..sizes.len()).filter(|&index| matches!(program.instructions[index], Instruction::MemWrite(..))).map(|index| (index, sizes[index])).collect();
+        largest_writes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        largest_writes.truncate(LARGEST_WRITES);
+
+        let mut savings = savings(&program.instructions, &sizes);
+        savings.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.index.cmp(&b.index)));
+
+        Ok(SizeReport { total, header: total - instructions - data, instructions, data, classes, largest_writes, savings })
+    }
+
+    /// Describes the report, naming instructions by their location in `program`.
+    pub fn describe(&self, program: &Program) -> String {
+        let location = |index: usize| program.debug_info.describe_location(index);
+        let mut text = format!("Total: {} bytes (header {}, instructions {}, data {})\n", self.total, self.header, self.instructions, self.data);
+
+        if !self.classes.is_empty() {
+            text.push_str("\nBy class:\n");
+            for size in &self.classes {
+                let share = size.bytes as f64 * 100.0 / self.total as f64;
+                text.push_str(&format!("    {:<16} {:>6} instruction(s) {:>8} bytes {:>5.1}%\n", size.class, size.count, size.bytes, share));
+            }
+        }
+
+        if !self.largest_writes.is_empty() {
+            text.push_str("\nLargest MEMWRITEs:\n");
+            for &(index, bytes) in &self.largest_writes {
+                let values = match &program.instructions[index] {
+                    Instruction::MemWrite(_, values) => values.len(),
+                    _ => 0,
+                };
+                text.push_str(&format!("    {}: {} bytes, {} value(s)\n", location(index), bytes, values));
+            }
+        }
+
+        if !self.savings.is_empty() {
+            text.push_str("\nPotential savings:\n");
+            for saving in &self.savings {
+                text.push_str(&format!("    {}: {} bytes {}\n", location(saving.index), saving.bytes, saving.suggestion));
+            }
+            let total: usize = self.savings.iter().map(|saving| saving.bytes).sum();
+            text.push_str(&format!("    Total: {} bytes ({:.1}%)\n", total, total as f64 * 100.0 / self.total as f64));
+        }
+        text
+    }
+}
+
+/// Names the group of the instruction set `instruction` belongs to, as in the README.
+fn class_of(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::Null
+        | Instruction::Push(_)
+        | Instruction::Dup
+        | Instruction::Swap
+        | Instruction::Rot
+        | Instruction::Over
+        | Instruction::Pick(_)
+        | Instruction::Depth
+        | Instruction::Clear
+        | Instruction::Pop => "stack",
+        Instruction::Ret
+        | Instruction::Halt(_)
+        | Instruction::Abort(..)
+        | Instruction::Jiz(_)
+        | Instruction::Jnz(_)
+        | Instruction::Jmp(_)
+        | Instruction::JmpDyn
+        | Instruction::Call(_)
+        | Instruction::HostCall(_)
+        | Instruction::Try(_)
+        | Instruction::EndTry
+        | Instruction::Throw => "control flow",
+        Instruction::AddS(_)
+        | Instruction::Add
+        | Instruction::SubS(_)
+        | Instruction::Sub
+        | Instruction::MultS(_)
+        | Instruction::Mult
+        | Instruction::DivS(_)
+        | Instruction::Div
+        | Instruction::ModS(_)
+        | Instruction::Mod => "arithmetic",
+        Instruction::Eq | Instruction::Neq | Instruction::Lt | Instruction::Gt | Instruction::Lte | Instruction::Gte => "comparison",
+        Instruction::Print(..) | Instruction::PrintInt | Instruction::PrintStr(_) | Instruction::ReadLine(..) | Instruction::ReadInt => "input/output",
+        Instruction::CodeRead | Instruction::CodeWrite => "self-modifying",
+        Instruction::PerfRead(_) | Instruction::VmInfo(_) | Instruction::ProfBegin(_) | Instruction::ProfEnd(_) => "introspection",
+        Instruction::Assert(_) | Instruction::AssertEq => "assertions",
+        _ => "memory",
+    }
+}
+
+/// Finds `MEMWRITE`s that a `MEMSET` or the `MEMWRITE` before them could replace.
+fn savings(instructions: &[Instruction], sizes: &[usize]) -> Vec<Saving> {
+    let targets: HashSet<u32> = instructions.iter().filter_map(Instruction::jump_target).collect();
+    let mut savings = Vec::new();
+    for (index, instruction) in instructions.iter().enumerate() {
+        let Instruction::MemWrite(addr, values) = instruction else {
+            continue;
+        };
+
+        // An empty MEMWRITE has no value to fill with
+        if let [first, rest @ ..] = values.as_slice()
+            && !rest.is_empty()
+            && rest.iter().all(|value| value == first)
+        {
+            let memset_size = encoded_size(&Instruction::MemSet(*addr, i32::try_from(values.len()).unwrap_or(i32::MAX), *first));
+            if sizes[index] > memset_size {
+                savings.push(Saving {
+                    index,
+                    bytes: sizes[index] - memset_size,
+                    suggestion: format!("with MEMSET {} {} {}, since every value is {}", addr, values.len(), first, first),
+                });
+            }
+        }
+
+        if let Some(Instruction::MemWrite(previous_addr, previous)) = index.checked_sub(1).map(|previous| &instructions[previous])
+            && i64::from(*previous_addr) + previous.len() as i64 == i64::from(*addr)
+            && !targets.contains(&(index as u32))
+        {
+            let merged = encoded_size(&Instruction::MemWrite(*previous_addr, [previous.as_slice(), values].concat()));
+            let bytes = (sizes[index - 1] + sizes[index]).saturating_sub(merged);
+            if bytes > 0 {
+                savings.push(Saving { index, bytes, suggestion: "by merging into the MEMWRITE before it".to_string() });
+            }
+        }
+    }
+    savings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spliter::parse_program;
+
+    #[test]
+    fn test_report_sizes() {
+        let program = parse_program(".data 100\n.word 1 2 3\n.text\nPUSH 1\nPUSH 2\nADD\nMEMWRITE 0 104 105\nMEMWRITE 2 33\nPRINT 0 3\nRET").unwrap();
+        let report = SizeReport::new(&program).unwrap();
+
+        assert_eq!(report.total, 12 + report.instructions + report.data);
+        assert_eq!(report.instructions, 2 + 2 + 1 + 5 + 4 + 3 + 1);
+        assert_eq!(report.classes[0], ClassSize { class: "memory", count: 2, bytes: 9 });
+        assert_eq!(report.largest_writes, vec![(3, 5), (4, 4)]);
+        assert_eq!(report.savings, vec![Saving { index: 4, bytes: 3, suggestion: "by merging into the MEMWRITE before it".to_string() }]);
+
+        let text = report.describe(&program);
+        assert!(text.contains("line 8: 3 bytes by merging into the MEMWRITE before it"));
+    }
+
+    #[test]
+    fn test_jump_targets_are_not_merged() {
+        let program = parse_program("MEMWRITE 0 1\nagain:\nMEMWRITE 1 2\nJMP again").unwrap();
+        assert_eq!(SizeReport::new(&program).unwrap().savings, []);
+    }
+
+    #[test]
+    fn test_empty_memwrites_are_measured() {
+        for source in ["MEMWRITE 5", "MEMWRITE 5 \"\""] {
+            let program = parse_program(source).unwrap();
+            assert_eq!(SizeReport::new(&program).unwrap().savings, []);
+        }
+    }
+}
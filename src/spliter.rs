@@ -1,5 +1,25 @@
-use crate::instruction::Instruction;
+use crate::diagnostic;
+use crate::diagnostics::Level;
+use crate::instruction::{Instruction, Word};
+use crate::program::{DataBlock, DebugInfo, Program, Region, Requirements};
+use crate::run::{local_slot, MAX_MEMORY_SIZE};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Jump target the parser gives references to labels that are never defined, before
+/// refusing them; it never ends up in a parsed program.
+pub const UNRESOLVED_TARGET: u32 = u32::MAX;
+
+/// Every instruction mnemonic the parser understands, in upper case.
+pub const MNEMONICS: &[&str] = &[
+    "NULL", "PUSH", "POP", "DUP", "SWAP", "ROT", "OVER", "PICK", "DEPTH", "CLEAR", "RET", "HALT", "ABORT", "JIZ", "JNZ", "JMP", "JMPDYN", "CALL", "HOSTCALL", "TRY", "ENDTRY", "THROW", "ADD", "ADDS",
+    "SUB", "SUBS", "MULT", "MULTS", "DIV", "DIVS", "MOD", "MODS", "EQ", "NEQ", "LT", "GT", "LTE", "GTE", "MEMWRITE",
+    "MEMWRITES", "MEMREAD", "LOAD", "STORE", "LOADLOCAL", "STORELOCAL", "MEMCOPY", "MEMSET", "MEMCMP",
+    "GROW", "MEMSIZE", "ALLOC", "FREE", "PROTECT", "PRINT", "PRINTINT", "PRINTSTR", "READLINE", "READINT", "CODEREAD", "CODEWRITE", "PERFREAD", "VMINFO", "PROFBEGIN", "PROFEND",
+    "ASSERT", "ASSERTEQ",
+];
 
 /// Parses assembly code into a sequence of instructions with label resolution.
 ///
@@ -7,6 +27,11 @@ use std::collections::HashMap;
 /// 1. First pass: Collect all label definitions and their instruction positions
 /// 2. Second pass: Parse instructions and resolve label references to addresses
 ///
+/// Unknown instructions and invalid operands are logged and left out, but jumps and calls to
+/// a label that is never defined or to an index past the end of the program are an error,
+/// described like [`try_parse_program`] does, and so is a `.requires` amount no run could
+/// meet.
+///
 /// # Examples
 ///
 /// Basic instruction parsing:
@@ -16,7 +41,7 @@ use std::collections::HashMap;
 /// use vortex_vm::instruction::Instruction;
 ///
 /// let assembly = "PUSH 42\nADD\nRET";
-/// let instructions = split_instructions(assembly);
+/// let instructions = split_instructions(assembly).unwrap();
 ///
 /// assert_eq!(instructions, vec![
 ///     Instruction::Push(42),
@@ -38,12 +63,13 @@ use std::collections::HashMap;
 ///     JNZ main
 ///     RET
 /// ";
-/// let instructions = split_instructions(assembly);
+/// let instructions = split_instructions(assembly).unwrap();
+///
+/// // The label "main" is resolved to instruction index 0
+/// assert_eq!(instructions[2], Instruction::Jnz(0));
 ///
-/// // The label "main" should be resolved to address "0"
-/// if let Instruction::Jnz(target) = &instructions[2] {
-///     assert_eq!(target, "0");
-/// }
+/// let err = split_instructions("JNZ missing").unwrap_err();
+/// assert!(err.starts_with("unknown label or invalid address 'missing' at line 1"));
 /// ```
 ///
 /// Memory operations with comments:
@@ -59,7 +85,7 @@ use std::collections::HashMap;
 ///     Print 0 5
 ///     RET
 /// ";
-/// let instructions = split_instructions(assembly);
+/// let instructions = split_instructions(assembly).unwrap();
 ///
 /// assert_eq!(instructions, vec![
 ///     Instruction::MemWrite(0, vec![72, 101, 108, 108, 111]),
@@ -67,31 +93,498 @@ use std::collections::HashMap;
 ///     Instruction::Ret,
 /// ]);
 /// ```
-pub fn split_instructions(instructions: &str) -> Vec<Instruction> {
-    let mut result = Vec::new();
+pub fn split_instructions(instructions: &str) -> Result<Vec<Instruction>, String> {
+    split_instructions_with_lines(instructions).map(|(instructions, _)| instructions)
+}
+
+/// Parses assembly code like [`split_instructions`], additionally returning the 1-based
+/// source line of each parsed instruction so tools can map instruction indices back to source.
+///
+/// ```
+/// use vortex_vm::spliter::split_instructions_with_lines;
+///
+/// let (instructions, lines) = split_instructions_with_lines("; header\nPUSH 1\n\nRET").unwrap();
+/// assert_eq!(instructions.len(), 2);
+/// assert_eq!(lines, vec![2, 4]);
+/// ```
+pub fn split_instructions_with_lines(instructions: &str) -> Result<(Vec<Instruction>, Vec<usize>), String> {
+    let parsed = parse_source(instructions);
+    report_errors(instructions, &parsed.errors);
+    check_fatal_errors(instructions, &parsed)?;
+    Ok((parsed.instructions, parsed.lines))
+}
+
+/// Parses assembly code into a [`Program`], keeping debug information such as
+/// `.region NAME start len` declarations alongside the instructions.
+///
+/// Like [`split_instructions`], only jumps and calls to unknown labels or past the end of
+/// the program and impossible `.requires` amounts are an error; other problems are logged.
+///
+/// `.align N` pads the program with `NULL`s until the next instruction index is a multiple
+/// of `N`; the padding is recorded in [`DebugInfo::padding`].
+///
+/// `NAME EQU value` and `%define NAME value` define constants that can be used in place of
+/// any number in operands and directives.
+///
+/// `.requires mem N` and `.requires fuel N` record the memory size and step limit the
+/// program needs in [`Program::requirements`].
+///
+/// Lines after `.data [ADDR]` lay out the initial memory image in [`Program::data`] instead
+/// of instructions, until `.text` switches back. Data starts at `ADDR`, or where the previous
+/// data section ended, and is declared with `.byte`, `.word`, `.string` and `.space`.
+///
+/// ```
+/// use vortex_vm::spliter::parse_program;
+///
+/// let program = parse_program(".data 10\n.string \"Hi\"\n.word 300\n.text\nPRINTSTR 10").unwrap();
+/// assert_eq!(program.data[0].start, 10);
+/// assert_eq!(program.data[0].values, vec![72, 105, 0, 300]);
+/// assert_eq!(program.instructions.len(), 1);
+/// ```
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::spliter::parse_program;
+///
+/// let program = parse_program("PUSH 1\n.align 4\nhandler:\nRET\nJMP handler").unwrap();
+/// assert_eq!(program.instructions[1..4], [Instruction::Null, Instruction::Null, Instruction::Null]);
+/// assert_eq!(program.instructions[5], Instruction::Jmp(4));
+/// assert_eq!(program.debug_info.padding, vec![1..4]);
+/// ```
+pub fn parse_program(source: &str) -> Result<Program, String> {
+    let parsed = parse_source(source);
+    report_errors(source, &parsed.errors);
+    check_fatal_errors(source, &parsed)?;
+    Ok(program_from(source, parsed))
+}
+
+/// Parses assembly code like [`parse_program`], but fails on unknown instructions, invalid
+/// operands, and jumps or calls to a label that is never defined or to an index past the
+/// end of the program.
+///
+/// The error describes every such problem with its line number, the offending line and a
+/// caret under the problem. Jumping to the index just past the last instruction is allowed;
+/// it ends the program like falling off the end does.
+///
+/// ```
+/// use vortex_vm::spliter::try_parse_program;
+///
+/// assert!(try_parse_program("loop:\nJMP loop\nJMP end\nend:").is_ok());
+///
+/// let err = try_parse_program("PUSH 1\nFOO 2").unwrap_err();
+/// assert_eq!(err, "unknown instruction 'FOO' at line 2\n    FOO 2\n    ^^^");
+/// ```
+pub fn try_parse_program(source: &str) -> Result<Program, String> {
+    try_parse_program_with(source, |line| format!("line {}", line))
+}
+
+/// Parses assembly code like [`try_parse_program`], describing the location of each error
+/// with `locate`, which receives its 1-based line number in `source`.
+///
+/// ```
+/// use vortex_vm::spliter::try_parse_program_with;
+///
+/// let err = try_parse_program_with("JIZ nowhere", |line| format!("program.vvm:{}", line)).unwrap_err();
+/// assert_eq!(err, "unknown label or invalid address 'nowhere' at program.vvm:1\n    JIZ nowhere\n        ^^^^^^^");
+/// ```
+pub fn try_parse_program_with(source: &str, locate: impl Fn(usize) -> String) -> Result<Program, String> {
+    let mut parsed = parse_source(source);
+    if !parsed.errors.is_empty() || !parsed.fatal_errors.is_empty() {
+        let mut errors = std::mem::take(&mut parsed.errors);
+        errors.append(&mut parsed.fatal_errors);
+        errors.sort_by_key(|error| error.line);
+        return Err(render_errors(source, &errors, locate));
+    }
+    Ok(program_from(source, parsed))
+}
+
+/// A problem with one line of assembly source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceError {
+    /// The 1-based line number in the parsed source.
+    pub line: usize,
+    /// The byte range within the line to point at.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl SourceError {
+    /// Formats the error as `message at location`, followed by `source_line` and a caret
+    /// under [`SourceError::span`].
+    ///
+    /// ```
+    /// use vortex_vm::spliter::SourceError;
+    ///
+    /// let error = SourceError { line: 3, span: 6..9, message: "unknown instruction 'FOO'".to_string() };
+    /// assert_eq!(
+    ///     error.render("main.vvm:3", "\tPUSH FOO"),
+    ///     "unknown instruction 'FOO' at main.vvm:3\n    \tPUSH FOO\n    \t     ^^^"
+    /// );
+    /// ```
+    pub fn render(&self, location: &str, source_line: &str) -> String {
+        let source_line = source_line.trim_end();
+        let start = self.span.start.min(source_line.len());
+        let end = self.span.end.clamp(start, source_line.len());
+
+        // Keep tabs in the indentation so the caret lines up however wide they are displayed
+        let indent: String = source_line[..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let width = source_line[start..end].chars().count().max(1);
+        format!("{} at {}\n    {}\n    {}{}", self.message, location, source_line, indent, "^".repeat(width))
+    }
+}
+
+fn program_from(source: &str, parsed: ParsedSource) -> Program {
+    let mut debug_info = parse_debug_info(source);
+    debug_info.lines = parsed.lines;
+    debug_info.padding = parsed.padding;
+    debug_info.labels = parsed.labels;
+    debug_info.phases = parsed.phases;
+
+    Program { instructions: parsed.instructions, data: parsed.data, requirements: parsed.requirements, debug_info }
+}
+
+/// Everything the two parsing passes produce.
+struct ParsedSource {
+    instructions: Vec<Instruction>,
+    lines: Vec<usize>,
+    padding: Vec<Range<usize>>,
+    data: Vec<DataBlock>,
+    requirements: Requirements,
+    /// Problems that make the program unsafe to run, such as unknown instructions.
+    errors: Vec<SourceError>,
+    /// Jumps and calls to labels that are never defined or past the end of the program, and
+    /// `.requires` amounts no run could meet, which even the lenient entry points refuse.
+    fatal_errors: Vec<SourceError>,
+    /// The index of every jump and call, with the span of its target in the source line.
+    jumps: Vec<(usize, Range<usize>)>,
+    labels: Vec<(String, usize)>,
+    phases: Vec<String>,
+}
+
+/// Describes every error in `errors` with the line of `source` it is on.
+fn render_errors(source: &str, errors: &[SourceError], locate: impl Fn(usize) -> String) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let rendered: Vec<String> = errors
+        .iter()
+        .map(|error| error.render(&locate(error.line), lines.get(error.line - 1).copied().unwrap_or_default()))
+        .collect();
+    rendered.join("\n")
+}
+
+/// Fails with the errors of `parsed` that even the lenient entry points refuse, if there
+/// are any.
+fn check_fatal_errors(source: &str, parsed: &ParsedSource) -> Result<(), String> {
+    if parsed.fatal_errors.is_empty() {
+        return Ok(());
+    }
+    Err(render_errors(source, &parsed.fatal_errors, |line| format!("line {}", line)))
+}
+
+fn report_errors(source: &str, errors: &[SourceError]) {
+    let lines: Vec<&str> = source.lines().collect();
+    for error in errors {
+        let source_line = lines.get(error.line - 1).copied().unwrap_or_default();
+        diagnostic!(Level::Error, "{}", error.render(&format!("line {}", error.line), source_line));
+    }
+}
+
+/// The part of the program the lines being parsed belong to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Text,
+    Data,
+}
+
+fn parse_source(source: &str) -> ParsedSource {
+    let mut parsed = ParsedSource {
+        instructions: Vec::new(),
+        lines: Vec::new(),
+        padding: Vec::new(),
+        data: Vec::new(),
+        requirements: Requirements::default(),
+        errors: Vec::new(),
+        fatal_errors: Vec::new(),
+        jumps: Vec::new(),
+        labels: Vec::new(),
+        phases: collect_phases(source),
+    };
     let mut labels = HashMap::new();
 
-    // Phase 1: Collect all labels and map them to instruction indices
-    collect_labels(instructions, &mut labels);
+    // Phase 1: Collect all constants and labels and map labels to instruction indices
+    let (constants, problems) = collect_constants(source);
+    for problem in problems {
+        diagnostic!(Level::Warn, "{}", problem);
+    }
+    collect_labels(source, &constants, &mut labels);
+    let data_labels = collect_data_labels(source, &constants);
+    diagnostic!(
+        Level::Debug,
+        "Found {} constants, {} labels and {} data labels",
+        constants.len(),
+        labels.len(),
+        data_labels.len()
+    );
+
+    // Phase 2: Parse instructions and resolve constant and label references
+    let phases = parsed.phases.iter().enumerate().map(|(id, name)| (name.clone(), id as Word)).collect();
+    parse_instructions(source, &constants, &labels, &data_labels, &phases, &mut parsed);
+    parsed.data.sort_by_key(|block| block.start);
+    check_jump_targets(&mut parsed);
+
+    parsed.labels = labels.into_iter().collect();
+    parsed.labels.sort_by(|(a_name, a), (b_name, b)| (a, a_name).cmp(&(b, b_name)));
+    parsed
+}
 
-    // Phase 2: Parse instructions and resolve label references
-    parse_instructions(instructions, &labels, &mut result);
+/// Collects the debug information declared by directives in `source` without parsing
+/// its instructions.
+///
+/// ```
+/// use vortex_vm::spliter::parse_debug_info;
+///
+/// let info = parse_debug_info(".region buffer 10 4 ; scratch space\nPUSH 1");
+/// assert_eq!(info.region_at(12).map(|r| r.name.as_str()), Some("buffer"));
+/// ```
+pub fn parse_debug_info(source: &str) -> DebugInfo {
+    let mut debug_info = DebugInfo::default();
+    let (constants, _) = collect_constants(source);
+    let phases = collect_phases(source);
 
-    // Phase 3: Replace label references with actual instruction indices
-    resolve_label_references(&mut result, &labels);
+    for line in source.lines() {
+        let clean_line = extract_code_portion(line);
 
-    result
+        if !is_directive(clean_line) {
+            continue;
+        }
+        let clean_line = &*substitute_constants(clean_line, &constants);
+
+        let parts: Vec<&str> = clean_line.split_whitespace().collect();
+        match parts[0].to_lowercase().as_str() {
+            // Handled while parsing instructions since they change instruction indices or data
+            ".align" | ".text" | ".data" | ".byte" | ".word" | ".string" | ".space" | ".requires" => {}
+            ".region" => match parse_region_directive(&parts) {
+                Some(region) => {
+                    // A later declaration with the same name replaces the earlier one
+                    debug_info.regions.retain(|existing| existing.name != region.name);
+                    debug_info.regions.push(region);
+                }
+                None => diagnostic!(Level::Warn, "Invalid region directive: {}", clean_line),
+            },
+            ".budget" => match parse_budget_directive(&parts, &phases) {
+                // The tightest budget declared for a phase applies
+                Some((phase, steps)) => {
+                    let budget = debug_info.budgets.entry(phase).or_insert(steps);
+                    *budget = (*budget).min(steps);
+                }
+                None => diagnostic!(Level::Warn, "Invalid budget directive: {}", clean_line),
+            },
+            _ => diagnostic!(Level::Warn, "Unknown directive: {}", clean_line),
+        }
+    }
+
+    debug_info
+}
+
+/// Parses a single line of assembly into an instruction, ignoring any trailing comment.
+///
+/// Returns `None` for blank lines, comments, label definitions and invalid instructions.
+/// There is no label table to resolve against, so jump targets must be numeric.
+///
+/// ```
+/// use vortex_vm::spliter::parse_line;
+/// use vortex_vm::instruction::Instruction;
+///
+/// assert_eq!(parse_line("push 7 ; seven"), Some(Instruction::Push(7)));
+/// assert_eq!(parse_line("loop:"), None);
+/// ```
+pub fn parse_line(line: &str) -> Option<Instruction> {
+    parse_line_with_labels(line, &HashMap::new())
+}
+
+/// Parses a single line of assembly like [`parse_line`], resolving jump targets against `labels`.
+///
+/// A jump or call to a label missing from `labels` is reported and gives `None`, like an
+/// unknown instruction.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::spliter::parse_line_with_labels;
+///
+/// let labels = HashMap::from([("loop".to_string(), 3)]);
+/// assert_eq!(parse_line_with_labels("JNZ loop", &labels), Some(Instruction::Jnz(3)));
+/// assert_eq!(parse_line_with_labels("JNZ done", &labels), None);
+/// ```
+pub fn parse_line_with_labels(line: &str, labels: &HashMap<String, usize>) -> Option<Instruction> {
+    let clean_line = extract_code_portion(line);
+
+    if clean_line.is_empty() || is_comment_line(clean_line) || is_label_definition(clean_line) || is_directive(clean_line) {
+        return None;
+    }
+
+    match parse_instruction_line(clean_line, labels) {
+        Ok(instruction) => {
+            if instruction.jump_target() == Some(UNRESOLVED_TARGET) {
+                diagnostic!(Level::Warn, "Unknown label or invalid address in '{}'", clean_line);
+                return None;
+            }
+            Some(instruction)
+        }
+        Err((message, _)) => {
+            diagnostic!(Level::Warn, "{}", message);
+            None
+        }
+    }
+}
+
+/// Returns the name defined by a label line such as `loop:`, ignoring any trailing comment.
+///
+/// ```
+/// use vortex_vm::spliter::label_definition;
+///
+/// assert_eq!(label_definition("  loop: ; top"), Some("loop".to_string()));
+/// assert_eq!(label_definition("JMP loop"), None);
+/// ```
+pub fn label_definition(line: &str) -> Option<String> {
+    let clean_line = extract_code_portion(line);
+    is_label_definition(clean_line).then(|| extract_label_name(clean_line))
+}
+
+/// Maps every label defined in `source` to the index of the instruction it labels.
+pub fn parse_labels(source: &str) -> HashMap<String, usize> {
+    let mut labels = HashMap::new();
+    collect_labels(source, &collect_constants(source).0, &mut labels);
+    labels
+}
+
+/// Maps every constant defined in `source` with `NAME EQU value` or `%define NAME value`
+/// to its value.
+///
+/// ```
+/// use vortex_vm::spliter::parse_constants;
+///
+/// let constants = parse_constants("SCREEN EQU 100\n%define END SCREEN\nPUSH END");
+/// assert_eq!(constants.get("END"), Some(&100));
+/// ```
+pub fn parse_constants(source: &str) -> HashMap<String, Word> {
+    collect_constants(source).0
+}
+
+/// Collects the constants defined in `source`, along with a description of every
+/// definition that was rejected.
+///
+/// A value may be a number or a constant defined on an earlier line. A constant can only
+/// be defined once; later definitions are rejected.
+fn collect_constants(source: &str) -> (HashMap<String, Word>, Vec<String>) {
+    let mut constants = HashMap::new();
+    let mut problems = Vec::new();
+
+    for line in source.lines() {
+        let clean_line = extract_code_portion(line);
+        let Some(definition) = parse_constant_definition(clean_line) else {
+            continue;
+        };
+
+        let result = definition.and_then(|(name, value)| {
+            let value = value
+                .parse::<Word>()
+                .ok()
+                .or_else(|| constants.get(value).copied())
+                .ok_or_else(|| format!("Invalid constant value '{}'", value))?;
+            if constants.contains_key(name) {
+                return Err(format!("Constant '{}' is already defined", name));
+            }
+            Ok((name.to_string(), value))
+        });
+
+        match result {
+            Ok((name, value)) => {
+                constants.insert(name, value);
+            }
+            Err(e) => problems.push(format!("{}: {}", e, clean_line)),
+        }
+    }
+
+    (constants, problems)
+}
+
+/// Recognizes `NAME EQU value` and `%define NAME value` lines.
+///
+/// Returns `None` for other lines, or the name and value text of the definition, or an
+/// error if the line is a malformed definition.
+fn parse_constant_definition(line: &str) -> Option<Result<(&str, &str), String>> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let (name, value) = match parts[..] {
+        [keyword, ..] if keyword.eq_ignore_ascii_case("%define") => match parts[..] {
+            [_, name, value] => (name, value),
+            _ => return Some(Err("Expected %define NAME value".to_string())),
+        },
+        [_, keyword, ..] if keyword.eq_ignore_ascii_case("equ") => match parts[..] {
+            [name, _, value] => (name, value),
+            _ => return Some(Err("Expected NAME EQU value".to_string())),
+        },
+        _ => return None,
+    };
+
+    let mut chars = name.chars();
+    let valid_name = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Some(Err(format!("Invalid constant name '{}'", name)));
+    }
+    Some(Ok((name, value)))
+}
+
+/// Replaces the operands of `line` that name a constant with the constant's value.
+///
+/// `HOSTCALL` names are left alone, since they name host functions rather than numbers.
+fn substitute_constants<'a>(line: &'a str, constants: &HashMap<String, Word>) -> Cow<'a, str> {
+    let parts = split_operands(line);
+    if parts.len() < 2
+        || parts[0].eq_ignore_ascii_case("hostcall")
+        || !parts[1..].iter().any(|part| constants.contains_key(*part))
+    {
+        return Cow::Borrowed(line);
+    }
+
+    let mut substituted = parts[0].to_string();
+    for part in &parts[1..] {
+        substituted.push(' ');
+        match constants.get(*part) {
+            Some(value) => substituted.push_str(&value.to_string()),
+            None => substituted.push_str(part),
+        }
+    }
+    Cow::Owned(substituted)
 }
 
 /// First pass: Scan through all lines to find label definitions and record their positions.
 /// Labels are identified by lines ending with ':' (after removing comments and whitespace).
-fn collect_labels(instructions: &str, labels: &mut HashMap<String, usize>) {
-    let mut instruction_index = 0;
+fn collect_labels(instructions: &str, constants: &HashMap<String, Word>, labels: &mut HashMap<String, usize>) {
+    let mut instruction_index: usize = 0;
+    let mut section = Section::Text;
 
     for line in instructions.lines() {
         let clean_line = extract_code_portion(line);
+        if parse_constant_definition(clean_line).is_some() {
+            continue;
+        }
+        let clean_line = &*substitute_constants(clean_line, constants);
+
+        if let Some((next, _)) = parse_section_directive(clean_line) {
+            section = next;
+            continue;
+        }
+        if section == Section::Data {
+            continue;
+        }
+
+        if let Some(alignment) = parse_align_directive(clean_line) {
+            instruction_index = instruction_index.next_multiple_of(alignment);
+            continue;
+        }
 
-        if clean_line.is_empty() || is_comment_line(clean_line) {
+        if clean_line.is_empty() || is_comment_line(clean_line) || is_directive(clean_line) {
             continue;
         }
 
@@ -105,55 +598,355 @@ fn collect_labels(instructions: &str, labels: &mut HashMap<String, usize>) {
     }
 }
 
-/// Second pass: Parse each line as an instruction, ignoring labels and comments.
-/// Label references (like "main" or "loop") are kept as strings for later resolution.
-fn parse_instructions(instructions: &str, _labels: &HashMap<String, usize>, result: &mut Vec<Instruction>) {
-    for line in instructions.lines() {
+/// Maps every label defined in a `.data` section to the address of the data that follows it.
+/// A label directly followed by `.string` also gets a `<label>_len` symbol holding the length
+/// of the first string, without its 0 terminator.
+///
+/// Data labels have their own namespace, so a data label may share its name with a code label:
+/// jumps and calls refer to the code label, every other operand to the data label.
+fn collect_data_labels(source: &str, constants: &HashMap<String, Word>) -> HashMap<String, Word> {
+    let mut data_labels = HashMap::new();
+    let mut section = Section::Text;
+    let mut data_address = 0;
+    // Labels waiting to see whether the data after them is a string
+    let mut pending = Vec::new();
+    // Only the addresses matter here; the second pass lays out the data and reports problems
+    let mut data = Vec::new();
+
+    for line in source.lines() {
         let clean_line = extract_code_portion(line);
+        if parse_constant_definition(clean_line).is_some() {
+            continue;
+        }
+        let clean_line = &*substitute_constants(clean_line, constants);
 
-        if clean_line.is_empty() || is_comment_line(clean_line) || is_label_definition(clean_line) {
+        if let Some((next, origin)) = parse_section_directive(clean_line) {
+            section = next;
+            data_address = origin.unwrap_or(data_address);
+            continue;
+        }
+        if section != Section::Data || clean_line.is_empty() || is_comment_line(clean_line) {
             continue;
         }
 
-        if let Some(instruction) = parse_instruction_line(clean_line) {
-            result.push(instruction);
+        let parts = split_operands(clean_line);
+        if is_label_definition(clean_line) {
+            let name = extract_label_name(clean_line);
+            data_labels.insert(name.clone(), data_address as Word);
+            pending.push(name);
+            continue;
+        }
+
+        let labels = std::mem::take(&mut pending);
+        if parts[0].eq_ignore_ascii_case(".string")
+            && let Some(Ok(string)) = parts.get(1).map(|literal| parse_string_literal(literal))
+        {
+            for name in labels {
+                data_labels.insert(format!("{}_len", name), string.len() as Word);
+            }
+        }
+
+        if parts[0].eq_ignore_ascii_case(".word") {
+            // Words may name data labels defined further down, so only count them
+            data_address += parts.len() - 1;
+        } else {
+            let _ = parse_data_line(clean_line, &mut data_address, &mut data);
         }
     }
+
+    data_labels
 }
 
-/// Third pass: Replace all label references in jump instructions with their actual instruction indices.
-/// Converts labels like "main" to their corresponding instruction index as a string.
-fn resolve_label_references(instructions: &mut [Instruction], labels: &HashMap<String, usize>) {
-    for instruction in instructions.iter_mut() {
-        match instruction {
-            Instruction::Jiz(target) | Instruction::Jnz(target) => {
-                if let Some(&address) = labels.get(target) {
-                    // Replace label with its instruction index
-                    *target = address.to_string();
-                } else if target.parse::<usize>().is_ok() {
-                    // It's already a numeric address, keep it as string
-                    // No change needed
-                } else {
-                    eprintln!("Warning: Unknown label or invalid address: {}", target);
+/// Returns the names of the profiling phases `PROFBEGIN`/`PROFEND` refer to in `source`,
+/// in the order they first appear, which gives each its id. Numeric operands are ids
+/// already and are not collected.
+fn collect_phases(source: &str) -> Vec<String> {
+    let mut phases: Vec<String> = Vec::new();
+    for line in source.lines() {
+        if let [mnemonic, name] = split_operands(extract_code_portion(line))[..]
+            && is_profiling_mnemonic(mnemonic)
+            && name.parse::<u32>().is_err()
+            && !phases.iter().any(|phase| phase == name)
+        {
+            phases.push(name.to_string());
+        }
+    }
+    phases
+}
+
+fn is_profiling_mnemonic(mnemonic: &str) -> bool {
+    mnemonic.eq_ignore_ascii_case("PROFBEGIN") || mnemonic.eq_ignore_ascii_case("PROFEND")
+}
+
+/// Replaces the operands of `line` that name a data label with the label's address, except
+/// in jumps, calls and `TRY`, whose operands name code labels, and in `PROFBEGIN`/`PROFEND`,
+/// whose operand names a phase and is replaced with its id from `phases`.
+fn substitute_data_labels<'a>(line: &'a str, data_labels: &HashMap<String, Word>, phases: &HashMap<String, Word>) -> Cow<'a, str> {
+    let Some(mnemonic) = split_operands(line).first().copied() else {
+        return Cow::Borrowed(line);
+    };
+    if ["JIZ", "JNZ", "JMP", "CALL", "TRY"].iter().any(|jump| mnemonic.eq_ignore_ascii_case(jump)) {
+        Cow::Borrowed(line)
+    } else if is_profiling_mnemonic(mnemonic) {
+        substitute_constants(line, phases)
+    } else {
+        substitute_constants(line, data_labels)
+    }
+}
+
+/// Second pass: Parse each line as an instruction, ignoring labels and comments.
+/// Label references (like "main" or "loop") are resolved to instruction indices as they are parsed,
+/// and data label references to memory addresses.
+fn parse_instructions(
+    instructions: &str,
+    constants: &HashMap<String, Word>,
+    labels: &HashMap<String, usize>,
+    data_labels: &HashMap<String, Word>,
+    phases: &HashMap<String, Word>,
+    parsed: &mut ParsedSource,
+) {
+    let mut section = Section::Text;
+    let mut data_address = 0;
+
+    for (line_index, line) in instructions.lines().enumerate() {
+        let code = extract_code_portion(line);
+        if parse_constant_definition(code).is_some() {
+            continue;
+        }
+        let substituted = match substitute_constants(code, constants) {
+            Cow::Borrowed(code) => substitute_data_labels(code, data_labels, phases),
+            Cow::Owned(line) => Cow::Owned(substitute_data_labels(&line, data_labels, phases).into_owned()),
+        };
+        let clean_line = &*substituted;
+
+        // Maps a span of `clean_line` back to `line`; after constant substitution the
+        // columns no longer match, so the whole instruction is pointed at instead
+        let code_start = code.as_ptr() as usize - line.as_ptr() as usize;
+        let span_in_line = |span: Range<usize>| match substituted {
+            Cow::Borrowed(_) => code_start + span.start..code_start + span.end,
+            Cow::Owned(_) => code_start..code_start + code.len(),
+        };
+
+        if let Some((next, origin)) = parse_section_directive(clean_line) {
+            section = next;
+            data_address = origin.unwrap_or(data_address);
+            continue;
+        }
+        if is_section_directive(clean_line) {
+            diagnostic!(Level::Warn, "Invalid section directive: {}", clean_line);
+            continue;
+        }
+        if is_requires_directive(clean_line) {
+            match parse_requires_directive(clean_line, &mut parsed.requirements) {
+                Ok(true) => {}
+                Ok(false) => diagnostic!(Level::Warn, "Invalid requires directive: {}", clean_line),
+                Err(message) => {
+                    let span = span_in_line(0..clean_line.len());
+                    parsed.fatal_errors.push(SourceError { line: line_index + 1, span, message });
                 }
             }
-            _ => {
-                // Not a jump instruction, no label resolution needed
+            continue;
+        }
+
+        if section == Section::Data {
+            if !is_comment_line(clean_line)
+                && !is_label_definition(clean_line)
+                && let Err(e) = parse_data_line(clean_line, &mut data_address, &mut parsed.data)
+            {
+                diagnostic!(Level::Warn, "{}: {}", e, clean_line);
+            }
+            continue;
+        }
+        if is_data_directive(clean_line) {
+            diagnostic!(Level::Warn, "Data directive outside a .data section: {}", clean_line);
+            continue;
+        }
+
+        if let Some(alignment) = parse_align_directive(clean_line) {
+            let start = parsed.instructions.len();
+            let end = start.next_multiple_of(alignment);
+            if end > start {
+                parsed.instructions.resize(end, Instruction::Null);
+                parsed.lines.resize(end, line_index + 1);
+                parsed.padding.push(start..end);
             }
+            continue;
         }
+        if is_align_directive(clean_line) {
+            diagnostic!(Level::Warn, "Invalid align directive: {}", clean_line);
+            continue;
+        }
+
+        if clean_line.is_empty() || is_comment_line(clean_line) || is_label_definition(clean_line) || is_directive(clean_line) {
+            continue;
+        }
+
+        let instruction = match parse_instruction_line(clean_line, labels) {
+            Ok(instruction) => instruction,
+            Err((message, span)) => {
+                parsed.errors.push(SourceError { line: line_index + 1, span: span_in_line(span), message });
+                continue;
+            }
+        };
+        if let Some(target) = instruction.jump_target() {
+            let operand = split_operands(clean_line)[1];
+            let span = span_in_line(span_of(clean_line, operand));
+            if target == UNRESOLVED_TARGET {
+                let message = format!("unknown label or invalid address '{}'", operand);
+                parsed.fatal_errors.push(SourceError { line: line_index + 1, span, message });
+            } else {
+                parsed.jumps.push((parsed.instructions.len(), span));
+            }
+        }
+        parsed.instructions.push(instruction);
+        parsed.lines.push(line_index + 1);
     }
 }
 
+/// Records an error for every resolved jump or call whose target lies past the end of the program.
+fn check_jump_targets(parsed: &mut ParsedSource) {
+    let len = parsed.instructions.len();
+    for (index, span) in &parsed.jumps {
+        if let Some(target) = parsed.instructions[*index].jump_target()
+            && target as usize > len
+        {
+            let message = format!("jump target {} is outside the program ({} instructions)", target, len);
+            parsed.fatal_errors.push(SourceError { line: parsed.lines[*index], span: span.clone(), message });
+        }
+    }
+}
+
+/// Returns the byte range `part`, a slice of `line`, covers within it.
+fn span_of(line: &str, part: &str) -> Range<usize> {
+    let start = part.as_ptr() as usize - line.as_ptr() as usize;
+    start..start + part.len()
+}
+
 /// Extracts the code portion of a line, removing comments and whitespace.
-/// Everything after the first ';' is considered a comment and ignored.
+/// Everything after the first ';' outside a string literal is considered a comment and ignored.
 fn extract_code_portion(line: &str) -> &str {
     let trimmed = line.trim();
+    match comment_start(trimmed) {
+        Some(pos) => trimmed[..pos].trim(),
+        None => trimmed,
+    }
+}
 
-    if let Some(semicolon_pos) = trimmed.find(';') {
-        trimmed[..semicolon_pos].trim()
-    } else {
-        trimmed
+/// Returns where the comment of a line starts: the first ';' outside a string literal.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (pos, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return Some(pos),
+            _ => {}
+        }
     }
+    None
+}
+
+/// A line of assembly split into the parts of its code and its comment, keeping
+/// everything but the whitespace between parts, for tools that rewrite source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineTokens<'a> {
+    /// The whitespace-separated parts of the code, with string literals kept whole.
+    pub parts: Vec<&'a str>,
+    /// The comment from its `;`, without trailing whitespace.
+    pub comment: Option<&'a str>,
+}
+
+/// Splits a line of assembly into [`LineTokens`].
+///
+/// ```
+/// use vortex_vm::spliter::tokenize_line;
+///
+/// let tokens = tokenize_line("  .string  \"a ; b\"   ; greeting  ");
+/// assert_eq!(tokens.parts, [".string", "\"a ; b\""]);
+/// assert_eq!(tokens.comment, Some("; greeting"));
+/// ```
+pub fn tokenize_line(line: &str) -> LineTokens<'_> {
+    let (code, comment) = match comment_start(line) {
+        Some(pos) => (&line[..pos], Some(line[pos..].trim_end())),
+        None => (line, None),
+    };
+    LineTokens { parts: split_operands(code), comment }
+}
+
+/// Splits a line into whitespace-separated parts, keeping each string literal (with its
+/// quotes) together as one part.
+fn split_operands(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (pos, c) in line.char_indices() {
+        if start.is_none() {
+            if c.is_whitespace() {
+                continue;
+            }
+            start = Some(pos);
+        }
+
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            c if c.is_whitespace() && !in_string => {
+                parts.push(&line[start.take().unwrap()..pos]);
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = start {
+        parts.push(&line[start..]);
+    }
+    parts
+}
+
+/// Decodes a quoted string literal such as `"Hi\n"` into its bytes.
+///
+/// Supports the escapes `\n`, `\t`, `\r`, `\0`, `\\`, `\"` and `\xHH`; other characters
+/// become their UTF-8 bytes.
+fn parse_string_literal(literal: &str) -> Result<Vec<u8>, String> {
+    let inner = literal
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .filter(|_| literal.len() >= 2)
+        .ok_or_else(|| format!("Unterminated string literal: {}", literal))?;
+
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => 0,
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| format!("Invalid escape '\\x{}' in {}", hex, literal))?
+            }
+            Some(other) => return Err(format!("Invalid escape '\\{}' in {}", other, literal)),
+            None => return Err(format!("Unterminated string literal: {}", literal)),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
 }
 
 /// Checks if a line is a comment (either starts with ';' or is empty after comment removal).
@@ -166,6 +959,175 @@ fn is_label_definition(line: &str) -> bool {
     line.ends_with(':')
 }
 
+/// Checks if a line is an assembler directive (starts with '.').
+fn is_directive(line: &str) -> bool {
+    line.starts_with('.')
+}
+
+fn is_align_directive(line: &str) -> bool {
+    line.split_whitespace().next().is_some_and(|directive| directive.eq_ignore_ascii_case(".align"))
+}
+
+/// Parses an `.align N` directive, returning `N` if it is a positive number.
+fn parse_align_directive(line: &str) -> Option<usize> {
+    if !is_align_directive(line) {
+        return None;
+    }
+
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    match parts[..] {
+        [_, alignment] => alignment.parse::<usize>().ok().filter(|&alignment| alignment > 0),
+        _ => None,
+    }
+}
+
+fn is_section_directive(line: &str) -> bool {
+    line.split_whitespace()
+        .next()
+        .is_some_and(|directive| directive.eq_ignore_ascii_case(".text") || directive.eq_ignore_ascii_case(".data"))
+}
+
+/// Parses `.text` or `.data [ADDR]`, returning the section and, for `.data`, the address
+/// its values start at if one is given.
+fn parse_section_directive(line: &str) -> Option<(Section, Option<usize>)> {
+    if !is_section_directive(line) {
+        return None;
+    }
+
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    match parts[..] {
+        [directive] if directive.eq_ignore_ascii_case(".text") => Some((Section::Text, None)),
+        [directive] if directive.eq_ignore_ascii_case(".data") => Some((Section::Data, None)),
+        [directive, address] if directive.eq_ignore_ascii_case(".data") => {
+            address.parse::<usize>().ok().map(|address| (Section::Data, Some(address)))
+        }
+        _ => None,
+    }
+}
+
+fn is_requires_directive(line: &str) -> bool {
+    line.split_whitespace().next().is_some_and(|directive| directive.eq_ignore_ascii_case(".requires"))
+}
+
+/// Parses `.requires mem N` or `.requires fuel N` into `requirements`, keeping the larger
+/// value if the resource was already required. Returns false if the directive is invalid,
+/// and an error for an amount no run could meet: 0, or more memory than [`MAX_MEMORY_SIZE`].
+fn parse_requires_directive(line: &str, requirements: &mut Requirements) -> Result<bool, String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let [_, resource, amount] = parts[..] else {
+        return Ok(false);
+    };
+    let Ok(amount) = amount.parse::<u64>() else {
+        return Ok(false);
+    };
+
+    let resource = resource.to_lowercase();
+    if amount == 0 && ["mem", "memory", "fuel"].contains(&resource.as_str()) {
+        return Err(format!("'.requires {} 0' can never be met; leave the directive out to require nothing", resource));
+    }
+    match resource.as_str() {
+        "mem" | "memory" => match usize::try_from(amount) {
+            Ok(memory) if memory <= MAX_MEMORY_SIZE => requirements.memory = requirements.memory.max(Some(memory)),
+            _ => return Err(format!("required memory of {} cells is more than the {} a VM may have", amount, MAX_MEMORY_SIZE)),
+        },
+        "fuel" => requirements.fuel = requirements.fuel.max(Some(amount)),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Parses `.budget PHASE STEPS`, where `PHASE` is a phase name used by `PROFBEGIN` or
+/// `PROFEND` in `phases`, or a numeric phase id.
+fn parse_budget_directive(parts: &[&str], phases: &[String]) -> Option<(u32, u64)> {
+    let [_, phase, steps] = parts else {
+        return None;
+    };
+    let phase = match phases.iter().position(|name| name == phase) {
+        Some(id) => id as u32,
+        None => phase.parse::<u32>().ok()?,
+    };
+    Some((phase, steps.parse::<u64>().ok()?))
+}
+
+fn is_data_directive(line: &str) -> bool {
+    line.split_whitespace().next().is_some_and(|directive| {
+        [".byte", ".word", ".string", ".space"].iter().any(|name| directive.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Lays out one line of a `.data` section at `address`, advancing it past the values.
+///
+/// `.space N` skips `N` cells without initializing them and `.align N` skips to the next
+/// multiple of `N`; `.string` stores each literal followed by a 0 terminator.
+fn parse_data_line(line: &str, address: &mut usize, data: &mut Vec<DataBlock>) -> Result<(), String> {
+    let parts = split_operands(line);
+    let operands = &parts[1..];
+
+    let values: Vec<Word> = match parts[0].to_lowercase().as_str() {
+        ".byte" => operands
+            .iter()
+            .map(|value| value.parse::<u8>().map(Word::from).map_err(|_| format!("Invalid byte '{}'", value)))
+            .collect::<Result<_, _>>()?,
+        ".word" => operands
+            .iter()
+            .map(|value| value.parse::<Word>().map_err(|_| format!("Invalid word '{}'", value)))
+            .collect::<Result<_, _>>()?,
+        ".string" => {
+            let mut values = Vec::new();
+            for literal in operands {
+                if !literal.starts_with('"') {
+                    return Err(format!("Expected a string literal instead of '{}'", literal));
+                }
+                values.extend(parse_string_literal(literal)?.into_iter().map(Word::from));
+                values.push(0);
+            }
+            values
+        }
+        ".space" | ".align" => {
+            let [amount] = operands else {
+                return Err(format!("{} takes exactly one number", parts[0]));
+            };
+            let amount = amount.parse::<usize>().map_err(|_| format!("Invalid size '{}'", amount))?;
+            let next = if parts[0].eq_ignore_ascii_case(".space") {
+                address.checked_add(amount)
+            } else {
+                address.checked_next_multiple_of(amount)
+            };
+            *address = next.ok_or_else(|| format!("Invalid size '{}'", amount))?;
+            return Ok(());
+        }
+        _ => return Err("Expected .byte, .word, .string or .space in a .data section".to_string()),
+    };
+
+    if values.is_empty() {
+        return Err(format!("{} needs at least one value", parts[0]));
+    }
+
+    let start = *address;
+    let end = start.checked_add(values.len()).ok_or_else(|| "Data does not fit in memory".to_string())?;
+    if let Some(existing) = data.iter().find(|block| block.start < end && start < block.end()) {
+        return Err(format!("Data at {}..{} overlaps data at {}..{}", start, end, existing.start, existing.end()));
+    }
+
+    match data.last_mut() {
+        Some(block) if block.end() == start => block.values.extend(values),
+        _ => data.push(DataBlock { start, values }),
+    }
+    *address = end;
+    Ok(())
+}
+
+/// Parses a `.region NAME start len` directive.
+fn parse_region_directive(parts: &[&str]) -> Option<Region> {
+    if parts.len() != 4 {
+        return None;
+    }
+
+    let start = parts[2].parse::<usize>().ok()?;
+    let len = parts[3].parse::<usize>().ok()?;
+    Some(Region { name: parts[1].to_string(), start, len })
+}
+
 /// Extracts the label name from a label definition line (removes the ':' suffix).
 fn extract_label_name(line: &str) -> String {
     line.strip_suffix(':').unwrap_or(line).trim().to_string()
@@ -173,25 +1135,42 @@ fn extract_label_name(line: &str) -> String {
 
 /// Parses a single instruction line into an Instruction enum variant.
 /// Handles all supported instruction types with their parameters.
-fn parse_instruction_line(line: &str) -> Option<Instruction> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
+///
+/// On failure returns a message and the span of `line` it is about.
+fn parse_instruction_line(line: &str, labels: &HashMap<String, usize>) -> Result<Instruction, (String, Range<usize>)> {
+    let parts = split_operands(line);
 
-    if parts.is_empty() {
-        return None;
-    }
+    let Some(&name) = parts.first() else {
+        return Err(("missing instruction".to_string(), 0..0));
+    };
+    let mnemonic = name.to_uppercase();
 
-    match parts[0].to_uppercase().as_str() {
+    let instruction = match mnemonic.as_str() {
         // Basic stack operations
         "NULL" => Some(Instruction::Null),
         "PUSH" => parse_push_instruction(&parts),
         "POP" => Some(Instruction::Pop),
         "DUP" => Some(Instruction::Dup),
         "SWAP" => Some(Instruction::Swap),
+        "ROT" => Some(Instruction::Rot),
+        "OVER" => Some(Instruction::Over),
+        "PICK" => parse_pick_instruction(&parts),
+        "DEPTH" => Some(Instruction::Depth),
+        "CLEAR" => Some(Instruction::Clear),
 
         // Control flow
         "RET" => Some(Instruction::Ret),
-        "JIZ" => parse_jump_instruction(&parts, Instruction::Jiz),
-        "JNZ" => parse_jump_instruction(&parts, Instruction::Jnz),
+        "HALT" => parse_halt_instruction(&parts),
+        "ABORT" => parse_two_operands(&parts, Instruction::Abort),
+        "JIZ" => parse_jump_instruction(&parts, labels, Instruction::Jiz),
+        "JNZ" => parse_jump_instruction(&parts, labels, Instruction::Jnz),
+        "JMP" => parse_jump_instruction(&parts, labels, Instruction::Jmp),
+        "JMPDYN" => Some(Instruction::JmpDyn),
+        "CALL" => parse_jump_instruction(&parts, labels, Instruction::Call),
+        "HOSTCALL" => parse_hostcall_instruction(&parts),
+        "TRY" => parse_jump_instruction(&parts, labels, Instruction::Try),
+        "ENDTRY" => Some(Instruction::EndTry),
+        "THROW" => Some(Instruction::Throw),
 
         // Arithmetic operations
         "ADD" => Some(Instruction::Add),
@@ -202,62 +1181,148 @@ fn parse_instruction_line(line: &str) -> Option<Instruction> {
         "MULTS" => parse_arithmetic_immediate(&parts, Instruction::MultS),
         "DIV" => Some(Instruction::Div),
         "DIVS" => parse_arithmetic_immediate(&parts, Instruction::DivS),
+        "MOD" => Some(Instruction::Mod),
+        "MODS" => parse_arithmetic_immediate(&parts, Instruction::ModS),
+
+        // Comparison operations
+        "EQ" => Some(Instruction::Eq),
+        "NEQ" => Some(Instruction::Neq),
+        "LT" => Some(Instruction::Lt),
+        "GT" => Some(Instruction::Gt),
+        "LTE" => Some(Instruction::Lte),
+        "GTE" => Some(Instruction::Gte),
 
         // Memory operations
         "MEMWRITE" => parse_memwrite_instruction(&parts),
         "MEMWRITES" => parse_memwrites_instruction(&parts),
         "MEMREAD" => parse_memread_instruction(&parts),
+        "LOAD" => Some(Instruction::Load),
+        "STORE" => Some(Instruction::Store),
+        "LOADLOCAL" => parse_local_instruction(&parts, Instruction::LoadLocal),
+        "STORELOCAL" => parse_local_instruction(&parts, Instruction::StoreLocal),
+        "MEMCOPY" => parse_three_operands(&parts, Instruction::MemCopy),
+        "MEMSET" => parse_three_operands(&parts, Instruction::MemSet),
+        "MEMCMP" => parse_three_operands(&parts, Instruction::MemCmp),
+        "GROW" => parse_grow_instruction(&parts),
+        "MEMSIZE" => Some(Instruction::MemSize),
+        "ALLOC" => Some(Instruction::Alloc),
+        "FREE" => Some(Instruction::Free),
+        "PROTECT" => parse_two_operands(&parts, Instruction::Protect),
         "PRINT" => parse_print_instruction(&parts),
+        "PRINTINT" => Some(Instruction::PrintInt),
+        "PRINTSTR" => parse_printstr_instruction(&parts),
+
+        // Input
+        "READLINE" => parse_readline_instruction(&parts),
+        "READINT" => Some(Instruction::ReadInt),
+
+        // Self-modifying code
+        "CODEREAD" => Some(Instruction::CodeRead),
+        "CODEWRITE" => Some(Instruction::CodeWrite),
+
+        // Introspection
+        "PERFREAD" => parse_perfread_instruction(&parts),
+        "VMINFO" => parse_vminfo_instruction(&parts),
+        "PROFBEGIN" => parse_phase_instruction(&parts, Instruction::ProfBegin),
+        "PROFEND" => parse_phase_instruction(&parts, Instruction::ProfEnd),
+
+        // Assertions
+        "ASSERT" => parse_assert_instruction(&parts),
+        "ASSERTEQ" => Some(Instruction::AssertEq),
 
         // Unknown instruction
-        _ => {
-            eprintln!("Unknown instruction: {}", line);
-            None
-        }
+        _ => return Err((format!("unknown instruction '{}'", name), span_of(line, name))),
+    };
+
+    instruction.ok_or_else(|| match parts.get(1) {
+        Some(&first) => (format!("invalid operands for '{}'", mnemonic), span_of(line, first).start..line.len()),
+        None => (format!("missing operands for '{}'", mnemonic), span_of(line, name)),
+    })
+}
+
+/// Parses a HOSTCALL instruction with the name of the host function to call.
+fn parse_hostcall_instruction(parts: &[&str]) -> Option<Instruction> {
+    if parts.len() == 2 {
+        Some(Instruction::HostCall(parts[1].to_string()))
+    } else {
+        None
+    }
+}
+
+/// Parses a HALT instruction with an optional exit code, which defaults to 0.
+fn parse_halt_instruction(parts: &[&str]) -> Option<Instruction> {
+    match parts.len() {
+        1 => Some(Instruction::Halt(0)),
+        2 => parts[1].parse::<i32>().ok().map(Instruction::Halt),
+        _ => None,
     }
 }
 
 /// Parses a PUSH instruction with its integer value parameter.
 fn parse_push_instruction(parts: &[&str]) -> Option<Instruction> {
     if parts.len() == 2 {
-        parts[1].parse::<i32>().ok().map(Instruction::Push)
+        parts[1].parse::<Word>().ok().map(Instruction::Push)
     } else {
         None
     }
 }
 
-/// Parses jump instructions (JIZ, JNZ) with their target address/label parameter.
-fn parse_jump_instruction<F>(parts: &[&str], constructor: F) -> Option<Instruction>
+/// Parses jump instructions (JIZ, JNZ, JMP, CALL, TRY) with their target address/label parameter.
+/// Labels are resolved to their instruction index; numeric targets are used as written.
+fn parse_jump_instruction<F>(parts: &[&str], labels: &HashMap<String, usize>, constructor: F) -> Option<Instruction>
 where
-    F: FnOnce(String) -> Instruction,
+    F: FnOnce(u32) -> Instruction,
 {
     if parts.len() == 2 {
-        Some(constructor(parts[1].to_string()))
+        Some(constructor(resolve_jump_target(parts[1], labels)))
     } else {
         None
     }
 }
 
-/// Parses arithmetic immediate instructions (ADDS, SUBS, MULTS, DIVS) with their integer parameter.
+/// Resolves a jump target to an instruction index.
+///
+/// Unknown labels resolve to [`UNRESOLVED_TARGET`], which is never a valid instruction
+/// index; the caller reports them.
+fn resolve_jump_target(target: &str, labels: &HashMap<String, usize>) -> u32 {
+    if let Some(&address) = labels.get(target) {
+        return address as u32;
+    }
+
+    target.parse::<u32>().unwrap_or(UNRESOLVED_TARGET)
+}
+
+/// Parses arithmetic immediate instructions (ADDS, SUBS, MULTS, DIVS, MODS) with their integer parameter.
 fn parse_arithmetic_immediate<F>(parts: &[&str], constructor: F) -> Option<Instruction>
 where
-    F: FnOnce(i32) -> Instruction,
+    F: FnOnce(Word) -> Instruction,
 {
     if parts.len() == 2 {
-        parts[1].parse::<i32>().ok().map(constructor)
+        parts[1].parse::<Word>().ok().map(constructor)
     } else {
         None
     }
 }
 
 /// Parses the MEMWRITE instruction with address and multiple values.
+/// String literals among the values are expanded to one value per byte.
 fn parse_memwrite_instruction(parts: &[&str]) -> Option<Instruction> {
     if parts.len() >= 2 {
         if let Ok(addr) = parts[1].parse::<i32>() {
-            let values: Vec<i32> = parts[2..]
-                .iter()
-                .filter_map(|v| v.parse::<i32>().ok())
-                .collect();
+            let mut values = Vec::new();
+            for part in &parts[2..] {
+                if part.starts_with('"') {
+                    match parse_string_literal(part) {
+                        Ok(bytes) => values.extend(bytes.into_iter().map(Word::from)),
+                        Err(e) => {
+                            diagnostic!(Level::Warn, "{}", e);
+                            return None;
+                        }
+                    }
+                } else {
+                    values.push(part.parse::<Word>().ok()?);
+                }
+            }
             Some(Instruction::MemWrite(addr, values))
         } else {
             None
@@ -289,6 +1354,49 @@ fn parse_memread_instruction(parts: &[&str]) -> Option<Instruction> {
     }
 }
 
+/// Parses a PICK instruction with the depth of the value to copy.
+fn parse_pick_instruction(parts: &[&str]) -> Option<Instruction> {
+    if parts.len() == 2 {
+        parts[1].parse::<i32>().ok().map(Instruction::Pick)
+    } else {
+        None
+    }
+}
+
+/// Parses LOADLOCAL or STORELOCAL, whose slot must be below
+/// [`MAX_LOCALS`](crate::run::MAX_LOCALS).
+fn parse_local_instruction(parts: &[&str], instruction: fn(i32) -> Instruction) -> Option<Instruction> {
+    match parts {
+        [_, index] => index.parse::<i32>().ok().filter(|&index| local_slot(index).is_some()).map(instruction),
+        _ => None,
+    }
+}
+
+/// Parses a GROW instruction with the number of pages to add.
+fn parse_grow_instruction(parts: &[&str]) -> Option<Instruction> {
+    if parts.len() == 2 {
+        parts[1].parse::<i32>().ok().map(Instruction::Grow)
+    } else {
+        None
+    }
+}
+
+/// Parses PROTECT, which takes an address and a length.
+fn parse_two_operands(parts: &[&str], instruction: fn(i32, i32) -> Instruction) -> Option<Instruction> {
+    match parts {
+        [_, a, b] => Some(instruction(a.parse().ok()?, b.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// Parses MEMCOPY, MEMSET and MEMCMP, which take three integer operands.
+fn parse_three_operands<T: FromStr>(parts: &[&str], instruction: fn(i32, i32, T) -> Instruction) -> Option<Instruction> {
+    match parts {
+        [_, a, b, c] => Some(instruction(a.parse().ok()?, b.parse().ok()?, c.parse().ok()?)),
+        _ => None,
+    }
+}
+
 /// Parses the PRINT instruction with address and length parameters.
 fn parse_print_instruction(parts: &[&str]) -> Option<Instruction> {
     if parts.len() == 3 {
@@ -302,6 +1410,60 @@ fn parse_print_instruction(parts: &[&str]) -> Option<Instruction> {
     }
 }
 
+/// Parses a PRINTSTR instruction with the address of a zero-terminated string.
+fn parse_printstr_instruction(parts: &[&str]) -> Option<Instruction> {
+    if parts.len() == 2 {
+        parts[1].parse::<i32>().ok().map(Instruction::PrintStr)
+    } else {
+        None
+    }
+}
+
+/// Parses a PERFREAD instruction with the counter to read.
+fn parse_perfread_instruction(parts: &[&str]) -> Option<Instruction> {
+    if parts.len() == 2 {
+        parts[1].parse::<i32>().ok().map(Instruction::PerfRead)
+    } else {
+        None
+    }
+}
+
+/// Parses a VMINFO instruction with the field to read.
+fn parse_vminfo_instruction(parts: &[&str]) -> Option<Instruction> {
+    if parts.len() == 2 {
+        parts[1].parse::<i32>().ok().map(Instruction::VmInfo)
+    } else {
+        None
+    }
+}
+
+/// Parses PROFBEGIN or PROFEND, whose phase name was already replaced with its id.
+fn parse_phase_instruction(parts: &[&str], instruction: fn(u32) -> Instruction) -> Option<Instruction> {
+    match parts {
+        [_, phase] => phase.parse::<u32>().ok().map(instruction),
+        _ => None,
+    }
+}
+
+/// Parses an ASSERT instruction with the value the top of the stack must equal.
+fn parse_assert_instruction(parts: &[&str]) -> Option<Instruction> {
+    match parts {
+        [_, value] => value.parse::<Word>().ok().map(Instruction::Assert),
+        _ => None,
+    }
+}
+
+/// Parses a READLINE instruction with its buffer address and maximum length.
+fn parse_readline_instruction(parts: &[&str]) -> Option<Instruction> {
+    if parts.len() == 3
+        && let (Ok(addr), Ok(max_len)) = (parts[1].parse::<i32>(), parts[2].parse::<i32>())
+    {
+        Some(Instruction::ReadLine(addr, max_len))
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -313,42 +1475,57 @@ mod tests {
         #[test]
         fn test_null_parse() {
             let input = "NULL".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Null]);
         }
 
         #[test]
         fn test_push_parse() {
             let input = "PUSH 42".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Push(42)]);
         }
 
+        #[test]
+        fn test_push_64_bit_parse() {
+            let input = "PUSH 9000000000\nPUSH -9223372036854775808".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Push(9_000_000_000), Instruction::Push(i64::MIN)]);
+        }
+
         #[test]
         fn test_pop_parse() {
             let input = "POP".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Pop]);
         }
 
         #[test]
         fn test_dup_parse() {
             let input = "DUP".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Dup]);
         }
 
         #[test]
         fn test_swap_parse() {
             let input = "SWAP".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Swap]);
         }
 
+        #[test]
+        fn test_rot_over_pick_parse() {
+            let parsed = split_instructions("ROT\nover\nPick 3").unwrap();
+            assert_eq!(parsed, vec![Instruction::Rot, Instruction::Over, Instruction::Pick(3)]);
+            assert!(try_parse_program("PICK").unwrap_err().contains("missing operands for 'PICK'"));
+            assert_eq!(split_instructions("Depth\nCLEAR").unwrap(), vec![Instruction::Depth, Instruction::Clear]);
+        }
+
         #[test]
         fn test_push_and_pop() {
             let input = "PUSH 42\nPOP".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Push(42), Instruction::Pop]);
         }
     }
@@ -359,33 +1536,177 @@ mod tests {
         #[test]
         fn test_ret_parse() {
             let input = "RET".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Ret]);
         }
 
         #[test]
         fn test_jiz_parse() {
-            let input = "JIZ 5".to_string();
-            let parsed = split_instructions(&input);
-            assert_eq!(parsed, vec![Instruction::Jiz("5".to_string())]);
+            let input = "JIZ 1".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Jiz(1)]);
         }
 
         #[test]
         fn test_jnz_parse() {
-            let input = "JNZ main".to_string();
-            let parsed = split_instructions(&input);
-            assert_eq!(parsed, vec![Instruction::Jnz("main".to_string())]);
+            let input = "main:\nJNZ main".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Jnz(0)]);
+        }
+
+        #[test]
+        fn test_jmp_parse() {
+            let input = "end:\nJMP end\nJmpDyn".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Jmp(0), Instruction::JmpDyn]);
+        }
+
+        #[test]
+        fn test_call_parse() {
+            let input = "CALL 1".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Call(1)]);
+        }
+
+        #[test]
+        fn test_printint_parse() {
+            assert_eq!(split_instructions("PrintInt\nprintint").unwrap(), vec![Instruction::PrintInt, Instruction::PrintInt]);
+            assert_eq!(split_instructions("PrintStr 12\nPRINTSTR\nPRINTSTR x").unwrap(), vec![Instruction::PrintStr(12)]);
+        }
+
+        #[test]
+        fn test_phase_names_get_ids() {
+            let program = parse_program("PROFBEGIN parse\nPROFBEGIN 7\nProfEnd parse\nPROFBEGIN eval\nPROFEND eval\nPROFEND 7").unwrap();
+            assert_eq!(program.instructions, vec![
+                Instruction::ProfBegin(0),
+                Instruction::ProfBegin(7),
+                Instruction::ProfEnd(0),
+                Instruction::ProfBegin(1),
+                Instruction::ProfEnd(1),
+                Instruction::ProfEnd(7),
+            ]);
+            assert_eq!(program.debug_info.phases, ["parse", "eval"]);
+            assert!(try_parse_program("PROFBEGIN").unwrap_err().contains("missing operands for 'PROFBEGIN'"));
+        }
+
+        #[test]
+        fn test_budget_directive() {
+            let source = "LIMIT EQU 40\n.budget sort 100\n.budget 3 LIMIT\n.budget sort 50\n.budget search 10\n.budget sort\nPROFBEGIN sort\nPROFEND sort";
+            let program = parse_program(source).unwrap();
+            assert_eq!(program.debug_info.budgets, std::collections::BTreeMap::from([(0, 50), (3, 40)]));
+            assert_eq!(program.instructions.len(), 2);
+        }
+
+        #[test]
+        fn test_read_parse() {
+            let parsed = split_instructions("ReadLine 0 16\nREADINT\nREADLINE 4").unwrap();
+            assert_eq!(parsed, vec![Instruction::ReadLine(0, 16), Instruction::ReadInt]);
+        }
+
+        #[test]
+        fn test_align_pads_with_null() {
+            let (parsed, lines) = split_instructions_with_lines("PUSH 1\nPUSH 2\n.align 2\nlate:\n.ALIGN 4\nJMP late\n.align 0\n.align 1").unwrap();
+            assert_eq!(
+                parsed,
+                vec![Instruction::Push(1), Instruction::Push(2), Instruction::Null, Instruction::Null, Instruction::Jmp(2)]
+            );
+            assert_eq!(lines, vec![1, 2, 5, 5, 6]);
+        }
+
+        #[test]
+        fn test_perfread_parse() {
+            assert_eq!(split_instructions("PerfRead 3\nPERFREAD").unwrap(), vec![Instruction::PerfRead(3)]);
+        }
+
+        #[test]
+        fn test_vminfo_parse() {
+            assert_eq!(split_instructions("VmInfo 2\nVMINFO\nVMINFO 1 2").unwrap(), vec![Instruction::VmInfo(2)]);
+        }
+
+        #[test]
+        fn test_halt_parse() {
+            let parsed = split_instructions("HALT\nhalt 3\nHALT -1\nHALT x").unwrap();
+            assert_eq!(parsed, vec![Instruction::Halt(0), Instruction::Halt(3), Instruction::Halt(-1)]);
+        }
+
+        #[test]
+        fn test_abort_parse() {
+            let parsed = split_instructions("Abort 16 4\nABORT 0\nABORT 1 2 3").unwrap();
+            assert_eq!(parsed, vec![Instruction::Abort(16, 4)]);
+        }
+
+        #[test]
+        fn test_hostcall_parse() {
+            let parsed = split_instructions("HostCall rand\nHOSTCALL").unwrap();
+            assert_eq!(parsed, vec![Instruction::HostCall("rand".to_string())]);
+        }
+
+        #[test]
+        fn test_call_label_resolution() {
+            let input = "
+                CALL routine
+                RET
+                routine:
+                PUSH 1
+                RET
+            ".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed[0], Instruction::Call(2));
+        }
+
+        #[test]
+        fn test_try_throw_parse() {
+            let parsed = split_instructions("TRY handler\nPUSH 3\nTHROW\nENDTRY\nhandler:\nRET").unwrap();
+            assert_eq!(parsed, vec![
+                Instruction::Try(4),
+                Instruction::Push(3),
+                Instruction::Throw,
+                Instruction::EndTry,
+                Instruction::Ret
+            ]);
+        }
+
+        #[test]
+        fn test_assert_parse() {
+            let parsed = split_instructions("ASSERT -9000000000\nASSERTEQ").unwrap();
+            assert_eq!(parsed, vec![Instruction::Assert(-9_000_000_000), Instruction::AssertEq]);
+            assert!(try_parse_program("ASSERT").is_err());
         }
 
         #[test]
         fn test_jumps_with_labels() {
-            let input = "JIZ start\nJNZ end".to_string();
-            let parsed = split_instructions(&input);
+            let input = "start:\nJIZ start\nJNZ end\nend:\nRET".to_string();
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![
-                Instruction::Jiz("start".to_string()),
-                Instruction::Jnz("end".to_string())
+                Instruction::Jiz(0),
+                Instruction::Jnz(2),
+                Instruction::Ret
             ]);
         }
+
+        #[test]
+        fn test_unknown_label_is_rejected() {
+            let err = split_instructions("JIZ nowhere").unwrap_err();
+            assert_eq!(err, "unknown label or invalid address 'nowhere' at line 1\n    JIZ nowhere\n        ^^^^^^^");
+            assert!(parse_program("PUSH 1\nCALL 9").unwrap_err().starts_with("jump target 9 is outside the program"));
+            assert_eq!(parse_line("JIZ nowhere"), None);
+        }
+
+        #[test]
+        fn test_try_parse_program_rejects_bad_jump_targets() {
+            let err = try_parse_program("start:\nJMP start\nJNZ missing\nCALL 5\nJIZ 4").unwrap_err();
+            let problems: Vec<&str> = err.lines().filter(|line| !line.starts_with(' ')).collect();
+            assert_eq!(problems, vec![
+                "unknown label or invalid address 'missing' at line 3",
+                "jump target 5 is outside the program (4 instructions) at line 4",
+            ]);
+        }
+
+        #[test]
+        fn test_try_parse_program_accepts_valid_targets() {
+            let program = try_parse_program("JMP end\nPUSH 1\nend:").unwrap();
+            assert_eq!(program.instructions, vec![Instruction::Jmp(2), Instruction::Push(1)]);
+        }
     }
 
     mod arithmetic_operations {
@@ -394,58 +1715,112 @@ mod tests {
         #[test]
         fn test_add_parse() {
             let input = "ADD".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Add]);
         }
 
         #[test]
         fn test_adds_parse() {
             let input = "ADDS 5".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::AddS(5)]);
         }
 
+        #[test]
+        fn test_immediates_take_64_bit_values() {
+            let parsed = split_instructions("MULTS 1000000000000\nMEMWRITE 0 -5000000000 1\nMEMSET 0 2 4294967296").unwrap();
+            assert_eq!(parsed, vec![
+                Instruction::MultS(1_000_000_000_000),
+                Instruction::MemWrite(0, vec![-5_000_000_000, 1]),
+                Instruction::MemSet(0, 2, 1 << 32),
+            ]);
+
+            // Values no word can hold are refused rather than dropped or truncated
+            let err = try_parse_program("ADDS 9223372036854775808\nMEMWRITE 0 1 99999999999999999999").unwrap_err();
+            let problems: Vec<&str> = err.lines().filter(|line| !line.starts_with(' ')).collect();
+            assert_eq!(problems, vec!["invalid operands for 'ADDS' at line 1", "invalid operands for 'MEMWRITE' at line 2"]);
+        }
+
         #[test]
         fn test_sub_parse() {
             let input = "SUB".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Sub]);
         }
 
         #[test]
         fn test_subs_parse() {
             let input = "SUBS 3".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::SubS(3)]);
         }
 
         #[test]
         fn test_mult_parse() {
             let input = "MULT".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Mult]);
         }
 
         #[test]
         fn test_mults_parse() {
             let input = "MULTS 2".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::MultS(2)]);
         }
 
         #[test]
         fn test_div_parse() {
             let input = "DIV".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Div]);
         }
 
         #[test]
         fn test_divs_parse() {
             let input = "DIVS 4".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::DivS(4)]);
         }
+
+        #[test]
+        fn test_mod_parse() {
+            let input = "MOD".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Mod]);
+        }
+
+        #[test]
+        fn test_mods_parse() {
+            let input = "MODS 3".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::ModS(3)]);
+        }
+    }
+
+    mod comparison_operations {
+        use super::*;
+
+        #[test]
+        fn test_comparison_parse() {
+            let input = "EQ\nNEQ\nLT\nGT\nLTE\nGTE".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![
+                Instruction::Eq,
+                Instruction::Neq,
+                Instruction::Lt,
+                Instruction::Gt,
+                Instruction::Lte,
+                Instruction::Gte,
+            ]);
+        }
+
+        #[test]
+        fn test_comparison_case_insensitive() {
+            let input = "eq\nGte\nlT".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Eq, Instruction::Gte, Instruction::Lt]);
+        }
     }
 
     mod memory_operations {
@@ -454,35 +1829,69 @@ mod tests {
         #[test]
         fn test_memwrites_parse() {
             let input = "MemWrites 10 4".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::MemWriteS(10, 4)]);
         }
 
         #[test]
         fn test_memwrite_parse() {
             let input = "MemWrite 10 1 2 3 4".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::MemWrite(10, vec![1, 2, 3, 4])]);
         }
 
         #[test]
         fn test_memread_parse() {
             let input = "MemRead 5".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::MemRead(5)]);
         }
 
+        #[test]
+        fn test_load_store_parse() {
+            let input = "LOAD\nStore".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Load, Instruction::Store]);
+        }
+
+        #[test]
+        fn test_local_parse() {
+            let parsed = split_instructions("LOADLOCAL 0
+StoreLocal 255").unwrap();
+            assert_eq!(parsed, vec![Instruction::LoadLocal(0), Instruction::StoreLocal(255)]);
+            assert!(try_parse_program("STORELOCAL 256").unwrap_err().contains("invalid operands for 'STORELOCAL'"));
+            assert!(try_parse_program("LOADLOCAL -1").unwrap_err().contains("invalid operands for 'LOADLOCAL'"));
+        }
+
+        #[test]
+        fn test_grow_memsize_parse() {
+            let parsed = split_instructions("GROW 2\nMemSize").unwrap();
+            assert_eq!(parsed, vec![Instruction::Grow(2), Instruction::MemSize]);
+            assert_eq!(split_instructions("ALLOC\nfree").unwrap(), vec![Instruction::Alloc, Instruction::Free]);
+            assert!(try_parse_program("GROW").unwrap_err().contains("missing operands for 'GROW'"));
+            assert_eq!(split_instructions("PROTECT 0 16").unwrap(), vec![Instruction::Protect(0, 16)]);
+            assert!(try_parse_program("PROTECT 0").unwrap_err().contains("invalid operands for 'PROTECT'"));
+        }
+
+        #[test]
+        fn test_bulk_memory_parse() {
+            let input = "MEMCOPY 10 0 4\nMemSet 0 8 -1\nMEMCMP 0 10 4".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::MemCopy(10, 0, 4), Instruction::MemSet(0, 8, -1), Instruction::MemCmp(0, 10, 4)]);
+            assert!(try_parse_program("MEMSET 0 8").unwrap_err().contains("invalid operands for 'MEMSET'"));
+        }
+
         #[test]
         fn test_print_parse() {
             let input = "Print 5 3".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Print(5, 3)]);
         }
 
         #[test]
         fn test_memwrite_complex() {
             let input = "memwrite 0 1 2\n memread 1".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(
                 parsed,
                 vec![
@@ -491,29 +1900,275 @@ mod tests {
                 ]
             );
         }
+
+        #[test]
+        fn test_memwrite_string_literal() {
+            let input = "MemWrite 0 \"Hi; you\" ; greeting".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::MemWrite(0, vec![72, 105, 59, 32, 121, 111, 117])]);
+        }
+
+        #[test]
+        fn test_memwrite_string_escapes() {
+            let input = r#"MemWrite 4 "a\n\t\"\\\x41\0" 7 "é""#.to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(
+                parsed,
+                vec![Instruction::MemWrite(4, vec![97, 10, 9, 34, 92, 65, 0, 7, 0xC3, 0xA9])]
+            );
+        }
+
+        #[test]
+        fn test_memwrite_invalid_string_literal() {
+            assert!(split_instructions(r#"MemWrite 0 "bad \q""#).unwrap().is_empty());
+            assert!(split_instructions(r#"MemWrite 0 "open"#).unwrap().is_empty());
+            assert!(split_instructions(r#"MemWrite 0 "\x4""#).unwrap().is_empty());
+        }
+    }
+
+    mod directives {
+        use super::*;
+
+        #[test]
+        fn test_region_directive() {
+            let input = ".region screen 100 80\n.REGION input 0 16 ; keyboard buffer\nPUSH 1";
+            let program = parse_program(input).unwrap();
+            assert_eq!(program.instructions, vec![Instruction::Push(1)]);
+            assert_eq!(program.debug_info.regions, vec![
+                Region { name: "screen".to_string(), start: 100, len: 80 },
+                Region { name: "input".to_string(), start: 0, len: 16 },
+            ]);
+        }
+
+        #[test]
+        fn test_directives_do_not_shift_labels() {
+            let input = "
+                .region data 0 4
+                start:
+                PUSH 1
+                JNZ start
+            ".to_string();
+            let parsed = split_instructions(&input).unwrap();
+            assert_eq!(parsed, vec![Instruction::Push(1), Instruction::Jnz(0)]);
+        }
+
+        #[test]
+        fn test_invalid_region_is_skipped() {
+            let info = parse_debug_info(".region broken 10\n.region ok 1 2");
+            assert_eq!(info.regions.len(), 1);
+            assert_eq!(info.regions[0].name, "ok");
+        }
+
+        #[test]
+        fn test_redeclared_region_replaces_previous() {
+            let info = parse_debug_info(".region buf 0 4\n.region buf 8 2");
+            assert_eq!(info.regions, vec![Region { name: "buf".to_string(), start: 8, len: 2 }]);
+        }
+
+        #[test]
+        fn test_data_section_layout() {
+            let input = "
+                PUSH 1
+                .data 4
+                .byte 1 255
+                .space 2
+                .word -5 ; comment
+                .text
+                loop:
+                JMP loop
+                .DATA
+                .align 4
+                .string \"a\" \"\"
+            ";
+            let program = parse_program(input).unwrap();
+            assert_eq!(program.instructions, vec![Instruction::Push(1), Instruction::Jmp(1)]);
+            assert_eq!(program.debug_info.lines, vec![2, 9]);
+            assert_eq!(program.debug_info.labels, vec![("loop".to_string(), 1)]);
+            assert_eq!(
+                program.data,
+                vec![
+                    DataBlock { start: 4, values: vec![1, 255] },
+                    DataBlock { start: 8, values: vec![-5] },
+                    DataBlock { start: 12, values: vec![97, 0, 0] },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_data_labels_resolve_to_addresses() {
+            let input = "
+                .data 10
+                table:
+                .word greeting msg ; forward references
+                greeting:
+                .string \"Hi\"
+                msg:
+                .string \"Bye\"
+                .text
+                msg:
+                PRINTSTR greeting
+                PUSH table
+                LOAD
+                CALL msg
+                RET
+            ";
+            let program = parse_program(input).unwrap();
+            assert_eq!(
+                program.instructions,
+                vec![Instruction::PrintStr(12), Instruction::Push(10), Instruction::Load, Instruction::Call(0), Instruction::Ret]
+            );
+            assert_eq!(program.data, vec![DataBlock { start: 10, values: vec![12, 15, 72, 105, 0, 66, 121, 101, 0] }]);
+            assert_eq!(program.debug_info.labels, vec![("msg".to_string(), 0)]);
+        }
+
+        #[test]
+        fn test_string_labels_define_lengths() {
+            let input = "
+                .data 4
+                greeting:
+                .string \"Hi\\n\" \"unused\"
+                empty:
+                .string \"\"
+                numbers:
+                .word 1 2
+                .text
+                PRINT greeting greeting_len
+                PUSH empty_len
+                PUSH numbers_len
+            ";
+            let program = try_parse_program(input);
+            assert!(program.unwrap_err().contains("invalid operands for 'PUSH'"));
+
+            let parsed = split_instructions(&input.replace("PUSH numbers_len", "")).unwrap();
+            assert_eq!(parsed, vec![Instruction::Print(4, 3), Instruction::Push(0)]);
+        }
+
+        #[test]
+        fn test_constants_in_operands() {
+            let input = "
+                SCREEN EQU 100
+                %define WIDTH 8
+                len equ WIDTH ; constants may refer to earlier ones
+                MemWrite SCREEN 1 WIDTH
+                Print SCREEN len
+                JMP WIDTH
+                HostCall WIDTH
+                .align WIDTH
+                target:
+                JMP target
+            ";
+            let parsed = split_instructions(input).unwrap();
+            assert_eq!(parsed[..5], [
+                Instruction::MemWrite(100, vec![1, 8]),
+                Instruction::Print(100, 8),
+                Instruction::Jmp(8),
+                Instruction::HostCall("WIDTH".to_string()),
+                Instruction::Null,
+            ]);
+            assert_eq!(parsed[8], Instruction::Jmp(8));
+        }
+
+        #[test]
+        fn test_constants_in_directives() {
+            let program = parse_program("BASE EQU 40\nSIZE EQU 2\n.region buf BASE SIZE\n.data BASE\n.word SIZE").unwrap();
+            assert_eq!(program.debug_info.regions, vec![Region { name: "buf".to_string(), start: 40, len: 2 }]);
+            assert_eq!(program.data, vec![DataBlock { start: 40, values: vec![2] }]);
+            assert!(program.instructions.is_empty());
+        }
+
+        #[test]
+        fn test_invalid_constants_are_skipped() {
+            let constants = parse_constants("A EQU 1\nA EQU 2\nB EQU C\nC EQU 3\n1X EQU 4\n%define D\nE EQU 5 6");
+            assert_eq!(constants, HashMap::from([("A".to_string(), 1), ("C".to_string(), 3)]));
+        }
+
+        #[test]
+        fn test_requires_directive() {
+            let program = parse_program("
+                .requires mem 4096
+                .REQUIRES fuel 100
+                .requires mem 8192
+                .requires mem 1024
+                .requires disk 1
+                .requires fuel -1
+                PUSH 1
+            ").unwrap();
+            assert_eq!(program.requirements, Requirements { memory: Some(8192), fuel: Some(100) });
+            assert_eq!(program.instructions, vec![Instruction::Push(1)]);
+
+            // Even the lenient parser refuses amounts no run could meet
+            let err = parse_program("PUSH 5\n.requires fuel 0").unwrap_err();
+            assert!(err.starts_with("'.requires fuel 0' can never be met; leave the directive out to require nothing at line 2"));
+            assert!(parse_program(".requires mem 0").is_err());
+            assert!(parse_program(".requires mem 1000000000000").is_err());
+        }
+
+        #[test]
+        fn test_invalid_data_is_skipped() {
+            let input = "
+                .byte 1
+                .data
+                .byte 256
+                .word 1 x
+                .string abc
+                .space
+                PUSH 1
+                .byte 7
+                .data 0
+                .word 8
+                .data x
+            ";
+            let program = parse_program(input).unwrap();
+            assert!(program.instructions.is_empty());
+            assert_eq!(program.data, vec![DataBlock { start: 0, values: vec![7] }]);
+        }
     }
 
     mod comment_and_edge_cases {
         use super::*;
 
+        #[test]
+        fn test_errors_point_at_the_offending_part() {
+            let source = "  PUSH 1 ; start\n  ADDS x y ; oops\nPUSH\nvalue EQU 3\nJMP value";
+            let err = try_parse_program_with(source, |line| format!("prog.vvm:{}", line)).unwrap_err();
+            assert_eq!(err.lines().collect::<Vec<_>>(), vec![
+                "invalid operands for 'ADDS' at prog.vvm:2",
+                "      ADDS x y ; oops",
+                "           ^^^",
+                "missing operands for 'PUSH' at prog.vvm:3",
+                "    PUSH",
+                "    ^^^^",
+                "jump target 3 is outside the program (2 instructions) at prog.vvm:5",
+                "    JMP value",
+                "    ^^^^^^^^^",
+            ]);
+        }
+
+        #[test]
+        fn test_lenient_parsing_skips_invalid_lines() {
+            let (instructions, lines) = split_instructions_with_lines("PUSH 1\nBOGUS\nPOP").unwrap();
+            assert_eq!(instructions, vec![Instruction::Push(1), Instruction::Pop]);
+            assert_eq!(lines, vec![1, 3]);
+        }
+
         #[test]
         fn test_inline_comments() {
             let input = "PUSH 42 ; This is a comment\nPOP ; Another comment".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Push(42), Instruction::Pop]);
         }
 
         #[test]
         fn test_empty_lines() {
             let input = "\nPUSH 42\n\nPOP\n".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Push(42), Instruction::Pop]);
         }
 
         #[test]
         fn test_case_insensitive() {
             let input = "push 42\nADD\nPop".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![Instruction::Push(42), Instruction::Add, Instruction::Pop]);
         }
 
@@ -526,18 +2181,16 @@ mod tests {
                 JNZ main
                 RET
             ".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
 
-            // The label "main" should be resolved to address "0"
-            if let Instruction::Jnz(target) = &parsed[2] {
-                assert_eq!(target, "0");
-            }
+            // The label "main" should be resolved to address 0
+            assert_eq!(parsed[2], Instruction::Jnz(0));
         }
 
         #[test]
         fn test_multiple_instructions() {
             let input = "PUSH 1\nPUSH 2\nADD\nPUSH 3\nMULT\nRET".to_string();
-            let parsed = split_instructions(&input);
+            let parsed = split_instructions(&input).unwrap();
             assert_eq!(parsed, vec![
                 Instruction::Push(1),
                 Instruction::Push(2),
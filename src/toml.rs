@@ -0,0 +1,188 @@
+//! A reader and writer for the small subset of TOML that run manifests and hook
+//! configurations use: tables, and keys with strings, integers, booleans or arrays of them.
+
+/// A `key = value` line, with the table it appears in (empty before the first table
+/// header) and its 1-based line number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub line: usize,
+    pub table: String,
+    pub key: String,
+    pub value: Value,
+}
+
+/// Parses every entry of `text`, in order.
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut table = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            table = name.trim().to_string();
+            continue;
+        }
+        let invalid = || format!("Invalid line {}: {}", index + 1, line);
+        let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+        let value = parse_value(value.trim()).ok_or_else(invalid)?;
+        entries.push(Entry { line: index + 1, table: table.clone(), key: key.trim().to_string(), value });
+    }
+    Ok(entries)
+}
+
+/// A TOML value of the kinds the VM's files use.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn string(self) -> Result<String, String> {
+        match self {
+            Value::String(string) => Ok(string),
+            other => Err(format!("Expected a string, found {:?}", other)),
+        }
+    }
+
+    pub fn integer<T: TryFrom<i64>>(self) -> Result<T, String> {
+        match self {
+            Value::Integer(integer) => T::try_from(integer).map_err(|_| format!("Number {} is out of range", integer)),
+            other => Err(format!("Expected a number, found {:?}", other)),
+        }
+    }
+
+    pub fn boolean(self) -> Result<bool, String> {
+        match self {
+            Value::Boolean(boolean) => Ok(boolean),
+            other => Err(format!("Expected true or false, found {:?}", other)),
+        }
+    }
+
+    pub fn array(self) -> Result<Vec<Value>, String> {
+        match self {
+            Value::Array(values) => Ok(values),
+            other => Err(format!("Expected an array, found {:?}", other)),
+        }
+    }
+
+    /// A 64-bit hash, written as 16 hex digits.
+    pub fn hash(self) -> Result<u64, String> {
+        let text = self.string()?;
+        u64::from_str_radix(&text, 16).map_err(|_| format!("Invalid hash '{}'", text))
+    }
+}
+
+/// Parses a whole value, allowing a trailing comment.
+pub fn parse_value(text: &str) -> Option<Value> {
+    let (value, rest) = parse_prefix(text)?;
+    let rest = rest.trim_start();
+    (rest.is_empty() || rest.starts_with('#')).then_some(value)
+}
+
+/// Parses the value `text` starts with and returns it with the text after it.
+fn parse_prefix(text: &str) -> Option<(Value, &str)> {
+    if let Some(rest) = text.strip_prefix('"') {
+        return parse_string(rest);
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Some((Value::Array(values), after));
+            }
+            let (value, after) = parse_prefix(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return None;
+            }
+        }
+    }
+
+    let end = text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '+' || c == '_')).unwrap_or(text.len());
+    let (token, rest) = text.split_at(end);
+    let value = match token {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => Value::Integer(token.replace('_', "").parse().ok()?),
+    };
+    Some((value, rest))
+}
+
+/// Parses a basic string whose opening quote was already consumed.
+fn parse_string(text: &str) -> Option<(Value, &str)> {
+    let mut string = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((Value::String(string), &text[index + 1..])),
+            '\\' => {
+                let escaped = match chars.next()?.1 {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '"' => '"',
+                    '\\' => '\\',
+                    'u' => {
+                        let hex: String = (0..4).map(|_| chars.next().map(|(_, c)| c)).collect::<Option<_>>()?;
+                        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    _ => return None,
+                };
+                string.push(escaped);
+            }
+            _ => string.push(c),
+        }
+    }
+    None
+}
+
+/// Quotes `text` as a TOML basic string.
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_and_values() {
+        let entries = parse("# comment\nname = \"a \\\"b\\\"\\u0041\"\n\n[table.sub]\nlist = [1, -2, [true]] # trailing").unwrap();
+        assert_eq!(entries, [
+            Entry { line: 2, table: String::new(), key: "name".to_string(), value: Value::String("a \"b\"A".to_string()) },
+            Entry {
+                line: 5,
+                table: "table.sub".to_string(),
+                key: "list".to_string(),
+                value: Value::Array(vec![Value::Integer(1), Value::Integer(-2), Value::Array(vec![Value::Boolean(true)])]),
+            },
+        ]);
+
+        assert!(parse("key").is_err());
+        assert!(parse("key = \"open").is_err());
+        assert!(parse("key = 1 2").is_err());
+        assert_eq!(quote("tab\t\"\u{1}"), "\"tab\\t\\\"\\u0001\"");
+    }
+}
@@ -0,0 +1,172 @@
+use crate::input::StringInput;
+use crate::instruction::Word;
+use crate::program::Program;
+use crate::run::{Vm, VmConfig, VmError};
+use std::mem;
+
+/// Maximum number of steps compared when the configuration has no step limit.
+pub const TRACE_DIFF_STEP_LIMIT: u64 = 1_000_000;
+
+/// What one program did at the step where two traces diverge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceSide {
+    /// The instruction executed at that step, or `None` if the program had already stopped.
+    pub pc: Option<usize>,
+    /// The stack after the step.
+    pub stack: Vec<Word>,
+    /// The trap the step raised, if any.
+    pub error: Option<VmError>,
+}
+
+impl TraceSide {
+    /// Describes the step, naming its instruction and location in `program`.
+    pub fn describe(&self, program: &Program) -> String {
+        let Some(pc) = self.pc else {
+            return format!("already stopped, stack {:?}", self.stack);
+        };
+        let location = program.symbolize(pc).map_or_else(|| format!("instruction {}", pc), |symbol| symbol.to_string());
+        let instruction = program.instructions.get(pc).map_or_else(|| "?".to_string(), |instruction| instruction.to_string());
+        match &self.error {
+            Some(error) => format!("{} ({}) trapped: {}", instruction, location, error),
+            None => format!("{} ({}), stack {:?}", instruction, location, self.stack),
+        }
+    }
+}
+
+/// How the traces of two programs compare, see [`diff_traces`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceDiff {
+    /// Both programs stopped after `steps` steps with the same stack after every step,
+    /// either normally or, at the next step, with the same kind of `trap` (the first
+    /// program's is kept).
+    Same { steps: u64, trap: Option<VmError> },
+    /// The stacks first differ after `step` steps (counting from 1), or only one of the
+    /// programs stopped or trapped there.
+    Diverged { step: u64, old: TraceSide, new: TraceSide },
+}
+
+/// Runs `old` and `new` side by side, one instruction at a time, with the same
+/// configuration and `input`, and compares their stacks after every step.
+///
+/// This checks that a refactored program computes the same values in the same order as
+/// the original. Both run for at most [`TRACE_DIFF_STEP_LIMIT`] steps unless `config` sets
+/// a step limit.
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::program::Program;
+/// use vortex_vm::run::VmConfig;
+/// use vortex_vm::trace_diff::{diff_traces, TraceDiff};
+///
+/// let old = Program::new(vec![Instruction::Push(2), Instruction::MultS(3), Instruction::AddS(1)]);
+/// let new = Program::new(vec![Instruction::Push(2), Instruction::MultS(3), Instruction::AddS(2)]);
+/// match diff_traces(&old, &new, &VmConfig::default(), b"") {
+///     TraceDiff::Diverged { step, old, new } => {
+///         assert_eq!(step, 3);
+///         assert_eq!((old.stack, new.stack), (vec![7], vec![8]));
+///     }
+///     TraceDiff::Same { .. } => unreachable!(),
+/// }
+/// ```
+pub fn diff_traces(old: &Program, new: &Program, config: &VmConfig, input: &[u8]) -> TraceDiff {
+    let mut config = config.clone();
+    config.max_steps.get_or_insert(TRACE_DIFF_STEP_LIMIT);
+    let mut old_vm = start(old, &config, input);
+    let mut new_vm = start(new, &config, input);
+    let mut old_output = Vec::new();
+    let mut new_output = Vec::new();
+
+    let mut step = 0;
+    loop {
+        let old_side = advance(&mut old_vm, &mut old_output);
+        let new_side = advance(&mut new_vm, &mut new_output);
+        if old_side.pc.is_none() && new_side.pc.is_none() {
+            return TraceDiff::Same { steps: step, trap: None };
+        }
+        step += 1;
+
+        // Traps only have to be of the same kind, as their instruction indices may differ
+        let same_trap = old_side.error.as_ref().map(mem::discriminant) == new_side.error.as_ref().map(mem::discriminant);
+        if old_side.pc.is_none() || new_side.pc.is_none() || old_side.stack != new_side.stack || !same_trap {
+            return TraceDiff::Diverged { step, old: old_side, new: new_side };
+        }
+        if old_side.error.is_some() {
+            return TraceDiff::Same { steps: step - 1, trap: old_side.error };
+        }
+    }
+}
+
+fn start(program: &Program, config: &VmConfig, input: &[u8]) -> Vm {
+    let mut config = config.clone();
+    program.requirements.apply(&mut config);
+    let mut vm = Vm::with_config(program.instructions.clone(), config);
+    vm.load_data(&program.data);
+    vm.set_input_source(StringInput::new(input));
+    vm
+}
+
+/// Executes one instruction, or returns a side without `pc` if the program has stopped.
+fn advance(vm: &mut Vm, output: &mut Vec<u8>) -> TraceSide {
+    if !vm.is_running() {
+        return TraceSide { pc: None, stack: vm.stack.clone(), error: None };
+    }
+    let pc = vm.pc;
+    let error = vm.step(output).err();
+    if error.is_some() {
+        // A trapped program does not run on
+        vm.halted = true;
+    }
+    TraceSide { pc: Some(pc), stack: vm.stack.clone(), error }
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+    use crate::spliter::parse_program;
+
+    #[test]
+    fn test_refactored_program_with_the_same_trace() {
+        let old = parse_program("PUSH 3\nloop:\nSUBS 1\nJNZ loop").unwrap();
+        let new = parse_program("N EQU 3\nPUSH N\nagain:\nSUBS 1\nJNZ again").unwrap();
+        assert_eq!(diff_traces(&old, &new, &VmConfig::default(), b""), TraceDiff::Same { steps: 7, trap: None });
+    }
+
+    #[test]
+    fn test_one_program_stopping_early_diverges() {
+        let old = parse_program("PUSH 1\nPUSH 2").unwrap();
+        let new = parse_program("PUSH 1").unwrap();
+        let TraceDiff::Diverged { step, old: old_side, new: new_side } = diff_traces(&old, &new, &VmConfig::default(), b"") else {
+            panic!("traces should diverge");
+        };
+        assert_eq!(step, 2);
+        assert_eq!(old_side.describe(&old), "PUSH 2 (start+1 at line 2), stack [1, 2]");
+        assert_eq!(new_side.describe(&new), "already stopped, stack [1]");
+    }
+
+    #[test]
+    fn test_both_programs_get_the_same_input() {
+        let old = parse_program("READINT\nDUP\nADD").unwrap();
+        let new = parse_program("READINT\nMULTS 2").unwrap();
+        assert_eq!(diff_traces(&old, &new, &VmConfig::default(), b"21\n"), TraceDiff::Diverged {
+            step: 2,
+            old: TraceSide { pc: Some(1), stack: vec![21, 21], error: None },
+            new: TraceSide { pc: Some(1), stack: vec![42], error: None },
+        });
+    }
+
+    #[test]
+    fn test_traps_are_compared_by_kind() {
+        let old = parse_program("PUSH 1\nDIVS 0").unwrap();
+        let new = parse_program("PUSH 1\nDIVS 0\nNULL").unwrap();
+        let config = VmConfig { strict: true, ..VmConfig::default() };
+        let trap = Some(VmError::DivisionByZero { pc: 1 });
+        assert_eq!(diff_traces(&old, &new, &config, b""), TraceDiff::Same { steps: 1, trap });
+
+        let new = parse_program("PUSH 1\nPOP\nPOP").unwrap();
+        let TraceDiff::Diverged { step, new: new_side, .. } = diff_traces(&old, &new, &config, b"") else {
+            panic!("traces should diverge");
+        };
+        assert_eq!(step, 2);
+        assert_eq!(new_side.stack, Vec::<Word>::new());
+    }
+}
@@ -0,0 +1,501 @@
+use crate::analysis::{analyze, stack_effect, successors};
+use crate::instruction::Instruction;
+use crate::program::Program;
+use crate::run::{Vm, VmConfig, VmError, MEMORY_SIZE};
+use std::fmt;
+
+/// The bounds a program must stay within to pass [`verify_program`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The most instructions the program may have.
+    pub max_instructions: usize,
+    /// The number of memory cells the program runs with; every address it names must be below it.
+    pub memory_size: usize,
+    /// The deepest the stack may get.
+    pub max_stack: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_instructions: 1 << 20, memory_size: MEMORY_SIZE, max_stack: 1 << 16 }
+    }
+}
+
+/// Why [`verify_program`] rejected a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The program has more than [`Limits::max_instructions`] instructions.
+    TooManyInstructions { count: usize, limit: usize },
+    /// A `JMPDYN`, `HOSTCALL`, `CODEWRITE`, `TRY` or `THROW`, whose effect cannot be
+    /// checked ahead of time.
+    Unverifiable { pc: usize, instruction: String },
+    /// A jump or call targets an index outside the program.
+    JumpOutOfRange { pc: usize, target: u32 },
+    /// An instruction names memory cells at or beyond [`Limits::memory_size`].
+    MemoryOutOfRange { pc: usize, end: usize },
+    /// The data section or `.requires mem` needs more memory than [`Limits::memory_size`].
+    MemoryTooSmall { needed: usize, limit: usize },
+    /// The instruction at `pc` may run with too few values on the stack.
+    StackUnderflow { pc: usize },
+    /// The stack may get deeper than [`Limits::max_stack`], or without bound if `depth` is `None`.
+    StackTooDeep { depth: Option<usize>, limit: usize },
+    /// The instruction at `pc` takes `needed` values but never has more than `depth` on
+    /// the stack, however execution gets there.
+    CertainUnderflow { pc: usize, depth: usize, needed: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::TooManyInstructions { count, limit } => {
+                write!(f, "program has {} instructions, more than the limit of {}", count, limit)
+            }
+            VerifyError::Unverifiable { pc, instruction } => {
+                write!(f, "'{}' at instruction {} cannot be verified", instruction, pc)
+            }
+            VerifyError::JumpOutOfRange { pc, target } => {
+                write!(f, "jump target {} at instruction {} is outside the program", target, pc)
+            }
+            VerifyError::MemoryOutOfRange { pc, end } => {
+                write!(f, "instruction {} uses memory up to cell {}, beyond the memory limit", pc, end - 1)
+            }
+            VerifyError::MemoryTooSmall { needed, limit } => {
+                write!(f, "program needs {} memory cells but the limit is {}", needed, limit)
+            }
+            VerifyError::StackUnderflow { pc } => write!(f, "instruction {} may underflow the stack", pc),
+            VerifyError::StackTooDeep { depth: Some(depth), limit } => {
+                write!(f, "stack may reach depth {}, more than the limit of {}", depth, limit)
+            }
+            VerifyError::StackTooDeep { depth: None, limit } => {
+                write!(f, "stack depth cannot be bounded statically (limit {})", limit)
+            }
+            VerifyError::CertainUnderflow { pc, depth, needed } => {
+                write!(f, "instruction {} always underflows the stack: it takes {} values but at most {} are there", pc, needed, depth)
+            }
+        }
+    }
+}
+
+impl VerifyError {
+    /// The instruction the problem is at, if it is at one.
+    pub fn pc(&self) -> Option<usize> {
+        match self {
+            VerifyError::Unverifiable { pc, .. }
+            | VerifyError::JumpOutOfRange { pc, .. }
+            | VerifyError::MemoryOutOfRange { pc, .. }
+            | VerifyError::StackUnderflow { pc }
+            | VerifyError::CertainUnderflow { pc, .. } => Some(*pc),
+            VerifyError::TooManyInstructions { .. } | VerifyError::MemoryTooSmall { .. } | VerifyError::StackTooDeep { .. } => None,
+        }
+    }
+
+    /// Describes the problem like its `Display` form, naming the `.region`s a memory
+    /// operand out of range points into when `program` declares them.
+    ///
+    /// ```
+    /// use vortex_vm::instruction::Instruction;
+    /// use vortex_vm::program::{Program, Region};
+    /// use vortex_vm::verify::check_program;
+    ///
+    /// let mut program = Program::new(vec![Instruction::MemSet(62, 8, 0)]);
+    /// program.debug_info.regions.push(Region { name: "buffer".to_string(), start: 60, len: 8 });
+    /// let errors = check_program(&program, 64).unwrap_err();
+    /// assert_eq!(errors[0].describe(&program), "instruction 0 uses memory up to cell 69, beyond the memory limit (at buffer+2)");
+    /// ```
+    pub fn describe(&self, program: &Program) -> String {
+        let VerifyError::MemoryOutOfRange { pc, .. } = self else {
+            return self.to_string();
+        };
+        let addresses = program.instructions.get(*pc).map(Instruction::memory_addresses).unwrap_or_default();
+        let names: Vec<String> = addresses.into_iter().filter_map(|addr| usize::try_from(addr).ok().and_then(|addr| program.debug_info.name_address(addr))).collect();
+        if names.is_empty() { self.to_string() } else { format!("{} (at {})", self, names.join(", ")) }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// A program that passed [`verify_program`]. It can only be created by verification, so
+/// holding one means the checks were done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedProgram {
+    program: Program,
+    limits: Limits,
+}
+
+impl VerifiedProgram {
+    /// The verified program.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// The limits the program was verified against.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+}
+
+/// Checks ahead of time that `program` cannot misbehave at runtime within `limits`: every
+/// jump and call lands inside the program, every memory operand and data block fits in
+/// memory, no instruction can underflow the stack and the stack stays within its limit.
+///
+/// Programs using `JMPDYN`, `HOSTCALL`, `CODEWRITE`, `TRY` or `THROW` are rejected since
+/// their targets and stack effects are only known at runtime. A `DIV` or `MOD` that divides
+/// by zero leaves no result, so code after one must not rely on it being there.
+/// Verification does not bound running time.
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::program::Program;
+/// use vortex_vm::verify::{execute_verified, verify_program, Limits, VerifyError};
+///
+/// let program = Program::new(vec![Instruction::Push(6), Instruction::MultS(7), Instruction::MemRead(2), Instruction::Add]);
+/// let verified = verify_program(&program, &Limits::default()).unwrap();
+/// let vm = execute_verified(&verified, &mut Vec::new()).unwrap();
+/// assert_eq!(vm.stack, vec![42]);
+///
+/// let program = Program::new(vec![Instruction::Push(1), Instruction::Add]);
+/// let err = verify_program(&program, &Limits::default()).unwrap_err();
+/// assert_eq!(err, VerifyError::StackUnderflow { pc: 1 });
+/// ```
+pub fn verify_program(program: &Program, limits: &Limits) -> Result<VerifiedProgram, VerifyError> {
+    let len = program.instructions.len();
+    if len > limits.max_instructions {
+        return Err(VerifyError::TooManyInstructions { count: len, limit: limits.max_instructions });
+    }
+
+    let needed = program.data.iter().map(|block| block.end()).chain(program.requirements.memory).max().unwrap_or(0);
+    if needed > limits.memory_size {
+        return Err(VerifyError::MemoryTooSmall { needed, limit: limits.memory_size });
+    }
+
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        match instruction {
+            Instruction::JmpDyn | Instruction::HostCall(_) | Instruction::CodeWrite | Instruction::Try(_) | Instruction::Throw => {
+                return Err(VerifyError::Unverifiable { pc, instruction: instruction.to_string() });
+            }
+            // Jumps may target the end of the program to stop it; calls must land on an instruction
+            Instruction::Jiz(target) | Instruction::Jnz(target) | Instruction::Jmp(target) if *target as usize > len => {
+                return Err(VerifyError::JumpOutOfRange { pc, target: *target });
+            }
+            Instruction::Call(target) if *target as usize >= len => {
+                return Err(VerifyError::JumpOutOfRange { pc, target: *target });
+            }
+            _ => {}
+        }
+
+        if let Some(end) = memory_end(instruction)
+            && end > limits.memory_size
+        {
+            return Err(VerifyError::MemoryOutOfRange { pc, end });
+        }
+    }
+
+    let stats = analyze(program);
+    if let Some(pc) = stats.underflow {
+        return Err(VerifyError::StackUnderflow { pc });
+    }
+    match stats.max_stack {
+        Some(depth) if depth <= limits.max_stack => {}
+        depth => return Err(VerifyError::StackTooDeep { depth, limit: limits.max_stack }),
+    }
+
+    Ok(VerifiedProgram { program: program.clone(), limits: limits.clone() })
+}
+
+/// Checks `instructions` like [`check_program`], against the default memory size.
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::verify::{verify, VerifyError};
+///
+/// assert!(verify(&[Instruction::Push(1), Instruction::HostCall("get".to_string()), Instruction::Add]).is_ok());
+/// assert_eq!(verify(&[Instruction::Push(1), Instruction::Add, Instruction::Jmp(9)]), Err(vec![
+///     VerifyError::CertainUnderflow { pc: 1, depth: 1, needed: 2 },
+///     VerifyError::JumpOutOfRange { pc: 2, target: 9 },
+/// ]));
+/// ```
+pub fn verify(instructions: &[Instruction]) -> Result<(), Vec<VerifyError>> {
+    check_program(&Program::new(instructions.to_vec()), MEMORY_SIZE)
+}
+
+/// Finds every problem `program` is certain to run into with `memory_size` memory cells:
+/// jumps and calls outside the program, memory operands and data beyond the end of memory,
+/// and instructions that underflow the stack however execution reaches them, in order of
+/// their instruction.
+///
+/// Unlike [`verify_program`] this accepts programs whose behavior depends on runtime
+/// values and reports all problems rather than the first, so it suits checking bytecode
+/// before running it on the regular interpreter. Stack depths are only tracked where they
+/// are known: after a `CALL` or `HOSTCALL`, in a `TRY` handler and anywhere in programs
+/// using `JMPDYN` or `CODEWRITE` no underflow is reported.
+pub fn check_program(program: &Program, memory_size: usize) -> Result<(), Vec<VerifyError>> {
+    let len = program.instructions.len();
+    let mut errors = Vec::new();
+
+    let needed = program.data.iter().map(|block| block.end()).max().unwrap_or(0);
+    if needed > memory_size {
+        errors.push(VerifyError::MemoryTooSmall { needed, limit: memory_size });
+    }
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        match instruction {
+            Instruction::Jiz(target) | Instruction::Jnz(target) | Instruction::Jmp(target) | Instruction::Try(target) if *target as usize > len => {
+                errors.push(VerifyError::JumpOutOfRange { pc, target: *target });
+            }
+            Instruction::Call(target) if *target as usize >= len => {
+                errors.push(VerifyError::JumpOutOfRange { pc, target: *target });
+            }
+            _ => {}
+        }
+        if let Some(end) = memory_end(instruction)
+            && end > memory_size
+        {
+            errors.push(VerifyError::MemoryOutOfRange { pc, end });
+        }
+    }
+    errors.extend(certain_underflows(&program.instructions));
+    errors.sort_by_key(VerifyError::pc);
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Widening a depth more often than this at one instruction means a loop grows the stack.
+const WIDEN_LIMIT: usize = 3;
+
+/// Finds instructions that take more values than the stack can hold when they run, by
+/// tracking the deepest the stack can be before each instruction along every path.
+fn certain_underflows(instructions: &[Instruction]) -> Vec<VerifyError> {
+    let opaque = instructions.iter().any(|instruction| matches!(instruction, Instruction::JmpDyn | Instruction::CodeWrite));
+    if opaque {
+        return Vec::new();
+    }
+
+    // usize::MAX stands for a depth that is unknown or unbounded
+    let mut depths: Vec<Option<usize>> = vec![None; instructions.len()];
+    let mut widened = vec![0; instructions.len()];
+    let mut work = vec![(0, 0)];
+    while let Some((pc, depth)) = work.pop() {
+        let Some(instruction) = instructions.get(pc) else {
+            continue;
+        };
+        let depth = match depths[pc] {
+            Some(known) if known >= depth => continue,
+            Some(_) if widened[pc] >= WIDEN_LIMIT => usize::MAX,
+            Some(_) => {
+                widened[pc] += 1;
+                depth
+            }
+            None => depth,
+        };
+        depths[pc] = Some(depth);
+
+        let (pops, pushes) = stack_effect(instruction);
+        let after = match instruction {
+            _ if depth == usize::MAX => usize::MAX,
+            Instruction::Clear => 0,
+            Instruction::HostCall(_) => usize::MAX,
+            // An instruction that underflows is skipped
+            _ if pops as usize > depth => depth,
+            _ => depth - pops as usize + pushes as usize,
+        };
+        match instruction {
+            // The callee runs on the caller's stack, and leaves it who knows how deep
+            Instruction::Call(target) => work.extend([(*target as usize, depth), (pc + 1, usize::MAX)]),
+            Instruction::Try(target) => work.extend([(*target as usize, usize::MAX), (pc + 1, after)]),
+            _ => work.extend(successors(instruction, pc).into_iter().map(|next| (next, after))),
+        }
+    }
+
+    depths
+        .iter()
+        .enumerate()
+        .filter_map(|(pc, depth)| {
+            let depth = (*depth)?;
+            let needed = stack_effect(&instructions[pc]).0 as usize;
+            (depth != usize::MAX && needed > depth).then_some(VerifyError::CertainUnderflow { pc, depth, needed })
+        })
+        .collect()
+}
+
+/// Runs a verified program to completion on a fresh VM with [`Limits::memory_size`] cells
+/// of memory, and returns the VM so its stack, memory and exit code can be inspected.
+///
+/// Verified programs take a fast path that skips the per-step checks verification made
+/// redundant.
+pub fn execute_verified(verified: &VerifiedProgram, output: &mut Vec<u8>) -> Result<Vm, VmError> {
+    let config = VmConfig { memory_size: Some(verified.limits.memory_size), ..VmConfig::default() };
+    let mut vm = Vm::with_config(verified.program.instructions.clone(), config);
+    vm.load_data(&verified.program.data);
+    vm.run_verified(output)?;
+    Ok(vm)
+}
+
+/// Runs a verified program like [`execute_verified`], but on a path that also skips the
+/// stack, jump target and memory bounds checks, for trusted generated code where
+/// interpreter throughput matters most.
+///
+/// ```
+/// use vortex_vm::instruction::Instruction;
+/// use vortex_vm::program::Program;
+/// use vortex_vm::verify::{execute_verified_unchecked, verify_program, Limits};
+///
+/// let program = Program::new(vec![
+///     Instruction::Push(20),
+///     Instruction::Dup,
+///     Instruction::Add,
+///     Instruction::Push(2),
+///     Instruction::Add,
+/// ]);
+/// let verified = verify_program(&program, &Limits::default()).unwrap();
+/// // SAFETY: the program comes from our own generator and passed verification
+/// let vm = unsafe { execute_verified_unchecked(&verified, &mut Vec::new()) }.unwrap();
+/// assert_eq!(vm.stack, vec![42]);
+/// ```
+///
+/// # Safety
+///
+/// The checks this path leaves out are replaced by [`verify_program`]'s static analysis.
+/// Only use it for programs from a trusted source: a program that slips past a flaw in the
+/// verifier reads out of bounds here instead of trapping.
+pub unsafe fn execute_verified_unchecked(verified: &VerifiedProgram, output: &mut Vec<u8>) -> Result<Vm, VmError> {
+    let config = VmConfig { memory_size: Some(verified.limits.memory_size), ..VmConfig::default() };
+    let mut vm = Vm::with_config(verified.program.instructions.clone(), config);
+    vm.load_data(&verified.program.data);
+    // SAFETY: verification established the operands, targets and addresses the fast path relies on
+    unsafe { vm.run_verified_unchecked(output)? };
+    Ok(vm)
+}
+
+/// Returns one past the last memory cell `instruction` names through its operands, if any.
+/// Negative addresses and lengths count as out of range.
+fn memory_end(instruction: &Instruction) -> Option<usize> {
+    let end = |addr: i32, len: usize| usize::try_from(addr).map_or(usize::MAX, |addr| addr.saturating_add(len));
+    let cells = |len: i32| usize::try_from(len).unwrap_or(usize::MAX);
+    match instruction {
+        Instruction::MemWrite(addr, values) => Some(end(*addr, values.len())),
+        Instruction::MemWriteS(addr, len)
+        | Instruction::Print(addr, len)
+        | Instruction::Abort(addr, len)
+        | Instruction::ReadLine(addr, len)
+        | Instruction::Protect(addr, len)
+        | Instruction::MemSet(addr, len, _) => Some(end(*addr, cells(*len))),
+        Instruction::MemRead(addr) | Instruction::PrintStr(addr) => Some(end(*addr, 1)),
+        Instruction::MemCopy(a, b, len) | Instruction::MemCmp(a, b, len) => Some(end(*a, cells(*len)).max(end(*b, cells(*len)))),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+    use crate::spliter::parse_program;
+
+    fn verify(source: &str) -> Result<VerifiedProgram, VerifyError> {
+        verify_program(&parse_program(source).unwrap(), &Limits { memory_size: 64, ..Limits::default() })
+    }
+
+    #[test]
+    fn test_fast_path_matches_the_interpreter() {
+        let source = ".data 8\n.string \"ok\"\n.text\nPUSH 5\nloop:\nDUP\nMEMWRITES 0 1\nSUBS 1\nJNZ loop\nCALL show\nHALT 3\nshow:\nPRINTSTR 8\nRET";
+        let verified = verify(source).unwrap();
+
+        let mut fast_output = Vec::new();
+        let fast = execute_verified(&verified, &mut fast_output).unwrap();
+
+        let program = parse_program(source).unwrap();
+        let mut vm = Vm::with_config(program.instructions, VmConfig { memory_size: Some(64), ..VmConfig::default() });
+        vm.load_data(&program.data);
+        let mut output = Vec::new();
+        vm.run(&mut output).unwrap();
+
+        let mut unchecked_output = Vec::new();
+        // SAFETY: the program passed verification
+        let unchecked = unsafe { execute_verified_unchecked(&verified, &mut unchecked_output) }.unwrap();
+        assert_eq!((&unchecked.stack, &unchecked.mem, unchecked.exit_code, &unchecked_output), (&vm.stack, &vm.mem, vm.exit_code, &output));
+
+        assert_eq!((fast.stack, fast.mem, fast.exit_code, fast_output), (vm.stack, vm.mem, vm.exit_code, output));
+    }
+
+    #[test]
+    fn test_unchecked_path_survives_division_by_zero() {
+        // These used to pass verification and pop an empty stack on the unchecked path
+        for source in ["PUSH 1\nPUSH 0\nDIV\nPOP\nPUSH 5", "PUSH 7\nPUSH 0\nMOD\nPOP"] {
+            assert!(verify(source).is_err(), "{}", source);
+        }
+
+        let source = "PUSH 1\nPUSH 7\nPUSH 0\nMOD\nPOP";
+        let verified = verify(source).unwrap();
+        // SAFETY: the program passed verification
+        let unchecked = unsafe { execute_verified_unchecked(&verified, &mut Vec::new()) }.unwrap();
+        let mut vm = Vm::new(parse_program(source).unwrap().instructions);
+        vm.run(&mut Vec::new()).unwrap();
+        assert_eq!((unchecked.stack, vm.stack), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn test_conditional_jump_on_empty_stack_is_rejected() {
+        assert_eq!(verify("JIZ 1\nPUSH 1"), Err(VerifyError::StackUnderflow { pc: 0 }));
+    }
+
+    #[test]
+    fn test_division_by_zero_may_leave_no_result() {
+        assert_eq!(verify("PUSH 1\nPUSH 0\nDIV\nPOP\nPUSH 5"), Err(VerifyError::StackUnderflow { pc: 3 }));
+        assert_eq!(verify("PUSH 7\nPUSH 0\nMOD\nPOP"), Err(VerifyError::StackUnderflow { pc: 3 }));
+        // The stack below the operands is still there either way
+        assert!(verify("PUSH 1\nPUSH 6\nPUSH 3\nDIV\nPOP").is_ok());
+        assert!(verify("PUSH 6\nDIVS 3\nPOP").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_what_cannot_be_checked() {
+        assert_eq!(verify("PUSH 0\nJMPDYN"), Err(VerifyError::Unverifiable { pc: 1, instruction: "JMPDYN".to_string() }));
+        // The parser refuses targets past the end, but bytecode can still hold them
+        let jump = Program::new(vec![Instruction::Jmp(5)]);
+        assert_eq!(verify_program(&jump, &Limits::default()), Err(VerifyError::JumpOutOfRange { pc: 0, target: 5 }));
+        assert_eq!(verify("CALL 1"), Err(VerifyError::JumpOutOfRange { pc: 0, target: 1 }));
+        assert!(verify("JMP 1").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_memory_outside_the_limit() {
+        assert_eq!(verify("MEMWRITE 62 1 2 3"), Err(VerifyError::MemoryOutOfRange { pc: 0, end: 65 }));
+        assert_eq!(verify("MEMREAD -1").unwrap_err(), VerifyError::MemoryOutOfRange { pc: 0, end: usize::MAX });
+        assert_eq!(verify(".data 60\n.word 1 2 3 4 5"), Err(VerifyError::MemoryTooSmall { needed: 65, limit: 64 }));
+        assert!(verify("MEMWRITE 61 1 2 3\nPRINT 0 64").is_ok());
+    }
+
+    #[test]
+    fn test_check_program_reports_every_problem() {
+        let mut program = parse_program(".data 70\n.word 1\n.text\nPUSH 1\nADD\nPRINT 60 8\nJIZ 0\nCALL 5").unwrap();
+        program.instructions[3] = Instruction::Jiz(9);
+        assert_eq!(check_program(&program, 64), Err(vec![
+            VerifyError::MemoryTooSmall { needed: 71, limit: 64 },
+            VerifyError::CertainUnderflow { pc: 1, depth: 1, needed: 2 },
+            VerifyError::MemoryOutOfRange { pc: 2, end: 68 },
+            VerifyError::JumpOutOfRange { pc: 3, target: 9 },
+            VerifyError::JumpOutOfRange { pc: 4, target: 5 },
+        ]));
+    }
+
+    #[test]
+    fn test_only_certain_underflows_are_reported() {
+        let check = |source: &str| check_program(&parse_program(source).unwrap(), MEMORY_SIZE);
+        // Only one path pushes enough
+        assert!(check("PUSH 1\nJIZ skip\nPUSH 2\nskip:\nADD").is_ok());
+        // A loop that pops one value per round
+        assert!(check("PUSH 3\nloop:\nPOP\nPUSH 1\nJNZ loop").is_ok());
+        // Subroutines see their caller's stack, but the depth after a call is unknown
+        assert!(check("PUSH 1\nPUSH 2\nCALL add\nADD\nHALT 0\nadd:\nADD\nRET").is_ok());
+        assert_eq!(check("PUSH 1\nCALL add\nHALT 0\nadd:\nADD\nRET"), Err(vec![VerifyError::CertainUnderflow { pc: 3, depth: 1, needed: 2 }]));
+        // A loop growing the stack does not make later pops underflow
+        assert!(check("loop:\nPUSH 1\nDEPTH\nSUBS 5\nJNZ loop\nPOP\nADD").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_unbounded_and_deep_stacks() {
+        assert_eq!(verify("loop:\nPUSH 1\nJMP loop"), Err(VerifyError::StackTooDeep { depth: None, limit: 1 << 16 }));
+
+        let limits = Limits { max_stack: 2, ..Limits::default() };
+        let err = verify_program(&parse_program("PUSH 1\nPUSH 2\nPUSH 3").unwrap(), &limits).unwrap_err();
+        assert_eq!(err, VerifyError::StackTooDeep { depth: Some(3), limit: 2 });
+    }
+}
@@ -5,7 +5,7 @@ use vortex_vm::spliter::split_instructions;
 #[test]
 fn test_math_example() {
     let content = fs::read_to_string("examples/math.vvm").expect("Failed to read math.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -18,7 +18,7 @@ fn test_math_example() {
 #[test]
 fn test_jmp_example() {
     let content = fs::read_to_string("examples/jmp.vvm").expect("Failed to read jmp.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -32,7 +32,7 @@ fn test_jmp_example() {
 #[test]
 fn test_labels_example() {
     let content = fs::read_to_string("examples/labels.vvm").expect("Failed to read labels.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, mem) = execute(&instructions, &mut output);
@@ -45,17 +45,30 @@ fn test_labels_example() {
     // Check that "Hello World!" was written to memory correctly
     let hello_world_bytes = b"Hello World!";
     for (i, &byte) in hello_world_bytes.iter().enumerate() {
-        assert_eq!(mem[i], byte as i32, "Memory mismatch at position {}", i);
+        assert_eq!(mem[i], byte as i64, "Memory mismatch at position {}", i);
     }
 
     // Stack should be empty after Ret
     assert!(stack.is_empty());
 }
 
+#[test]
+fn test_labels_example_without_dead_code() {
+    use vortex_vm::spliter::parse_program;
+
+    let content = fs::read_to_string("examples/labels.vvm").expect("Failed to read labels.vvm");
+    let mut program = parse_program(&content).unwrap();
+    assert_eq!(program.eliminate_dead_code(), 4);
+
+    let mut output = Vec::new();
+    execute(&program.instructions, &mut output);
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello World!");
+}
+
 #[test]
 fn test_operations_example() {
     let content = fs::read_to_string("examples/operations.vvm").expect("Failed to read operations.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -70,7 +83,7 @@ fn test_operations_example() {
 #[test]
 fn test_mult_example() {
     let content = fs::read_to_string("examples/mult.vvm").expect("Failed to read mult.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (_stack, _mem) = execute(&instructions, &mut output);
@@ -82,7 +95,7 @@ fn test_mult_example() {
 #[test]
 fn test_ret_example() {
     let content = fs::read_to_string("examples/ret.vvm").expect("Failed to read ret.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -114,7 +127,7 @@ end_loop:
     Ret
 "#;
 
-    let instructions = split_instructions(&program.to_string());
+    let instructions = split_instructions(program).unwrap();
 
     let mut output = Vec::new();
     let (stack, mem) = execute(&instructions, &mut output);
@@ -151,7 +164,7 @@ end_program:
     Ret
 "#;
 
-    let instructions = split_instructions(&program.to_string());
+    let instructions = split_instructions(program).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -181,7 +194,7 @@ start:
     Ret
 "#;
 
-    let instructions = split_instructions(&program.to_string());
+    let instructions = split_instructions(program).unwrap();
 
     let mut output = Vec::new();
     let (stack, mem) = execute(&instructions, &mut output);
@@ -212,7 +225,7 @@ start:
     Ret
 "#;
 
-    let instructions = split_instructions(&program.to_string());
+    let instructions = split_instructions(program).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -235,7 +248,7 @@ start:
     Ret
 "#;
 
-    let instructions = split_instructions(&program.to_string());
+    let instructions = split_instructions(program).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -259,7 +272,7 @@ target:
     Ret
 "#;
 
-    let instructions = split_instructions(&program.to_string());
+    let instructions = split_instructions(program).unwrap();
 
     let mut output = Vec::new();
     let (_stack, _mem) = execute(&instructions, &mut output);
@@ -279,7 +292,7 @@ start:
     Ret
 "#;
 
-    let instructions = split_instructions(&program.to_string());
+    let instructions = split_instructions(program).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -293,7 +306,7 @@ start:
 #[test]
 fn test_factorial_example() {
     let content = fs::read_to_string("examples/factorial.vvm").expect("Failed to read factorial.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -304,10 +317,21 @@ fn test_factorial_example() {
     assert!(output.is_empty());
 }
 
+#[test]
+fn test_locals_example() {
+    let content = fs::read_to_string("examples/locals.vvm").expect("Failed to read locals.vvm");
+    let instructions = split_instructions(&content).unwrap();
+
+    let mut output = Vec::new();
+    let (stack, _mem) = execute(&instructions, &mut output);
+
+    assert_eq!(stack, vec![120]);
+}
+
 #[test]
 fn test_string_manipulation_example() {
     let content = fs::read_to_string("examples/string_manipulation.vvm").expect("Failed to read string_manipulation.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, mem) = execute(&instructions, &mut output);
@@ -330,7 +354,7 @@ fn test_string_manipulation_example() {
 #[test]
 fn test_arithmetic_test_example() {
     let content = fs::read_to_string("examples/arithmetic_test.vvm").expect("Failed to read arithmetic_test.vvm");
-    let instructions = split_instructions(&content);
+    let instructions = split_instructions(&content).unwrap();
 
     let mut output = Vec::new();
     let (stack, _mem) = execute(&instructions, &mut output);
@@ -339,3 +363,135 @@ fn test_arithmetic_test_example() {
     assert_eq!(stack, vec![12]);
     assert!(output.is_empty());
 }
+
+#[test]
+fn test_call_example() {
+    let content = fs::read_to_string("examples/call.vvm").expect("Failed to read call.vvm");
+    let instructions = split_instructions(&content).unwrap();
+
+    let mut output = Vec::new();
+    let (stack, _mem) = execute(&instructions, &mut output);
+
+    // 7 * 7 + 1 = 50, computed through the square subroutine
+    assert_eq!(stack, vec![50]);
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_notebook_example_renders() {
+    use vortex_vm::render::Notebook;
+    use vortex_vm::run::VmConfig;
+
+    let content = fs::read_to_string("examples/notebook.vvm").expect("Failed to read notebook.vvm");
+    let notebook = Notebook::run(&content, VmConfig::default()).unwrap();
+
+    // The introduction plus four annotated sections, all executed without a trap
+    assert_eq!(notebook.sections.len(), 5);
+    assert_eq!(notebook.error, None);
+    assert_eq!(notebook.sections[3].visits, 7);
+    assert!(notebook.to_html("notebook").contains("<pre class=\"output\">done\n</pre>"));
+}
+
+#[test]
+fn test_sum_input_example() {
+    use std::io::Cursor;
+    use vortex_vm::run::Vm;
+
+    let content = fs::read_to_string("examples/sum_input.vvm").expect("Failed to read sum_input.vvm");
+    let mut vm = Vm::new(split_instructions(&content).unwrap());
+    vm.set_input(Cursor::new("3\n10\n20\n12\n"));
+
+    vm.run(&mut Vec::new()).unwrap();
+    assert_eq!(vm.stack, vec![42]);
+}
+
+#[test]
+fn test_data_example() {
+    use vortex_vm::run::Vm;
+    use vortex_vm::spliter::parse_program;
+
+    let content = fs::read_to_string("examples/data.vvm").expect("Failed to read data.vvm");
+    let program = parse_program(&content).unwrap();
+    let mut vm = Vm::new(program.instructions);
+    vm.load_data(&program.data);
+
+    let mut output = Vec::new();
+    vm.run(&mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello from .data!\n42");
+    assert!(vm.stack.is_empty());
+}
+
+#[test]
+fn test_array_example() {
+    use vortex_vm::run::Vm;
+    use vortex_vm::spliter::parse_program;
+
+    let content = fs::read_to_string("examples/array.vvm").expect("Failed to read array.vvm");
+    let program = parse_program(&content).unwrap();
+    let mut vm = Vm::new(program.instructions);
+    vm.load_data(&program.data);
+
+    let mut output = Vec::new();
+    vm.run(&mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "23");
+    assert!(vm.stack.is_empty());
+    assert_eq!(vm.mem[10], 23);
+}
+
+#[test]
+fn test_include_example() {
+    use vortex_vm::include::read_source;
+
+    let source = read_source("examples/include.vvm", &[]).expect("Failed to expand include.vvm");
+    let mut output = Vec::new();
+    let (stack, _memory) = execute(&split_instructions(&source.text).unwrap(), &mut output);
+
+    assert_eq!(String::from_utf8(output).unwrap(), "42\n");
+    assert!(stack.is_empty());
+}
+
+#[test]
+fn test_formatting_examples_keeps_their_programs() {
+    use vortex_vm::formatter::format_source;
+    use vortex_vm::include::expand_source;
+    use vortex_vm::spliter::parse_program;
+
+    for entry in fs::read_dir("examples").expect("Failed to read examples") {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "vvm") {
+            continue;
+        }
+        let content = fs::read_to_string(&path).unwrap();
+        let formatted = format_source(&content);
+
+        // Included files are expanded so the labels they define resolve
+        let parse = |text: &str| parse_program(&expand_source(text, &path, &[]).unwrap().text).unwrap();
+        let (program, reformatted) = (parse(&content), parse(&formatted));
+        assert_eq!(program.instructions, reformatted.instructions, "{}", path.display());
+        assert_eq!(program.data, reformatted.data, "{}", path.display());
+        assert_eq!(format_source(&formatted), formatted, "{}", path.display());
+    }
+}
+
+#[test]
+fn test_disassembled_examples_assemble_to_the_same_program() {
+    use vortex_vm::assembler::{assemble_program, assemble_program_with_debug_info, disassemble_program};
+    use vortex_vm::include::read_source;
+    use vortex_vm::spliter::parse_program;
+
+    for entry in fs::read_dir("examples").expect("Failed to read examples") {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|extension| extension != "vvm") {
+            continue;
+        }
+        let program = parse_program(&read_source(&path.to_string_lossy(), &[]).unwrap().text).unwrap();
+
+        for bytecode in [assemble_program(&program).unwrap(), assemble_program_with_debug_info(&program).unwrap()] {
+            let source = disassemble_program(&bytecode).unwrap().to_source();
+            let reassembled = parse_program(&source).unwrap();
+            assert_eq!(program.instructions, reassembled.instructions, "{}", path.display());
+            assert_eq!(program.data, reassembled.data, "{}", path.display());
+            assert_eq!(program.requirements, reassembled.requirements, "{}", path.display());
+        }
+    }
+}